@@ -1,71 +1,77 @@
 extern crate embedgdb;
-use embedgdb::stream::BufferedStream;
+use embedgdb::error::Errors;
+use embedgdb::stream::Connection;
+use embedgdb::stub::{GdbStub, StubState};
 use embedgdb::target::VirtualTarget;
-use embedgdb::{
-    command::{Command, SupportedCommands},
-    parser::Parser,
-};
+use embedgdb::watchdog::Clock;
 use std::{
-    io::{Read, Write},
     net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
 };
 
-struct DebugCommands;
-impl<'a> SupportedCommands<'a> for DebugCommands {}
+// detach if gdb is silent for this long
+const WATCHDOG_TIMEOUT_MS: u64 = 60_000;
+
+// packets larger than this are rejected
+const PACKET_SIZE: usize = 2048;
 
-fn handle(mut stream: TcpStream) -> std::io::Result<()> {
-    let mut buffer = [0xFF; 2048];
+struct SystemClock(Instant);
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.0.elapsed().as_millis() as u64
+    }
+}
 
-    let mut target = VirtualTarget::new();
+/// logs the traffic
+struct LoggingConnection {
+    stream: TcpStream,
+}
+
+impl Connection for LoggingConnection {
+    fn read(&mut self) -> Result<Option<u8>, Errors> {
+        let byte = self.stream.read()?;
+        if let Some(byte) = byte {
+            println!("<< {}", std::ascii::escape_default(byte));
+        }
+        Ok(byte)
+    }
 
-    'readloop: loop {
-        buffer.fill(0);
-        match stream.read(&mut buffer) {
-            Ok(0) => break 'readloop,
-            Ok(n) => {
-                println!(
-                    "{} bytes >> {}",
-                    n,
-                    std::str::from_utf8(&buffer).unwrap_or("")
-                );
-                let mut parser = Parser::new(&buffer);
+    fn peek(&mut self) -> Result<Option<u8>, Errors> {
+        Connection::peek(&mut self.stream)
+    }
 
-                let result = parser.parse_packet(&DebugCommands);
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+        println!(">> {}", String::from_utf8_lossy(bytes));
+        Connection::write_all(&mut self.stream, bytes)
+    }
 
-                if let Some(mut response) = result.response {
-                    let mut rstream = BufferedStream::new();
-                    let size = response.response(&mut rstream, &mut target).unwrap();
+    fn flush(&mut self) -> Result<(), Errors> {
+        Connection::flush(&mut self.stream)
+    }
+}
 
-                    println!(
-                        "{} {:?} res >> {}",
-                        size,
-                        response,
-                        std::str::from_utf8(&rstream.buffer).unwrap_or("")
-                    );
-                    if size > 0 {
-                        stream.write_all(&rstream.buffer)?;
-                    }
-                }
+fn handle(stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(500)))?;
 
-                if let Some(mut command) = result.command {
-                    let mut rstream = BufferedStream::new();
-                    let size = command.response(&mut rstream, &mut target).unwrap();
+    let clock = SystemClock(Instant::now());
+    let connection = LoggingConnection { stream };
+    let mut packet = [0; PACKET_SIZE];
+    let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut packet)
+        .with_watchdog(WATCHDOG_TIMEOUT_MS, &clock);
 
-                    println!(
-                        "{} {:?} cmd >> {}",
-                        size,
-                        command,
-                        std::str::from_utf8(&rstream.buffer).unwrap_or("")
-                    );
-                    if size > 0 {
-                        stream.write_all(&rstream.buffer)?;
-                    }
-                }
+    loop {
+        match stub.run() {
+            Ok(StubState::Idle) => (),
+            Ok(StubState::Detached) => {
+                println!("detached");
+                return Ok(());
+            }
+            Err(err) => {
+                println!("connection closed: {:?}", err);
+                return Ok(());
             }
-            Err(err) => return Err(err),
         }
     }
-    Ok(())
 }
 
 fn main() -> std::io::Result<()> {
@@ -0,0 +1,17 @@
+use embedgdb::budget::{stack_bytes, static_bytes, Usage, REPORT};
+
+fn main() {
+    println!("{:<36} {:>8} kind", "subsystem", "bytes");
+    for budget in REPORT {
+        let kind = match budget.usage {
+            Usage::Static => "static",
+            Usage::Packet => "stack",
+            Usage::Command => "stack, largest command",
+            Usage::Compression => "stack, compression",
+        };
+        println!("{:<36} {:>8} {}", budget.name, budget.bytes, kind);
+    }
+    println!();
+    println!("{:<36} {:>8}", "static total", static_bytes());
+    println!("{:<36} {:>8}", "stack total", stack_bytes());
+}
@@ -0,0 +1,215 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, RegisterEncoding, TargetDescription};
+
+/// gdb register number of the program counter
+pub const PC_REGNO: usize = 15;
+
+/// gdb register number of cpsr
+pub const CPSR_REGNO: usize = 16;
+
+/// gdb register number of d0 if a vfp is present
+pub const D0_REGNO: usize = 17;
+
+/// bkpt #0 in the arm instruction set
+pub const ARM_BKPT: [u8; 4] = [0x70, 0x00, 0x20, 0xe1];
+
+/// bkpt #0 in the thumb instruction set
+pub const THUMB_BKPT: [u8; 2] = [0x00, 0xbe];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.arm.core";
+const VFP_FEATURE: &[u8] = b"org.gnu.gdb.arm.vfp";
+
+const fn core(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+const fn double(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 64)
+        .with_encoding(RegisterEncoding::Ieee754)
+        .with_group(b"float")
+        .with_dwarf(dwarf)
+        .with_feature(VFP_FEATURE)
+}
+
+const fn fpscr() -> RegisterDesc<'static> {
+    RegisterDesc::new(b"fpscr", 32)
+        .with_group(b"float")
+        .with_feature(VFP_FEATURE)
+}
+
+macro_rules! core_registers {
+    ($($extra:expr),*) => {
+        [
+            core(b"r0", 0),
+            core(b"r1", 1),
+            core(b"r2", 2),
+            core(b"r3", 3),
+            core(b"r4", 4),
+            core(b"r5", 5),
+            core(b"r6", 6),
+            core(b"r7", 7),
+            core(b"r8", 8),
+            core(b"r9", 9),
+            core(b"r10", 10),
+            core(b"r11", 11).with_generic(GenericRegister::Fp),
+            core(b"r12", 12),
+            core(b"sp", 13).with_generic(GenericRegister::Sp),
+            core(b"lr", 14).with_generic(GenericRegister::Ra),
+            core(b"pc", 15).with_generic(GenericRegister::Pc),
+            RegisterDesc::new(b"cpsr", 32).with_generic(GenericRegister::Flags),
+            $($extra),*
+        ]
+    };
+}
+
+const REGISTERS: [RegisterDesc; 17] = core_registers!();
+
+// vfpv3-d16 and vfpv4-d16, s0-s31 are pseudo registers of gdb
+const VFP_D16_REGISTERS: [RegisterDesc; 34] = core_registers!(
+    double(b"d0", 256),
+    double(b"d1", 257),
+    double(b"d2", 258),
+    double(b"d3", 259),
+    double(b"d4", 260),
+    double(b"d5", 261),
+    double(b"d6", 262),
+    double(b"d7", 263),
+    double(b"d8", 264),
+    double(b"d9", 265),
+    double(b"d10", 266),
+    double(b"d11", 267),
+    double(b"d12", 268),
+    double(b"d13", 269),
+    double(b"d14", 270),
+    double(b"d15", 271),
+    fpscr()
+);
+
+// vfpv3 and neon, q0-q15 are pseudo registers of gdb
+const VFP_D32_REGISTERS: [RegisterDesc; 50] = core_registers!(
+    double(b"d0", 256),
+    double(b"d1", 257),
+    double(b"d2", 258),
+    double(b"d3", 259),
+    double(b"d4", 260),
+    double(b"d5", 261),
+    double(b"d6", 262),
+    double(b"d7", 263),
+    double(b"d8", 264),
+    double(b"d9", 265),
+    double(b"d10", 266),
+    double(b"d11", 267),
+    double(b"d12", 268),
+    double(b"d13", 269),
+    double(b"d14", 270),
+    double(b"d15", 271),
+    double(b"d16", 272),
+    double(b"d17", 273),
+    double(b"d18", 274),
+    double(b"d19", 275),
+    double(b"d20", 276),
+    double(b"d21", 277),
+    double(b"d22", 278),
+    double(b"d23", 279),
+    double(b"d24", 280),
+    double(b"d25", 281),
+    double(b"d26", 282),
+    double(b"d27", 283),
+    double(b"d28", 284),
+    double(b"d29", 285),
+    double(b"d30", 286),
+    double(b"d31", 287),
+    fpscr()
+);
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"arm");
+
+const VFP_D16_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &VFP_D16_REGISTERS).with_architecture(b"arm");
+
+const VFP_D32_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &VFP_D32_REGISTERS).with_architecture(b"arm");
+
+/// the floating point registers of the core
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Vfp {
+    None,
+    /// d0-d15
+    D16,
+    /// d0-d31
+    D32,
+}
+
+/// ARMv7-A and AArch32 cores, r0-r15 and cpsr
+/// the banked registers of the other modes are not part of the g packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Arm {
+    pub vfp: Vfp,
+    /// true if the code runs in thumb state
+    /// gdb then uses 2 byte breakpoints
+    pub thumb: bool,
+}
+
+impl Arm {
+    pub const fn new(vfp: Vfp) -> Self {
+        Self { vfp, thumb: false }
+    }
+
+    pub const fn with_thumb(mut self) -> Self {
+        self.thumb = true;
+        self
+    }
+}
+
+/// cores without vfp
+pub const ARMV7A: Arm = Arm::new(Vfp::None);
+
+/// e.g. Cortex-A5/A7/A9 with vfpv3-d16 or vfpv4-d16
+pub const ARMV7A_VFP_D16: Arm = Arm::new(Vfp::D16);
+
+/// e.g. Cortex-A8/A15 with neon
+pub const ARMV7A_VFP_D32: Arm = Arm::new(Vfp::D32);
+
+impl Arch for Arm {
+    fn description(&self) -> &TargetDescription<'_> {
+        match self.vfp {
+            Vfp::None => &DESCRIPTION,
+            Vfp::D16 => &VFP_D16_DESCRIPTION,
+            Vfp::D32 => &VFP_D32_DESCRIPTION,
+        }
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        if self.thumb {
+            THUMB_BKPT.len()
+        } else {
+            ARM_BKPT.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(ARMV7A.register_count(), 17);
+        assert_eq!(ARMV7A.registers_size(), 68);
+        assert_eq!(ARMV7A.pc(), Some(PC_REGNO));
+        assert_eq!(ARMV7A.register_name(CPSR_REGNO), Some(&b"cpsr"[..]));
+
+        assert_eq!(ARMV7A_VFP_D16.register_count(), 34);
+        assert_eq!(ARMV7A_VFP_D16.registers_size(), 68 + 16 * 8 + 4);
+        assert_eq!(ARMV7A_VFP_D32.register_count(), 50);
+        assert_eq!(ARMV7A_VFP_D32.register_size(D0_REGNO + 31), Some(8));
+        assert_eq!(ARMV7A_VFP_D32.register_name(49), Some(&b"fpscr"[..]));
+    }
+
+    #[test]
+    fn it_should_pick_the_breakpoint_size() {
+        assert_eq!(ARMV7A.breakpoint_kind(), 4);
+        assert_eq!(ARMV7A.with_thumb().breakpoint_kind(), 2);
+    }
+}
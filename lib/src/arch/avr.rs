@@ -0,0 +1,143 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+
+pub const SREG_REGNO: usize = 32;
+pub const SP_REGNO: usize = 33;
+/// gdb exchanges the 22 bit pc as byte address in 32 bits
+/// the core counts words, so it has to be doubled
+pub const PC_REGNO: usize = 34;
+
+/// the break instruction
+pub const BREAK: [u8; 2] = [0x98, 0x95];
+
+/// gdb maps the separate address spaces into one,
+/// data memory starts at this offset
+pub const SRAM_OFFSET: usize = 0x80_0000;
+
+/// start of the eeprom in gdb's address space
+pub const EEPROM_OFFSET: usize = 0x81_0000;
+
+/// end of the eeprom in gdb's address space
+/// fuses, lock bits and signature follow but are not handled
+pub const EEPROM_END: usize = 0x82_0000;
+
+/// the memory an address of gdb refers to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressSpace {
+    Flash,
+    Sram,
+    Eeprom,
+}
+
+impl AddressSpace {
+    /// splits an address of an m or M packet into its space and offset
+    pub fn decode(addr: usize) -> Option<(Self, usize)> {
+        match addr {
+            0..SRAM_OFFSET => Some((Self::Flash, addr)),
+            SRAM_OFFSET..EEPROM_OFFSET => Some((Self::Sram, addr - SRAM_OFFSET)),
+            EEPROM_OFFSET..EEPROM_END => Some((Self::Eeprom, addr - EEPROM_OFFSET)),
+            _ => None,
+        }
+    }
+
+    /// the address gdb uses for offset, e.g. in a memory map
+    pub fn encode(&self, offset: usize) -> usize {
+        match self {
+            Self::Flash => offset,
+            Self::Sram => SRAM_OFFSET + offset,
+            Self::Eeprom => EEPROM_OFFSET + offset,
+        }
+    }
+}
+
+const fn gpr(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 8).with_dwarf(dwarf)
+}
+
+const REGISTERS: [RegisterDesc; 35] = [
+    gpr(b"r0", 0),
+    gpr(b"r1", 1),
+    gpr(b"r2", 2),
+    gpr(b"r3", 3),
+    gpr(b"r4", 4),
+    gpr(b"r5", 5),
+    gpr(b"r6", 6),
+    gpr(b"r7", 7),
+    gpr(b"r8", 8),
+    gpr(b"r9", 9),
+    gpr(b"r10", 10),
+    gpr(b"r11", 11),
+    gpr(b"r12", 12),
+    gpr(b"r13", 13),
+    gpr(b"r14", 14),
+    gpr(b"r15", 15),
+    gpr(b"r16", 16),
+    gpr(b"r17", 17),
+    gpr(b"r18", 18),
+    gpr(b"r19", 19),
+    gpr(b"r20", 20),
+    gpr(b"r21", 21),
+    gpr(b"r22", 22),
+    gpr(b"r23", 23),
+    gpr(b"r24", 24),
+    gpr(b"r25", 25),
+    gpr(b"r26", 26),
+    gpr(b"r27", 27),
+    gpr(b"r28", 28),
+    gpr(b"r29", 29),
+    gpr(b"r30", 30),
+    gpr(b"r31", 31),
+    RegisterDesc::new(b"SREG", 8).with_generic(GenericRegister::Flags),
+    RegisterDesc::new(b"SP", 16).with_generic(GenericRegister::Sp),
+    RegisterDesc::new(b"PC", 32).with_generic(GenericRegister::Pc),
+];
+
+// gdb's avr support ignores the registers of target.xml
+// and always uses the layout above
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(b"org.gnu.gdb.avr.cpu", &REGISTERS).with_architecture(b"avr");
+
+/// 8 bit AVR cores
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Avr;
+
+impl Arch for Avr {
+    fn description(&self) -> &TargetDescription<'_> {
+        &DESCRIPTION
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        BREAK.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(Avr.register_count(), 35);
+        assert_eq!(Avr.pc(), Some(PC_REGNO));
+        assert_eq!(Avr.register_size(SP_REGNO), Some(2));
+        assert_eq!(Avr.registers_size(), 32 + 1 + 2 + 4);
+    }
+
+    #[test]
+    fn it_should_split_address_spaces() {
+        assert_eq!(
+            AddressSpace::decode(0x1234),
+            Some((AddressSpace::Flash, 0x1234))
+        );
+        assert_eq!(
+            AddressSpace::decode(0x800100),
+            Some((AddressSpace::Sram, 0x100))
+        );
+        assert_eq!(
+            AddressSpace::decode(0x810010),
+            Some((AddressSpace::Eeprom, 0x10))
+        );
+        assert_eq!(AddressSpace::decode(0x820000), None);
+        assert_eq!(AddressSpace::Sram.encode(0x100), 0x800100);
+    }
+}
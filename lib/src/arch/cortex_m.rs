@@ -0,0 +1,148 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, RegisterEncoding, TargetDescription};
+
+/// gdb register number of the program counter
+pub const PC_REGNO: usize = 15;
+
+/// gdb register number of xPSR
+pub const XPSR_REGNO: usize = 16;
+
+/// bkpt is the only breakpoint instruction of the thumb instruction set
+pub const BKPT: [u8; 2] = [0x00, 0xbe];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.arm.m-profile";
+const VFP_FEATURE: &[u8] = b"org.gnu.gdb.arm.vfp";
+
+const fn core(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+const fn double(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 64)
+        .with_encoding(RegisterEncoding::Ieee754)
+        .with_group(b"float")
+        .with_dwarf(dwarf)
+        .with_feature(VFP_FEATURE)
+}
+
+macro_rules! core_registers {
+    ($($extra:expr),*) => {
+        [
+            core(b"r0", 0),
+            core(b"r1", 1),
+            core(b"r2", 2),
+            core(b"r3", 3),
+            core(b"r4", 4),
+            core(b"r5", 5),
+            core(b"r6", 6),
+            core(b"r7", 7),
+            core(b"r8", 8),
+            core(b"r9", 9),
+            core(b"r10", 10),
+            core(b"r11", 11),
+            core(b"r12", 12),
+            core(b"sp", 13).with_generic(GenericRegister::Sp),
+            core(b"lr", 14).with_generic(GenericRegister::Ra),
+            core(b"pc", 15).with_generic(GenericRegister::Pc),
+            RegisterDesc::new(b"xpsr", 32).with_generic(GenericRegister::Flags),
+            $($extra),*
+        ]
+    };
+}
+
+const REGISTERS: [RegisterDesc; 17] = core_registers!();
+
+// the single precision registers s0-s31 are pseudo registers of gdb
+const FPU_REGISTERS: [RegisterDesc; 34] = core_registers!(
+    double(b"d0", 256),
+    double(b"d1", 257),
+    double(b"d2", 258),
+    double(b"d3", 259),
+    double(b"d4", 260),
+    double(b"d5", 261),
+    double(b"d6", 262),
+    double(b"d7", 263),
+    double(b"d8", 264),
+    double(b"d9", 265),
+    double(b"d10", 266),
+    double(b"d11", 267),
+    double(b"d12", 268),
+    double(b"d13", 269),
+    double(b"d14", 270),
+    double(b"d15", 271),
+    RegisterDesc::new(b"fpscr", 32)
+        .with_group(b"float")
+        .with_feature(VFP_FEATURE)
+);
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"arm");
+
+const FPU_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &FPU_REGISTERS).with_architecture(b"arm");
+
+/// Cortex-M cores, r0-r15 and xPSR
+/// cores with a single or double precision fpu add d0-d15 and fpscr
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CortexM {
+    pub fpu: bool,
+}
+
+/// Cortex-M0/M0+/M3 and cores without fpu
+pub const CORTEX_M: CortexM = CortexM { fpu: false };
+
+/// Cortex-M4F/M7/M33 with fpu
+pub const CORTEX_M_FPU: CortexM = CortexM { fpu: true };
+
+impl Arch for CortexM {
+    fn description(&self) -> &TargetDescription<'_> {
+        if self.fpu {
+            &FPU_DESCRIPTION
+        } else {
+            &DESCRIPTION
+        }
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        BKPT.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{BufferedStream, Stream};
+
+    #[test]
+    fn it_should_describe_the_core_registers() {
+        assert_eq!(CORTEX_M.register_count(), 17);
+        assert_eq!(CORTEX_M.registers_size(), 68);
+        assert_eq!(CORTEX_M.pc(), Some(PC_REGNO));
+        assert_eq!(CORTEX_M.register_name(XPSR_REGNO), Some(&b"xpsr"[..]));
+        assert_eq!(CORTEX_M.breakpoint_kind(), 2);
+
+        assert_eq!(CORTEX_M_FPU.register_count(), 34);
+        assert_eq!(CORTEX_M_FPU.register_size(17), Some(8));
+        assert_eq!(CORTEX_M_FPU.registers_size(), 68 + 16 * 8 + 4);
+    }
+
+    #[test]
+    fn it_should_write_the_fpu_feature() {
+        // the whole document does not fit into the test stream
+        let mut s = BufferedStream::new();
+        TargetDescription::new(FEATURE, &FPU_REGISTERS[15..19])
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <feature name=\"org.gnu.gdb.arm.m-profile\">\
+            <reg name=\"pc\" bitsize=\"32\" regnum=\"0\" type=\"code_ptr\"/>\
+            <reg name=\"xpsr\" bitsize=\"32\" regnum=\"1\" type=\"int\"/></feature>\
+            <feature name=\"org.gnu.gdb.arm.vfp\">\
+            <reg name=\"d0\" bitsize=\"64\" regnum=\"2\" type=\"ieee_double\" group=\"float\"/>\
+            <reg name=\"d1\" bitsize=\"64\" regnum=\"3\" type=\"ieee_double\" group=\"float\"/>\
+            </feature></target>"[..]
+        );
+    }
+}
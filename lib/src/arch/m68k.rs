@@ -0,0 +1,77 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+use crate::parser::Endianness;
+
+/// gdb register number of a0
+pub const A0_REGNO: usize = 8;
+pub const PS_REGNO: usize = 16;
+pub const PC_REGNO: usize = 17;
+
+/// trap #15
+pub const TRAP: [u8; 2] = [0x4e, 0x4f];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.m68k.core";
+
+const fn reg(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+// gdb names a6 and a7 fp and sp
+const REGISTERS: [RegisterDesc; 18] = [
+    reg(b"d0", 0),
+    reg(b"d1", 1),
+    reg(b"d2", 2),
+    reg(b"d3", 3),
+    reg(b"d4", 4),
+    reg(b"d5", 5),
+    reg(b"d6", 6),
+    reg(b"d7", 7),
+    reg(b"a0", 8),
+    reg(b"a1", 9),
+    reg(b"a2", 10),
+    reg(b"a3", 11),
+    reg(b"a4", 12),
+    reg(b"a5", 13),
+    reg(b"fp", 14).with_generic(GenericRegister::Fp),
+    reg(b"sp", 15).with_generic(GenericRegister::Sp),
+    RegisterDesc::new(b"ps", 32).with_generic(GenericRegister::Flags),
+    RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+];
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"m68k");
+
+/// Motorola 68000 family, d0-d7, a0-a7, ps and pc
+/// e.g. the cpus of the Amiga and the Mega Drive
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct M68k;
+
+impl Arch for M68k {
+    fn description(&self) -> &TargetDescription<'_> {
+        &DESCRIPTION
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::Big
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        TRAP.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(M68k.register_count(), 18);
+        assert_eq!(M68k.registers_size(), 18 * 4);
+        assert_eq!(M68k.pc(), Some(PC_REGNO));
+        assert_eq!(M68k.register_name(A0_REGNO), Some(&b"a0"[..]));
+        assert_eq!(M68k.register_name(PS_REGNO), Some(&b"ps"[..]));
+        assert_eq!(M68k.endianness(), Endianness::Big);
+        assert_eq!(M68k.breakpoint_kind(), 2);
+    }
+}
@@ -0,0 +1,147 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+use crate::parser::Endianness;
+
+/// gdb register numbers of the cp0 and special registers
+pub const STATUS_REGNO: usize = 32;
+pub const LO_REGNO: usize = 33;
+pub const HI_REGNO: usize = 34;
+pub const BADVADDR_REGNO: usize = 35;
+pub const CAUSE_REGNO: usize = 36;
+pub const PC_REGNO: usize = 37;
+
+/// size of the break instruction
+pub const BREAK_SIZE: usize = 4;
+
+const FEATURE: &[u8] = b"org.gnu.gdb.mips.cpu";
+const CP0_FEATURE: &[u8] = b"org.gnu.gdb.mips.cp0";
+
+const fn gpr(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+const fn cp0(name: &'static [u8]) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_feature(CP0_FEATURE)
+}
+
+// r0-r31, sr, lo, hi, bad, cause, pc as in gdb's mips g packet
+const REGISTERS: [RegisterDesc; 38] = [
+    gpr(b"r0", 0),
+    gpr(b"r1", 1),
+    gpr(b"r2", 2),
+    gpr(b"r3", 3),
+    gpr(b"r4", 4),
+    gpr(b"r5", 5),
+    gpr(b"r6", 6),
+    gpr(b"r7", 7),
+    gpr(b"r8", 8),
+    gpr(b"r9", 9),
+    gpr(b"r10", 10),
+    gpr(b"r11", 11),
+    gpr(b"r12", 12),
+    gpr(b"r13", 13),
+    gpr(b"r14", 14),
+    gpr(b"r15", 15),
+    gpr(b"r16", 16),
+    gpr(b"r17", 17),
+    gpr(b"r18", 18),
+    gpr(b"r19", 19),
+    gpr(b"r20", 20),
+    gpr(b"r21", 21),
+    gpr(b"r22", 22),
+    gpr(b"r23", 23),
+    gpr(b"r24", 24),
+    gpr(b"r25", 25),
+    gpr(b"r26", 26),
+    gpr(b"r27", 27),
+    gpr(b"r28", 28),
+    gpr(b"r29", 29).with_generic(GenericRegister::Sp),
+    gpr(b"r30", 30).with_generic(GenericRegister::Fp),
+    gpr(b"r31", 31).with_generic(GenericRegister::Ra),
+    cp0(b"status").with_generic(GenericRegister::Flags),
+    gpr(b"lo", 65),
+    gpr(b"hi", 64),
+    cp0(b"badvaddr"),
+    cp0(b"cause"),
+    RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+];
+
+// gdb rejects a mips description without the fpu feature,
+// describing it would not leave room for the g reply in the reply buffer
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).architecture_only(b"mips");
+
+/// 32 bit MIPS cores, e.g. the R3000 of the PS1 or the VR4300 of the N64
+/// running with 32 bit registers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mips {
+    pub endianness: Endianness,
+}
+
+pub const MIPS32_BE: Mips = Mips {
+    endianness: Endianness::Big,
+};
+
+pub const MIPS32_LE: Mips = Mips {
+    endianness: Endianness::Little,
+};
+
+impl Arch for Mips {
+    fn description(&self) -> &TargetDescription<'_> {
+        &DESCRIPTION
+    }
+
+    fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        BREAK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{BufferedStream, Stream};
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(MIPS32_BE.register_count(), 38);
+        assert_eq!(MIPS32_BE.pc(), Some(PC_REGNO));
+        assert_eq!(MIPS32_BE.register_name(CAUSE_REGNO), Some(&b"cause"[..]));
+        assert_eq!(MIPS32_LE.endianness(), Endianness::Little);
+    }
+
+    #[test]
+    fn it_should_only_name_the_architecture() {
+        let mut s = BufferedStream::new();
+        DESCRIPTION.write_xml(&mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <architecture>mips</architecture></target>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_group_cp0_registers() {
+        let mut s = BufferedStream::new();
+        TargetDescription::new(FEATURE, &REGISTERS[STATUS_REGNO..])
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <feature name=\"org.gnu.gdb.mips.cp0\">\
+            <reg name=\"status\" bitsize=\"32\" regnum=\"0\" type=\"int\"/>\
+            <reg name=\"badvaddr\" bitsize=\"32\" regnum=\"3\" type=\"int\"/>\
+            <reg name=\"cause\" bitsize=\"32\" regnum=\"4\" type=\"int\"/></feature>\
+            <feature name=\"org.gnu.gdb.mips.cpu\">\
+            <reg name=\"lo\" bitsize=\"32\" regnum=\"1\" type=\"int\"/>\
+            <reg name=\"hi\" bitsize=\"32\" regnum=\"2\" type=\"int\"/>\
+            <reg name=\"pc\" bitsize=\"32\" regnum=\"5\" type=\"code_ptr\"/>\
+            </feature></target>"[..]
+        );
+    }
+}
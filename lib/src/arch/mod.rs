@@ -0,0 +1,441 @@
+use super::description::{GenericRegister, RegisterDesc, TargetDescription};
+use super::error::Errors;
+use super::parser::{Endianness, Parser};
+use super::register::{RegisterValue, MAX_REGISTER_SIZE};
+use super::stream::Stream;
+use super::target::Target;
+use super::thread;
+
+#[cfg(feature = "arch")]
+pub mod arm;
+#[cfg(feature = "arch")]
+pub mod avr;
+#[cfg(feature = "arch")]
+pub mod cortex_m;
+#[cfg(feature = "arch")]
+pub mod m68k;
+// VirtualTarget is a mips cpu, so mips is always available
+pub mod mips;
+#[cfg(feature = "arch")]
+pub mod msp430;
+#[cfg(feature = "arch")]
+pub mod powerpc;
+#[cfg(feature = "arch")]
+pub mod riscv;
+#[cfg(feature = "arch")]
+pub mod x86_64;
+#[cfg(feature = "arch")]
+pub mod xtensa;
+
+/// Register layout and conventions of a cpu architecture.
+/// A target selects one with Target::arch, the g, G, p and P packets
+/// are then encoded from the single register accessors
+/// instead of rd_registers and wr_registers.
+pub trait Arch {
+    /// the registers in g packet order
+    fn description(&self) -> &TargetDescription<'_>;
+
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+
+    /// the kind gdb sends with software breakpoints
+    /// usually the size of the breakpoint instruction
+    fn breakpoint_kind(&self) -> usize;
+
+    fn register_count(&self) -> usize {
+        self.description().registers.len()
+    }
+
+    /// size of register regnum in bytes
+    fn register_size(&self, regnum: usize) -> Option<usize> {
+        self.description()
+            .registers
+            .get(regnum)
+            .map(|reg| reg.bitsize.div_ceil(8))
+    }
+
+    fn register_name(&self, regnum: usize) -> Option<&[u8]> {
+        self.description().registers.get(regnum).map(|reg| reg.name)
+    }
+
+    /// index of the program counter
+    fn pc(&self) -> Option<usize> {
+        self.description()
+            .registers
+            .iter()
+            .position(|reg| reg.generic == Some(GenericRegister::Pc))
+    }
+
+    /// the csr number if register regnum is a control and status register
+    /// csrs are accessed with Target::rd_csr and wr_csr
+    fn csr(&self, _regnum: usize) -> Option<usize> {
+        None
+    }
+
+    /// size of the g packet in bytes before hex encoding
+    fn registers_size(&self) -> usize {
+        (0..self.register_count())
+            .filter_map(|regnum| self.register_size(regnum))
+            .sum()
+    }
+}
+
+/// A register layout built at runtime from a slice of descriptors,
+/// e.g. by an emulator whose cpu model is configurable.
+/// The target owns the map and returns it from Target::arch,
+/// target.xml and the g packet follow the descriptors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegisterMap<'a> {
+    pub description: TargetDescription<'a>,
+    pub endianness: Endianness,
+    pub breakpoint_kind: usize,
+}
+
+impl<'a> RegisterMap<'a> {
+    pub const fn new(
+        feature: &'a [u8],
+        registers: &'a [RegisterDesc<'a>],
+        breakpoint_kind: usize,
+    ) -> Self {
+        Self {
+            description: TargetDescription::new(feature, registers),
+            endianness: Endianness::Little,
+            breakpoint_kind,
+        }
+    }
+
+    pub const fn with_architecture(mut self, architecture: &'a [u8]) -> Self {
+        self.description = self.description.with_architecture(architecture);
+        self
+    }
+
+    pub const fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+}
+
+impl Arch for RegisterMap<'_> {
+    fn description(&self) -> &TargetDescription<'_> {
+        &self.description
+    }
+
+    fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        self.breakpoint_kind
+    }
+}
+
+/// decodes a hex encoded register of size bytes
+/// registers that are not integers are decoded into buffer
+pub fn decode_register<'a>(
+    data: &[u8],
+    size: usize,
+    endianness: Endianness,
+    buffer: &'a mut [u8; MAX_REGISTER_SIZE],
+) -> Option<RegisterValue<'a>> {
+    if data.len() != size * 2 {
+        return None;
+    }
+    match RegisterValue::from_hex(data, endianness) {
+        Some(value) => Some(value),
+        None => {
+            let size = Parser::from_hex_bytes(data, buffer)?;
+            Some(RegisterValue::Bytes(&buffer[..size]))
+        }
+    }
+}
+
+/// reads register regnum with the target hook that serves it
+/// registers of a suspended thread come from its saved context
+pub fn read_register<'a>(
+    arch: &dyn Arch,
+    ctx: &'a dyn Target,
+    regnum: usize,
+) -> Result<RegisterValue<'a>, Errors> {
+    if !ctx.register_available(regnum) {
+        return Err(Errors::InvalidRegister);
+    }
+    if let Some((threads, thread)) = thread::suspended_thread(ctx) {
+        return threads.rd_thread_register(thread, regnum);
+    }
+    match arch.csr(regnum) {
+        Some(csr) => ctx.rd_csr(csr),
+        None => ctx.rd_register(regnum),
+    }
+}
+
+/// writes register regnum with the target hook that serves it
+pub fn write_register(
+    ctx: &mut dyn Target,
+    regnum: usize,
+    value: RegisterValue,
+) -> Result<usize, Errors> {
+    if !ctx.register_available(regnum) {
+        return Err(Errors::InvalidRegister);
+    }
+    // saved contexts are read only
+    if thread::suspended_thread(ctx).is_some() {
+        return Err(Errors::AccessDenied);
+    }
+    match ctx.arch().and_then(|arch| arch.csr(regnum)) {
+        Some(csr) => ctx.wr_csr(csr, value),
+        None => ctx.wr_register(regnum, value),
+    }
+}
+
+/// writes a single register for p or g
+/// registers that are unavailable or the target cannot read are sent as x
+pub fn encode_register(
+    arch: &dyn Arch,
+    ctx: &dyn Target,
+    regnum: usize,
+    stream: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let size = arch.register_size(regnum).ok_or(Errors::InvalidRegister)?;
+    match read_register(arch, ctx, regnum) {
+        Ok(value) if value.size() == size => value.write(arch.endianness(), stream),
+        Ok(_) => Err(Errors::LengthMismatch),
+        Err(_) => {
+            for _ in 0..size * 2 {
+                stream.write(b'x')?;
+            }
+            Ok(size * 2)
+        }
+    }
+}
+
+/// writes the g packet payload
+pub fn encode_registers(
+    arch: &dyn Arch,
+    ctx: &dyn Target,
+    stream: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let mut size = 0;
+    for regnum in 0..arch.register_count() {
+        size += encode_register(arch, ctx, regnum, stream)?;
+    }
+    Ok(size)
+}
+
+/// writes the registers of a G packet one by one
+/// registers sent as x or unavailable are skipped
+/// the arch is looked up for every register,
+/// so it may be borrowed from the target itself
+pub fn decode_registers(ctx: &mut dyn Target, data: &[u8]) -> Result<usize, Errors> {
+    let (count, total) = match ctx.arch() {
+        Some(arch) => (arch.register_count(), arch.registers_size()),
+        None => return Err(Errors::Unsupported),
+    };
+    if data.len() != total * 2 {
+        return Err(Errors::LengthMismatch);
+    }
+
+    let mut offset = 0;
+    for regnum in 0..count {
+        let (size, endianness) = match ctx.arch() {
+            Some(arch) => (arch.register_size(regnum).unwrap_or(0), arch.endianness()),
+            None => return Err(Errors::Unsupported),
+        };
+        let field = &data[offset..offset + size * 2];
+        offset += size * 2;
+        if field.iter().all(|b| *b == b'x') || !ctx.register_available(regnum) {
+            continue;
+        }
+
+        let mut buffer = [0; MAX_REGISTER_SIZE];
+        let value =
+            decode_register(field, size, endianness, &mut buffer).ok_or(Errors::BadNumber)?;
+        write_register(ctx, regnum, value)?;
+    }
+    Ok(0)
+}
+
+/// writes all registers hex encoded as in a g reply
+/// targets without an arch encode them with rd_registers
+pub fn read_registers(ctx: &dyn Target, stream: &mut dyn Stream) -> Result<usize, Errors> {
+    match ctx.arch() {
+        Some(arch) => encode_registers(arch, ctx, stream),
+        None => ctx.rd_registers(stream),
+    }
+}
+
+/// writes all registers of a G payload
+/// targets without an arch decode them with wr_registers
+pub fn write_registers(ctx: &mut dyn Target, data: &[u8]) -> Result<usize, Errors> {
+    match ctx.arch() {
+        Some(_) => decode_registers(ctx, data),
+        None => ctx.wr_registers(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{BufferedStream, Stream};
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    const REGISTERS: [RegisterDesc; 3] = [
+        RegisterDesc::new(b"r0", 32),
+        RegisterDesc::new(b"sr", 16),
+        RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+    ];
+
+    struct TestArch;
+    impl Arch for TestArch {
+        fn description(&self) -> &TargetDescription<'_> {
+            const DESCRIPTION: TargetDescription = TargetDescription::new(b"test", &REGISTERS);
+            &DESCRIPTION
+        }
+
+        fn breakpoint_kind(&self) -> usize {
+            2
+        }
+    }
+
+    struct ArchTarget {
+        registers: [u32; 3],
+    }
+
+    impl Target for ArchTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&TestArch)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            match regno {
+                0 | 2 => Ok(RegisterValue::U32(self.registers[regno])),
+                // the status register is not readable
+                _ => Err(Errors::Unsupported),
+            }
+        }
+
+        fn wr_register(&mut self, regno: usize, value: RegisterValue) -> Result<usize, Errors> {
+            match (regno, value) {
+                (0 | 2, RegisterValue::U32(value)) => self.registers[regno] = value,
+                (1, RegisterValue::U16(value)) => self.registers[1] = value as u32,
+                _ => return Err(Errors::InvalidRegister),
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_describe_the_layout() {
+        assert_eq!(TestArch.register_count(), 3);
+        assert_eq!(TestArch.register_size(1), Some(2));
+        assert_eq!(TestArch.register_size(3), None);
+        assert_eq!(TestArch.register_name(2), Some(&b"pc"[..]));
+        assert_eq!(TestArch.pc(), Some(2));
+        assert_eq!(TestArch.registers_size(), 10);
+    }
+
+    #[test]
+    fn it_should_encode_registers() {
+        let target = ArchTarget {
+            registers: [1, 0, 0x08000100],
+        };
+        let mut s = BufferedStream::new();
+        let size = encode_registers(&TestArch, &target, &mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"01000000xxxx00010008"[..]);
+    }
+
+    #[test]
+    fn it_should_decode_registers() {
+        let mut target = ArchTarget { registers: [0; 3] };
+        decode_registers(&mut target, b"02000000xxxx04030201").unwrap();
+        assert_eq!(target.registers, [2, 0, 0x01020304]);
+
+        decode_registers(&mut target, b"0200000034120403020100").unwrap_err();
+        decode_registers(&mut target, b"020000003412040302010").unwrap_err();
+        decode_registers(&mut target, b"0200000034120403020g").unwrap_err();
+
+        decode_registers(&mut target, b"ffffffff341204030201").unwrap();
+        assert_eq!(target.registers, [0xffffffff, 0x1234, 0x01020304]);
+    }
+
+    #[test]
+    fn it_should_decode_odd_sized_registers() {
+        let mut buffer = [0; MAX_REGISTER_SIZE];
+        assert_eq!(
+            decode_register(b"0102030405", 5, Endianness::Little, &mut buffer),
+            Some(RegisterValue::Bytes(&[1, 2, 3, 4, 5]))
+        );
+        let mut buffer = [0; MAX_REGISTER_SIZE];
+        assert_eq!(
+            decode_register(b"0102", 1, Endianness::Little, &mut buffer),
+            None
+        );
+    }
+
+    struct EmulatorTarget<'a> {
+        map: RegisterMap<'a>,
+        registers: Vec<u16>,
+    }
+
+    impl Target for EmulatorTarget<'_> {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&self.map)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            self.registers
+                .get(regno)
+                .map(|value| RegisterValue::U16(*value))
+                .ok_or(Errors::InvalidRegister)
+        }
+
+        fn wr_register(&mut self, regno: usize, value: RegisterValue) -> Result<usize, Errors> {
+            match (self.registers.get_mut(regno), value) {
+                (Some(reg), RegisterValue::U16(value)) => *reg = value,
+                _ => return Err(Errors::InvalidRegister),
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_use_runtime_register_maps() {
+        // a cpu model configured with two general purpose registers
+        let names: Vec<String> = (0..2).map(|i| format!("r{i}")).collect();
+        let mut registers: Vec<RegisterDesc> = names
+            .iter()
+            .map(|name| RegisterDesc::new(name.as_bytes(), 16))
+            .collect();
+        registers.push(RegisterDesc::new(b"pc", 16).with_generic(GenericRegister::Pc));
+
+        let mut target = EmulatorTarget {
+            map: RegisterMap::new(b"emu.core", &registers, 2)
+                .with_architecture(b"emu")
+                .with_endianness(Endianness::Big),
+            registers: std::vec![1, 2, 0x100],
+        };
+        assert_eq!(target.map.pc(), Some(2));
+
+        let mut s = BufferedStream::new();
+        encode_registers(&target.map, &target, &mut s).unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"000100020100"[..]);
+
+        decode_registers(&mut target, b"00030004xxxx").unwrap();
+        assert_eq!(target.registers, [3, 4, 0x100]);
+
+        let mut s = BufferedStream::new();
+        target.map.description.write_xml(&mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <architecture>emu</architecture><feature name=\"emu.core\">\
+            <reg name=\"r0\" bitsize=\"16\" regnum=\"0\" type=\"int\"/>\
+            <reg name=\"r1\" bitsize=\"16\" regnum=\"1\" type=\"int\"/>\
+            <reg name=\"pc\" bitsize=\"16\" regnum=\"2\" type=\"code_ptr\"/>\
+            </feature></target>"[..]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+
+pub const PC_REGNO: usize = 0;
+pub const SP_REGNO: usize = 1;
+pub const SR_REGNO: usize = 2;
+
+/// the breakpoint gdb writes, an unused encoding of r3
+pub const BREAK: [u8; 2] = [0x43, 0x43];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.msp430.core";
+
+macro_rules! registers {
+    ($bits:expr) => {
+        [
+            RegisterDesc::new(b"pc", $bits).with_generic(GenericRegister::Pc),
+            RegisterDesc::new(b"sp", $bits).with_generic(GenericRegister::Sp),
+            RegisterDesc::new(b"sr", $bits).with_generic(GenericRegister::Flags),
+            RegisterDesc::new(b"cg", $bits),
+            RegisterDesc::new(b"r4", $bits).with_generic(GenericRegister::Fp),
+            RegisterDesc::new(b"r5", $bits),
+            RegisterDesc::new(b"r6", $bits),
+            RegisterDesc::new(b"r7", $bits),
+            RegisterDesc::new(b"r8", $bits),
+            RegisterDesc::new(b"r9", $bits),
+            RegisterDesc::new(b"r10", $bits),
+            RegisterDesc::new(b"r11", $bits),
+            RegisterDesc::new(b"r12", $bits),
+            RegisterDesc::new(b"r13", $bits),
+            RegisterDesc::new(b"r14", $bits),
+            RegisterDesc::new(b"r15", $bits),
+        ]
+    };
+}
+
+const REGISTERS: [RegisterDesc; 16] = registers!(16);
+
+// the 20 bit registers are exchanged in 32 bits
+const X_REGISTERS: [RegisterDesc; 16] = registers!(32);
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"msp430");
+
+const X_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &X_REGISTERS).with_architecture(b"MSP430X");
+
+/// MSP430 cores, r0-r15 where r0-r3 are pc, sp, sr and
+/// the constant generator
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Msp430 {
+    /// true for MSP430X cores with 20 bit registers
+    pub extended: bool,
+}
+
+pub const MSP430: Msp430 = Msp430 { extended: false };
+pub const MSP430X: Msp430 = Msp430 { extended: true };
+
+impl Arch for Msp430 {
+    fn description(&self) -> &TargetDescription<'_> {
+        if self.extended {
+            &X_DESCRIPTION
+        } else {
+            &DESCRIPTION
+        }
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        BREAK.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_both_register_sizes() {
+        assert_eq!(MSP430.register_count(), 16);
+        assert_eq!(MSP430.registers_size(), 32);
+        assert_eq!(MSP430.pc(), Some(PC_REGNO));
+        assert_eq!(MSP430.register_name(SR_REGNO), Some(&b"sr"[..]));
+        assert_eq!(MSP430X.register_size(SP_REGNO), Some(4));
+        assert_eq!(MSP430X.registers_size(), 64);
+        assert_eq!(MSP430X.breakpoint_kind(), 2);
+    }
+}
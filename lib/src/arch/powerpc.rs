@@ -0,0 +1,178 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, RegisterEncoding, TargetDescription};
+use crate::parser::Endianness;
+
+/// gdb register numbers of the special registers
+pub const PC_REGNO: usize = 32;
+pub const MSR_REGNO: usize = 33;
+pub const CR_REGNO: usize = 34;
+pub const LR_REGNO: usize = 35;
+pub const CTR_REGNO: usize = 36;
+pub const XER_REGNO: usize = 37;
+
+/// gdb register number of f0 if a fpu is present
+pub const F0_REGNO: usize = 38;
+
+/// trap
+pub const TRAP: [u8; 4] = [0x7f, 0xe0, 0x00, 0x08];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.power.core";
+const FPU_FEATURE: &[u8] = b"org.gnu.gdb.power.fpu";
+
+const fn gpr(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+const fn fpr(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 64)
+        .with_encoding(RegisterEncoding::Ieee754)
+        .with_group(b"float")
+        .with_dwarf(dwarf)
+        .with_feature(FPU_FEATURE)
+}
+
+// gdb requires msr in the core feature next to the registers of the user isa
+macro_rules! core_registers {
+    ($($extra:expr),*) => {
+        [
+            gpr(b"r0", 0),
+            gpr(b"r1", 1).with_generic(GenericRegister::Sp),
+            gpr(b"r2", 2),
+            gpr(b"r3", 3),
+            gpr(b"r4", 4),
+            gpr(b"r5", 5),
+            gpr(b"r6", 6),
+            gpr(b"r7", 7),
+            gpr(b"r8", 8),
+            gpr(b"r9", 9),
+            gpr(b"r10", 10),
+            gpr(b"r11", 11),
+            gpr(b"r12", 12),
+            gpr(b"r13", 13),
+            gpr(b"r14", 14),
+            gpr(b"r15", 15),
+            gpr(b"r16", 16),
+            gpr(b"r17", 17),
+            gpr(b"r18", 18),
+            gpr(b"r19", 19),
+            gpr(b"r20", 20),
+            gpr(b"r21", 21),
+            gpr(b"r22", 22),
+            gpr(b"r23", 23),
+            gpr(b"r24", 24),
+            gpr(b"r25", 25),
+            gpr(b"r26", 26),
+            gpr(b"r27", 27),
+            gpr(b"r28", 28),
+            gpr(b"r29", 29),
+            gpr(b"r30", 30),
+            gpr(b"r31", 31),
+            RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+            RegisterDesc::new(b"msr", 32).with_generic(GenericRegister::Flags),
+            RegisterDesc::new(b"cr", 32),
+            RegisterDesc::new(b"lr", 32).with_generic(GenericRegister::Ra),
+            RegisterDesc::new(b"ctr", 32),
+            RegisterDesc::new(b"xer", 32),
+            $($extra),*
+        ]
+    };
+}
+
+const REGISTERS: [RegisterDesc; 38] = core_registers!();
+
+const FPU_REGISTERS: [RegisterDesc; 71] = core_registers!(
+    fpr(b"f0", 32),
+    fpr(b"f1", 33),
+    fpr(b"f2", 34),
+    fpr(b"f3", 35),
+    fpr(b"f4", 36),
+    fpr(b"f5", 37),
+    fpr(b"f6", 38),
+    fpr(b"f7", 39),
+    fpr(b"f8", 40),
+    fpr(b"f9", 41),
+    fpr(b"f10", 42),
+    fpr(b"f11", 43),
+    fpr(b"f12", 44),
+    fpr(b"f13", 45),
+    fpr(b"f14", 46),
+    fpr(b"f15", 47),
+    fpr(b"f16", 48),
+    fpr(b"f17", 49),
+    fpr(b"f18", 50),
+    fpr(b"f19", 51),
+    fpr(b"f20", 52),
+    fpr(b"f21", 53),
+    fpr(b"f22", 54),
+    fpr(b"f23", 55),
+    fpr(b"f24", 56),
+    fpr(b"f25", 57),
+    fpr(b"f26", 58),
+    fpr(b"f27", 59),
+    fpr(b"f28", 60),
+    fpr(b"f29", 61),
+    fpr(b"f30", 62),
+    fpr(b"f31", 63),
+    RegisterDesc::new(b"fpscr", 32)
+        .with_group(b"float")
+        .with_feature(FPU_FEATURE)
+);
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"powerpc:common");
+
+const FPU_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &FPU_REGISTERS).with_architecture(b"powerpc:common");
+
+/// 32 bit big endian PowerPC cores, r0-r31, pc, msr, cr, lr, ctr and xer
+/// cores with a fpu add f0-f31 and fpscr, the paired singles
+/// of the Gekko and Broadway are not described
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PowerPc {
+    pub fpu: bool,
+}
+
+/// e.g. the PowerPC 405 or e200
+pub const POWERPC: PowerPc = PowerPc { fpu: false };
+
+/// e.g. the 750 family including Gekko and Broadway
+pub const POWERPC_FPU: PowerPc = PowerPc { fpu: true };
+
+impl Arch for PowerPc {
+    fn description(&self) -> &TargetDescription<'_> {
+        if self.fpu {
+            &FPU_DESCRIPTION
+        } else {
+            &DESCRIPTION
+        }
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::Big
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        TRAP.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(POWERPC.register_count(), 38);
+        assert_eq!(POWERPC.registers_size(), 38 * 4);
+        assert_eq!(POWERPC.pc(), Some(PC_REGNO));
+        assert_eq!(POWERPC.register_name(CR_REGNO), Some(&b"cr"[..]));
+        assert_eq!(POWERPC.register_name(LR_REGNO), Some(&b"lr"[..]));
+        assert_eq!(POWERPC.register_name(CTR_REGNO), Some(&b"ctr"[..]));
+        assert_eq!(POWERPC.register_name(XER_REGNO), Some(&b"xer"[..]));
+        assert_eq!(POWERPC.endianness(), Endianness::Big);
+
+        assert_eq!(POWERPC_FPU.register_count(), 71);
+        assert_eq!(POWERPC_FPU.register_size(F0_REGNO), Some(8));
+        assert_eq!(POWERPC_FPU.registers_size(), 38 * 4 + 32 * 8 + 4);
+    }
+}
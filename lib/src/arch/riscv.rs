@@ -0,0 +1,205 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+use crate::parser::Endianness;
+pub use crate::step::riscv::{Xlen, PC_REGNO};
+use crate::step::riscv::{C_EBREAK, EBREAK};
+
+/// gdb register number of the first csr of the variants with csrs
+pub const CSR_REGNO: usize = PC_REGNO + 1;
+
+/// the csr numbers of the machine mode csrs in register order
+/// mstatus, misa, mie, mtvec, mscratch, mepc, mcause, mtval and mip
+pub const MACHINE_CSRS: [usize; 9] = [
+    0x300, 0x301, 0x304, 0x305, 0x340, 0x341, 0x342, 0x343, 0x344,
+];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.riscv.cpu";
+const CSR_FEATURE: &[u8] = b"org.gnu.gdb.riscv.csr";
+
+const fn csr(name: &'static [u8], bits: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, bits)
+        .with_group(b"csr")
+        .with_feature(CSR_FEATURE)
+}
+
+macro_rules! registers {
+    ($bits:expr $(, $extra:expr)*) => {
+        [
+            RegisterDesc::new(b"zero", $bits).with_dwarf(0),
+            RegisterDesc::new(b"ra", $bits)
+                .with_dwarf(1)
+                .with_generic(GenericRegister::Ra),
+            RegisterDesc::new(b"sp", $bits)
+                .with_dwarf(2)
+                .with_generic(GenericRegister::Sp),
+            RegisterDesc::new(b"gp", $bits).with_dwarf(3),
+            RegisterDesc::new(b"tp", $bits).with_dwarf(4),
+            RegisterDesc::new(b"t0", $bits).with_dwarf(5),
+            RegisterDesc::new(b"t1", $bits).with_dwarf(6),
+            RegisterDesc::new(b"t2", $bits).with_dwarf(7),
+            RegisterDesc::new(b"fp", $bits)
+                .with_dwarf(8)
+                .with_generic(GenericRegister::Fp),
+            RegisterDesc::new(b"s1", $bits).with_dwarf(9),
+            RegisterDesc::new(b"a0", $bits).with_dwarf(10),
+            RegisterDesc::new(b"a1", $bits).with_dwarf(11),
+            RegisterDesc::new(b"a2", $bits).with_dwarf(12),
+            RegisterDesc::new(b"a3", $bits).with_dwarf(13),
+            RegisterDesc::new(b"a4", $bits).with_dwarf(14),
+            RegisterDesc::new(b"a5", $bits).with_dwarf(15),
+            RegisterDesc::new(b"a6", $bits).with_dwarf(16),
+            RegisterDesc::new(b"a7", $bits).with_dwarf(17),
+            RegisterDesc::new(b"s2", $bits).with_dwarf(18),
+            RegisterDesc::new(b"s3", $bits).with_dwarf(19),
+            RegisterDesc::new(b"s4", $bits).with_dwarf(20),
+            RegisterDesc::new(b"s5", $bits).with_dwarf(21),
+            RegisterDesc::new(b"s6", $bits).with_dwarf(22),
+            RegisterDesc::new(b"s7", $bits).with_dwarf(23),
+            RegisterDesc::new(b"s8", $bits).with_dwarf(24),
+            RegisterDesc::new(b"s9", $bits).with_dwarf(25),
+            RegisterDesc::new(b"s10", $bits).with_dwarf(26),
+            RegisterDesc::new(b"s11", $bits).with_dwarf(27),
+            RegisterDesc::new(b"t3", $bits).with_dwarf(28),
+            RegisterDesc::new(b"t4", $bits).with_dwarf(29),
+            RegisterDesc::new(b"t5", $bits).with_dwarf(30),
+            RegisterDesc::new(b"t6", $bits).with_dwarf(31),
+            RegisterDesc::new(b"pc", $bits).with_generic(GenericRegister::Pc),
+            $($extra),*
+        ]
+    };
+}
+
+macro_rules! machine_registers {
+    ($bits:expr) => {
+        registers!(
+            $bits,
+            csr(b"mstatus", $bits),
+            csr(b"misa", $bits),
+            csr(b"mie", $bits),
+            csr(b"mtvec", $bits),
+            csr(b"mscratch", $bits),
+            csr(b"mepc", $bits),
+            csr(b"mcause", $bits),
+            csr(b"mtval", $bits),
+            csr(b"mip", $bits)
+        )
+    };
+}
+
+const RV32_REGISTERS: [RegisterDesc; 33] = registers!(32);
+const RV64_REGISTERS: [RegisterDesc; 33] = registers!(64);
+const RV32_MACHINE_REGISTERS: [RegisterDesc; 42] = machine_registers!(32);
+const RV64_MACHINE_REGISTERS: [RegisterDesc; 42] = machine_registers!(64);
+
+const RV32_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &RV32_REGISTERS).with_architecture(b"riscv:rv32");
+
+const RV64_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &RV64_REGISTERS).with_architecture(b"riscv:rv64");
+
+const RV32_MACHINE_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &RV32_MACHINE_REGISTERS).with_architecture(b"riscv:rv32");
+
+const RV64_MACHINE_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &RV64_MACHINE_REGISTERS).with_architecture(b"riscv:rv64");
+
+/// RISC-V cores, x0-x31 and pc of the base integer isa
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Riscv {
+    pub xlen: Xlen,
+    /// true if the core implements the C extension
+    /// gdb then uses c.ebreak for breakpoints
+    pub compressed: bool,
+    /// true to describe the machine mode csrs after the pc
+    /// they are accessed with Target::rd_csr and wr_csr
+    pub machine_csrs: bool,
+}
+
+impl Riscv {
+    pub const fn new(xlen: Xlen) -> Self {
+        Self {
+            xlen,
+            compressed: false,
+            machine_csrs: false,
+        }
+    }
+
+    pub const fn with_compressed(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    pub const fn with_machine_csrs(mut self) -> Self {
+        self.machine_csrs = true;
+        self
+    }
+}
+
+pub const RV32: Riscv = Riscv::new(Xlen::Rv32);
+pub const RV64: Riscv = Riscv::new(Xlen::Rv64);
+
+impl Arch for Riscv {
+    fn description(&self) -> &TargetDescription<'_> {
+        match (self.xlen, self.machine_csrs) {
+            (Xlen::Rv32, false) => &RV32_DESCRIPTION,
+            (Xlen::Rv64, false) => &RV64_DESCRIPTION,
+            (Xlen::Rv32, true) => &RV32_MACHINE_DESCRIPTION,
+            (Xlen::Rv64, true) => &RV64_MACHINE_DESCRIPTION,
+        }
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+
+    fn csr(&self, regnum: usize) -> Option<usize> {
+        if !self.machine_csrs {
+            return None;
+        }
+        let index = regnum.checked_sub(CSR_REGNO)?;
+        MACHINE_CSRS.get(index).copied()
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        if self.compressed {
+            C_EBREAK.len()
+        } else {
+            EBREAK.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_both_xlens() {
+        assert_eq!(RV32.register_count(), 33);
+        assert_eq!(RV32.pc(), Some(PC_REGNO));
+        assert_eq!(RV32.registers_size(), 33 * 4);
+        assert_eq!(RV64.registers_size(), 33 * 8);
+        assert_eq!(RV64.register_name(8), Some(&b"fp"[..]));
+        assert_eq!(RV64.description().architecture, Some(&b"riscv:rv64"[..]));
+    }
+
+    #[test]
+    fn it_should_pick_the_breakpoint_size() {
+        assert_eq!(RV32.breakpoint_kind(), 4);
+        assert_eq!(RV32.with_compressed().breakpoint_kind(), 2);
+    }
+
+    #[test]
+    fn it_should_number_machine_csrs() {
+        let rv32 = RV32.with_machine_csrs();
+        assert_eq!(rv32.register_count(), 42);
+        assert_eq!(rv32.register_name(CSR_REGNO), Some(&b"mstatus"[..]));
+        assert_eq!(rv32.csr(CSR_REGNO), Some(0x300));
+        assert_eq!(rv32.register_name(CSR_REGNO + 6), Some(&b"mcause"[..]));
+        assert_eq!(rv32.csr(CSR_REGNO + 6), Some(0x342));
+        assert_eq!(rv32.csr(PC_REGNO), None);
+        assert_eq!(rv32.csr(42), None);
+        assert_eq!(RV64.csr(CSR_REGNO), None);
+        assert_eq!(RV64.with_machine_csrs().register_size(CSR_REGNO), Some(8));
+    }
+}
@@ -0,0 +1,161 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, RegisterEncoding, TargetDescription};
+
+/// gdb register number of rip
+pub const RIP_REGNO: usize = 16;
+
+/// gdb register number of eflags
+pub const EFLAGS_REGNO: usize = 17;
+
+/// gdb register number of st0, the x87 registers are 80 bit wide
+/// and exchanged as RegisterValue::Bytes
+pub const ST0_REGNO: usize = 24;
+
+/// gdb register number of xmm0
+pub const XMM0_REGNO: usize = 40;
+
+/// int3
+pub const INT3: [u8; 1] = [0xcc];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.i386.core";
+const SSE_FEATURE: &[u8] = b"org.gnu.gdb.i386.sse";
+
+const fn gpr(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 64).with_dwarf(dwarf)
+}
+
+const fn segment(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_dwarf(dwarf)
+}
+
+const fn st(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 80)
+        .with_encoding(RegisterEncoding::Ieee754)
+        .with_type(b"i387_ext")
+        .with_group(b"float")
+        .with_dwarf(dwarf)
+}
+
+const fn x87(name: &'static [u8]) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_group(b"float")
+}
+
+const fn xmm(name: &'static [u8], dwarf: usize) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 128)
+        .with_encoding(RegisterEncoding::Vector)
+        .with_type(b"uint128")
+        .with_group(b"vector")
+        .with_dwarf(dwarf)
+        .with_feature(SSE_FEATURE)
+}
+
+// the order of gdb's amd64 g packet
+const REGISTERS: [RegisterDesc; 57] = [
+    gpr(b"rax", 0),
+    gpr(b"rbx", 3),
+    gpr(b"rcx", 2),
+    gpr(b"rdx", 1),
+    gpr(b"rsi", 4),
+    gpr(b"rdi", 5),
+    gpr(b"rbp", 6).with_generic(GenericRegister::Fp),
+    gpr(b"rsp", 7).with_generic(GenericRegister::Sp),
+    gpr(b"r8", 8),
+    gpr(b"r9", 9),
+    gpr(b"r10", 10),
+    gpr(b"r11", 11),
+    gpr(b"r12", 12),
+    gpr(b"r13", 13),
+    gpr(b"r14", 14),
+    gpr(b"r15", 15),
+    gpr(b"rip", 16).with_generic(GenericRegister::Pc),
+    segment(b"eflags", 49)
+        .with_type(b"i386_eflags")
+        .with_generic(GenericRegister::Flags),
+    segment(b"cs", 51),
+    segment(b"ss", 52),
+    segment(b"ds", 53),
+    segment(b"es", 50),
+    segment(b"fs", 54),
+    segment(b"gs", 55),
+    st(b"st0", 33),
+    st(b"st1", 34),
+    st(b"st2", 35),
+    st(b"st3", 36),
+    st(b"st4", 37),
+    st(b"st5", 38),
+    st(b"st6", 39),
+    st(b"st7", 40),
+    x87(b"fctrl").with_dwarf(65),
+    x87(b"fstat").with_dwarf(66),
+    x87(b"ftag"),
+    x87(b"fiseg"),
+    x87(b"fioff"),
+    x87(b"foseg"),
+    x87(b"fooff"),
+    x87(b"fop"),
+    xmm(b"xmm0", 17),
+    xmm(b"xmm1", 18),
+    xmm(b"xmm2", 19),
+    xmm(b"xmm3", 20),
+    xmm(b"xmm4", 21),
+    xmm(b"xmm5", 22),
+    xmm(b"xmm6", 23),
+    xmm(b"xmm7", 24),
+    xmm(b"xmm8", 25),
+    xmm(b"xmm9", 26),
+    xmm(b"xmm10", 27),
+    xmm(b"xmm11", 28),
+    xmm(b"xmm12", 29),
+    xmm(b"xmm13", 30),
+    xmm(b"xmm14", 31),
+    xmm(b"xmm15", 32),
+    RegisterDesc::new(b"mxcsr", 32)
+        .with_type(b"i386_mxcsr")
+        .with_group(b"vector")
+        .with_dwarf(64)
+        .with_feature(SSE_FEATURE),
+];
+
+const DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &REGISTERS).with_architecture(b"i386:x86-64");
+
+/// 64 bit x86 cores with the core, x87 and sse registers gdb requires
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Amd64;
+
+impl Arch for Amd64 {
+    fn description(&self) -> &TargetDescription<'_> {
+        &DESCRIPTION
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        INT3.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{BufferedStream, Stream};
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(Amd64.register_count(), 57);
+        assert_eq!(Amd64.pc(), Some(RIP_REGNO));
+        assert_eq!(Amd64.register_size(ST0_REGNO), Some(10));
+        assert_eq!(Amd64.register_size(XMM0_REGNO), Some(16));
+        assert_eq!(Amd64.register_name(EFLAGS_REGNO), Some(&b"eflags"[..]));
+        assert_eq!(Amd64.registers_size(), 536);
+        assert_eq!(Amd64.breakpoint_kind(), 1);
+    }
+
+    #[test]
+    fn it_should_write_x87_types() {
+        let mut s = BufferedStream::new();
+        REGISTERS[ST0_REGNO].write_xml(&mut s, ST0_REGNO).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<reg name=\"st0\" bitsize=\"80\" regnum=\"24\" type=\"i387_ext\" group=\"float\"/>"[..]
+        );
+    }
+}
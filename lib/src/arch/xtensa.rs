@@ -0,0 +1,239 @@
+use super::Arch;
+use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+
+/// gdb register number of the pc
+pub const PC_REGNO: usize = 0;
+
+/// gdb register number of the physical address register ar0
+pub const AR0_REGNO: usize = 1;
+
+/// number of physical address registers of the ESP32 cores
+pub const AR_COUNT: usize = 64;
+
+/// gdb register numbers of the window registers
+pub const WINDOWBASE_REGNO: usize = 69;
+pub const WINDOWSTART_REGNO: usize = 70;
+
+/// gdb register number of ps
+pub const PS_REGNO: usize = 73;
+
+/// break.n 1, 0
+/// the ESP32 cores implement the code density option
+pub const BREAK_N: [u8; 2] = [0x2d, 0xf0];
+
+const FEATURE: &[u8] = b"org.gnu.gdb.xtensa.core";
+
+/// The address registers are windowed: a0-a15 are a view of 16 of the
+/// physical registers ar0-ar63 that moves by windowbase * 4.
+/// A call4/8/12 rotates the window, windowstart has a bit set for every
+/// window that holds live registers of a frame.
+/// gdb reads the physical registers together with windowbase and
+/// windowstart and derives a0-a15 as pseudo registers, so the target
+/// reports the physical register file and not the current window.
+/// Frames of windows that were already spilled to the stack by a
+/// window overflow exception are read from memory by gdb.
+///
+/// Returns the gdb register number of a<n> for windowbase.
+pub fn window_register(windowbase: usize, n: usize) -> usize {
+    AR0_REGNO + (windowbase * 4 + n) % AR_COUNT
+}
+
+const fn core(name: &'static [u8]) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32)
+}
+
+const fn fpu(name: &'static [u8]) -> RegisterDesc<'static> {
+    RegisterDesc::new(name, 32).with_group(b"float")
+}
+
+// the register file of ESP-IDF's gdbstub,
+// the double precision accelerator registers of the LX6 precede the fpu
+macro_rules! registers {
+    ($($dfp:expr),*) => {
+        [
+            core(b"pc").with_generic(GenericRegister::Pc),
+            core(b"ar0"),
+            core(b"ar1"),
+            core(b"ar2"),
+            core(b"ar3"),
+            core(b"ar4"),
+            core(b"ar5"),
+            core(b"ar6"),
+            core(b"ar7"),
+            core(b"ar8"),
+            core(b"ar9"),
+            core(b"ar10"),
+            core(b"ar11"),
+            core(b"ar12"),
+            core(b"ar13"),
+            core(b"ar14"),
+            core(b"ar15"),
+            core(b"ar16"),
+            core(b"ar17"),
+            core(b"ar18"),
+            core(b"ar19"),
+            core(b"ar20"),
+            core(b"ar21"),
+            core(b"ar22"),
+            core(b"ar23"),
+            core(b"ar24"),
+            core(b"ar25"),
+            core(b"ar26"),
+            core(b"ar27"),
+            core(b"ar28"),
+            core(b"ar29"),
+            core(b"ar30"),
+            core(b"ar31"),
+            core(b"ar32"),
+            core(b"ar33"),
+            core(b"ar34"),
+            core(b"ar35"),
+            core(b"ar36"),
+            core(b"ar37"),
+            core(b"ar38"),
+            core(b"ar39"),
+            core(b"ar40"),
+            core(b"ar41"),
+            core(b"ar42"),
+            core(b"ar43"),
+            core(b"ar44"),
+            core(b"ar45"),
+            core(b"ar46"),
+            core(b"ar47"),
+            core(b"ar48"),
+            core(b"ar49"),
+            core(b"ar50"),
+            core(b"ar51"),
+            core(b"ar52"),
+            core(b"ar53"),
+            core(b"ar54"),
+            core(b"ar55"),
+            core(b"ar56"),
+            core(b"ar57"),
+            core(b"ar58"),
+            core(b"ar59"),
+            core(b"ar60"),
+            core(b"ar61"),
+            core(b"ar62"),
+            core(b"ar63"),
+            core(b"lbeg"),
+            core(b"lend"),
+            core(b"lcount"),
+            core(b"sar"),
+            core(b"windowbase"),
+            core(b"windowstart"),
+            core(b"configid0"),
+            core(b"configid1"),
+            core(b"ps").with_generic(GenericRegister::Flags),
+            core(b"threadptr"),
+            core(b"br"),
+            core(b"scompare1"),
+            core(b"acclo"),
+            core(b"acchi"),
+            core(b"m0"),
+            core(b"m1"),
+            core(b"m2"),
+            core(b"m3"),
+            $($dfp,)*
+            fpu(b"f0"),
+            fpu(b"f1"),
+            fpu(b"f2"),
+            fpu(b"f3"),
+            fpu(b"f4"),
+            fpu(b"f5"),
+            fpu(b"f6"),
+            fpu(b"f7"),
+            fpu(b"f8"),
+            fpu(b"f9"),
+            fpu(b"f10"),
+            fpu(b"f11"),
+            fpu(b"f12"),
+            fpu(b"f13"),
+            fpu(b"f14"),
+            fpu(b"f15"),
+            fpu(b"fcr"),
+            fpu(b"fsr"),
+        ]
+    };
+}
+
+const LX6_REGISTERS: [RegisterDesc; 105] = registers!(
+    core(b"expstate"),
+    core(b"f64r_lo"),
+    core(b"f64r_hi"),
+    core(b"f64s")
+);
+
+const LX7_REGISTERS: [RegisterDesc; 101] = registers!();
+
+// gdb's xtensa support takes the register layout from the
+// configuration it was built for and ignores target.xml
+const LX6_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &LX6_REGISTERS).with_architecture(b"xtensa");
+
+const LX7_DESCRIPTION: TargetDescription =
+    TargetDescription::new(FEATURE, &LX7_REGISTERS).with_architecture(b"xtensa");
+
+/// Xtensa cores of the ESP32 family
+/// the layout has to match the xtensa-esp32-elf-gdb
+/// or xtensa-esp32s3-elf-gdb that is used
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Xtensa {
+    /// LX6 of the esp32
+    Lx6,
+    /// LX7 of the esp32-s3
+    Lx7,
+}
+
+/// esp32
+pub const ESP32: Xtensa = Xtensa::Lx6;
+
+/// esp32-s3
+pub const ESP32_S3: Xtensa = Xtensa::Lx7;
+
+impl Arch for Xtensa {
+    fn description(&self) -> &TargetDescription<'_> {
+        match self {
+            Self::Lx6 => &LX6_DESCRIPTION,
+            Self::Lx7 => &LX7_DESCRIPTION,
+        }
+    }
+
+    fn breakpoint_kind(&self) -> usize {
+        BREAK_N.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_describe_the_registers() {
+        assert_eq!(ESP32.register_count(), 105);
+        assert_eq!(ESP32.registers_size(), 105 * 4);
+        assert_eq!(ESP32.pc(), Some(PC_REGNO));
+        assert_eq!(ESP32.register_name(AR0_REGNO + 63), Some(&b"ar63"[..]));
+        assert_eq!(
+            ESP32.register_name(WINDOWBASE_REGNO),
+            Some(&b"windowbase"[..])
+        );
+        assert_eq!(
+            ESP32.register_name(WINDOWSTART_REGNO),
+            Some(&b"windowstart"[..])
+        );
+        assert_eq!(ESP32.register_name(PS_REGNO), Some(&b"ps"[..]));
+        assert_eq!(ESP32.register_name(87), Some(&b"f0"[..]));
+        assert_eq!(ESP32.breakpoint_kind(), 2);
+
+        assert_eq!(ESP32_S3.register_count(), 101);
+        assert_eq!(ESP32_S3.register_name(83), Some(&b"f0"[..]));
+    }
+
+    #[test]
+    fn it_should_map_the_register_window() {
+        assert_eq!(window_register(0, 0), AR0_REGNO);
+        assert_eq!(window_register(1, 1), AR0_REGNO + 5);
+        assert_eq!(window_register(15, 5), AR0_REGNO + 1);
+    }
+}
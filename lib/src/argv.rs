@@ -0,0 +1,60 @@
+use super::parser::Parser;
+
+/// Hex encoded argument list of a vRun packet.
+/// The first entry is the program name,
+/// which is empty to run the current program again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Argv<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> Argv<'a> {
+    /// raw is the ; separated list of hex encoded arguments
+    pub fn new(raw: &'a [u8]) -> Self {
+        Self { raw }
+    }
+
+    /// hex encoded arguments
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.raw.split(|b| *b == b';')
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// the program name is always present
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// returns the hex encoded argument at index
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        self.iter().nth(index)
+    }
+
+    /// decodes the argument at index into out
+    /// returns its length
+    pub fn decode(&self, index: usize, out: &mut [u8]) -> Option<usize> {
+        Parser::from_hex_bytes(self.get(index)?, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_split_arguments() {
+        let argv = Argv::new(b";2d76;6869");
+        assert_eq!(argv.len(), 3);
+        assert_eq!(argv.get(0), Some(&b""[..]));
+        assert_eq!(argv.get(3), None);
+
+        let mut out = [0; 2];
+        assert_eq!(argv.decode(1, &mut out), Some(2));
+        assert_eq!(out, *b"-v");
+        assert_eq!(Argv::new(b"616263").decode(0, &mut out), None);
+        assert_eq!(Argv::new(b"").len(), 1);
+    }
+}
@@ -0,0 +1,258 @@
+use super::command::{Commands, ErrorReply};
+use super::error::Errors;
+use super::parser::Parsed;
+
+/// interrupt request sent by gdb on Ctrl-C
+pub const INTERRUPT: u8 = 0x03;
+
+/// a complete unit of input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// a $data#cs packet of the given length is in the buffer
+    Packet(usize),
+    /// + outside of a packet
+    Ack,
+    /// - outside of a packet
+    Nak,
+    /// 0x03 outside of a packet, also between packets of the same read
+    /// inside of a packet it is binary data, e.g. of X
+    Interrupt,
+    /// a packet did not fit into the buffer and was dropped
+    /// answer it with overflow_reply
+    Overflow,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Idle,
+    Data,
+    /// amount of checksum digits still expected
+    Checksum(u8),
+    /// skipping the rest of a packet that did not fit
+    Overflow,
+    OverflowChecksum(u8),
+}
+
+/// Collects packets from a byte stream into a fixed buffer.
+/// Packets larger than the buffer are consumed up to their checksum
+/// and reported as Frame::Overflow so the stub stays in sync
+/// with the host instead of parsing a truncated packet.
+#[derive(Debug)]
+pub struct PacketAssembler<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    state: State,
+}
+
+impl<'a> PacketAssembler<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            state: State::Idle,
+        }
+    }
+
+    /// the packet reported by the last Frame::Packet
+    pub fn packet(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// drops any partially received packet
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.state = State::Idle;
+    }
+
+    fn store(&mut self, byte: u8) -> bool {
+        match self.buffer.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// feeds the next input byte
+    /// returns a frame once one is complete
+    pub fn push(&mut self, byte: u8) -> Option<Frame> {
+        // $ is always escaped inside of packets
+        // so it can only be the start of a new one
+        if byte == b'$' {
+            self.len = 0;
+            self.state = if self.store(byte) {
+                State::Data
+            } else {
+                State::Overflow
+            };
+            return None;
+        }
+
+        match self.state {
+            State::Idle => match byte {
+                b'+' => Some(Frame::Ack),
+                b'-' => Some(Frame::Nak),
+                INTERRUPT => Some(Frame::Interrupt),
+                _ => None,
+            },
+            State::Data => {
+                if !self.store(byte) {
+                    self.state = State::Overflow;
+                    return self.push(byte);
+                }
+                if byte == b'#' {
+                    self.state = State::Checksum(2);
+                }
+                None
+            }
+            State::Checksum(remaining) => {
+                if !self.store(byte) {
+                    self.state = State::OverflowChecksum(remaining);
+                    return self.push(byte);
+                }
+                if remaining > 1 {
+                    self.state = State::Checksum(remaining - 1);
+                    None
+                } else {
+                    self.state = State::Idle;
+                    Some(Frame::Packet(self.len))
+                }
+            }
+            State::Overflow => {
+                if byte == b'#' {
+                    self.state = State::OverflowChecksum(2);
+                }
+                None
+            }
+            State::OverflowChecksum(remaining) => {
+                if remaining > 1 {
+                    self.state = State::OverflowChecksum(remaining - 1);
+                    None
+                } else {
+                    self.len = 0;
+                    self.state = State::Idle;
+                    Some(Frame::Overflow)
+                }
+            }
+        }
+    }
+
+    /// acknowledges a dropped packet and answers it with an error
+    /// a nak would only make gdb resend the same oversized packet
+    pub fn overflow_reply() -> Parsed<'a> {
+        Parsed::ack(Some(Commands::Error(ErrorReply::new(
+            Errors::PacketOverflow,
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::stream::{BufferedStream, Stream};
+    use crate::target::VirtualTarget;
+
+    fn feed(assembler: &mut PacketAssembler, input: &[u8]) -> std::vec::Vec<Frame> {
+        input.iter().filter_map(|b| assembler.push(*b)).collect()
+    }
+
+    #[test]
+    fn it_should_assemble_packets() {
+        let mut buffer = [0; 16];
+        let mut assembler = PacketAssembler::new(&mut buffer);
+
+        assert_eq!(
+            feed(&mut assembler, b"+$m64,4#37-\x03"),
+            [Frame::Ack, Frame::Packet(9), Frame::Nak, Frame::Interrupt]
+        );
+        assert_eq!(assembler.packet(), b"$m64,4#37");
+    }
+
+    #[test]
+    fn it_should_interrupt_between_packets() {
+        let mut buffer = [0; 16];
+        let mut assembler = PacketAssembler::new(&mut buffer);
+
+        // the interrupt may follow a packet directly while the target runs
+        assert_eq!(
+            feed(&mut assembler, b"$c#63\x03$X0,1:\x03#22"),
+            [Frame::Packet(5), Frame::Interrupt, Frame::Packet(10)]
+        );
+        assert_eq!(assembler.packet(), b"$X0,1:\x03#22");
+    }
+
+    #[test]
+    fn it_should_fit_packets_exactly() {
+        let mut buffer = [0; 9];
+        let mut assembler = PacketAssembler::new(&mut buffer);
+
+        assert_eq!(feed(&mut assembler, b"$m64,4#37"), [Frame::Packet(9)]);
+        assert_eq!(assembler.packet(), b"$m64,4#37");
+    }
+
+    #[test]
+    fn it_should_drop_oversized_packets() {
+        // overflow in the data, on the # and in each checksum digit
+        for size in 1..9 {
+            let mut buffer = [0; 9];
+            let mut assembler = PacketAssembler::new(&mut buffer[..size]);
+
+            assert_eq!(
+                feed(&mut assembler, b"$m64,4#37"),
+                [Frame::Overflow],
+                "buffer size {}",
+                size
+            );
+            assert_eq!(assembler.packet(), b"");
+
+            // the next packet that fits is received again
+            assert_eq!(
+                feed(&mut assembler, b"+$?#3f"),
+                if size >= 5 {
+                    std::vec![Frame::Ack, Frame::Packet(5)]
+                } else {
+                    std::vec![Frame::Ack, Frame::Overflow]
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_resync_on_new_packets() {
+        let mut buffer = [0; 8];
+        let mut assembler = PacketAssembler::new(&mut buffer);
+
+        // a lost # or a new packet during an overflow restarts the frame
+        assert_eq!(
+            feed(&mut assembler, b"$garbage and more$?#3f"),
+            [Frame::Packet(5)]
+        );
+        assert_eq!(assembler.packet(), b"$?#3f");
+    }
+
+    #[test]
+    fn it_should_reply_to_overflows() {
+        let mut stream = BufferedStream::new();
+        let mut target = VirtualTarget::new();
+        let mut parsed = PacketAssembler::overflow_reply();
+
+        parsed
+            .response
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"+"[..]);
+
+        parsed
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+}
@@ -0,0 +1,255 @@
+/*
+ * Breakpoints and watchpoints
+ */
+
+use crate::breakpoint::{Breakpoint, BreakpointKind};
+use crate::command::*;
+use crate::error::Errors;
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+
+/**
+ * Ztype,addr,kind
+ * ztype,addr,kind
+ * target side conditions and commands are not advertised and ignored
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct BreakpointCommand<'a> {
+    state: ResponseWriter<'a>,
+    insert: bool,
+    applied: Option<Breakpoint>,
+}
+
+impl<'a> BreakpointCommand<'a> {
+    /// insert is true for Z and false for z
+    pub fn new(args: &'a [u8], insert: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            insert,
+            applied: None,
+        }
+    }
+
+    /// true for Z and false for z
+    pub fn inserts(&self) -> bool {
+        self.insert
+    }
+
+    /// the breakpoint once the target inserted or removed it
+    pub fn applied(&self) -> Option<Breakpoint> {
+        self.applied
+    }
+
+    fn parse(&self) -> Result<Breakpoint, Errors> {
+        let mut parser = Parser::new(self.state.fields);
+        let (kind, addr, size) = match (
+            parser.next_token(),
+            parser.next_token(),
+            parser.next_token(),
+        ) {
+            (Some(kind), Some(addr), Some(size)) => (kind, addr, size),
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let addr = Parser::from_hex_address(addr).ok_or(Errors::BadNumber)?;
+        let size = Parser::from_hexu(size).ok_or(Errors::BadNumber)?;
+        let kind = BreakpointKind::parse(kind).ok_or(Errors::Unsupported)?;
+        Ok(Breakpoint::new(kind, addr, size))
+    }
+
+    fn apply(&mut self, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        let breakpoint = self.parse()?;
+        let result = match self.insert {
+            true => breakpoint.insert(ctx),
+            false => breakpoint.remove(ctx),
+        }?;
+        self.applied = Some(breakpoint);
+        Ok(result)
+    }
+}
+
+impl Command for BreakpointCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        match self.apply(ctx) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            // an empty reply makes gdb fall back to memory writes
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakpoint::{HwBreakpoints, SwBreakpoints, Watchpoints};
+    use crate::command::testing::exec;
+    use crate::memory::{GuestAddress, MemoryKind, MemoryRegion};
+    use crate::stop::WatchKind;
+    use crate::target::VirtualTarget;
+
+    #[derive(Default)]
+    struct BreakTarget {
+        breakpoint: Option<(GuestAddress, usize)>,
+        watchpoint: Option<(GuestAddress, usize, WatchKind)>,
+    }
+
+    impl Target for BreakTarget {
+        fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+            Some(self)
+        }
+
+        fn watchpoints(&mut self) -> Option<&mut dyn Watchpoints> {
+            Some(self)
+        }
+    }
+
+    impl SwBreakpoints for BreakTarget {
+        fn add_sw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors> {
+            self.breakpoint = Some((addr, kind));
+            Ok(0)
+        }
+
+        fn remove_sw_breakpoint(
+            &mut self,
+            addr: GuestAddress,
+            kind: usize,
+        ) -> Result<usize, Errors> {
+            match self.breakpoint.take() {
+                Some(breakpoint) if breakpoint == (addr, kind) => Ok(0),
+                _ => Err(Errors::CommandError),
+            }
+        }
+    }
+
+    impl Watchpoints for BreakTarget {
+        fn add_watchpoint(
+            &mut self,
+            addr: GuestAddress,
+            len: usize,
+            kind: WatchKind,
+        ) -> Result<usize, Errors> {
+            match kind {
+                WatchKind::Read => Err(Errors::Unsupported),
+                _ => {
+                    self.watchpoint = Some((addr, len, kind));
+                    Ok(0)
+                }
+            }
+        }
+
+        fn remove_watchpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _len: usize,
+            _kind: WatchKind,
+        ) -> Result<usize, Errors> {
+            self.watchpoint = None;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_insert_breakpoints() {
+        let mut target = BreakTarget::default();
+        assert_eq!(exec(b"$Z0,8000,2#dc", &mut target), b"$OK#9a");
+        assert_eq!(target.breakpoint, Some((0x8000, 2)));
+
+        assert_eq!(exec(b"$z0,8000,2#fc", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$z0,8000,2#fc", &mut target), b"$E00#a5");
+
+        // no hardware breakpoints
+        assert_eq!(exec(b"$Z1,8000,2#dd", &mut target), b"$#00");
+        assert_eq!(exec(b"$Z0,8000,2#dc", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    const FLASH_MAP: [MemoryRegion; 2] = [
+        MemoryRegion::new(0x0, 0x8000, MemoryKind::Flash { block_size: 0x400 }),
+        MemoryRegion::new(0x8000, 0x1000, MemoryKind::Ram),
+    ];
+
+    #[derive(Default)]
+    struct FlashTarget {
+        sw: BreakTarget,
+        hw: Option<(GuestAddress, usize)>,
+        comparators: bool,
+    }
+
+    impl Target for FlashTarget {
+        fn memory_map(&self) -> &[MemoryRegion] {
+            &FLASH_MAP
+        }
+
+        fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+            Some(&mut self.sw)
+        }
+
+        fn hw_breakpoints(&mut self) -> Option<&mut dyn HwBreakpoints> {
+            match self.comparators {
+                true => Some(self),
+                false => None,
+            }
+        }
+    }
+
+    impl HwBreakpoints for FlashTarget {
+        fn add_hw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors> {
+            self.hw = Some((addr, kind));
+            Ok(0)
+        }
+
+        fn remove_hw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            self.hw = None;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_use_hardware_breakpoints_in_flash() {
+        let mut target = FlashTarget {
+            comparators: true,
+            ..Default::default()
+        };
+        assert_eq!(exec(b"$Z0,100,2#a5", &mut target), b"$OK#9a");
+        assert_eq!(target.hw, Some((0x100, 2)));
+        assert_eq!(target.sw.breakpoint, None);
+
+        assert_eq!(exec(b"$Z0,8000,2#dc", &mut target), b"$OK#9a");
+        assert_eq!(target.sw.breakpoint, Some((0x8000, 2)));
+
+        assert_eq!(exec(b"$z0,100,2#c5", &mut target), b"$OK#9a");
+        assert_eq!(target.hw, None);
+
+        // no comparators, gdb must not patch flash with M
+        target.comparators = false;
+        assert_eq!(exec(b"$Z0,100,2#a5", &mut target), b"$E13#a9");
+    }
+
+    #[test]
+    fn it_should_insert_watchpoints() {
+        let mut target = BreakTarget::default();
+        assert_eq!(exec(b"$Z4,20000100,4#9d", &mut target), b"$OK#9a");
+        assert_eq!(target.watchpoint, Some((0x20000100, 4, WatchKind::Access)));
+
+        assert_eq!(exec(b"$Z3,20000100,4#9c", &mut target), b"$#00");
+
+        exec(b"$z4,20000100,4#bd", &mut target);
+        assert_eq!(target.watchpoint, None);
+    }
+}
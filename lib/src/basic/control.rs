@@ -0,0 +1,1922 @@
+/*
+ * Execution control
+ */
+
+use crate::argv::Argv;
+use crate::basic::required::write_stop_reply;
+use crate::command::*;
+use crate::error::Errors;
+#[cfg(feature = "fileio")]
+use crate::fileio::FileIoReply;
+use crate::parser::Parser;
+#[cfg(feature = "reverse")]
+use crate::replay::{Replay, ReplayStop};
+use crate::resume::{self, InputPoll, ResumeAction, ResumeActions, ResumeKind};
+use crate::signal::SignalSet;
+use crate::stop::{StopQueue, StopReason};
+use crate::stream::{BufferStrategy, SinkStream, Stream};
+use crate::syscall::{CatchSyscalls, SyscallList};
+use crate::target::Target;
+
+/**
+ * D
+ * D;pid with the multiprocess extension
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct DetachCommand<'a> {
+    state: ResponseWriter<'a>,
+    detached: bool,
+}
+
+impl<'a> DetachCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            detached: false,
+        }
+    }
+
+    /// true once the target accepted the detach
+    pub fn detached(&self) -> bool {
+        self.detached
+    }
+}
+
+impl Command for DetachCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let pid = match self.state.fields {
+            b"" => None,
+            [b';', pid @ ..] => Some(Parser::from_hexu(pid).ok_or(Errors::BadNumber)?),
+            _ => return Err(Errors::BadNumber),
+        };
+        self.state.start(stream)?;
+        match ctx.detach(pid) {
+            Ok(_) => {
+                self.detached = true;
+                self.state.ok(stream)?
+            }
+            Err(err) => self.state.error(stream, err)?,
+        };
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QNonStop:0|1
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct NonStopCommand<'a> {
+    state: ResponseWriter<'a>,
+    enabled: Option<bool>,
+}
+
+impl<'a> NonStopCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            enabled: None,
+        }
+    }
+
+    /// the mode the target switched to, None unless it accepted
+    pub fn enabled(&self) -> Option<bool> {
+        self.enabled
+    }
+}
+
+impl Command for NonStopCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let enabled = match self.state.fields {
+            b"0" => false,
+            b"1" => true,
+            _ => return Err(Errors::BadNumber),
+        };
+        match ctx.set_non_stop(enabled) {
+            Ok(_) => {
+                self.enabled = Some(enabled);
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QThreadEvents:0|1
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadEventsCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ThreadEventsCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ThreadEventsCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let result = match self.state.fields {
+            b"0" => ctx.set_thread_events(false),
+            b"1" => ctx.set_thread_events(true),
+            _ => return Err(Errors::BadNumber),
+        };
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * Qbtrace:bts
+ * Qbtrace:off
+ */
+#[cfg(feature = "btrace")]
+#[derive(Debug, PartialEq)]
+pub struct BtraceCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+#[cfg(feature = "btrace")]
+impl<'a> BtraceCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+#[cfg(feature = "btrace")]
+impl Command for BtraceCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let result = match self.state.fields {
+            b"bts" => ctx.set_btrace(true),
+            b"off" => ctx.set_btrace(false),
+            // other formats such as pt are not supported
+            _ => Err(Errors::Unsupported),
+        };
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QPassSignals:sig;sig;...
+ * QProgramSignals:sig;sig;...
+ * each packet replaces the previous list
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SignalsCommand<'a> {
+    state: ResponseWriter<'a>,
+    program: bool,
+}
+
+impl<'a> SignalsCommand<'a> {
+    pub fn new(args: &'a [u8], program: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            program,
+        }
+    }
+}
+
+impl Command for SignalsCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let signals = SignalSet::parse(self.state.fields)?;
+        let result = match self.program {
+            true => ctx.set_program_signals(&signals),
+            false => ctx.set_pass_signals(&signals),
+        };
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QCatchSyscalls:0
+ * QCatchSyscalls:1[;sysno;sysno...]
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct CatchSyscallsCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> CatchSyscallsCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for CatchSyscallsCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let catch = match self.state.fields {
+            b"0" => CatchSyscalls::Disabled,
+            b"1" => CatchSyscalls::All,
+            fields => match fields.strip_prefix(b"1;") {
+                Some(list) => CatchSyscalls::Only(SyscallList::parse(list)?),
+                None => return Err(Errors::BadNumber),
+            },
+        };
+        match ctx.catch_syscalls(catch) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * vStopped
+ * gdb repeats it until all queued stop replies are fetched
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct StoppedCommand<'a> {
+    state: ResponseWriter<'a>,
+    drained: bool,
+}
+
+impl<'a> StoppedCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            drained: false,
+        }
+    }
+
+    /// true once it was answered with OK because no stop was left
+    pub fn drained(&self) -> bool {
+        self.drained
+    }
+}
+
+impl StoppedCommand<'_> {
+    /// answers with stop or OK once all stops were fetched
+    /// GdbStub passes the stops it queued first,
+    /// ? in non-stop mode is answered the same way
+    pub fn reply(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+        stop: Option<StopReason>,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        self.drained = stop.is_none();
+        match stop {
+            Some(reason) => reason.write(ctx, stream)?,
+            None => self.state.ok(stream)?,
+        };
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+impl Command for StoppedCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        let stop = ctx.next_stop();
+        self.reply(stream, ctx, stop)
+    }
+}
+
+/**
+ * !
+ * the mode switch itself happens in Session::apply
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ExtendedModeCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ExtendedModeCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ExtendedModeCommand<'_> {
+    fn response(
+        &mut self,
+        stream: &mut dyn Stream,
+        _ctx: &mut dyn Target,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        self.state.ok(stream)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * vRun;filename;arg...
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct RunCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> RunCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for RunCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        match ctx.run(&Argv::new(self.state.fields)) {
+            // the new program stops right away
+            Ok(_) => {
+                self.state.start(stream)?;
+                write_stop_reply(stream, ctx)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * A arglen,argnum,arg,...
+ * arglen is the length of the hex encoded arg
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ArgumentsCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ArgumentsCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+
+    /// decodes every argument into the target's buffers
+    fn decode(&self, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        let mut parser = Parser::new(self.state.fields);
+        let mut argc = 0;
+        while let Some(len) = parser.next_token() {
+            let len = Parser::from_hexu(len).ok_or(Errors::BadNumber)?;
+            let index = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+            let index = Parser::from_hexu(index).ok_or(Errors::BadNumber)?;
+            let arg = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+            if arg.len() != len {
+                return Err(Errors::LengthMismatch);
+            }
+
+            let out = ctx
+                .arg_buffer(index, len / 2)
+                .ok_or(Errors::AddressOutOfRange)?;
+            if out.len() != len / 2 || Parser::from_hex_bytes(arg, out) != Some(len / 2) {
+                return Err(Errors::BadNumber);
+            }
+            argc += 1;
+        }
+        Ok(argc)
+    }
+}
+
+impl Command for ArgumentsCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let mut argc = 0;
+        while parser.next_token().is_some() {
+            argc += 1;
+        }
+
+        let result = match ctx.set_argc(argc / 3) {
+            Ok(_) => self.decode(ctx),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/// maximum length of a decoded name=value environment entry
+pub const ENVIRONMENT_LEN: usize = 256;
+
+/// the QEnvironment packet variants
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EnvironmentOp {
+    Set,
+    Unset,
+    Reset,
+}
+
+/**
+ * QEnvironmentHexEncoded:hex(name=value)
+ * QEnvironmentUnset:hex(name)
+ * QEnvironmentReset
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct EnvironmentCommand<'a> {
+    state: ResponseWriter<'a>,
+    op: EnvironmentOp,
+}
+
+impl<'a> EnvironmentCommand<'a> {
+    pub fn new(args: &'a [u8], op: EnvironmentOp) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            op,
+        }
+    }
+}
+
+impl Command for EnvironmentCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut buffer = [0; ENVIRONMENT_LEN];
+        let len = match self.op {
+            EnvironmentOp::Reset => 0,
+            _ => Parser::from_hex_bytes(self.state.fields, &mut buffer).ok_or(Errors::BadNumber)?,
+        };
+        let entry = &buffer[..len];
+
+        let result = match self.op {
+            EnvironmentOp::Set => match entry.iter().position(|b| *b == b'=') {
+                Some(i) => ctx.set_env(&entry[..i], &entry[i + 1..]),
+                None => ctx.set_env(entry, &[]),
+            },
+            EnvironmentOp::Unset => ctx.unset_env(entry),
+            EnvironmentOp::Reset => ctx.reset_env(),
+        };
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * vAttach;pid
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct AttachCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> AttachCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for AttachCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        if self.state.fields.is_empty() {
+            return Err(Errors::InsufficientArguments);
+        }
+        let pid = Parser::from_hexu(self.state.fields).ok_or(Errors::BadNumber)?;
+
+        match ctx.attach(pid) {
+            // the attached process is reported as stopped
+            Ok(_) => {
+                self.state.start(stream)?;
+                write_stop_reply(stream, ctx)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(feature = "reverse")]
+/// runs f on the target's recording and writes the stop reply
+fn reverse(
+    state: &mut ResponseWriter,
+    stream: &mut dyn Stream,
+    ctx: &mut dyn Target,
+    f: fn(&mut dyn Replay) -> Result<ReplayStop, Errors>,
+) -> Result<usize, Errors> {
+    let result = match ctx.replay() {
+        Some(replay) => f(replay),
+        None => return state.empty(stream),
+    };
+
+    state.start(stream)?;
+    match result {
+        Ok(stop) => match stop.reply() {
+            Some(reply) => state.write_all(stream, reply)?,
+            None => write_stop_reply(stream, ctx)?,
+        },
+        Err(err) => state.error(stream, err)?,
+    };
+    state.end(stream)
+}
+
+/**
+ * bc
+ */
+#[cfg(feature = "reverse")]
+#[derive(Debug, PartialEq)]
+pub struct ReverseContinueCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+#[cfg(feature = "reverse")]
+impl<'a> ReverseContinueCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+#[cfg(feature = "reverse")]
+impl Command for ReverseContinueCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        reverse(&mut self.state, stream, ctx, |r| r.reverse_continue())?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * bs
+ */
+#[cfg(feature = "reverse")]
+#[derive(Debug, PartialEq)]
+pub struct ReverseStepCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+#[cfg(feature = "reverse")]
+impl<'a> ReverseStepCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+#[cfg(feature = "reverse")]
+impl Command for ReverseStepCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        reverse(&mut self.state, stream, ctx, |r| r.reverse_step())?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * c[addr], C sig[;addr]
+ * s[addr], S sig[;addr]
+ * vCont;action[:thread]...
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ResumeCommand<'a> {
+    state: ResponseWriter<'a>,
+    kind: Option<ResumeKind>,
+    signal: bool,
+}
+
+impl<'a> ResumeCommand<'a> {
+    /// c, C, s and S, signal is true for the upper case variants
+    pub fn new(args: &'a [u8], kind: ResumeKind, signal: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            kind: Some(kind),
+            signal,
+        }
+    }
+
+    pub fn vcont(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            kind: None,
+            signal: false,
+        }
+    }
+
+    /// the requested actions
+    pub fn parse(&self) -> Result<ResumeActions<'a>, Errors> {
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None => return ResumeActions::parse_vcont(self.state.fields).ok_or(Errors::BadNumber),
+        };
+
+        let mut fields = self.state.fields;
+        let mut action = ResumeAction::new(kind);
+        if self.signal {
+            let mut parts = fields.splitn(2, |b| *b == b';');
+            let signal = Parser::from_hexu(parts.next().unwrap_or(&[])).ok_or(Errors::BadNumber)?;
+            action = action.with_signal(u8::try_from(signal).map_err(|_| Errors::BadNumber)?);
+            fields = parts.next().unwrap_or(&[]);
+        }
+        let addr = match fields {
+            b"" => None,
+            addr => Some(Parser::from_hexu(addr).ok_or(Errors::BadNumber)?),
+        };
+        Ok(ResumeActions::single(action, addr))
+    }
+
+    /// resumes the target and writes the stop reply once it halted
+    /// input is read while the target runs to catch Ctrl-C
+    /// console output of the running target is written to console
+    pub fn run(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+        input: &mut InputPoll,
+        console: &mut dyn Stream,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+
+        let actions = self.parse()?;
+        let result = resume::run_until_halt(ctx, &actions, input, console);
+        write_halt(stream, ctx, result)
+    }
+
+    /// starts the target and returns right away
+    /// returns true once the stop reply was written, false while the target runs
+    /// the caller polls the target and writes the stop reply with write_halt
+    pub fn start(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<bool, Errors> {
+        stream.reset();
+
+        let actions = self.parse()?;
+        let result = match ctx.execution() {
+            Some(execution) => execution.start(&actions),
+            None => Err(Errors::Unsupported),
+        };
+        match result {
+            Ok(None) => Ok(false),
+            Ok(Some(reason)) => write_halt(stream, ctx, Ok(reason)).map(|_| true),
+            Err(err) => write_halt(stream, ctx, Err(err)).map(|_| true),
+        }
+    }
+
+    /// starts the target in non-stop mode and answers with OK right away
+    /// returns true while the target runs, the stop of a target
+    /// that halted at once is queued and reported with %Stop later
+    pub fn start_non_stop(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+        stops: &mut StopQueue,
+    ) -> Result<bool, Errors> {
+        stream.reset();
+
+        let actions = self.parse()?;
+        let result = match ctx.execution() {
+            Some(execution) => execution.start(&actions),
+            None => Err(Errors::Unsupported),
+        };
+        let running = match result {
+            Ok(None) => true,
+            Ok(Some(reason)) => {
+                stops.push(reason)?;
+                false
+            }
+            Err(err) => return write_halt(stream, ctx, Err(err)).map(|_| false),
+        };
+        self.state.start(stream)?;
+        self.state.ok(stream)?;
+        self.state.end(stream)?;
+        Ok(running)
+    }
+}
+
+/// writes the reply to a resume once the target halted or failed to run
+pub fn write_halt(
+    stream: &mut dyn Stream,
+    ctx: &mut dyn Target,
+    result: Result<StopReason, Errors>,
+) -> Result<usize, Errors> {
+    let mut state = ResponseWriter::new(&[]);
+    match result {
+        Ok(reason) => {
+            state.start(stream)?;
+            reason.write(ctx, stream)?;
+            state.end(stream)
+        }
+        Err(Errors::Unsupported) => state.empty(stream),
+        Err(err) => {
+            state.start(stream)?;
+            state.error(stream, err)?;
+            state.end(stream)
+        }
+    }?;
+    Ok(stream.pos())
+}
+
+impl Command for ResumeCommand<'_> {
+    /// the command cannot read input and drops console output,
+    /// GdbStub calls run with its connection
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        let mut discard = |_: &[u8]| Ok(());
+        let mut console = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut discard);
+        self.run(stream, ctx, &mut || Ok(None), &mut console)
+    }
+}
+
+/**
+ * vCont?
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct VContSupportedCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> VContSupportedCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for VContSupportedCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let execution = match ctx.execution() {
+            Some(execution) => execution,
+            None => return self.state.empty(stream),
+        };
+
+        self.state.start(stream)?;
+        stream.write_all(b"vCont;c;C")?;
+        if execution.single_step() {
+            stream.write_all(b";s;S")?;
+        }
+        if execution.range_step() {
+            stream.write_all(b";r")?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * i[addr[,nnn]]
+ * I[sig[;addr[,nnn]]]
+ * steps nnn clock cycles, one by default
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct CycleStepCommand<'a> {
+    state: ResponseWriter<'a>,
+    signal: bool,
+}
+
+impl<'a> CycleStepCommand<'a> {
+    pub fn new(args: &'a [u8], signal: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            signal,
+        }
+    }
+
+    fn parse(&self) -> Result<(Option<u8>, Option<usize>, usize), Errors> {
+        let mut fields = self.state.fields;
+        let mut signal = None;
+        if self.signal {
+            let mut parts = fields.splitn(2, |b| *b == b';');
+            signal = match parts.next() {
+                Some(b"") | None => None,
+                Some(sig) => Some(Parser::from_hexu(sig).ok_or(Errors::BadNumber)? as u8),
+            };
+            fields = parts.next().unwrap_or(&[]);
+        }
+
+        let mut parts = fields.splitn(2, |b| *b == b',');
+        let addr = match parts.next() {
+            Some(b"") | None => None,
+            Some(addr) => Some(Parser::from_hexu(addr).ok_or(Errors::BadNumber)?),
+        };
+        let cycles = match parts.next() {
+            Some(cycles) if !cycles.is_empty() => {
+                Parser::from_hexu(cycles).ok_or(Errors::BadNumber)?
+            }
+            Some(_) => return Err(Errors::BadNumber),
+            None => 1,
+        };
+        Ok((signal, addr, cycles))
+    }
+}
+
+impl Command for CycleStepCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let (signal, addr, cycles) = self.parse()?;
+        match ctx.cycle_step(signal, addr, cycles) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                write_stop_reply(stream, ctx)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * vKill;pid
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct KillCommand<'a> {
+    state: ResponseWriter<'a>,
+    killed: bool,
+}
+
+impl<'a> KillCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            killed: false,
+        }
+    }
+
+    /// true once the target killed the process
+    pub fn killed(&self) -> bool {
+        self.killed
+    }
+}
+
+impl Command for KillCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        if self.state.fields.is_empty() {
+            return Err(Errors::InsufficientArguments);
+        }
+        let pid = Parser::from_hexu(self.state.fields).ok_or(Errors::BadNumber)?;
+
+        match ctx.kill(pid) {
+            Ok(_) => {
+                self.killed = true;
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * 0x03
+ * sent outside of a packet while the target runs
+ * gdb expects the stop reply once the target halted
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct InterruptCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> InterruptCommand<'a> {
+    pub fn new() -> Self {
+        Self {
+            state: ResponseWriter::new(&[]),
+        }
+    }
+}
+
+impl<'a> Default for InterruptCommand<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Command for InterruptCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        match ctx.interrupt() {
+            // a target without the hook is already halted
+            Ok(_) | Err(Errors::Unsupported) => write_stop_reply(stream, ctx)?,
+            Err(err) => self.state.error(stream, err)?,
+        };
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * vCtrlC
+ * the non-stop mode interrupt, the stop is reported as notification
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct CtrlCCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> CtrlCCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for CtrlCCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        match ctx.interrupt() {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * R XX
+ * only valid in extended mode, see Session::apply
+ * there is no reply
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct RestartCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> RestartCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for RestartCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        ctx.restart()?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * Fretcode,errno,C
+ * gdb's reply to a File-I/O request
+ */
+#[cfg(feature = "fileio")]
+#[derive(Debug, PartialEq)]
+pub struct FileIoReplyCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+#[cfg(feature = "fileio")]
+impl<'a> FileIoReplyCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+#[cfg(feature = "fileio")]
+impl Command for FileIoReplyCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        if ctx.file_io_request().is_none() {
+            return Err(Errors::CommandError);
+        }
+        let reply = FileIoReply::parse(self.state.fields)?;
+
+        // the target either runs until it stops again
+        // or reports SIGINT if the call was interrupted
+        ctx.file_io_reply(reply)?;
+        self.state.start(stream)?;
+        write_stop_reply(stream, ctx)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::{exec, TestCommands};
+    use crate::features::GdbFeatures;
+    #[cfg(feature = "fileio")]
+    use crate::fileio::FileIoCall;
+    use crate::host::ExitStatus;
+    use crate::resume::Execution;
+    use crate::stop::{ProcessEvent, StopReason, StopReply, ThreadExit};
+    use crate::stream::BufferedStream;
+    use crate::syscall::SyscallStop;
+    use crate::target::VirtualTarget;
+    use crate::thread::ThreadId;
+
+    #[test]
+    fn it_should_detach() {
+        let mut target = VirtualTarget::new();
+        let mut parser = Parser::new(b"$D#44");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+    }
+
+    struct DetachTarget {
+        detached: Option<Option<usize>>,
+    }
+
+    impl Target for DetachTarget {
+        fn detach(&mut self, pid: Option<usize>) -> Result<usize, Errors> {
+            self.detached = Some(pid);
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_detach_processes() {
+        let mut target = DetachTarget { detached: None };
+        assert_eq!(exec(b"$D;2a#12", &mut target), b"$OK#9a");
+        assert_eq!(target.detached, Some(Some(0x2a)));
+
+        exec(b"$D#44", &mut target);
+        assert_eq!(target.detached, Some(None));
+    }
+
+    struct NonStopTarget {
+        non_stop: bool,
+        stops: &'static [StopReason],
+    }
+
+    impl Target for NonStopTarget {
+        fn set_non_stop(&mut self, enabled: bool) -> Result<usize, Errors> {
+            self.non_stop = enabled;
+            Ok(0)
+        }
+
+        fn next_stop(&mut self) -> Option<StopReason> {
+            let (first, rest) = self.stops.split_first()?;
+            self.stops = rest;
+            Some(*first)
+        }
+    }
+
+    #[test]
+    fn it_should_switch_to_non_stop() {
+        let mut target = NonStopTarget {
+            non_stop: false,
+            stops: &[],
+        };
+
+        assert_eq!(exec(b"$QNonStop:1#8d", &mut target), b"$OK#9a");
+        assert!(target.non_stop);
+
+        exec(b"$QNonStop:0#8c", &mut target);
+        assert!(!target.non_stop);
+
+        assert_eq!(exec(b"$QNonStop:1#8d", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[test]
+    fn it_should_iterate_stop_replies() {
+        const STOPS: [StopReason; 2] = [
+            StopReason::Stop(StopReply::new(5).with_thread(2)),
+            StopReason::Stop(StopReply::new(0x13).with_thread(3)),
+        ];
+        let mut target = NonStopTarget {
+            non_stop: true,
+            stops: &STOPS,
+        };
+
+        assert_eq!(exec(b"$vStopped#55", &mut target), b"$T05thread:2;#d8");
+        assert_eq!(exec(b"$vStopped#55", &mut target), b"$T13thread:3;#d8");
+        assert_eq!(exec(b"$vStopped#55", &mut target), b"$OK#9a");
+    }
+
+    #[test]
+    fn it_should_enter_extended_mode() {
+        assert_eq!(exec(b"$!#21", &mut VirtualTarget::new()), b"$OK#9a");
+    }
+
+    #[test]
+    fn it_should_run_programs() {
+        struct RunTarget {
+            runs: usize,
+            arg: [u8; 8],
+        }
+
+        impl Target for RunTarget {
+            fn run(&mut self, argv: &Argv) -> Result<usize, Errors> {
+                if argv.len() != 3 || argv.get(0) != Some(&b""[..]) {
+                    return Err(Errors::CommandError);
+                }
+                argv.decode(2, &mut self.arg).ok_or(Errors::BadNumber)?;
+                self.runs += 1;
+                Ok(0)
+            }
+        }
+
+        let mut target = RunTarget {
+            runs: 0,
+            arg: [0; 8],
+        };
+        assert_eq!(exec(b"$vRun;;2d76;6869#3c", &mut target), b"$S05#b8");
+        assert_eq!(target.runs, 1);
+        assert_eq!(target.arg[..2], *b"hi");
+
+        assert_eq!(exec(b"$vRun;6100#ad", &mut target), b"$E00#a5");
+
+        // targets that can only reset restart by default
+        assert_eq!(exec(b"$vRun;#e6", &mut VirtualTarget::new()), b"$S05#b8");
+    }
+
+    #[test]
+    fn it_should_attach_to_processes() {
+        struct AttachTarget(Option<usize>);
+        impl Target for AttachTarget {
+            fn attach(&mut self, pid: usize) -> Result<usize, Errors> {
+                match pid {
+                    0x2a => {
+                        self.0 = Some(pid);
+                        Ok(0)
+                    }
+                    _ => Err(Errors::CommandError),
+                }
+            }
+
+            fn stop_reason(&self) -> StopReason {
+                StopReason::Stop(StopReply::new(0x13).with_thread(0x2a))
+            }
+        }
+
+        let mut target = AttachTarget(None);
+        assert_eq!(exec(b"$vAttach;2a#99", &mut target), b"$T13thread:2a;#38");
+        assert_eq!(target.0, Some(0x2a));
+
+        assert_eq!(exec(b"$vAttach;2b#9a", &mut target), b"$E00#a5");
+
+        assert_eq!(exec(b"$vAttach;2a#99", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[test]
+    fn it_should_restart_silently() {
+        let mut target = VirtualTarget::new();
+        target.wr_memory(0x10, b"ab").unwrap();
+
+        assert!(exec(b"$R00#b2", &mut target).is_empty());
+
+        let mut stream = BufferedStream::new();
+        target.rd_memory(0x10, 1, &mut stream).unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"00"[..]);
+    }
+
+    #[cfg(feature = "fileio")]
+    struct SemihostTarget {
+        pending: Option<FileIoCall>,
+        written: isize,
+        reason: StopReason,
+    }
+
+    #[cfg(feature = "fileio")]
+    impl Target for SemihostTarget {
+        fn stop_reason(&self) -> StopReason {
+            self.reason
+        }
+
+        fn file_io_request(&self) -> Option<FileIoCall> {
+            self.pending
+        }
+
+        fn file_io_reply(&mut self, reply: FileIoReply) -> Result<usize, Errors> {
+            self.pending = None;
+            self.written = reply.result;
+            self.reason = StopReason::Signal(if reply.interrupted { 2 } else { 5 });
+            Ok(0)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fileio")]
+    fn it_should_forward_file_io_requests() {
+        let mut target = SemihostTarget {
+            pending: Some(FileIoCall::Write {
+                fd: 1,
+                buf: 0x100,
+                count: 5,
+            }),
+            written: 0,
+            reason: StopReason::Signal(5),
+        };
+
+        assert_eq!(exec(b"$?#3f", &mut target), b"$Fwrite,1,100,5#ec");
+
+        assert_eq!(exec(b"$F5#7b", &mut target), b"$S05#b8");
+        assert_eq!(target.written, 5);
+
+        // replies without a request are dropped
+        assert_eq!(
+            Parser::new(b"$F5#7b")
+                .parse_packet(&TestCommands)
+                .command
+                .unwrap()
+                .response(&mut BufferedStream::new(), &mut target),
+            Err(Errors::CommandError)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fileio")]
+    fn it_should_stop_on_interrupted_file_io() {
+        let mut target = SemihostTarget {
+            pending: Some(FileIoCall::Read {
+                fd: 0,
+                buf: 0x100,
+                count: 0x10,
+            }),
+            written: 0,
+            reason: StopReason::Signal(5),
+        };
+
+        assert_eq!(exec(b"$F-1,4,C#73", &mut target), b"$S02#b5");
+        assert_eq!(target.written, -1);
+    }
+
+    #[cfg(feature = "reverse")]
+    struct Recording {
+        pos: usize,
+    }
+
+    #[cfg(feature = "reverse")]
+    impl Replay for Recording {
+        fn reverse_continue(&mut self) -> Result<ReplayStop, Errors> {
+            self.pos = 0;
+            Ok(ReplayStop::Begin)
+        }
+
+        fn reverse_step(&mut self) -> Result<ReplayStop, Errors> {
+            match self.pos {
+                0 => Ok(ReplayStop::Begin),
+                _ => {
+                    self.pos -= 1;
+                    Ok(ReplayStop::Stopped)
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "reverse")]
+    struct ReplayTarget {
+        recording: Recording,
+    }
+
+    #[cfg(feature = "reverse")]
+    impl Target for ReplayTarget {
+        fn replay(&mut self) -> Option<&mut dyn Replay> {
+            Some(&mut self.recording)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "reverse")]
+    fn it_should_step_backwards() {
+        let mut target = ReplayTarget {
+            recording: Recording { pos: 1 },
+        };
+
+        assert_eq!(exec(b"$bs#d5", &mut target), b"$S05#b8");
+        assert_eq!(target.recording.pos, 0);
+
+        let stream = exec(b"$bs#d5", &mut target);
+        assert_eq!(stream[..], b"$T05replaylog:begin;#02"[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "reverse")]
+    fn it_should_continue_backwards() {
+        let mut target = ReplayTarget {
+            recording: Recording { pos: 10 },
+        };
+
+        let stream = exec(b"$bc#c5", &mut target);
+        assert_eq!(stream[..], b"$T05replaylog:begin;#02"[..]);
+        assert_eq!(target.recording.pos, 0);
+
+        assert_eq!(exec(b"$bc#c5", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[derive(Default)]
+    struct SignalTarget {
+        pass: SignalSet,
+        program: SignalSet,
+    }
+
+    impl Target for SignalTarget {
+        fn set_pass_signals(&mut self, signals: &SignalSet) -> Result<usize, Errors> {
+            self.pass = *signals;
+            Ok(0)
+        }
+
+        fn set_program_signals(&mut self, signals: &SignalSet) -> Result<usize, Errors> {
+            self.program = *signals;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_set_signal_filters() {
+        let mut target = SignalTarget::default();
+
+        assert_eq!(exec(b"$QPassSignals:e;14#f8", &mut target), b"$OK#9a");
+        assert!(target.pass.contains(0x0e));
+        assert!(target.pass.contains(0x14));
+        assert!(target.program.is_empty());
+
+        exec(b"$QProgramSignals:2#66", &mut target);
+        assert!(target.program.contains(2));
+
+        // an empty list clears the filter
+        exec(b"$QPassSignals:#f3", &mut target);
+        assert!(target.pass.is_empty());
+
+        assert_eq!(
+            exec(b"$QPassSignals:e#58", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    #[derive(Default)]
+    struct SyscallTarget {
+        catching: Option<(bool, bool)>,
+        stop: Option<SyscallStop>,
+    }
+
+    impl Target for SyscallTarget {
+        fn catch_syscalls(&mut self, catch: CatchSyscalls) -> Result<usize, Errors> {
+            self.catching = match catch {
+                CatchSyscalls::Disabled => None,
+                catch => Some((catch.catches(0x3f), catch.catches(0x01))),
+            };
+            Ok(0)
+        }
+
+        fn syscall_stop(&self) -> Option<SyscallStop> {
+            self.stop
+        }
+    }
+
+    #[test]
+    fn it_should_catch_syscalls() {
+        let mut target = SyscallTarget::default();
+
+        assert_eq!(exec(b"$QCatchSyscalls:1;3f;e7#98", &mut target), b"$OK#9a");
+        assert_eq!(target.catching, Some((true, false)));
+
+        exec(b"$QCatchSyscalls:1#ed", &mut target);
+        assert_eq!(target.catching, Some((true, true)));
+
+        exec(b"$QCatchSyscalls:0#ec", &mut target);
+        assert_eq!(target.catching, None);
+
+        target.stop = Some(SyscallStop::Entry(0x3f));
+        let stream = exec(b"$?#3f", &mut target);
+        assert_eq!(stream[..], b"$T05syscall_entry:3f;#53"[..]);
+
+        assert_eq!(
+            exec(b"$QCatchSyscalls:1#ed", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    #[derive(Default)]
+    struct ArgsTarget {
+        argc: usize,
+        args: [[u8; 4]; 2],
+        lens: [usize; 2],
+    }
+
+    impl Target for ArgsTarget {
+        fn set_argc(&mut self, argc: usize) -> Result<usize, Errors> {
+            self.argc = argc;
+            Ok(0)
+        }
+
+        fn arg_buffer(&mut self, index: usize, len: usize) -> Option<&mut [u8]> {
+            *self.lens.get_mut(index)? = len;
+            self.args.get_mut(index)?.get_mut(..len)
+        }
+    }
+
+    #[test]
+    fn it_should_set_program_arguments() {
+        let mut target = ArgsTarget::default();
+
+        assert_eq!(exec(b"$A4,0,2d76,6,1,616263#23", &mut target), b"$OK#9a");
+        assert_eq!(target.argc, 2);
+        assert_eq!(target.args[0][..target.lens[0]], b"-v"[..]);
+        assert_eq!(target.args[1][..target.lens[1]], b"abc"[..]);
+
+        // arglen has to match the encoded argument
+        assert_eq!(exec(b"$A6,0,2d76#02", &mut target), b"$E00#a5");
+
+        assert_eq!(exec(b"$A4,0,2d76#00", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[derive(Default)]
+    struct EnvTarget {
+        name: [u8; 4],
+        value: [u8; 4],
+        entries: usize,
+    }
+
+    impl Target for EnvTarget {
+        fn set_env(&mut self, name: &[u8], value: &[u8]) -> Result<usize, Errors> {
+            self.name[..name.len()].copy_from_slice(name);
+            self.value[..value.len()].copy_from_slice(value);
+            self.entries += 1;
+            Ok(0)
+        }
+
+        fn unset_env(&mut self, name: &[u8]) -> Result<usize, Errors> {
+            match &self.name[..name.len()] == name {
+                true => {
+                    self.entries -= 1;
+                    Ok(0)
+                }
+                false => Err(Errors::BadNumber),
+            }
+        }
+
+        fn reset_env(&mut self) -> Result<usize, Errors> {
+            self.entries = 0;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_set_the_environment() {
+        let mut target = EnvTarget::default();
+
+        // HOME=/a
+        assert_eq!(
+            exec(b"$QEnvironmentHexEncoded:484f4d453d2f61#94", &mut target),
+            b"$OK#9a"
+        );
+        assert_eq!(&target.name, b"HOME");
+        assert_eq!(&target.value[..2], b"/a");
+
+        exec(b"$QEnvironmentUnset:484f4d45#36", &mut target);
+        assert_eq!(target.entries, 0);
+
+        exec(b"$QEnvironmentHexEncoded:484f4d453d2f61#94", &mut target);
+        exec(b"$QEnvironmentReset#e9", &mut target);
+        assert_eq!(target.entries, 0);
+
+        assert_eq!(
+            exec(b"$QEnvironmentReset#e9", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    struct ResumeTarget {
+        actions: Option<(Option<ResumeAction>, Option<usize>)>,
+        threads: [Option<ResumeKind>; 2],
+    }
+
+    impl Target for ResumeTarget {
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+    }
+
+    impl Execution for ResumeTarget {
+        fn resume(&mut self, actions: &ResumeActions) -> Result<StopReason, Errors> {
+            self.actions = Some((actions.iter().next(), actions.addr));
+            for (tid, kind) in self.threads.iter_mut().enumerate() {
+                *kind = actions.action_for(ThreadId::new(tid + 1)).map(|a| a.kind);
+            }
+            match actions.iter().next().map(|a| a.kind) {
+                Some(ResumeKind::Step) => Ok(StopReason::Signal(5)),
+                _ => Ok(StopReason::Exited { code: 0 }),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_resume() {
+        let mut target = ResumeTarget {
+            actions: None,
+            threads: [None; 2],
+        };
+        assert_eq!(exec(b"$s#73", &mut target), b"$S05#b8");
+        assert_eq!(
+            target.actions,
+            Some((Some(ResumeAction::new(ResumeKind::Step)), None))
+        );
+
+        exec(b"$s100#04", &mut target);
+        assert_eq!(target.actions.unwrap().1, Some(0x100));
+
+        assert_eq!(exec(b"$c#63", &mut target), b"$W00#b7");
+
+        exec(b"$C0b;200#a2", &mut target);
+        assert_eq!(
+            target.actions,
+            Some((
+                Some(ResumeAction::new(ResumeKind::Continue).with_signal(0xb)),
+                Some(0x200)
+            ))
+        );
+        exec(b"$S02#b5", &mut target);
+        assert_eq!(
+            target.actions,
+            Some((
+                Some(ResumeAction::new(ResumeKind::Step).with_signal(2)),
+                None
+            ))
+        );
+
+        assert_eq!(exec(b"$s#73", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[test]
+    fn it_should_resume_with_vcont() {
+        let mut target = ResumeTarget {
+            actions: None,
+            threads: [None; 2],
+        };
+        assert_eq!(exec(b"$vCont?#49", &mut target), b"$vCont;c;C;s;S#62");
+
+        assert_eq!(exec(b"$vCont;s:2;c#c2", &mut target), b"$S05#b8");
+        assert_eq!(
+            target.threads,
+            [Some(ResumeKind::Continue), Some(ResumeKind::Step)]
+        );
+
+        exec(b"$vCont;c:1#13", &mut target);
+        assert_eq!(target.threads, [Some(ResumeKind::Continue), None]);
+
+        assert_eq!(exec(b"$vCont?#49", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    struct CycleTarget {
+        step: Option<(Option<u8>, Option<usize>, usize)>,
+    }
+
+    impl Target for CycleTarget {
+        fn cycle_step(
+            &mut self,
+            signal: Option<u8>,
+            addr: Option<usize>,
+            cycles: usize,
+        ) -> Result<usize, Errors> {
+            self.step = Some((signal, addr, cycles));
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_step_cycles() {
+        let mut target = CycleTarget { step: None };
+        assert_eq!(exec(b"$i#69", &mut target), b"$S05#b8");
+        assert_eq!(target.step, Some((None, None, 1)));
+
+        exec(b"$i100,10#87", &mut target);
+        assert_eq!(target.step, Some((None, Some(0x100), 0x10)));
+
+        exec(b"$I02;100#77", &mut target);
+        assert_eq!(target.step, Some((Some(2), Some(0x100), 1)));
+
+        exec(b"$I#49", &mut target);
+        assert_eq!(target.step, Some((None, None, 1)));
+
+        assert_eq!(exec(b"$i#69", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[derive(Default)]
+    struct NoResumedTarget {
+        features: GdbFeatures,
+    }
+
+    impl Target for NoResumedTarget {
+        fn set_gdb_features(&mut self, features: GdbFeatures) {
+            self.features = features;
+        }
+
+        fn no_resumed(&self) -> bool {
+            self.features.no_resumed
+        }
+    }
+
+    #[test]
+    fn it_should_report_no_resumed_threads() {
+        let mut target = NoResumedTarget::default();
+        assert_eq!(exec(b"$?#3f", &mut target), b"$S05#b8");
+
+        exec(b"$qSupported:no-resumed+#9b", &mut target);
+        assert_eq!(exec(b"$?#3f", &mut target), b"$N#4e");
+    }
+
+    struct ThreadEventsTarget {
+        enabled: bool,
+    }
+
+    impl Target for ThreadEventsTarget {
+        fn set_thread_events(&mut self, enabled: bool) -> Result<usize, Errors> {
+            self.enabled = enabled;
+            Ok(0)
+        }
+
+        fn thread_exit(&self) -> Option<ThreadExit> {
+            self.enabled.then_some(ThreadExit {
+                thread: ThreadId::new(2),
+                status: 0,
+            })
+        }
+    }
+
+    #[test]
+    fn it_should_report_thread_events() {
+        let mut target = ThreadEventsTarget { enabled: false };
+        assert_eq!(exec(b"$QThreadEvents:1#89", &mut target), b"$OK#9a");
+        assert!(target.enabled);
+
+        assert_eq!(exec(b"$?#3f", &mut target), b"$w00;2#44");
+
+        assert_eq!(
+            exec(b"$QThreadEvents:1#89", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    #[cfg(feature = "btrace")]
+    struct BtraceTarget {
+        enabled: bool,
+    }
+
+    #[cfg(feature = "btrace")]
+    impl Target for BtraceTarget {
+        fn set_btrace(&mut self, enabled: bool) -> Result<usize, Errors> {
+            self.enabled = enabled;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "btrace")]
+    fn it_should_toggle_branch_tracing() {
+        let mut target = BtraceTarget { enabled: false };
+        assert_eq!(exec(b"$Qbtrace:bts#45", &mut target), b"$OK#9a");
+        assert!(target.enabled);
+
+        exec(b"$Qbtrace:off#37", &mut target);
+        assert!(!target.enabled);
+
+        assert_eq!(exec(b"$Qbtrace:pt#e0", &mut target), b"$#00");
+    }
+
+    struct KillTarget {
+        killed: Option<usize>,
+    }
+
+    impl Target for KillTarget {
+        fn kill(&mut self, pid: usize) -> Result<usize, Errors> {
+            match pid {
+                0x2a => {
+                    self.killed = Some(pid);
+                    Ok(0)
+                }
+                _ => Err(Errors::BadNumber),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_kill_processes() {
+        let mut target = KillTarget { killed: None };
+
+        assert_eq!(exec(b"$vKill;2a#d0", &mut target), b"$OK#9a");
+        assert_eq!(target.killed, Some(0x2a));
+
+        assert_eq!(exec(b"$vKill;2b#d1", &mut target), b"$E00#a5");
+
+        assert_eq!(exec(b"$vKill;2a#d0", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    struct InterruptTarget {
+        running: bool,
+    }
+
+    impl Target for InterruptTarget {
+        fn interrupt(&mut self) -> Result<usize, Errors> {
+            self.running = false;
+            Ok(0)
+        }
+
+        fn stop_reason(&self) -> StopReason {
+            StopReason::Signal(2)
+        }
+    }
+
+    #[test]
+    fn it_should_interrupt() {
+        let mut target = InterruptTarget { running: true };
+        assert_eq!(exec(b"\x03", &mut target), b"$S02#b5");
+        assert!(!target.running);
+
+        let mut target = InterruptTarget { running: true };
+        assert_eq!(exec(b"$vCtrlC#4e", &mut target), b"$OK#9a");
+        assert!(!target.running);
+
+        assert_eq!(exec(b"$vCtrlC#4e", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    struct ForkTarget;
+    impl Target for ForkTarget {
+        fn process_event(&self) -> Option<ProcessEvent<'_>> {
+            Some(ProcessEvent::Fork {
+                pid: 0x2b,
+                tid: 0x2b,
+            })
+        }
+    }
+
+    #[test]
+    fn it_should_report_process_events() {
+        assert_eq!(exec(b"$?#3f", &mut ForkTarget), b"$T05fork:p2b.2b;#a6");
+    }
+
+    struct ExitTarget {
+        status: ExitStatus,
+    }
+
+    impl Target for ExitTarget {
+        fn exit_status(&self) -> Option<ExitStatus> {
+            Some(self.status)
+        }
+    }
+
+    #[test]
+    fn it_should_report_exits() {
+        let mut target = ExitTarget {
+            status: ExitStatus::Code(1),
+        };
+        assert_eq!(exec(b"$?#3f", &mut target), b"$W01#b8");
+
+        let mut target = ExitTarget {
+            status: ExitStatus::Signal(0x0b),
+        };
+        assert_eq!(exec(b"$?#3f", &mut target), b"$X0b#ea");
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ * Flash programming
+ */
+
+use crate::command::*;
+use crate::error::Errors;
+use crate::memory::{GuestAddress, MemoryKind, MemoryRegion};
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+
+/// bytes of a vFlashWrite payload that are unescaped at once
+pub const FLASH_WRITE_CHUNK: usize = 64;
+
+/// returns the flash region containing start and its erase block size
+fn flash_region(ctx: &dyn Target, start: GuestAddress) -> Option<(MemoryRegion, usize)> {
+    ctx.memory_map()
+        .iter()
+        .find(|region| region.contains(start))
+        .and_then(|region| match region.kind {
+            MemoryKind::Flash { block_size } => Some((*region, block_size)),
+            _ => None,
+        })
+}
+
+/// only whole blocks inside a single flash region can be erased
+fn erasable(region: &MemoryRegion, block_size: usize, start: usize, len: usize) -> bool {
+    let (block_size, len) = (block_size as GuestAddress, len as GuestAddress);
+    let offset = start as GuestAddress - region.start;
+    offset.checked_rem(block_size) == Some(0)
+        && len.checked_rem(block_size) == Some(0)
+        && len <= region.len - offset
+}
+
+fn reply(
+    state: &mut ResponseWriter,
+    stream: &mut dyn Stream,
+    result: Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    match result {
+        Ok(_) => {
+            state.start(stream)?;
+            state.ok(stream)?;
+            state.end(stream)
+        }
+        Err(Errors::Unsupported) => state.empty(stream),
+        Err(err) => {
+            state.start(stream)?;
+            state.error(stream, err)?;
+            state.end(stream)
+        }
+    }?;
+    Ok(stream.pos())
+}
+
+/**
+ * vFlashErase:addr,length
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct FlashEraseCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> FlashEraseCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for FlashEraseCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let (start, len) = match (parser.next_token(), parser.next_token()) {
+            (Some(start), Some(len)) => (start, len),
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let start = Parser::from_hexu(start).ok_or(Errors::BadNumber)?;
+        let len = Parser::from_hexu(len).ok_or(Errors::BadNumber)?;
+
+        let result = match flash_region(ctx, start as GuestAddress) {
+            Some((region, block_size)) if erasable(&region, block_size, start, len) => ctx
+                .flash()
+                .map_or(Err(Errors::Unsupported), |f| f.erase(start, len)),
+            _ => Err(Errors::AddressOutOfRange),
+        };
+        reply(&mut self.state, stream, result)
+    }
+}
+
+/**
+ * vFlashWrite:addr:XX...
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct FlashWriteCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> FlashWriteCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for FlashWriteCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let start = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+        let start = Parser::from_hexu(start).ok_or(Errors::BadNumber)?;
+        let data = parser.parse_until_end();
+
+        // gdb expects E.memtype for writes outside of flash
+        let region = match flash_region(ctx, start as GuestAddress) {
+            Some((region, _)) => region,
+            None => {
+                self.state.start(stream)?;
+                self.state.write_all(stream, b"E.memtype")?;
+                self.state.end(stream)?;
+                return Ok(stream.pos());
+            }
+        };
+
+        let mut chunk = [0; FLASH_WRITE_CHUNK];
+        let result = Parser::unescape_chunks(data, &mut chunk, &mut |n, data| {
+            let last = start + n + data.len().saturating_sub(1);
+            if !region.contains(last as GuestAddress) {
+                return Err(Errors::AddressOutOfRange);
+            }
+            ctx.flash()
+                .ok_or(Errors::Unsupported)?
+                .write(start + n, data)?;
+            Ok(data.len())
+        });
+        reply(&mut self.state, stream, result)
+    }
+}
+
+/**
+ * vFlashDone
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct FlashDoneCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> FlashDoneCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for FlashDoneCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let result = ctx.flash().map_or(Err(Errors::Unsupported), |f| f.done());
+        reply(&mut self.state, stream, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::respond;
+    use crate::flash::Flash;
+    use crate::stream::BufferedStream;
+
+    const FLASH_MAP: [MemoryRegion; 2] = [
+        MemoryRegion::new(0, 0x40, MemoryKind::Flash { block_size: 0x10 }),
+        MemoryRegion::new(0x100, 0x40, MemoryKind::Ram),
+    ];
+
+    struct FlashTarget {
+        flash: [u8; 0x40],
+        pending: [u8; 0x40],
+        erased: usize,
+        done: bool,
+    }
+
+    impl FlashTarget {
+        fn new() -> Self {
+            Self {
+                flash: [0; 0x40],
+                pending: [0xff; 0x40],
+                erased: 0,
+                done: false,
+            }
+        }
+    }
+
+    impl Target for FlashTarget {
+        fn memory_map(&self) -> &[MemoryRegion] {
+            &FLASH_MAP
+        }
+
+        fn flash(&mut self) -> Option<&mut dyn Flash> {
+            Some(self)
+        }
+    }
+
+    impl Flash for FlashTarget {
+        fn erase(&mut self, start: usize, len: usize) -> Result<usize, Errors> {
+            self.erased += len;
+            self.pending[start..start + len].fill(0xff);
+            Ok(0)
+        }
+
+        fn write(&mut self, start: usize, data: &[u8]) -> Result<usize, Errors> {
+            self.pending[start..start + data.len()].copy_from_slice(data);
+            Ok(0)
+        }
+
+        fn done(&mut self) -> Result<usize, Errors> {
+            self.flash = self.pending;
+            self.done = true;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_erase_whole_blocks() {
+        let mut target = FlashTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$vFlashErase:10,20#7d", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert_eq!(target.erased, 0x20);
+
+        respond(b"$vFlashErase:18,10#84", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+        respond(b"$vFlashErase:30,20#7f", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+        respond(b"$vFlashErase:100,10#ac", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+        assert_eq!(target.erased, 0x20);
+    }
+
+    #[test]
+    fn it_should_write_and_commit_flash() {
+        let mut target = FlashTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$vFlashWrite:4:a}]:b#ee", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert_eq!(target.flash[4..8], [0; 4]);
+
+        respond(b"$vFlashDone#ea", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert!(target.done);
+        assert_eq!(target.flash[4..8], *b"a}:b");
+    }
+
+    #[test]
+    fn it_should_reject_writes_outside_flash() {
+        let mut target = FlashTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$vFlashWrite:100:ab#37", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E.memtype#74"[..]);
+
+        respond(b"$vFlashWrite:3e:abc#a1", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+}
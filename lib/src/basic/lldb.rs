@@ -0,0 +1,751 @@
+/*
+ * LLDB extensions
+ */
+
+use crate::command::*;
+#[cfg(feature = "compression")]
+use crate::compress::{Compression, COMPRESSION_MIN_SIZE};
+use crate::error::Errors;
+use crate::json::JsonWriter;
+use crate::memory::{GuestAddress, MemoryRegion, Permissions};
+use crate::parser::Parser;
+use crate::stream::{EscapeStream, Stream};
+use crate::target::Target;
+use crate::thread::ThreadInfo;
+
+/**
+ * qHostInfo
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct HostInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> HostInfoCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for HostInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let info = match ctx.host_info() {
+            Some(info) => info,
+            None => return self.state.empty(stream),
+        };
+        self.state.start(stream)?;
+        info.write(ctx.endianess(), stream)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qRegisterInfo<regnum>
+ * lldb asks for registers until it receives an error
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct RegisterInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> RegisterInfoCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for RegisterInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let regnum = Parser::from_hexu(self.state.fields).ok_or(Errors::BadNumber)?;
+        let description = match ctx.description() {
+            Some(description) => description,
+            None => return self.state.empty(stream),
+        };
+
+        self.state.start(stream)?;
+        match description.register(regnum) {
+            Some((reg, offset)) => reg.write_lldb(stream, offset)?,
+            None => self.state.error(stream, Errors::AddressOutOfRange)?,
+        };
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qMemoryRegionInfo:addr
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct MemoryRegionInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> MemoryRegionInfoCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+/// returns the region containing addr or the unmapped gap up to the next region
+fn region_at(
+    map: &[MemoryRegion],
+    addr: GuestAddress,
+) -> (GuestAddress, GuestAddress, Option<Permissions>) {
+    if let Some(region) = map.iter().find(|region| region.contains(addr)) {
+        return (region.start, region.len, Some(region.perms));
+    }
+    let end = map
+        .iter()
+        .map(|region| region.start)
+        .filter(|start| *start > addr)
+        .min()
+        .unwrap_or(GuestAddress::MAX);
+    (addr, end - addr, None)
+}
+
+impl Command for MemoryRegionInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let addr = Parser::from_hex_address(self.state.fields).ok_or(Errors::BadNumber)?;
+        let map = ctx.memory_map();
+        if map.is_empty() {
+            return self.state.empty(stream);
+        }
+        let (start, len, perms) = region_at(map, addr);
+
+        self.state.start(stream)?;
+        self.state.write_all(stream, b"start:")?;
+        Parser::to_hex_address(start, stream)?;
+        self.state.write_all(stream, b";size:")?;
+        Parser::to_hex_address(len, stream)?;
+        self.state.write(stream, b';')?;
+        // unmapped gaps are reported without permissions
+        if let Some(perms) = perms {
+            self.state.write_all(stream, b"permissions:")?;
+            perms.write(&mut EscapeStream::new(stream))?;
+            self.state.write(stream, b';')?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * _Msize,permissions
+ * allocates memory, e.g. for the arguments of functions lldb calls
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct AllocateCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> AllocateCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for AllocateCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let (size, perms) = match (parser.next_token(), parser.next_token()) {
+            (Some(size), Some(perms)) if !size.is_empty() => (size, perms),
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let size = Parser::from_hexu(size).ok_or(Errors::BadNumber)?;
+        let perms = Permissions::parse(perms)?;
+
+        match ctx.allocate(size, perms) {
+            Ok(addr) => {
+                self.state.start(stream)?;
+                Parser::to_hex_number(addr, stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * _maddr
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct DeallocateCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> DeallocateCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for DeallocateCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        if self.state.fields.is_empty() {
+            return Err(Errors::InsufficientArguments);
+        }
+        let addr = Parser::from_hexu(self.state.fields).ok_or(Errors::BadNumber)?;
+
+        match ctx.deallocate(addr) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QSaveRegisterState
+ * replies with the decimal id of the saved state
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SaveRegisterStateCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> SaveRegisterStateCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for SaveRegisterStateCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        match ctx.save_registers() {
+            Ok(id) => {
+                self.state.start(stream)?;
+                Parser::to_decimal(id, stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QRestoreRegisterState:id
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct RestoreRegisterStateCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> RestoreRegisterStateCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for RestoreRegisterStateCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let id = Parser::new(self.state.fields)
+            .next_token()
+            .and_then(Parser::from_decimal)
+            .ok_or(Errors::BadNumber)?;
+
+        match ctx.restore_registers(id) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QListThreadsInStopReply
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ListThreadsInStopReplyCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ListThreadsInStopReplyCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ListThreadsInStopReplyCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        match ctx.set_threads_in_stop_reply(true) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * jThreadsInfo
+ * describes all threads with their stop reason and expedited registers
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadsInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ThreadsInfoCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+/// writes a single thread object
+/// signal is only set for the thread that stopped
+fn write_thread_info(
+    json: &mut JsonWriter,
+    ctx: &dyn Target,
+    thread: ThreadInfo,
+    signal: Option<usize>,
+) -> Result<usize, Errors> {
+    let mut size = json.begin_object()?;
+    size += json.key(b"tid")?;
+    size += json.number(thread.id)?;
+    if let Some(name) = thread.name {
+        size += json.key(b"name")?;
+        size += json.string(name)?;
+    }
+
+    if let Some(signal) = signal {
+        size += json.key(b"reason")?;
+        size += json.string(b"signal")?;
+        size += json.key(b"signal")?;
+        size += json.number(signal)?;
+
+        // the registers lldb needs to unwind the stopped thread
+        if let Some(description) = ctx.description() {
+            size += json.key(b"registers")?;
+            size += json.begin_object()?;
+            for (regnum, reg) in description.registers.iter().enumerate() {
+                if reg.generic.is_none() {
+                    continue;
+                }
+                if let Ok(value) = ctx.rd_register(regnum) {
+                    size += json.key_number(regnum)?;
+                    size += json.raw(&mut |out| {
+                        let mut size = out.write(b'"')?;
+                        size += value.write(ctx.endianess(), out)?;
+                        size += out.write(b'"')?;
+                        Ok(size)
+                    })?;
+                }
+            }
+            size += json.end_object()?;
+        }
+    }
+    size += json.end_object()?;
+    Ok(size)
+}
+
+impl Command for ThreadsInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let ctx: &dyn Target = ctx;
+        let threads = match ctx.thread_target() {
+            Some(threads) => threads,
+            None => return self.state.empty(stream),
+        };
+
+        // without a thread field the first thread is the one that stopped
+        let reason = ctx.stop_reason();
+        let (stopped, signal) = (reason.thread(), reason.signal().map(usize::from));
+
+        self.state.start(stream)?;
+        {
+            let mut escaped = EscapeStream::new(stream);
+            let mut json = JsonWriter::new(&mut escaped);
+            let mut first = true;
+            json.begin_array()?;
+            threads.threads(&mut |thread| {
+                let is_stopped = match stopped {
+                    Some(id) => id == thread.id,
+                    None => first,
+                };
+                first = false;
+                write_thread_info(&mut json, ctx, thread, signal.filter(|_| is_stopped))
+            })?;
+            json.end_array()?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QEnableCompression:type:<name>;[minsize:<decimal>;]
+ * the OK is sent uncompressed, Session::apply enables
+ * compression for the replies after it
+ */
+#[cfg(feature = "compression")]
+#[derive(Debug, PartialEq)]
+pub struct EnableCompressionCommand<'a> {
+    state: ResponseWriter<'a>,
+    compression: Option<Compression>,
+    min_size: usize,
+}
+
+#[cfg(feature = "compression")]
+impl<'a> EnableCompressionCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        let mut compression = None;
+        let mut min_size = COMPRESSION_MIN_SIZE;
+        for field in args.split(|b| *b == b';') {
+            let mut kv = field.splitn(2, |b| *b == b':');
+            match (kv.next(), kv.next()) {
+                (Some(b"type"), Some(name)) => compression = Compression::parse(name),
+                (Some(b"minsize"), Some(size)) => {
+                    min_size = Parser::from_decimal(size).unwrap_or(min_size)
+                }
+                _ => (),
+            }
+        }
+        Self {
+            state: ResponseWriter::new(args),
+            compression,
+            min_size,
+        }
+    }
+
+    /// the requested compression and the smallest reply it applies to
+    pub fn mode(&self) -> Option<(Compression, usize)> {
+        self.compression.map(|c| (c, self.min_size))
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Command for EnableCompressionCommand<'_> {
+    fn response(
+        &mut self,
+        stream: &mut dyn Stream,
+        _ctx: &mut dyn Target,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        match self.compression {
+            Some(_) => self.state.ok(stream)?,
+            None => self.state.error(stream, Errors::Unsupported)?,
+        };
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::exec;
+    use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+    use crate::lldb::{HostInfo, ScratchAllocator};
+    use crate::register::RegisterValue;
+    use crate::signal::SIGTRAP;
+    use crate::stop::{StopReason, StopReply};
+    use crate::target::VirtualTarget;
+    use crate::thread::{ThreadCallback, ThreadTarget};
+
+    const REGISTERS: [RegisterDesc; 2] = [
+        RegisterDesc::new(b"r0", 32),
+        RegisterDesc::new(b"r1", 32)
+            .with_dwarf(1)
+            .with_generic(GenericRegister::Pc),
+    ];
+
+    const DESCRIPTION: TargetDescription =
+        TargetDescription::new(b"org.gnu.gdb.arm.core", &REGISTERS);
+
+    struct LldbTarget;
+    impl Target for LldbTarget {
+        fn host_info(&self) -> Option<HostInfo<'_>> {
+            Some(HostInfo::new(b"arm", 4).with_vendor(b"none"))
+        }
+
+        fn description(&self) -> Option<&TargetDescription<'_>> {
+            Some(&DESCRIPTION)
+        }
+    }
+
+    #[test]
+    fn it_should_list_threads_in_stop_replies() {
+        struct ListTarget {
+            enabled: bool,
+        }
+        impl Target for ListTarget {
+            fn set_threads_in_stop_reply(&mut self, enabled: bool) -> Result<usize, Errors> {
+                self.enabled = enabled;
+                Ok(0)
+            }
+
+            fn threads_in_stop_reply(&self) -> bool {
+                self.enabled
+            }
+
+            fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+                Some(self)
+            }
+
+            fn stop_reason(&self) -> StopReason {
+                StopReason::Stop(StopReply::new(SIGTRAP).with_thread(1))
+            }
+        }
+
+        impl ThreadTarget for ListTarget {
+            fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+                f(ThreadInfo::new(1).with_pc(0x8000))?;
+                f(ThreadInfo::new(0x1a).with_pc(0x8124))
+            }
+        }
+
+        let mut target = ListTarget { enabled: false };
+        assert_eq!(exec(b"$?#3f", &mut target), b"$T05thread:1;#d7");
+
+        assert_eq!(exec(b"$QListThreadsInStopReply#21", &mut target), b"$OK#9a");
+        assert!(target.enabled);
+
+        let stream = exec(b"$?#3f", &mut target);
+        assert_eq!(
+            stream[..],
+            b"$T05thread:1;threads:1,1a;thread-pcs:8000,8124;#49"[..]
+        );
+
+        assert_eq!(
+            exec(b"$QListThreadsInStopReply#21", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    #[test]
+    fn it_should_reply_host_info() {
+        let stream = exec(b"$qHostInfo#9b", &mut LldbTarget);
+        assert_eq!(
+            stream[..],
+            b"$triple:61726d;ptrsize:4;endian:little;vendor:none;#4e"[..]
+        );
+
+        assert_eq!(exec(b"$qHostInfo#9b", &mut VirtualTarget::new()), b"$#00");
+    }
+
+    #[test]
+    fn it_should_enumerate_registers() {
+        let stream = exec(b"$qRegisterInfo1#73", &mut LldbTarget);
+        assert_eq!(
+            stream[..],
+            b"$name:r1;bitsize:32;offset:4;encoding:uint;format:hex;set:general;gcc:1;dwarf:1;generic:pc;#cd"
+                [..]
+        );
+
+        assert_eq!(exec(b"$qRegisterInfo2#74", &mut LldbTarget), b"$E00#a5");
+    }
+
+    #[test]
+    fn it_should_describe_memory_regions() {
+        let mut target = VirtualTarget::new();
+
+        let stream = exec(b"$qMemoryRegionInfo:10#75", &mut target);
+        assert_eq!(stream[..], b"$start:0;size:200;permissions:rw;#af"[..]);
+
+        let stream = exec(b"$qMemoryRegionInfo:200#a6", &mut target);
+        // the gap reaches the end of the address space
+        let expected = format!("$start:200;size:{:x};", usize::MAX - 0x200);
+        assert_eq!(stream[..stream.len() - 3], *expected.as_bytes());
+
+        assert_eq!(exec(b"$qMemoryRegionInfo:10#75", &mut LldbTarget), b"$#00");
+    }
+
+    struct ScratchTarget {
+        scratch: ScratchAllocator,
+    }
+
+    impl Target for ScratchTarget {
+        fn allocate(&mut self, size: usize, perms: Permissions) -> Result<usize, Errors> {
+            match perms.write {
+                true => self.scratch.allocate(size),
+                false => Err(Errors::Unsupported),
+            }
+        }
+
+        fn deallocate(&mut self, addr: usize) -> Result<usize, Errors> {
+            self.scratch.deallocate(addr).map(|_| 0)
+        }
+    }
+
+    #[test]
+    fn it_should_allocate_memory() {
+        let mut target = ScratchTarget {
+            scratch: ScratchAllocator::new(0x2000_0000, 0x100),
+        };
+
+        assert_eq!(exec(b"$_M20,rw#23", &mut target), b"$20000000#82");
+        assert_eq!(exec(b"$_M20,rwx#9b", &mut target), b"$20000020#84");
+        assert_eq!(exec(b"$_M20,rx#24", &mut target), b"$#00");
+
+        assert_eq!(exec(b"$_m20000000#4e", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$_m20000000#4e", &mut target), b"$E00#a5");
+    }
+
+    struct CheckpointTarget {
+        saved: Option<u32>,
+        pc: u32,
+    }
+
+    impl Target for CheckpointTarget {
+        fn save_registers(&mut self) -> Result<usize, Errors> {
+            self.saved = Some(self.pc);
+            Ok(1)
+        }
+
+        fn restore_registers(&mut self, id: usize) -> Result<usize, Errors> {
+            match (id, self.saved.take()) {
+                (1, Some(pc)) => {
+                    self.pc = pc;
+                    Ok(0)
+                }
+                _ => Err(Errors::BadNumber),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_checkpoint_registers() {
+        let mut target = CheckpointTarget {
+            saved: None,
+            pc: 0x100,
+        };
+
+        assert_eq!(exec(b"$QSaveRegisterState#26", &mut target), b"$1#31");
+        target.pc = 0x200;
+
+        assert_eq!(exec(b"$QRestoreRegisterState:1#e6", &mut target), b"$OK#9a");
+        assert_eq!(target.pc, 0x100);
+
+        assert_eq!(
+            exec(b"$QRestoreRegisterState:1#e6", &mut target),
+            b"$E00#a5"
+        );
+
+        assert_eq!(
+            exec(b"$QSaveRegisterState#26", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    struct ThreadsTarget;
+    impl Target for ThreadsTarget {
+        fn stop_reason(&self) -> StopReason {
+            StopReason::Stop(StopReply::new(SIGTRAP).with_thread(2))
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(0x1000 + regno as u32))
+        }
+
+        fn description(&self) -> Option<&TargetDescription<'_>> {
+            Some(&DESCRIPTION)
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+    }
+
+    impl ThreadTarget for ThreadsTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            Ok(f(ThreadInfo::new(1))? + f(ThreadInfo::new(2).with_name(b"idle"))?)
+        }
+    }
+
+    #[test]
+    fn it_should_describe_threads_in_json() {
+        let stream = exec(b"$jThreadsInfo#c1", &mut ThreadsTarget);
+        // json braces are escaped
+        assert_eq!(
+            stream[..],
+            b"$[{\"tid\":1}],{\"tid\":2,\"name\":\"idle\",\"reason\":\"signal\",\"signal\":5,\
+            \"registers\":{\"1\":\"01100000\"}]}]]#39"[..]
+        );
+
+        assert_eq!(exec(b"$jThreadsInfo#c1", &mut LldbTarget), b"$#00");
+    }
+}
@@ -1 +1,16 @@
+pub mod breakpoint;
+pub mod control;
+#[cfg(feature = "flash")]
+pub mod flash;
+#[cfg(feature = "lldb")]
+pub mod lldb;
+pub mod query;
+pub mod register;
 pub mod required;
+pub mod thread;
+#[cfg(feature = "tracepoints")]
+pub mod tracepoint;
+#[cfg(feature = "vfile")]
+pub mod vfile;
+#[cfg(feature = "xfer")]
+pub mod xfer;
@@ -0,0 +1,1080 @@
+/*
+ * General query packets
+ */
+
+#[cfg(all(feature = "xfer", feature = "btrace"))]
+use crate::btrace::BtraceRead;
+use crate::command::*;
+use crate::crc::CrcStream;
+use crate::error::Errors;
+use crate::features::GdbFeatures;
+use crate::memory::{read_hex, read_memory, GuestAddress};
+use crate::parser::Parser;
+#[cfg(feature = "monitor")]
+use crate::stream::ConsoleStream;
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::thread::ThreadId;
+
+/// longest pattern qSearch:memory accepts
+pub const SEARCH_PATTERN_LEN: usize = 64;
+
+/// bytes of memory qSearch:memory reads at once
+pub const SEARCH_CHUNK: usize = 64;
+
+/// maximum length of a decoded symbol name
+pub const SYMBOL_NAME_LEN: usize = 128;
+
+/// maximum length of a decoded monitor command
+#[cfg(feature = "monitor")]
+pub const MONITOR_CMD_LEN: usize = 256;
+
+/// writes a ; separated list of qSupported features
+struct FeatureList {
+    first: bool,
+}
+
+impl FeatureList {
+    fn new() -> Self {
+        Self { first: true }
+    }
+
+    fn add(
+        &mut self,
+        state: &mut ResponseWriter,
+        stream: &mut dyn Stream,
+        feature: &[u8],
+    ) -> Result<usize, Errors> {
+        let mut size = 0;
+        if !self.first {
+            size += state.write(stream, b';')?;
+        }
+        self.first = false;
+        size += state.write_all(stream, feature)?;
+        Ok(size)
+    }
+}
+
+/**
+ * qSupported
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SupportedCommand<'a> {
+    state: ResponseWriter<'a>,
+    packet_size: Option<usize>,
+}
+
+impl<'a> SupportedCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            packet_size: None,
+        }
+    }
+
+    /// the largest packet the stub can receive and reply with
+    /// set by Session::apply
+    pub fn set_packet_size(&mut self, size: Option<usize>) {
+        self.packet_size = size;
+    }
+}
+
+impl Command for SupportedCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        ctx.set_gdb_features(GdbFeatures::parse(self.state.fields));
+        self.state.start(stream)?;
+
+        let mut features = FeatureList::new();
+        if let Some(size) = self.packet_size {
+            features.add(&mut self.state, stream, b"PacketSize=")?;
+            Parser::to_hex_number(size, stream)?;
+        }
+        features.add(&mut self.state, stream, b"QStartNoAckMode+")?;
+        if ctx.non_stop() {
+            features.add(&mut self.state, stream, b"QNonStop+")?;
+        }
+        if ctx.pass_signals() {
+            features.add(&mut self.state, stream, b"QPassSignals+")?;
+        }
+        if ctx.program_signals() {
+            features.add(&mut self.state, stream, b"QProgramSignals+")?;
+        }
+        if ctx.syscall_catching() {
+            features.add(&mut self.state, stream, b"QCatchSyscalls+")?;
+        }
+        if ctx.environment() {
+            features.add(&mut self.state, stream, b"QEnvironmentHexEncoded+")?;
+            features.add(&mut self.state, stream, b"QEnvironmentUnset+")?;
+            features.add(&mut self.state, stream, b"QEnvironmentReset+")?;
+        }
+        if ctx.sw_breakpoints().is_some() {
+            features.add(&mut self.state, stream, b"swbreak+")?;
+        }
+        if ctx.hw_breakpoints().is_some() {
+            features.add(&mut self.state, stream, b"hwbreak+")?;
+        }
+        if ctx.multiprocess() {
+            features.add(&mut self.state, stream, b"multiprocess+")?;
+        }
+        if ctx.process_events() {
+            features.add(&mut self.state, stream, b"fork-events+")?;
+            features.add(&mut self.state, stream, b"vfork-events+")?;
+            features.add(&mut self.state, stream, b"exec-events+")?;
+        }
+        if ctx.thread_events() {
+            features.add(&mut self.state, stream, b"QThreadEvents+")?;
+        }
+        #[cfg(feature = "btrace")]
+        if ctx.branch_tracing() {
+            features.add(&mut self.state, stream, b"Qbtrace:bts+")?;
+            features.add(&mut self.state, stream, b"Qbtrace:off+")?;
+        }
+        #[cfg(feature = "reverse")]
+        if ctx.replay().is_some() {
+            features.add(&mut self.state, stream, b"ReverseStep+")?;
+            features.add(&mut self.state, stream, b"ReverseContinue+")?;
+        }
+        #[cfg(feature = "tracepoints")]
+        if ctx.tracepoints().is_some() {
+            features.add(&mut self.state, stream, b"EnableDisableTracepoints+")?;
+        }
+        // the stub only compresses, so every algorithm is always available
+        #[cfg(feature = "compression")]
+        features.add(
+            &mut self.state,
+            stream,
+            b"SupportedCompressions=zlib-deflate,lz4",
+        )?;
+        #[cfg(feature = "xfer")]
+        {
+            if ctx.features(b"target.xml").is_some() || ctx.description().is_some() {
+                features.add(&mut self.state, stream, b"qXfer:features:read+")?;
+            }
+            if !ctx.memory_map().is_empty() {
+                features.add(&mut self.state, stream, b"qXfer:memory-map:read+")?;
+            }
+            if ctx.thread_target().is_some() {
+                features.add(&mut self.state, stream, b"qXfer:threads:read+")?;
+            }
+            if ctx.libraries(&mut |_| Ok(0)) != Err(Errors::Unsupported) {
+                features.add(&mut self.state, stream, b"qXfer:libraries:read+")?;
+            }
+            if ctx.libraries_svr4(&mut |_| Ok(0)) != Err(Errors::Unsupported) {
+                features.add(&mut self.state, stream, b"qXfer:libraries-svr4:read+")?;
+            }
+            #[cfg(feature = "btrace")]
+            if ctx.btrace(BtraceRead::All, &mut |_| Ok(0)) != Err(Errors::Unsupported) {
+                features.add(&mut self.state, stream, b"qXfer:btrace:read+")?;
+            }
+            #[cfg(feature = "tracepoints")]
+            if let Some(tracepoints) = ctx.tracepoints() {
+                if tracepoints.frame_info(&mut |_| Ok(0)) != Err(Errors::Unsupported) {
+                    features.add(&mut self.state, stream, b"qXfer:traceframe-info:read+")?;
+                }
+            }
+            if ctx.auxv().is_some() {
+                features.add(&mut self.state, stream, b"qXfer:auxv:read+")?;
+            }
+            if ctx.exec_file(None).is_some() {
+                features.add(&mut self.state, stream, b"qXfer:exec-file:read+")?;
+            }
+            if ctx.osdata(b"", &mut |_| Ok(0)) != Err(Errors::Unsupported) {
+                features.add(&mut self.state, stream, b"qXfer:osdata:read+")?;
+            }
+            if ctx.siginfo().is_some() {
+                features.add(&mut self.state, stream, b"qXfer:siginfo:read+")?;
+            }
+            if ctx.wr_siginfo(0, &[]) != Err(Errors::Unsupported) {
+                features.add(&mut self.state, stream, b"qXfer:siginfo:write+")?;
+            }
+        }
+
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QStartNoAckMode
+ * the mode switch itself happens in Session::apply
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct StartNoAckModeCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> StartNoAckModeCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for StartNoAckModeCommand<'_> {
+    fn response(
+        &mut self,
+        stream: &mut dyn Stream,
+        _ctx: &mut dyn Target,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        self.state.ok(stream)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qCRC:addr,length
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct CrcCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> CrcCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for CrcCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let (addr, len) = match (parser.next_token(), parser.next_token()) {
+            (Some(addr), Some(len)) if !addr.is_empty() && !len.is_empty() => (addr, len),
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let addr = Parser::from_hex_address(addr).ok_or(Errors::BadNumber)?;
+        let len = Parser::from_hexu(len).ok_or(Errors::BadNumber)?;
+
+        // memory is checksummed while the target writes it
+        let mut crc = CrcStream::new();
+        let read = read_hex(ctx, addr, len, &mut crc);
+
+        self.state.start(stream)?;
+        if read == Ok(len) && crc.checked() == len {
+            self.state.write(stream, b'C')?;
+            Parser::to_hexu(&crc.crc().to_be_bytes(), stream)?;
+        } else {
+            self.state.error(stream, Errors::AddressOutOfRange)?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qGetTLSAddr:thread-id,offset,lm
+ * lm is the address of the link map of the object owning the variable
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TlsAddressCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TlsAddressCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TlsAddressCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let (thread, offset, lm) = match (
+            parser.next_token(),
+            parser.next_token(),
+            parser.next_token(),
+        ) {
+            (Some(thread), Some(offset), Some(lm)) if !offset.is_empty() && !lm.is_empty() => {
+                (thread, offset, lm)
+            }
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let thread = ThreadId::parse(thread).ok_or(Errors::BadNumber)?;
+        let offset = Parser::from_hexu(offset).ok_or(Errors::BadNumber)?;
+        let lm = Parser::from_hexu(lm).ok_or(Errors::BadNumber)?;
+
+        match ctx.tls_address(thread, offset, lm) {
+            Ok(addr) => {
+                self.state.start(stream)?;
+                Parser::to_hex_number(addr, stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qSearch:memory:addr;length;pattern
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SearchMemoryCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> SearchMemoryCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+/// returns the address of the first match of pattern
+/// memory is read in chunks that overlap by the pattern length
+fn search_memory(
+    ctx: &dyn Target,
+    start: usize,
+    len: usize,
+    pattern: &[u8],
+) -> Result<Option<usize>, Errors> {
+    if pattern.is_empty() {
+        return Err(Errors::InsufficientArguments);
+    }
+    let mut window = [0; SEARCH_CHUNK + SEARCH_PATTERN_LEN];
+    let mut base = start;
+    let mut filled = 0;
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let n = usize::min(remaining, window.len() - filled);
+        if read_memory(
+            ctx,
+            (base + filled) as GuestAddress,
+            &mut window[filled..filled + n],
+        )? != n
+        {
+            return Err(Errors::AddressOutOfRange);
+        }
+        filled += n;
+        remaining -= n;
+
+        if let Some(pos) = window[..filled]
+            .windows(pattern.len())
+            .position(|w| w == pattern)
+        {
+            return Ok(Some(base + pos));
+        }
+
+        // keep the tail in case a match spans two chunks
+        let keep = usize::min(pattern.len() - 1, filled);
+        window.copy_within(filled - keep..filled, 0);
+        base += filled - keep;
+        filled = keep;
+    }
+    Ok(None)
+}
+
+impl Command for SearchMemoryCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let (space, addr, len) = match (
+            parser.next_token(),
+            parser.next_token(),
+            parser.next_token(),
+        ) {
+            (Some(space), Some(addr), Some(len)) if !addr.is_empty() && !len.is_empty() => {
+                (space, addr, len)
+            }
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        if space != b"memory" {
+            return self.state.empty(stream);
+        }
+        let addr = Parser::from_hexu(addr).ok_or(Errors::BadNumber)?;
+        let len = Parser::from_hexu(len).ok_or(Errors::BadNumber)?;
+
+        let mut buffer = [0; SEARCH_PATTERN_LEN];
+        let mut pattern_len = 0;
+        let pattern =
+            Parser::unescape_chunks(parser.parse_until_end(), &mut buffer, &mut |_, chunk| {
+                // the pattern has to fit into a single chunk
+                pattern_len += chunk.len();
+                match pattern_len {
+                    0 => Err(Errors::InsufficientArguments),
+                    n if n > SEARCH_PATTERN_LEN || chunk.len() != n => Err(Errors::LengthMismatch),
+                    n => Ok(n),
+                }
+            });
+
+        let result = match pattern {
+            Ok(pattern_len) => search_memory(ctx, addr, len, &buffer[..pattern_len]),
+            Err(err) => Err(err),
+        };
+
+        self.state.start(stream)?;
+        match result {
+            Ok(Some(found)) => {
+                self.state.write_all(stream, b"1,")?;
+                Parser::to_hex_number(found, stream)?;
+            }
+            Ok(None) => {
+                self.state.write(stream, b'0')?;
+            }
+            Err(err) => {
+                self.state.error(stream, err)?;
+            }
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qSymbol
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SymbolCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> SymbolCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for SymbolCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+
+        // qSymbol:: starts the lookup
+        // every following packet answers the previous request as value:name
+        // where value is empty if gdb does not know the symbol
+        let mut parser = Parser::new(self.state.fields);
+        let value = parser.next_token();
+        let name = parser.next_token();
+
+        if let (Some(value), Some(name)) = (value, name) {
+            let mut buffer = [0; SYMBOL_NAME_LEN];
+            let len = Parser::from_hex_bytes(name, &mut buffer).ok_or(Errors::BadNumber)?;
+            let value = if value.is_empty() {
+                None
+            } else {
+                Some(Parser::from_hexu(value).ok_or(Errors::BadNumber)?)
+            };
+            ctx.symbol_value(&buffer[..len], value);
+        }
+
+        match ctx.next_symbol() {
+            Some(name) => {
+                self.state.write_all(stream, b"qSymbol:")?;
+                Parser::to_hexu(name, stream)?;
+            }
+            None => {
+                self.state.ok(stream)?;
+            }
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qRcmd
+ */
+#[cfg(feature = "monitor")]
+#[derive(Debug, PartialEq)]
+pub struct MonitorCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+#[cfg(feature = "monitor")]
+impl<'a> MonitorCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+
+    /// runs the command, its output is written to console as O packets
+    /// and the final reply to stream
+    pub fn run(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+        console: &mut dyn Stream,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        let result = self.print(ctx, console)?;
+        self.reply(stream, result)
+    }
+
+    /// runs the command with its output wrapped in CONSOLE_PACKET_LEN chunks
+    /// fails if the command could not be decoded or the output not be written
+    fn print(
+        &mut self,
+        ctx: &mut dyn Target,
+        console: &mut dyn Stream,
+    ) -> Result<Result<usize, Errors>, Errors> {
+        let mut buffer = [0; MONITOR_CMD_LEN];
+        let len =
+            Parser::from_hex_bytes(self.state.fields, &mut buffer).ok_or(Errors::BadNumber)?;
+
+        let mut console = ConsoleStream::new(console);
+        let result = ctx.monitor(&buffer[..len], &mut console);
+        console.finish()?;
+        Ok(result)
+    }
+
+    fn reply(
+        &mut self,
+        stream: &mut dyn Stream,
+        result: Result<usize, Errors>,
+    ) -> Result<usize, Errors> {
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)?;
+            }
+            Err(Errors::Unsupported) => {
+                self.state.empty(stream)?;
+            }
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)?;
+            }
+        }
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(feature = "monitor")]
+impl Command for MonitorCommand<'_> {
+    /// the output is sent as O packets before the final reply in stream,
+    /// GdbStub calls run to send them right away
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let result = self.print(ctx, stream)?;
+        self.reply(stream, result)
+    }
+}
+
+/**
+ * qSnapshotRead:offset,length
+ */
+#[derive(Debug, PartialEq)]
+pub struct SnapshotReadCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> SnapshotReadCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for SnapshotReadCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let offset = parser.next_token();
+        let length = parser.next_token();
+
+        if let (Some(offset), Some(length)) = (offset, length) {
+            let offset = Parser::from_hexu(offset).ok_or(Errors::BadNumber)?;
+            let length = Parser::from_hexu(length).ok_or(Errors::BadNumber)?;
+
+            let snapshot = match ctx.snapshot() {
+                Some(snapshot) => snapshot,
+                None => return self.state.empty(stream),
+            };
+
+            // reads are stateless so the host can retry any chunk
+            // m means more data follows, l marks the last chunk
+            let start = usize::min(offset, snapshot.len());
+            let end = usize::min(start.saturating_add(length), snapshot.len());
+            self.state.start(stream)?;
+            if end < snapshot.len() {
+                self.state.write(stream, b'm')?;
+            } else {
+                self.state.write(stream, b'l')?;
+            }
+            Parser::to_hexu(&snapshot[start..end], stream)?;
+            self.state.end(stream)?;
+            Ok(stream.pos())
+        } else {
+            Err(Errors::InsufficientArguments)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakpoint::SwBreakpoints;
+    use crate::command::testing::{respond, TestCommands};
+    use crate::memory::{GuestAddress, PhysAddr};
+    use crate::signal::SignalSet;
+    use crate::stream::BufferedStream;
+    use crate::syscall::CatchSyscalls;
+
+    #[derive(Default)]
+    struct SymbolTarget {
+        task_list: Option<Option<usize>>,
+    }
+
+    impl Target for SymbolTarget {
+        fn next_symbol(&mut self) -> Option<&[u8]> {
+            match self.task_list {
+                None => Some(b"pxReadyTasksLists"),
+                Some(_) => None,
+            }
+        }
+
+        fn symbol_value(&mut self, name: &[u8], value: Option<usize>) {
+            if name == b"pxReadyTasksLists" {
+                self.task_list = Some(value);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "xfer")]
+    fn it_should_reply_supported_features() {
+        let mut target = crate::target::VirtualTarget::new();
+        let mut parser = Parser::new(b"$qSupported:multiprocess+;swbreak+#1b");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        #[cfg(not(feature = "compression"))]
+        let expected = &b"$QStartNoAckMode+;qXfer:features:read+;qXfer:memory-map:read+;qXfer:threads:read+#9d"[..];
+        #[cfg(feature = "compression")]
+        let expected = &b"$QStartNoAckMode+;SupportedCompressions=zlib-deflate,lz4;qXfer:features:read+;qXfer:memory-map:read+;qXfer:threads:read+#d9"[..];
+        assert_eq!(stream.buffer[..stream.pos()], *expected);
+    }
+
+    struct BreakTarget;
+    impl Target for BreakTarget {
+        fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+            Some(self)
+        }
+    }
+
+    impl SwBreakpoints for BreakTarget {
+        fn add_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            Ok(0)
+        }
+
+        fn remove_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_derive_features_from_capabilities() {
+        let stream = search(b"$qSupported:swbreak+#8b", &mut BreakTarget);
+        let reply = &stream.buffer[..stream.pos()];
+        assert!(reply.windows(8).any(|w| w == b"swbreak+"));
+        assert!(!reply.windows(8).any(|w| w == b"hwbreak+"));
+        assert!(!reply.windows(19).any(|w| w == b"qXfer:threads:read+"));
+    }
+
+    /// counts the calls that change the target
+    #[derive(Default)]
+    struct ProbeTarget {
+        changes: usize,
+    }
+
+    impl Target for ProbeTarget {
+        fn set_non_stop(&mut self, _enabled: bool) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn non_stop(&self) -> bool {
+            true
+        }
+
+        fn set_pass_signals(&mut self, _signals: &SignalSet) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn pass_signals(&self) -> bool {
+            true
+        }
+
+        fn set_program_signals(&mut self, _signals: &SignalSet) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn program_signals(&self) -> bool {
+            true
+        }
+
+        fn catch_syscalls(&mut self, _catch: CatchSyscalls) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn syscall_catching(&self) -> bool {
+            true
+        }
+
+        fn reset_env(&mut self) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn environment(&self) -> bool {
+            true
+        }
+
+        #[cfg(feature = "btrace")]
+        fn set_btrace(&mut self, _enabled: bool) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        #[cfg(feature = "btrace")]
+        fn branch_tracing(&self) -> bool {
+            true
+        }
+
+        fn set_thread_events(&mut self, _enabled: bool) -> Result<usize, Errors> {
+            self.changes += 1;
+            Ok(0)
+        }
+
+        fn thread_events(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn it_should_advertise_features_without_changing_the_target() {
+        let mut target = ProbeTarget::default();
+        let stream = search(b"$qSupported#37", &mut target);
+        let reply = &stream.buffer[..stream.pos()];
+        assert!(reply.windows(9).any(|w| w == b"QNonStop+"));
+        assert!(reply.windows(13).any(|w| w == b"QPassSignals+"));
+        assert!(reply.windows(16).any(|w| w == b"QProgramSignals+"));
+        assert!(reply.windows(15).any(|w| w == b"QCatchSyscalls+"));
+        assert!(reply.windows(18).any(|w| w == b"QEnvironmentReset+"));
+        #[cfg(feature = "btrace")]
+        assert!(reply.windows(12).any(|w| w == b"Qbtrace:bts+"));
+        assert!(reply.windows(14).any(|w| w == b"QThreadEvents+"));
+        assert_eq!(target.changes, 0);
+    }
+
+    #[test]
+    fn it_should_checksum_memory() {
+        let mut target = crate::target::VirtualTarget::new();
+        target.wr_memory(0x10, b"313233343536373839").unwrap();
+        let mut stream = BufferedStream::new();
+
+        let mut result = Parser::new(b"$qCRC:10,9#49").parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$C0376e6e7#4a"[..]);
+
+        // the range ends outside of memory
+        stream.reset();
+        let mut result = Parser::new(b"$qCRC:1f0,20#d8").parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    /// two pages of 0x40 bytes at 0x8000, mapped in reverse order
+    struct PagedTarget(crate::target::VirtualTarget);
+
+    impl Target for PagedTarget {
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            self.0.rd_memory(start, size, stream)
+        }
+
+        fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+            self.0.wr_memory(start, data)
+        }
+
+        fn translate(&self, addr: GuestAddress) -> Option<PhysAddr> {
+            match addr {
+                0x8000..=0x803f => Some(addr - 0x8000 + 0x40),
+                0x8040..=0x807f => Some(addr - 0x8040),
+                _ => None,
+            }
+        }
+
+        fn page_size(&self) -> Option<usize> {
+            Some(0x40)
+        }
+    }
+
+    #[test]
+    fn it_should_checksum_virtual_memory() {
+        let mut target = PagedTarget(crate::target::VirtualTarget::new());
+        crate::memory::write_memory(&mut target, 0x803c, b"123456789").unwrap();
+        let mut stream = BufferedStream::new();
+
+        // the range spans both pages
+        respond(b"$qCRC:803c,9#e6", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$C0376e6e7#4a"[..]);
+    }
+
+    fn search(packet: &[u8], target: &mut dyn Target) -> BufferedStream {
+        let mut parser = Parser::new(packet);
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, target)
+            .unwrap();
+        stream
+    }
+
+    #[test]
+    fn it_should_search_memory() {
+        let mut target = crate::target::VirtualTarget::new();
+        // the match spans the first two chunks
+        crate::memory::write_memory(&mut target, 0x3e, b"ab}c").unwrap();
+
+        let stream = search(b"$qSearch:memory:0;200;ab}]c#0c", &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$1,3e#f5"[..]);
+    }
+
+    #[test]
+    fn it_should_not_find_missing_patterns() {
+        let mut target = crate::target::VirtualTarget::new();
+        crate::memory::write_memory(&mut target, 0x3e, b"ab").unwrap();
+
+        let stream = search(b"$qSearch:memory:3f;100;ab#37", &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$0#30"[..]);
+    }
+
+    #[test]
+    fn it_should_fail_searching_empty_patterns() {
+        let mut target = crate::target::VirtualTarget::new();
+        let stream = search(b"$qSearch:memory:0;10;#db", &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    #[test]
+    fn it_should_fail_searching_out_of_range() {
+        let mut target = crate::target::VirtualTarget::new();
+        let stream = search(b"$qSearch:memory:1f0;20;ab#36", &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    struct TlsTarget;
+    impl Target for TlsTarget {
+        fn tls_address(&self, thread: ThreadId, offset: usize, lm: usize) -> Result<usize, Errors> {
+            match (thread.tid, lm) {
+                (2, 0x4000) => Ok(0x2000_0000 + offset),
+                _ => Err(Errors::AddressOutOfRange),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_resolve_thread_local_addresses() {
+        let stream = search(b"$qGetTLSAddr:p1.2,10,4000#b7", &mut TlsTarget);
+        assert_eq!(stream.buffer[..stream.pos()], b"$20000010#83"[..]);
+
+        let stream = search(b"$qGetTLSAddr:3,10,4000#e9", &mut TlsTarget);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+
+        let stream = search(
+            b"$qGetTLSAddr:2,10,4000#e8",
+            &mut crate::target::VirtualTarget::new(),
+        );
+        assert_eq!(stream.buffer[..stream.pos()], b"$#00"[..]);
+    }
+
+    #[test]
+    fn it_should_accept_no_ack_mode() {
+        let mut target = crate::target::VirtualTarget::new();
+        let mut parser = Parser::new(b"$QStartNoAckMode#b0");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "monitor")]
+    fn it_should_run_monitor_commands() {
+        let mut target = crate::target::VirtualTarget::new();
+        let mut parser = Parser::new(b"$qRcmd,7265736574#37");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$O7461726765742072657365740a#d4$OK#9a"[..]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "monitor")]
+    fn it_should_reply_empty_to_unknown_monitor_commands() {
+        let mut target = crate::target::VirtualTarget::new();
+        let mut parser = Parser::new(b"$qRcmd,6a756d70#27");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$#00"[..]);
+    }
+
+    struct SnapshotTarget;
+    impl Target for SnapshotTarget {
+        fn snapshot(&self) -> Option<&[u8]> {
+            Some(&[0xde, 0xad, 0xbe, 0xef, 0x01])
+        }
+    }
+
+    #[test]
+    fn it_should_read_snapshots() {
+        let mut parser = Parser::new(b"$qSnapshotRead:1,2#06");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut SnapshotTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$madbe#f9"[..]);
+    }
+
+    #[test]
+    fn it_should_read_last_snapshot_chunk() {
+        let mut parser = Parser::new(b"$qSnapshotRead:3,10#37");
+        let mut stream = BufferedStream::new();
+
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut SnapshotTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$lef01#98"[..]);
+    }
+
+    #[test]
+    fn it_should_request_symbols() {
+        let mut target = SymbolTarget::default();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$qSymbol::#5b", &mut stream, &mut target);
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$qSymbol:707852656164795461736b734c69737473#8b"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_receive_symbol_values() {
+        let mut target = SymbolTarget::default();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qSymbol:80001000:707852656164795461736b734c69737473#4e",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(target.task_list, Some(Some(0x80001000)));
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+    }
+
+    #[test]
+    fn it_should_receive_unknown_symbols() {
+        let mut target = SymbolTarget::default();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qSymbol::707852656164795461736b734c69737473#c5",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(target.task_list, Some(None));
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+    }
+}
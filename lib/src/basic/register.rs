@@ -0,0 +1,356 @@
+/*
+ * Single register access
+ */
+
+use crate::arch;
+use crate::command::*;
+use crate::error::Errors;
+use crate::parser::Parser;
+use crate::register::{RegisterValue, MAX_REGISTER_SIZE};
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::thread;
+
+/**
+ * p
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ReadRegisterCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ReadRegisterCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ReadRegisterCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let regno = Parser::from_hexu(self.state.fields).ok_or(Errors::BadNumber)?;
+        let endianness = ctx.endianess();
+
+        if let Some(arch) = ctx.arch() {
+            self.state.start(stream)?;
+            if let Err(err) = arch::encode_register(arch, ctx, regno, stream) {
+                stream.reset();
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+            }
+            self.state.end(stream)?;
+            return Ok(stream.pos());
+        }
+
+        let value = match ctx.register_available(regno) {
+            true => thread::rd_register(ctx, regno),
+            false => Err(Errors::InvalidRegister),
+        };
+        match value {
+            Ok(value) => {
+                self.state.start(stream)?;
+                value.write(endianness, stream)?;
+                self.state.end(stream)?;
+            }
+            Err(Errors::Unsupported) => {
+                self.state.empty(stream)?;
+            }
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)?;
+            }
+        }
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * P
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct WriteRegisterCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> WriteRegisterCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for WriteRegisterCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        // expecting n=r
+        let fields = self.state.fields;
+        let split = fields
+            .iter()
+            .position(|b| *b == b'=')
+            .ok_or(Errors::InsufficientArguments)?;
+        let regno = Parser::from_hexu(&fields[..split]).ok_or(Errors::BadNumber)?;
+        let data = &fields[split + 1..];
+        let mut buffer = [0; MAX_REGISTER_SIZE];
+        let value = match ctx.arch() {
+            Some(arch) => match arch.register_size(regno) {
+                Some(size) => arch::decode_register(data, size, arch.endianness(), &mut buffer)
+                    .ok_or(Errors::LengthMismatch),
+                None => Err(Errors::InvalidRegister),
+            },
+            None => Ok(RegisterValue::from_hex(data, ctx.endianess()).ok_or(Errors::BadNumber)?),
+        };
+        let result = value.and_then(|value| arch::write_register(ctx, regno, value));
+
+        match result {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)?;
+            }
+            Err(Errors::Unsupported) => {
+                self.state.empty(stream)?;
+            }
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)?;
+            }
+        }
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "arch")]
+    use crate::arch::riscv::{Riscv, RV32};
+    use crate::arch::Arch;
+    use crate::command::testing::respond;
+    use crate::description::{GenericRegister, RegisterDesc, TargetDescription};
+    use crate::parser::Endianness;
+    use crate::stream::BufferedStream;
+    use crate::target::VirtualTarget;
+
+    #[test]
+    fn it_should_read_a_register() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$p25#d7", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$0000c0bf#1b"[..]);
+    }
+
+    #[test]
+    fn it_should_write_a_register() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$P25=bfc00180#18", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+
+        respond(b"$p25#d7", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$bfc00180#24"[..]);
+    }
+
+    struct ArchTarget {
+        pc: u32,
+    }
+
+    const ARCH_REGISTERS: [RegisterDesc; 2] = [
+        RegisterDesc::new(b"r0", 32),
+        RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+    ];
+
+    struct TestArch;
+    impl Arch for TestArch {
+        fn description(&self) -> &TargetDescription<'_> {
+            const DESCRIPTION: TargetDescription = TargetDescription::new(b"test", &ARCH_REGISTERS);
+            &DESCRIPTION
+        }
+
+        fn endianness(&self) -> Endianness {
+            Endianness::Big
+        }
+
+        fn breakpoint_kind(&self) -> usize {
+            4
+        }
+    }
+
+    impl Target for ArchTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&TestArch)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            match regno {
+                1 => Ok(RegisterValue::U32(self.pc)),
+                _ => Err(Errors::Unsupported),
+            }
+        }
+
+        fn wr_register(&mut self, regno: usize, value: RegisterValue) -> Result<usize, Errors> {
+            match (regno, value) {
+                (1, RegisterValue::U32(pc)) => self.pc = pc,
+                _ => return Err(Errors::Unsupported),
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_use_the_arch_layout() {
+        let mut target = ArchTarget { pc: 0x8000 };
+        let mut stream = BufferedStream::new();
+
+        respond(b"$p1#a1", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$00008000#88"[..]);
+        respond(b"$p0#a0", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$xxxxxxxx#c0"[..]);
+        respond(b"$p2#a2", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+
+        respond(b"$P1=0800#86", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+        respond(b"$P1=00000800#46", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert_eq!(target.pc, 0x800);
+
+        respond(b"$g#67", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$xxxxxxxx00000800#48"[..]);
+    }
+
+    const FPU_REGISTERS: [RegisterDesc; 2] = [
+        RegisterDesc::new(b"pc", 16).with_generic(GenericRegister::Pc),
+        RegisterDesc::new(b"f0", 16).with_group(b"float"),
+    ];
+
+    struct FpuArch;
+    impl Arch for FpuArch {
+        fn description(&self) -> &TargetDescription<'_> {
+            const DESCRIPTION: TargetDescription = TargetDescription::new(b"test", &FPU_REGISTERS);
+            &DESCRIPTION
+        }
+
+        fn breakpoint_kind(&self) -> usize {
+            2
+        }
+    }
+
+    struct FpuTarget {
+        fpu: bool,
+        registers: [u16; 2],
+    }
+
+    impl Target for FpuTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&FpuArch)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U16(self.registers[regno]))
+        }
+
+        fn wr_register(&mut self, regno: usize, value: RegisterValue) -> Result<usize, Errors> {
+            match value {
+                RegisterValue::U16(value) => self.registers[regno] = value,
+                _ => return Err(Errors::LengthMismatch),
+            }
+            Ok(0)
+        }
+
+        fn register_available(&self, regno: usize) -> bool {
+            FPU_REGISTERS[regno].group != Some(b"float") || self.fpu
+        }
+    }
+
+    #[test]
+    fn it_should_skip_unavailable_registers() {
+        let mut target = FpuTarget {
+            fpu: false,
+            registers: [0x100, 0x3c00],
+        };
+        let mut stream = BufferedStream::new();
+
+        respond(b"$g#67", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$0001xxxx#a1"[..]);
+        respond(b"$p1#a1", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$xxxx#e0"[..]);
+        respond(b"$P1=0040#82", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+        respond(b"$G02000040#cd", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert_eq!(target.registers, [0x2, 0x3c00]);
+
+        target.fpu = true;
+        respond(b"$p1#a1", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$003c#f6"[..]);
+    }
+
+    #[cfg(feature = "arch")]
+    struct CsrTarget {
+        mcause: u32,
+    }
+
+    #[cfg(feature = "arch")]
+    const RV32_CSRS: Riscv = RV32.with_machine_csrs();
+
+    #[cfg(feature = "arch")]
+    impl Target for CsrTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&RV32_CSRS)
+        }
+
+        fn rd_register(&self, _regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(0))
+        }
+
+        fn rd_csr(&self, csr: usize) -> Result<RegisterValue<'_>, Errors> {
+            match csr {
+                0x342 => Ok(RegisterValue::U32(self.mcause)),
+                _ => Err(Errors::Unsupported),
+            }
+        }
+
+        fn wr_csr(&mut self, csr: usize, value: RegisterValue) -> Result<usize, Errors> {
+            match (csr, value) {
+                (0x342, RegisterValue::U32(value)) => self.mcause = value,
+                _ => return Err(Errors::Unsupported),
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arch")]
+    fn it_should_access_csrs() {
+        let mut target = CsrTarget { mcause: 0x8000000b };
+        let mut stream = BufferedStream::new();
+
+        // mcause is register 0x27
+        respond(b"$p27#d9", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$0b000080#ba"[..]);
+        respond(b"$p21#d3", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$xxxxxxxx#c0"[..]);
+        respond(b"$P27=02000000#78", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert_eq!(target.mcause, 2);
+    }
+
+    #[test]
+    fn it_should_reject_unknown_registers() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$p40#d4", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+}
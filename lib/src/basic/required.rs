@@ -2,12 +2,46 @@
  * All the required commands
  */
 
+use crate::arch;
+#[cfg(debug_assertions)]
+use crate::check;
 use crate::command::*;
 use crate::error::Errors;
+use crate::memory;
 use crate::parser::Parser;
 use crate::stream::Stream;
 use crate::target::Target;
 
+/// writes the contents of the stop reply for the current halt
+/// an exit, no resumed threads, a thread exit, a pending File-I/O request,
+/// a caught syscall or a process event is reported instead of the halt reason
+pub fn write_stop_reply(stream: &mut dyn Stream, ctx: &dyn Target) -> Result<usize, Errors> {
+    if let Some(status) = ctx.exit_status() {
+        return status.write(stream);
+    }
+    if ctx.no_resumed() {
+        return stream.write(b'N');
+    }
+    if let Some(exit) = ctx.thread_exit() {
+        return exit.write(stream);
+    }
+    #[cfg(feature = "fileio")]
+    if let Some(call) = ctx.file_io_request() {
+        return call.write(stream);
+    }
+    if let Some(stop) = ctx.syscall_stop() {
+        return stop.write(stream);
+    }
+    if let Some(event) = ctx.process_event() {
+        return event.write(stream);
+    }
+
+    let reason = ctx.stop_reason();
+    #[cfg(debug_assertions)]
+    check::check_stop_reason(ctx, &reason, &mut |v| ctx.violation(v));
+    reason.write(ctx, stream)
+}
+
 /**
  * ?
  */
@@ -30,7 +64,7 @@ impl Command for ReasonCommand<'_> {
         stream.reset();
         self.state.start(stream)?;
 
-        self.state.write_all(stream, ctx.reason())?;
+        write_stop_reply(stream, ctx)?;
         self.state.end(stream)
     }
 }
@@ -62,7 +96,7 @@ impl Command for ReadRegistersCommand<'_> {
         stream.reset();
         self.state.start(stream)?;
 
-        ctx.rd_registers(stream)?;
+        arch::read_registers(ctx, stream)?;
         self.state.end(stream)?;
         Ok(stream.pos())
     }
@@ -95,7 +129,7 @@ impl Command for WriteRegistersCommand<'_> {
     fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
         stream.reset();
         self.state.start(stream)?;
-        match ctx.wr_registers(self.state.fields) {
+        match arch::write_registers(ctx, self.state.fields) {
             Ok(_) => self.state.ok(stream)?,
             Err(err) => self.state.error(stream, err)?,
         };
@@ -131,11 +165,17 @@ impl Command for ReadMemoryCommand<'_> {
         let size = parser.next_token();
 
         if let (Some(addr), Some(size)) = (addr, size) {
-            let addr = Parser::from_hexu(addr);
+            let addr = Parser::from_hex_address(addr);
             let size = Parser::from_hexu(size);
 
             if let (Some(addr), Some(size)) = (addr, size) {
-                ctx.rd_memory(addr as *const u8, size as usize, stream)?;
+                // reads are cut at the end of the readable memory
+                if let Err(err) = memory::read_hex(ctx, addr, size, stream) {
+                    // drops what a failing memory hook wrote
+                    stream.reset();
+                    self.state.start(stream)?;
+                    self.state.error(stream, err)?;
+                }
                 self.state.end(stream)?;
                 Ok(stream.pos())
             } else {
@@ -175,7 +215,7 @@ impl Command for WriteMemoryCommand<'_> {
         let bytes = parser.next_token();
 
         if let (Some(addr), Some(size), Some(bytes)) = (addr, size, bytes) {
-            let addr = Parser::from_hexu(addr);
+            let addr = Parser::from_hex_address(addr);
             let size = Parser::from_hexu(size);
 
             // mismatched lenght!
@@ -183,8 +223,11 @@ impl Command for WriteMemoryCommand<'_> {
                 if bytes.len() / 2 != size {
                     Err(Errors::LengthMismatch)
                 } else {
-                    ctx.wr_memory(addr as *const u8, bytes)?;
-                    self.state.ok(stream)?;
+                    // writes must not be cut short
+                    match memory::write_hex(ctx, addr, bytes) {
+                        Ok(_) => self.state.ok(stream)?,
+                        Err(err) => self.state.error(stream, err)?,
+                    };
                     self.state.end(stream)?;
                     Ok(stream.pos())
                 }
@@ -0,0 +1,339 @@
+/*
+ * Thread selection and enumeration
+ */
+
+use crate::command::*;
+use crate::error::Errors;
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::thread::{self, ThreadId, ThreadOp};
+
+/**
+ * Hg thread
+ * Hc thread
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct SelectThreadCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> SelectThreadCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for SelectThreadCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let (op, thread) = self
+            .state
+            .fields
+            .split_first()
+            .ok_or(Errors::InsufficientArguments)?;
+        let op = match op {
+            b'g' => ThreadOp::General,
+            b'c' => ThreadOp::Continue,
+            _ => return Err(Errors::CommandError),
+        };
+        let thread = ThreadId::parse(thread).ok_or(Errors::BadNumber)?;
+
+        match ctx.select_thread(op, thread) {
+            Ok(_) => {
+                self.state.start(stream)?;
+                self.state.ok(stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qfThreadInfo
+ * qsThreadInfo
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+    first: bool,
+}
+
+impl<'a> ThreadInfoCommand<'a> {
+    pub fn new(args: &'a [u8], first: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            first,
+        }
+    }
+}
+
+impl Command for ThreadInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let threads = match ctx.thread_target() {
+            Some(threads) => threads,
+            None => {
+                self.state.empty(stream)?;
+                return Ok(stream.pos());
+            }
+        };
+
+        self.state.start(stream)?;
+        // every thread is sent with qfThreadInfo, qsThreadInfo ends the list
+        if self.first {
+            let mut separator = b'm';
+            threads.threads(&mut |thread| {
+                let size = stream.write(separator)?;
+                separator = b',';
+                Ok(size + thread.thread_id().write(stream)?)
+            })?;
+        }
+        if stream.pos() == 1 {
+            self.state.write(stream, b'l')?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qC
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct CurrentThreadCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> CurrentThreadCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for CurrentThreadCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        match ctx
+            .thread_target()
+            .and_then(|threads| threads.current_thread())
+        {
+            Some(thread) => {
+                self.state.start(stream)?;
+                self.state.write_all(stream, b"QC")?;
+                ThreadId::new(thread).write(stream)?;
+                self.state.end(stream)
+            }
+            None => self.state.empty(stream),
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * T thread
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadAliveCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ThreadAliveCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ThreadAliveCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let id = ThreadId::parse(self.state.fields).ok_or(Errors::BadNumber)?;
+        let threads = match ctx.thread_target() {
+            Some(threads) => threads,
+            None => {
+                self.state.empty(stream)?;
+                return Ok(stream.pos());
+            }
+        };
+
+        self.state.start(stream)?;
+        match thread::find_thread(threads, id, &mut |_| Ok(0)) {
+            Ok(_) => self.state.ok(stream),
+            Err(err) => self.state.error(stream, err),
+        }?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qThreadExtraInfo,thread
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadExtraInfoCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> ThreadExtraInfoCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for ThreadExtraInfoCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let id = ThreadId::parse(self.state.fields).ok_or(Errors::BadNumber)?;
+        let threads = match ctx.thread_target() {
+            Some(threads) => threads,
+            None => {
+                self.state.empty(stream)?;
+                return Ok(stream.pos());
+            }
+        };
+
+        self.state.start(stream)?;
+        // the description is sent hex encoded
+        let result = thread::find_thread(threads, id, &mut |thread| {
+            let extra = thread.extra.or(thread.name).unwrap_or_default();
+            Parser::to_hexu(extra, stream)?;
+            Ok(extra.len() * 2)
+        });
+        if let Err(err) = result {
+            stream.reset();
+            self.state.start(stream)?;
+            self.state.error(stream, err)?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::mips::MIPS32_LE;
+    use crate::arch::Arch;
+    use crate::command::testing::exec;
+    use crate::register::RegisterValue;
+    use crate::target::VirtualTarget;
+    use crate::thread::{ThreadCallback, ThreadInfo, ThreadTarget};
+
+    /// two tasks, task 1 runs on the cpu
+    struct TaskTarget {
+        selected: Option<usize>,
+    }
+
+    impl ThreadTarget for TaskTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            let mut size = f(ThreadInfo::new(1).with_name(b"main"))?;
+            size += f(ThreadInfo::new(2).with_name(b"idle").with_extra(b"Ready"))?;
+            Ok(size)
+        }
+
+        fn current_thread(&self) -> Option<usize> {
+            Some(1)
+        }
+
+        fn selected_thread(&self) -> Option<usize> {
+            self.selected
+        }
+
+        fn rd_thread_register(
+            &self,
+            _thread: usize,
+            regno: usize,
+        ) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(0x200 + regno as u32))
+        }
+    }
+
+    impl Target for TaskTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&MIPS32_LE)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(0x100 + regno as u32))
+        }
+
+        fn wr_register(&mut self, _regno: usize, _value: RegisterValue) -> Result<usize, Errors> {
+            Ok(0)
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+
+        fn select_thread(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+            if op == ThreadOp::General {
+                self.selected = Some(thread.tid);
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_list_threads() {
+        let mut target = TaskTarget { selected: None };
+        assert_eq!(exec(b"$qfThreadInfo#bb", &mut target), b"$m1,2#fc");
+        assert_eq!(exec(b"$qsThreadInfo#c8", &mut target), b"$l#6c");
+        assert_eq!(exec(b"$qC#b4", &mut target), b"$QC1#c5");
+        assert_eq!(exec(b"$T2#86", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$T3#87", &mut target), b"$E00#a5");
+        assert_eq!(
+            exec(b"$qThreadExtraInfo,1#b6", &mut target),
+            b"$6d61696e#0b"
+        );
+        assert_eq!(
+            exec(b"$qThreadExtraInfo,2#b7", &mut target),
+            b"$5265616479#13"
+        );
+    }
+
+    #[test]
+    fn it_should_read_saved_registers() {
+        let mut target = TaskTarget { selected: None };
+        assert_eq!(exec(b"$p1#a1", &mut target), b"$01010000#82");
+        assert_eq!(exec(b"$Hg2#e1", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p1#a1", &mut target), b"$01020000#83");
+        assert_eq!(exec(b"$P1=00000000#3e", &mut target), b"$E13#a9");
+        assert_eq!(exec(b"$Hg1#e0", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p1#a1", &mut target), b"$01010000#82");
+        assert_eq!(exec(b"$P1=00000000#3e", &mut target), b"$OK#9a");
+
+        // gdb selects any thread on connect
+        for packet in [&b"$Hg0#df"[..], b"$Hg-1#0d"] {
+            assert_eq!(exec(packet, &mut target), b"$OK#9a");
+            assert_eq!(exec(b"$p1#a1", &mut target), b"$01010000#82");
+            assert_eq!(exec(b"$P1=00000000#3e", &mut target), b"$OK#9a");
+        }
+    }
+
+    #[test]
+    fn it_should_not_report_an_unknown_current_thread() {
+        let mut target = VirtualTarget::new();
+        assert_eq!(exec(b"$qC#b4", &mut target), b"$#00");
+    }
+}
@@ -0,0 +1,593 @@
+/*
+ * Tracepoints
+ */
+
+use crate::command::*;
+use crate::error::Errors;
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::tracepoint::{FrameQuery, ReadOnlyRanges, TraceTarget, Tracepoint};
+
+/// the frame number gdb sends to return to the live target
+const FRAME_LIVE: usize = 0xffffffff;
+
+/// runs f on the target's tracepoints and replies OK
+/// targets without tracepoints get an empty reply
+fn reply(
+    state: &mut ResponseWriter,
+    stream: &mut dyn Stream,
+    ctx: &mut dyn Target,
+    f: &mut dyn FnMut(&mut dyn TraceTarget) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    match ctx.tracepoints().map(f) {
+        Some(Ok(_)) => {
+            state.start(stream)?;
+            state.ok(stream)?;
+            state.end(stream)
+        }
+        None | Some(Err(Errors::Unsupported)) => state.empty(stream),
+        Some(Err(err)) => {
+            state.start(stream)?;
+            state.error(stream, err)?;
+            state.end(stream)
+        }
+    }?;
+    Ok(stream.pos())
+}
+
+fn parse_hex(token: Option<&[u8]>) -> Result<usize, Errors> {
+    Parser::from_hexu(token.ok_or(Errors::InsufficientArguments)?).ok_or(Errors::BadNumber)
+}
+
+/**
+ * QTinit
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceInitCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceInitCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TraceInitCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        reply(&mut self.state, stream, ctx, &mut |t| t.init())
+    }
+}
+
+/**
+ * QTDP:n:addr:E|D:step:pass[:Xlen,cond][-]
+ * QTDP:-n:addr:action[-]
+ * a trailing - announces more actions
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceDefineCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceDefineCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TraceDefineCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let fields = self.state.fields;
+        let fields = fields.strip_suffix(b"-").unwrap_or(fields);
+        let mut parser = Parser::new(fields);
+        let number = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+        let addr = parse_hex(parser.next_token())?;
+
+        if let Some(number) = number.strip_prefix(b"-") {
+            let number = Parser::from_hexu(number).ok_or(Errors::BadNumber)?;
+            let action = parser.parse_until_end();
+            return reply(&mut self.state, stream, ctx, &mut |t| {
+                t.add_action(number, addr, action)
+            });
+        }
+
+        let number = Parser::from_hexu(number).ok_or(Errors::BadNumber)?;
+        let enabled = match parser.next_token() {
+            Some(b"E") => true,
+            Some(b"D") => false,
+            Some(_) => return Err(Errors::BadNumber),
+            None => return Err(Errors::InsufficientArguments),
+        };
+        let step = parse_hex(parser.next_token())?;
+        let pass = parse_hex(parser.next_token())?;
+
+        // fast tracepoints are not supported, only conditions are kept
+        let mut condition = None;
+        while let Some(token) = parser.next_token() {
+            // Xlen,cond is split at the comma
+            if token.starts_with(b"X") {
+                condition = parser.next_token();
+            }
+        }
+
+        let tracepoint = Tracepoint {
+            number,
+            addr,
+            enabled,
+            step,
+            pass,
+            condition,
+        };
+        reply(&mut self.state, stream, ctx, &mut |t| t.define(tracepoint))
+    }
+}
+
+/**
+ * QTEnable:n:addr
+ * QTDisable:n:addr
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceEnableCommand<'a> {
+    state: ResponseWriter<'a>,
+    enabled: bool,
+}
+
+impl<'a> TraceEnableCommand<'a> {
+    pub fn new(args: &'a [u8], enabled: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            enabled,
+        }
+    }
+}
+
+impl Command for TraceEnableCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let number = parse_hex(parser.next_token())?;
+        let addr = parse_hex(parser.next_token())?;
+        let enabled = self.enabled;
+        reply(&mut self.state, stream, ctx, &mut |t| {
+            t.set_enabled(number, addr, enabled)
+        })
+    }
+}
+
+/**
+ * QTStart
+ * QTStop
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceRunCommand<'a> {
+    state: ResponseWriter<'a>,
+    start: bool,
+}
+
+impl<'a> TraceRunCommand<'a> {
+    pub fn new(args: &'a [u8], start: bool) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+            start,
+        }
+    }
+}
+
+impl Command for TraceRunCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        match self.start {
+            true => reply(&mut self.state, stream, ctx, &mut |t| t.start()),
+            false => reply(&mut self.state, stream, ctx, &mut |t| t.stop()),
+        }
+    }
+}
+
+/**
+ * QTFrame:n
+ * QTFrame:pc:addr
+ * QTFrame:tdp:n
+ * QTFrame:range:start:end
+ * QTFrame:outside:start:end
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceFrameCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceFrameCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+
+    fn query(&self) -> Result<FrameQuery, Errors> {
+        let mut parser = Parser::new(self.state.fields);
+        Ok(match parser.next_token() {
+            Some(b"pc") => FrameQuery::Pc(parse_hex(parser.next_token())?),
+            Some(b"tdp") => FrameQuery::Tracepoint(parse_hex(parser.next_token())?),
+            Some(b"range") => FrameQuery::Range(
+                parse_hex(parser.next_token())?,
+                parse_hex(parser.next_token())?,
+            ),
+            Some(b"outside") => FrameQuery::Outside(
+                parse_hex(parser.next_token())?,
+                parse_hex(parser.next_token())?,
+            ),
+            number => match parse_hex(number)? {
+                FRAME_LIVE => FrameQuery::Live,
+                number => FrameQuery::Number(number),
+            },
+        })
+    }
+}
+
+impl Command for TraceFrameCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let query = self.query()?;
+        let result = match ctx.tracepoints() {
+            Some(tracepoints) => tracepoints.find_frame(query),
+            None => return self.state.empty(stream),
+        };
+
+        self.state.start(stream)?;
+        match result {
+            Ok(Some(frame)) => {
+                self.state.write(stream, b'F')?;
+                Parser::to_hex_number(frame.number, stream)?;
+                self.state.write(stream, b'T')?;
+                Parser::to_hex_number(frame.tracepoint, stream)?;
+            }
+            Ok(None) => {
+                self.state.write_all(stream, b"F-1")?;
+            }
+            Err(err) => {
+                self.state.error(stream, err)?;
+            }
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * qTStatus
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceStatusCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceStatusCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TraceStatusCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let status = match ctx.tracepoints() {
+            Some(tracepoints) => tracepoints.status(),
+            None => return self.state.empty(stream),
+        };
+        self.state.start(stream)?;
+        status.write(stream)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+/**
+ * QTro:start,end:start,end...
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceReadOnlyCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceReadOnlyCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TraceReadOnlyCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+        let ranges = ReadOnlyRanges::parse(self.state.fields)?;
+        reply(&mut self.state, stream, ctx, &mut |t| {
+            t.set_read_only(ranges)
+        })
+    }
+}
+
+/// bytes of the trace buffer qTBuffer reads at once
+pub const TRACE_BUFFER_CHUNK: usize = 32;
+
+/**
+ * qTBuffer:offset,len
+ * replies l once offset is past the end of the buffer
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct TraceBufferCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> TraceBufferCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl Command for TraceBufferCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let offset = parse_hex(parser.next_token())?;
+        let len = parse_hex(parser.next_token())?;
+        let tracepoints = match ctx.tracepoints() {
+            Some(tracepoints) => tracepoints,
+            None => return self.state.empty(stream),
+        };
+
+        let mut chunk = [0; TRACE_BUFFER_CHUNK];
+        let mut read = 0;
+        while read < len {
+            let want = (len - read).min(TRACE_BUFFER_CHUNK);
+            let n = match tracepoints.read_buffer(offset + read, &mut chunk[..want]) {
+                Ok(n) => n.min(want),
+                Err(Errors::Unsupported) if read == 0 => return self.state.empty(stream),
+                Err(err) if read == 0 => {
+                    self.state.start(stream)?;
+                    self.state.error(stream, err)?;
+                    self.state.end(stream)?;
+                    return Ok(stream.pos());
+                }
+                // the chunks read so far are still valid
+                Err(_) => break,
+            };
+            if n == 0 {
+                break;
+            }
+            if read == 0 {
+                self.state.start(stream)?;
+            }
+            Parser::to_hexu(&chunk[..n], stream)?;
+            read += n;
+        }
+
+        if read == 0 {
+            self.state.start(stream)?;
+            self.state.write(stream, b'l')?;
+        }
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::exec;
+    use crate::target::VirtualTarget;
+    use crate::tracepoint::{TraceFrame, TraceStatus, TraceStop};
+
+    #[derive(Default)]
+    struct Trace {
+        tracepoint: Option<(usize, usize, bool, usize, usize)>,
+        condition: [u8; 4],
+        actions: usize,
+        running: bool,
+        query: Option<FrameQuery>,
+        read_only: usize,
+    }
+
+    impl TraceTarget for Trace {
+        fn init(&mut self) -> Result<usize, Errors> {
+            *self = Self::default();
+            Ok(0)
+        }
+
+        fn define(&mut self, tp: Tracepoint) -> Result<usize, Errors> {
+            self.tracepoint = Some((tp.number, tp.addr, tp.enabled, tp.step, tp.pass));
+            if let Some(cond) = tp.condition {
+                self.condition.copy_from_slice(cond);
+            }
+            Ok(0)
+        }
+
+        fn add_action(
+            &mut self,
+            number: usize,
+            _addr: usize,
+            action: &[u8],
+        ) -> Result<usize, Errors> {
+            match self.tracepoint {
+                Some((n, ..)) if n == number && action == b"R0f" => {
+                    self.actions += 1;
+                    Ok(0)
+                }
+                _ => Err(Errors::BadNumber),
+            }
+        }
+
+        fn set_enabled(
+            &mut self,
+            _number: usize,
+            _addr: usize,
+            enabled: bool,
+        ) -> Result<usize, Errors> {
+            match self.tracepoint.as_mut() {
+                Some(tp) => {
+                    tp.2 = enabled;
+                    Ok(0)
+                }
+                None => Err(Errors::BadNumber),
+            }
+        }
+
+        fn start(&mut self) -> Result<usize, Errors> {
+            self.running = true;
+            Ok(0)
+        }
+
+        fn stop(&mut self) -> Result<usize, Errors> {
+            self.running = false;
+            Ok(0)
+        }
+
+        fn status(&self) -> TraceStatus {
+            TraceStatus {
+                running: self.running,
+                stop: TraceStop::NotRun,
+                frames: 0,
+                created: 0,
+            }
+        }
+
+        fn find_frame(&mut self, query: FrameQuery) -> Result<Option<TraceFrame>, Errors> {
+            self.query = Some(query);
+            match query {
+                FrameQuery::Number(3) => Ok(Some(TraceFrame {
+                    number: 3,
+                    tracepoint: 1,
+                })),
+                _ => Ok(None),
+            }
+        }
+        fn set_read_only(&mut self, ranges: ReadOnlyRanges) -> Result<usize, Errors> {
+            self.read_only = ranges.iter().count();
+            Ok(0)
+        }
+
+        fn read_buffer(&self, offset: usize, out: &mut [u8]) -> Result<usize, Errors> {
+            let buffer = [0xab; 40];
+            let data = buffer.get(offset..).unwrap_or(&[]);
+            let n = data.len().min(out.len());
+            out[..n].copy_from_slice(&data[..n]);
+            Ok(n)
+        }
+    }
+
+    #[derive(Default)]
+    struct TraceTestTarget {
+        trace: Trace,
+    }
+
+    impl Target for TraceTestTarget {
+        fn tracepoints(&mut self) -> Option<&mut dyn TraceTarget> {
+            Some(&mut self.trace)
+        }
+    }
+
+    #[test]
+    fn it_should_register_read_only_ranges() {
+        let mut target = TraceTestTarget::default();
+
+        assert_eq!(
+            exec(b"$QTro:1000,2000:8000,8010#66", &mut target),
+            b"$OK#9a"
+        );
+        assert_eq!(target.trace.read_only, 2);
+    }
+
+    #[test]
+    fn it_should_read_the_trace_buffer() {
+        let mut target = TraceTestTarget::default();
+
+        // 0x24 bytes span two chunks but the buffer ends after 0x22
+        let stream = exec(b"$qTBuffer:6,24#21", &mut target);
+        let mut expected = std::vec::Vec::from(&b"$"[..]);
+        expected.extend(core::iter::repeat_n(b"ab", 0x22).flatten());
+        expected.extend_from_slice(b"#e6");
+        assert_eq!(stream[..], expected[..]);
+
+        assert_eq!(exec(b"$qTBuffer:28,10#50", &mut target), b"$l#6c");
+
+        assert_eq!(
+            exec(b"$qTBuffer:0,10#16", &mut VirtualTarget::new()),
+            b"$#00"
+        );
+    }
+
+    #[test]
+    fn it_should_define_tracepoints() {
+        let mut target = TraceTestTarget::default();
+
+        assert_eq!(exec(b"$QTinit#59", &mut target), b"$OK#9a");
+
+        assert_eq!(
+            exec(b"$QTDP:1:1000:E:0:2:X2,2201-#d6", &mut target),
+            b"$OK#9a"
+        );
+        assert_eq!(target.trace.tracepoint, Some((1, 0x1000, true, 0, 2)));
+        assert_eq!(&target.trace.condition, b"2201");
+
+        assert_eq!(exec(b"$QTDP:-1:1000:R0f#ee", &mut target), b"$OK#9a");
+        assert_eq!(target.trace.actions, 1);
+
+        assert_eq!(exec(b"$QTDP:-2:1000:R0f#ef", &mut target), b"$E00#a5");
+
+        exec(b"$QTDisable:1:1000#bf", &mut target);
+        assert_eq!(target.trace.tracepoint, Some((1, 0x1000, false, 0, 2)));
+    }
+
+    #[test]
+    fn it_should_run_experiments() {
+        let mut target = TraceTestTarget::default();
+
+        exec(b"$QTStart#b3", &mut target);
+        let stream = exec(b"$qTStatus#49", &mut target);
+        assert_eq!(stream[..], b"$T1;tnotrun:0;tframes:0;tcreated:0#cc"[..]);
+
+        exec(b"$QTStop#4b", &mut target);
+        assert!(!target.trace.running);
+    }
+
+    #[test]
+    fn it_should_select_frames() {
+        let mut target = TraceTestTarget::default();
+
+        assert_eq!(exec(b"$QTFrame:3#fd", &mut target), b"$F3T1#fe");
+
+        assert_eq!(exec(b"$QTFrame:range:100:1ff#d9", &mut target), b"$F-1#a4");
+        assert_eq!(target.trace.query, Some(FrameQuery::Range(0x100, 0x1ff)));
+
+        exec(b"$QTFrame:ffffffff#fa", &mut target);
+        assert_eq!(target.trace.query, Some(FrameQuery::Live));
+    }
+
+    #[test]
+    fn it_should_reply_empty_without_tracepoints() {
+        assert_eq!(exec(b"$qTStatus#49", &mut VirtualTarget::new()), b"$#00");
+    }
+}
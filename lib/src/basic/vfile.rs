@@ -0,0 +1,301 @@
+/*
+ * vFile host I/O
+ */
+
+use crate::command::*;
+use crate::error::Errors;
+use crate::hostfs::{write_fs_result, FileStat, HostFs};
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+
+/// maximum length of a decoded file name
+pub const VFILE_PATH_LEN: usize = 256;
+
+/// largest amount of data returned by a single pread or readlink
+/// gdb repeats short reads
+pub const VFILE_READ_CHUNK: usize = 128;
+
+/// bytes of a pwrite payload that are unescaped at once
+pub const VFILE_WRITE_CHUNK: usize = 64;
+
+/**
+ * vFile:operation:parameter...
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct VFileCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> VFileCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+fn number(token: Option<&[u8]>) -> Result<usize, Errors> {
+    match token {
+        Some(token) if !token.is_empty() => Parser::from_hexu(token).ok_or(Errors::BadNumber),
+        _ => Err(Errors::InsufficientArguments),
+    }
+}
+
+fn path<'b>(token: Option<&[u8]>, buffer: &'b mut [u8]) -> Result<&'b [u8], Errors> {
+    let token = token.ok_or(Errors::InsufficientArguments)?;
+    let len = Parser::from_hex_bytes(token, buffer).ok_or(Errors::BadNumber)?;
+    Ok(&buffer[..len])
+}
+
+impl VFileCommand<'_> {
+    /// writes F<len>;<data> for successful reads
+    fn write_data(
+        &mut self,
+        stream: &mut dyn Stream,
+        result: Result<usize, usize>,
+        data: &[u8],
+    ) -> Result<usize, Errors> {
+        write_fs_result(result, stream)?;
+        if let Ok(len) = result {
+            self.state.write(stream, b';')?;
+            self.state.write_all(stream, &data[..len])?;
+        }
+        Ok(stream.pos())
+    }
+
+    fn run(
+        &mut self,
+        stream: &mut dyn Stream,
+        fs: &mut dyn HostFs,
+        operation: &[u8],
+        parser: &mut Parser,
+    ) -> Result<usize, Errors> {
+        let mut buffer = [0; VFILE_PATH_LEN];
+        match operation {
+            b"open" => {
+                let path = path(parser.next_token(), &mut buffer)?;
+                let flags = number(parser.next_token())?;
+                let mode = number(parser.next_token())?;
+                write_fs_result(fs.open(path, flags, mode), stream)
+            }
+            b"close" => {
+                let fd = number(parser.next_token())?;
+                write_fs_result(fs.close(fd).map(|_| 0), stream)
+            }
+            b"pread" => {
+                let fd = number(parser.next_token())?;
+                let count = number(parser.next_token())?;
+                let offset = number(parser.next_token())?;
+
+                let mut data = [0; VFILE_READ_CHUNK];
+                let len = usize::min(count, data.len());
+                let result = fs.pread(fd, offset, &mut data[..len]);
+                self.write_data(stream, result.map(|n| usize::min(n, len)), &data)
+            }
+            b"pwrite" => {
+                let fd = number(parser.next_token())?;
+                let offset = number(parser.next_token())?;
+                let data = parser.parse_until_end();
+
+                // a failing or short write ends the transfer
+                // gdb resends whatever was not written
+                let mut chunk = [0; VFILE_WRITE_CHUNK];
+                let mut result = Ok(0);
+                let _ = Parser::unescape_chunks(data, &mut chunk, &mut |n, data| {
+                    if data.is_empty() {
+                        return Ok(0);
+                    }
+                    match fs.pwrite(fd, offset + n, data) {
+                        Ok(written) => {
+                            result = Ok(n + written);
+                            if written == data.len() {
+                                Ok(written)
+                            } else {
+                                Err(Errors::LengthMismatch)
+                            }
+                        }
+                        Err(errno) => {
+                            if n == 0 {
+                                result = Err(errno);
+                            }
+                            Err(Errors::CommandError)
+                        }
+                    }
+                });
+                write_fs_result(result, stream)
+            }
+            b"fstat" => {
+                let fd = number(parser.next_token())?;
+                let stat = fs.fstat(fd);
+                let bytes = stat.map(|s| s.to_bytes()).unwrap_or([0; FileStat::SIZE]);
+                self.write_data(stream, stat.map(|_| FileStat::SIZE), &bytes)
+            }
+            b"unlink" => {
+                let path = path(parser.next_token(), &mut buffer)?;
+                write_fs_result(fs.unlink(path).map(|_| 0), stream)
+            }
+            b"readlink" => {
+                let mut data = [0; VFILE_READ_CHUNK];
+                let path = path(parser.next_token(), &mut buffer)?;
+                let result = fs.readlink(path, &mut data);
+                self.write_data(stream, result.map(|n| usize::min(n, data.len())), &data)
+            }
+            b"setfs" => {
+                let pid = number(parser.next_token())?;
+                write_fs_result(fs.setfs(pid).map(|_| 0), stream)
+            }
+            _ => Err(Errors::Unsupported),
+        }
+    }
+}
+
+/// operations handled by VFileCommand
+const OPERATIONS: [&[u8]; 8] = [
+    b"open",
+    b"close",
+    b"pread",
+    b"pwrite",
+    b"fstat",
+    b"unlink",
+    b"readlink",
+    b"setfs",
+];
+
+impl Command for VFileCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let operation = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+
+        let fs = match ctx.host_fs() {
+            Some(fs) if OPERATIONS.contains(&operation) => fs,
+            _ => return self.state.empty(stream),
+        };
+
+        self.state.start(stream)?;
+        self.run(stream, fs, operation, &mut parser)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::exec;
+    use crate::fileio::{EBADF, ENOENT};
+    use crate::hostfs::FsResult;
+
+    struct MemFs {
+        data: [u8; 32],
+        len: usize,
+        open: bool,
+    }
+
+    impl HostFs for MemFs {
+        fn open(&mut self, path: &[u8], _flags: usize, _mode: usize) -> FsResult<usize> {
+            match path {
+                b"/a" => {
+                    self.open = true;
+                    Ok(3)
+                }
+                _ => Err(ENOENT),
+            }
+        }
+
+        fn close(&mut self, fd: usize) -> FsResult<()> {
+            match fd {
+                3 if self.open => {
+                    self.open = false;
+                    Ok(())
+                }
+                _ => Err(EBADF),
+            }
+        }
+
+        fn pread(&mut self, _fd: usize, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+            let end = usize::min(offset + buf.len(), self.len);
+            let len = end.saturating_sub(offset);
+            buf[..len].copy_from_slice(&self.data[offset..offset + len]);
+            Ok(len)
+        }
+
+        fn pwrite(&mut self, _fd: usize, offset: usize, data: &[u8]) -> FsResult<usize> {
+            self.data[offset..offset + data.len()].copy_from_slice(data);
+            self.len = usize::max(self.len, offset + data.len());
+            Ok(data.len())
+        }
+
+        fn fstat(&mut self, _fd: usize) -> FsResult<FileStat> {
+            Ok(FileStat {
+                size: self.len as u64,
+                ..Default::default()
+            })
+        }
+    }
+
+    struct FsTarget(MemFs);
+    impl Target for FsTarget {
+        fn host_fs(&mut self) -> Option<&mut dyn HostFs> {
+            Some(&mut self.0)
+        }
+    }
+
+    fn target() -> FsTarget {
+        FsTarget(MemFs {
+            data: [0; 32],
+            len: 0,
+            open: false,
+        })
+    }
+
+    #[test]
+    fn it_should_open_and_close_files() {
+        let mut target = target();
+
+        assert_eq!(exec(b"$vFile:open:2f61,0,1a4#69", &mut target), b"$F3#79");
+        assert_eq!(
+            exec(b"$vFile:open:2f62,0,1a4#6a", &mut target),
+            b"$F-1,2#02"
+        );
+        assert_eq!(exec(b"$vFile:close:3#b3", &mut target), b"$F0#76");
+        assert_eq!(exec(b"$vFile:close:3#b3", &mut target), b"$F-1,9#09");
+    }
+
+    #[test]
+    fn it_should_transfer_binary_data() {
+        let mut target = target();
+
+        assert_eq!(exec(b"$vFile:pwrite:3,2:a}]b#6d", &mut target), b"$F3#79");
+        assert_eq!(target.0.data[..5], *b"\0\0a}b");
+
+        assert_eq!(
+            exec(b"$vFile:pread:3,10,1#93", &mut target),
+            b"$F4;\0a}]b#52"
+        );
+
+        let stream = exec(b"$vFile:fstat:3#bf", &mut target);
+        assert_eq!(stream.len(), 5 + 64 + 3);
+        assert_eq!(stream[..5], *b"$F40;");
+    }
+
+    #[test]
+    fn it_should_use_defaults_for_optional_operations() {
+        let mut target = target();
+
+        assert_eq!(exec(b"$vFile:unlink:2f61#fa", &mut target), b"$F-1,270f#cf");
+        assert_eq!(exec(b"$vFile:setfs:0#bf", &mut target), b"$F0#76");
+
+        assert_eq!(exec(b"$vFile:chmod:2f61#74", &mut target), b"$#00");
+        assert_eq!(
+            exec(
+                b"$vFile:setfs:0#bf",
+                &mut crate::target::VirtualTarget::new(),
+            ),
+            b"$#00"
+        );
+    }
+}
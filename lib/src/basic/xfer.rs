@@ -0,0 +1,755 @@
+/*
+ * qXfer transfer objects
+ */
+
+#[cfg(feature = "btrace")]
+use crate::btrace::{write_btrace, BtraceRead};
+#[cfg(debug_assertions)]
+use crate::check;
+use crate::command::*;
+use crate::error::Errors;
+use crate::library::{write_library_list, write_library_list_svr4};
+use crate::memory::write_memory_map;
+use crate::osdata::write_osdata;
+use crate::parser::Parser;
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::thread::write_thread_list;
+#[cfg(feature = "tracepoints")]
+use crate::tracepoint::write_traceframe_info;
+
+/// Stream that only forwards the bytes of a transfer object
+/// that fall into the requested window.
+/// Without an inner stream it only counts the size of the object.
+pub struct XferStream<'a> {
+    inner: Option<&'a mut dyn Stream>,
+    state: ResponseWriter<'a>,
+    offset: usize,
+    length: usize,
+    total: usize,
+}
+
+impl<'a> XferStream<'a> {
+    pub fn new(inner: Option<&'a mut dyn Stream>, offset: usize, length: usize) -> Self {
+        Self {
+            inner,
+            state: ResponseWriter::new(&[]),
+            offset,
+            length,
+            total: 0,
+        }
+    }
+
+    /// total size of the object written so far
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+impl Stream for XferStream<'_> {
+    fn pos(&self) -> usize {
+        self.total
+    }
+
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        let in_window =
+            self.total >= self.offset && self.total < self.offset.saturating_add(self.length);
+        self.total += 1;
+
+        match (&mut self.inner, in_window) {
+            (Some(inner), true) => self.state.write(*inner, byte),
+            _ => Ok(1),
+        }
+    }
+}
+
+/// writes a transfer object reply for the requested window
+/// generate is called twice, once to measure the object
+/// and once to write the requested part of it.
+/// This way objects can be generated on the fly without buffering them.
+pub fn write_xfer(
+    state: &mut ResponseWriter,
+    stream: &mut dyn Stream,
+    offset: usize,
+    length: usize,
+    generate: &mut dyn FnMut(&mut dyn Stream) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut counter = XferStream::new(None, offset, length);
+    generate(&mut counter)?;
+
+    state.start(stream)?;
+    // m means there is more data to read, l marks the last chunk
+    if counter.total() > offset.saturating_add(length) {
+        state.write(stream, b'm')?;
+    } else {
+        state.write(stream, b'l')?;
+    }
+
+    let mut window = XferStream::new(Some(stream), offset, length);
+    generate(&mut window)?;
+
+    state.end(stream)?;
+    Ok(stream.pos())
+}
+
+/// bytes of a write request that are unescaped at once
+pub const XFER_WRITE_CHUNK: usize = 32;
+
+/**
+ * qXfer:object:read:annex:offset,length
+ * qXfer:object:write:annex:offset:data
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct XferCommand<'a> {
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> XferCommand<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            state: ResponseWriter::new(args),
+        }
+    }
+}
+
+impl XferCommand<'_> {
+    /// qXfer:object:write:annex:offset:data
+    /// replies with the amount of bytes written
+    fn write_object(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+        object: &[u8],
+        offset: usize,
+        data: &[u8],
+    ) -> Result<usize, Errors> {
+        // the data is unescaped in small chunks to avoid a packet sized buffer
+        let mut chunk = [0; XFER_WRITE_CHUNK];
+        let written = Parser::unescape_chunks(data, &mut chunk, &mut |n, data| match object {
+            b"siginfo" => ctx.wr_siginfo(offset + n, data),
+            _ => Err(Errors::Unsupported),
+        });
+
+        match written {
+            Ok(n) => {
+                self.state.start(stream)?;
+                Parser::to_hex_number(n, stream)?;
+                self.state.end(stream)
+            }
+            Err(Errors::Unsupported) => self.state.empty(stream),
+            Err(err) => {
+                self.state.start(stream)?;
+                self.state.error(stream, err)?;
+                self.state.end(stream)
+            }
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+impl Command for XferCommand<'_> {
+    fn response(&mut self, stream: &mut dyn Stream, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        stream.reset();
+
+        let mut parser = Parser::new(self.state.fields);
+        let object = parser.next_token();
+        let operation = parser.next_token();
+        let annex = parser.next_token();
+        let offset = parser.next_token();
+
+        let (object, operation, annex, offset) = match (object, operation, annex, offset) {
+            (Some(object), Some(operation), Some(annex), Some(offset)) => {
+                (object, operation, annex, offset)
+            }
+            _ => return Err(Errors::InsufficientArguments),
+        };
+        let offset = Parser::from_hexu(offset).ok_or(Errors::BadNumber)?;
+
+        // writes carry escaped binary data instead of a length
+        if operation == b"write" {
+            let data = parser.parse_until_end();
+            return self.write_object(stream, ctx, object, offset, data);
+        }
+
+        let length = parser.next_token().ok_or(Errors::InsufficientArguments)?;
+        let length = Parser::from_hexu(length).ok_or(Errors::BadNumber)?;
+
+        match (object, operation) {
+            (b"features", b"read") => match (ctx.features(annex), ctx.description()) {
+                (Some(xml), _) => write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                    out.write_all(xml)
+                }),
+                // target.xml is generated from the description
+                (None, Some(description)) if annex == b"target.xml" => {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        description.write_xml(out)
+                    })
+                }
+                _ => {
+                    self.state.start(stream)?;
+                    self.state.error(stream, Errors::Unsupported)?;
+                    self.state.end(stream)
+                }
+            },
+            (b"memory-map", b"read") => {
+                let regions = ctx.memory_map();
+                if regions.is_empty() {
+                    self.state.empty(stream)
+                } else {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_memory_map(out, regions)
+                    })
+                }
+            }
+            (b"threads", b"read") => match ctx.thread_target() {
+                Some(threads) => {
+                    #[cfg(debug_assertions)]
+                    if offset == 0 {
+                        check::check_threads(ctx, &mut |v| ctx.violation(v));
+                    }
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_thread_list(out, &mut |f| threads.threads(f))
+                    })
+                }
+                None => self.state.empty(stream),
+            },
+            (b"libraries", b"read") => {
+                if ctx.libraries(&mut |_| Ok(0)) == Err(Errors::Unsupported) {
+                    self.state.empty(stream)
+                } else {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_library_list(out, &mut |f| ctx.libraries(f))
+                    })
+                }
+            }
+            (b"libraries-svr4", b"read") => {
+                if ctx.libraries_svr4(&mut |_| Ok(0)) == Err(Errors::Unsupported) {
+                    self.state.empty(stream)
+                } else {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_library_list_svr4(out, &mut |f| ctx.libraries_svr4(f))
+                    })
+                }
+            }
+            (b"auxv", b"read") => match ctx.auxv() {
+                Some(auxv) => write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                    out.write_all(auxv)
+                }),
+                None => self.state.empty(stream),
+            },
+            (b"exec-file", b"read") => {
+                // an empty annex selects the current process
+                let pid = if annex.is_empty() {
+                    None
+                } else {
+                    Some(Parser::from_hexu(annex).ok_or(Errors::BadNumber)?)
+                };
+                match ctx.exec_file(pid) {
+                    Some(path) => write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        out.write_all(path)
+                    }),
+                    None => {
+                        self.state.start(stream)?;
+                        self.state.error(stream, Errors::Unsupported)?;
+                        self.state.end(stream)
+                    }
+                }
+            }
+            (b"osdata", b"read") => {
+                if ctx.osdata(b"", &mut |_| Ok(0)) == Err(Errors::Unsupported) {
+                    self.state.empty(stream)
+                } else if ctx.osdata(annex, &mut |_| Ok(0)).is_err() {
+                    self.state.start(stream)?;
+                    self.state.error(stream, Errors::Unsupported)?;
+                    self.state.end(stream)
+                } else {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_osdata(out, annex, &mut |f| ctx.osdata(annex, f))
+                    })
+                }
+            }
+            (b"siginfo", b"read") => match ctx.siginfo() {
+                Some(siginfo) => write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                    out.write_all(siginfo)
+                }),
+                None => self.state.empty(stream),
+            },
+            #[cfg(feature = "btrace")]
+            (b"btrace", b"read") => {
+                let read = BtraceRead::parse(annex)?;
+                if ctx.btrace(BtraceRead::All, &mut |_| Ok(0)) == Err(Errors::Unsupported) {
+                    self.state.empty(stream)
+                } else if ctx.btrace(read, &mut |_| Ok(0)).is_err() {
+                    // gdb falls back to reading everything
+                    self.state.start(stream)?;
+                    self.state.error(stream, Errors::Unsupported)?;
+                    self.state.end(stream)
+                } else {
+                    write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                        write_btrace(out, &mut |f| ctx.btrace(read, f))
+                    })
+                }
+            }
+            #[cfg(feature = "tracepoints")]
+            (b"traceframe-info", b"read") => match ctx.tracepoints() {
+                Some(tracepoints) => {
+                    if tracepoints.frame_info(&mut |_| Ok(0)) == Err(Errors::Unsupported) {
+                        self.state.empty(stream)
+                    } else {
+                        write_xfer(&mut self.state, stream, offset, length, &mut |out| {
+                            write_traceframe_info(out, &mut |f| tracepoints.frame_info(f))
+                        })
+                    }
+                }
+                None => self.state.empty(stream),
+            },
+            _ => self.state.empty(stream),
+        }?;
+        Ok(stream.pos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "btrace")]
+    use crate::btrace::{BtraceBlock, BtraceCallback};
+    use crate::command::testing::{respond, TestCommands};
+    use crate::library::{LibraryCallback, LibraryInfo, ModuleCallback, ModuleInfo};
+    use crate::osdata::{OsColumn, OsDataCallback};
+    use crate::stream::BufferedStream;
+    use crate::target::VirtualTarget;
+
+    #[test]
+    fn it_should_window_objects() {
+        let mut state = ResponseWriter::new(&[]);
+        let mut stream = BufferedStream::new();
+
+        write_xfer(&mut state, &mut stream, 2, 3, &mut |out| {
+            out.write_all(b"abcdefg")
+        })
+        .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$mcde#99"[..]);
+
+        stream.reset();
+        write_xfer(&mut state, &mut stream, 5, 3, &mut |out| {
+            out.write_all(b"abcdefg")
+        })
+        .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$lfg#39"[..]);
+    }
+
+    #[test]
+    fn it_should_escape_objects() {
+        let mut state = ResponseWriter::new(&[]);
+        let mut stream = BufferedStream::new();
+
+        write_xfer(&mut state, &mut stream, 0, 16, &mut |out| {
+            out.write_all(b"a#b")
+        })
+        .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$la}\x03b#af"[..]);
+    }
+
+    #[test]
+    fn it_should_read_target_xml() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qXfer:features:read:target.xml:0,7#82",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(stream.buffer[..stream.pos()], b"$m<?xml v#cf"[..]);
+    }
+
+    #[test]
+    fn it_should_read_past_the_end() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qXfer:features:read:target.xml:ffffffffffffffff,10#dc",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(stream.buffer[..stream.pos()], b"$l#6c"[..]);
+    }
+
+    #[test]
+    fn it_should_read_memory_map() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qXfer:memory-map:read::9a,fff#86",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<memory type=\"ram\" start=\"0x0\" length=\"0x200\"/></memory-map>#02"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_read_threads() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$qXfer:threads:read::0,fff#03", &mut stream, &mut target);
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<?xml version=\"1.0\"?><threads><thread id=\"1\" core=\"0\" name=\"main\"/></threads>#05"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_read_modules() {
+        struct ModuleTarget;
+        impl Target for ModuleTarget {
+            fn libraries(&self, f: &mut ModuleCallback) -> Result<usize, Errors> {
+                f(ModuleInfo::new(b"ovl", 0x8000))
+            }
+        }
+
+        let mut parser = Parser::new(b"$qXfer:libraries:read::0,fff#d5");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut ModuleTarget)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<library-list><library name=\"ovl\"><segment address=\"0x8000\"/></library></library-list>#7e"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_read_libraries() {
+        struct LibraryTarget;
+        impl Target for LibraryTarget {
+            fn libraries_svr4(&self, f: &mut LibraryCallback) -> Result<usize, Errors> {
+                f(LibraryInfo::new(b"libc.so", 0x10, 0x20, 0x30))
+            }
+        }
+
+        let mut parser = Parser::new(b"$qXfer:libraries-svr4:read::21,fff#c4");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut LibraryTarget)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<library name=\"libc.so\" lm=\"0x10\" l_addr=\"0x20\" l_ld=\"0x30\"/></library-list-svr4>#14"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_read_auxv() {
+        struct AuxvTarget;
+        impl Target for AuxvTarget {
+            fn auxv(&self) -> Option<&[u8]> {
+                // AT_PAGESZ 0x1000, AT_NULL
+                Some(&[6, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+            }
+        }
+
+        let mut parser = Parser::new(b"$qXfer:auxv:read::0,8#e2");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut AuxvTarget)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$m\x06\0\0\0\0\x10\0\0#83"[..]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "btrace")]
+    fn it_should_read_branch_traces() {
+        struct BtraceTarget;
+        impl Target for BtraceTarget {
+            fn btrace(&self, read: BtraceRead, f: &mut BtraceCallback) -> Result<usize, Errors> {
+                match read {
+                    BtraceRead::Delta => Err(Errors::Unsupported),
+                    _ => f(BtraceBlock::new(0x100, 0x10c)),
+                }
+            }
+        }
+
+        let mut parser = Parser::new(b"$qXfer:btrace:read:all:50,ff#91");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut BtraceTarget)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<block begin=\"0x100\" end=\"0x10c\"/></btrace>#5d"[..]
+        );
+
+        let mut parser = Parser::new(b"$qXfer:btrace:read:delta:0,ff#2d");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut BtraceTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "tracepoints")]
+    fn it_should_read_traceframe_info() {
+        use crate::tracepoint::{
+            Collected, CollectedCallback, FrameQuery, TraceFrame, TraceStatus, TraceStop,
+            TraceTarget, Tracepoint,
+        };
+
+        struct Frame;
+        impl TraceTarget for Frame {
+            fn init(&mut self) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn define(&mut self, _tracepoint: Tracepoint) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn add_action(&mut self, _: usize, _: usize, _: &[u8]) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn set_enabled(&mut self, _: usize, _: usize, _: bool) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn start(&mut self) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn stop(&mut self) -> Result<usize, Errors> {
+                Ok(0)
+            }
+            fn status(&self) -> TraceStatus {
+                TraceStatus {
+                    running: false,
+                    stop: TraceStop::NotRun,
+                    frames: 0,
+                    created: 0,
+                }
+            }
+            fn find_frame(&mut self, _query: FrameQuery) -> Result<Option<TraceFrame>, Errors> {
+                Ok(None)
+            }
+            fn frame_info(&self, f: &mut CollectedCallback) -> Result<usize, Errors> {
+                f(Collected::Variable(3))
+            }
+        }
+
+        struct FrameTarget(Frame);
+        impl Target for FrameTarget {
+            fn tracepoints(&mut self) -> Option<&mut dyn TraceTarget> {
+                Some(&mut self.0)
+            }
+        }
+
+        let mut parser = Parser::new(b"$qXfer:traceframe-info:read::0,ff#a5");
+        let mut stream = BufferedStream::new();
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut FrameTarget(Frame))
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l<traceframe-info><tvar id=\"3\"/></traceframe-info>#7c"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_read_exec_file() {
+        struct ExecTarget;
+        impl Target for ExecTarget {
+            fn exec_file(&self, pid: Option<usize>) -> Option<&[u8]> {
+                match pid {
+                    None | Some(0x2a) => Some(b"/bin/true"),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut stream = BufferedStream::new();
+        let mut parser = Parser::new(b"$qXfer:exec-file:read:2a:0,fff#1d");
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut ExecTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$l/bin/true#c3"[..]);
+
+        let mut parser = Parser::new(b"$qXfer:exec-file:read:2b:0,fff#1e");
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut ExecTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    #[test]
+    fn it_should_read_osdata() {
+        struct OsTarget;
+        impl Target for OsTarget {
+            fn osdata(&self, annex: &[u8], f: &mut OsDataCallback) -> Result<usize, Errors> {
+                match annex {
+                    b"" => f(&[OsColumn::text(b"Type", b"tasks")]),
+                    b"tasks" => f(&[OsColumn::number(b"id", 1)]),
+                    _ => Err(Errors::Unsupported),
+                }
+            }
+        }
+
+        let mut stream = BufferedStream::new();
+        let mut parser = Parser::new(b"$qXfer:osdata:read:tasks:41,fff#ef");
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut OsTarget)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$l type=\"tasks\"><item><column name=\"id\">1</column></item></osdata>#58"[..]
+        );
+
+        let mut parser = Parser::new(b"$qXfer:osdata:read:queues:0,fff#2c");
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut OsTarget)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    struct SiginfoTarget {
+        siginfo: [u8; 40],
+    }
+
+    impl Target for SiginfoTarget {
+        fn siginfo(&self) -> Option<&[u8]> {
+            Some(&self.siginfo)
+        }
+
+        fn wr_siginfo(&mut self, offset: usize, data: &[u8]) -> Result<usize, Errors> {
+            let end = offset + data.len();
+            if end > self.siginfo.len() {
+                return Err(Errors::AddressOutOfRange);
+            }
+            self.siginfo[offset..end].copy_from_slice(data);
+            Ok(data.len())
+        }
+    }
+
+    fn exec_siginfo(packet: &[u8], stream: &mut BufferedStream, target: &mut SiginfoTarget) {
+        let mut parser = Parser::new(packet);
+        let mut result = parser.parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(stream, target)
+            .unwrap();
+    }
+
+    #[test]
+    fn it_should_read_siginfo() {
+        let mut target = SiginfoTarget { siginfo: [0; 40] };
+        target.siginfo[..4].copy_from_slice(b"\x0b#ab");
+        let mut stream = BufferedStream::new();
+
+        exec_siginfo(b"$qXfer:siginfo:read::0,4#09", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$m\x0b}\x03ab#bb"[..]);
+    }
+
+    #[test]
+    fn it_should_write_siginfo() {
+        let mut target = SiginfoTarget { siginfo: [0; 40] };
+        let mut stream = BufferedStream::new();
+
+        // 34 bytes span two chunks, } escapes the #
+        let mut packet = std::vec::Vec::new();
+        packet.extend_from_slice(b"$qXfer:siginfo:write::2:}\x03");
+        packet.extend_from_slice(&[b'x'; 33]);
+        let sum = Parser::chksm(&packet);
+        packet.push(b'#');
+        packet.extend_from_slice(std::format!("{:02x}", sum).as_bytes());
+
+        exec_siginfo(&packet, &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$22#64"[..]);
+        assert_eq!(target.siginfo[..3], b"\0\0#"[..]);
+        assert_eq!(target.siginfo[3..36], [b'x'; 33]);
+        assert_eq!(target.siginfo[36..], [0; 4]);
+
+        stream.reset();
+        exec_siginfo(b"$qXfer:siginfo:write::26:abc#d0", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    #[test]
+    fn it_should_not_write_unknown_objects() {
+        let mut stream = BufferedStream::new();
+        respond(
+            b"$qXfer:features:write::0:abc#08",
+            &mut stream,
+            &mut VirtualTarget::new(),
+        );
+        assert_eq!(stream.buffer[..stream.pos()], b"$#00"[..]);
+    }
+
+    #[test]
+    fn it_should_reject_unknown_annex() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(
+            b"$qXfer:features:read:other.xml:0,7#1d",
+            &mut stream,
+            &mut target,
+        );
+        assert_eq!(stream.buffer[..stream.pos()], b"$E00#a5"[..]);
+    }
+
+    #[test]
+    fn it_should_reply_empty_to_unknown_objects() {
+        let mut target = VirtualTarget::new();
+        let mut stream = BufferedStream::new();
+
+        respond(b"$qXfer:unknown:read::0,7#2d", &mut stream, &mut target);
+        assert_eq!(stream.buffer[..stream.pos()], b"$#00"[..]);
+    }
+}
@@ -0,0 +1,241 @@
+use super::error::Errors;
+use super::memory::{GuestAddress, MemoryKind, MemoryRegion};
+use super::stop::WatchKind;
+use super::target::Target;
+
+/// Software breakpoints inserted with Z0 and removed with z0.
+/// Supporting them advertises swbreak+ and the target should stop
+/// with StopReason::SwBreak when one is hit.
+/// Without them gdb patches breakpoint instructions with M packets.
+/// Z0 in flash regions of the memory map is served by HwBreakpoints,
+/// or rejected with E13 if the target has none.
+pub trait SwBreakpoints {
+    /// kind is the breakpoint size, see Arch::breakpoint_kind
+    fn add_sw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors>;
+
+    fn remove_sw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors>;
+}
+
+/// true if addr lies in a flash region of the memory map
+/// breakpoint instructions cannot be patched into flash
+pub fn in_flash(map: &[MemoryRegion], addr: GuestAddress) -> bool {
+    map.iter()
+        .any(|region| matches!(region.kind, MemoryKind::Flash { .. }) && region.contains(addr))
+}
+
+/// Hardware breakpoints inserted with Z1 and removed with z1.
+/// Supporting them advertises hwbreak+ and the target should stop
+/// with StopReason::HwBreak when one is hit.
+pub trait HwBreakpoints {
+    /// fails with Errors::CommandError once all comparators are in use
+    fn add_hw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors>;
+
+    fn remove_hw_breakpoint(&mut self, addr: GuestAddress, kind: usize) -> Result<usize, Errors>;
+}
+
+/// Watchpoints inserted with Z2, Z3 and Z4.
+/// A hit is reported with StopReason::Watch.
+pub trait Watchpoints {
+    /// watches len bytes starting at addr
+    /// kinds the hardware cannot watch are rejected with Errors::Unsupported
+    fn add_watchpoint(
+        &mut self,
+        addr: GuestAddress,
+        len: usize,
+        kind: WatchKind,
+    ) -> Result<usize, Errors>;
+
+    fn remove_watchpoint(
+        &mut self,
+        addr: GuestAddress,
+        len: usize,
+        kind: WatchKind,
+    ) -> Result<usize, Errors>;
+}
+
+/// the type field of Z and z
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BreakpointKind {
+    Software,
+    Hardware,
+    Watch(WatchKind),
+}
+
+impl BreakpointKind {
+    pub fn parse(kind: &[u8]) -> Option<Self> {
+        match kind {
+            b"0" => Some(Self::Software),
+            b"1" => Some(Self::Hardware),
+            b"2" => Some(Self::Watch(WatchKind::Write)),
+            b"3" => Some(Self::Watch(WatchKind::Read)),
+            b"4" => Some(Self::Watch(WatchKind::Access)),
+            _ => None,
+        }
+    }
+}
+
+/// a breakpoint or watchpoint as sent with Z and z
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub kind: BreakpointKind,
+    pub addr: GuestAddress,
+    /// the breakpoint kind or the number of watched bytes
+    pub size: usize,
+}
+
+impl Breakpoint {
+    pub const fn new(kind: BreakpointKind, addr: GuestAddress, size: usize) -> Self {
+        Self { kind, addr, size }
+    }
+
+    pub fn insert(&self, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        self.apply(ctx, true)
+    }
+
+    pub fn remove(&self, ctx: &mut dyn Target) -> Result<usize, Errors> {
+        self.apply(ctx, false)
+    }
+
+    fn apply(&self, ctx: &mut dyn Target, insert: bool) -> Result<usize, Errors> {
+        let (addr, size) = (self.addr, self.size);
+        match self.kind {
+            // flash cannot be patched, a comparator is used instead
+            // an empty reply would make gdb fall back to M writes
+            BreakpointKind::Software if in_flash(ctx.memory_map(), addr) => {
+                let breakpoints = ctx.hw_breakpoints().ok_or(Errors::AccessDenied)?;
+                match insert {
+                    true => breakpoints.add_hw_breakpoint(addr, size),
+                    false => breakpoints.remove_hw_breakpoint(addr, size),
+                }
+            }
+            BreakpointKind::Software => {
+                let breakpoints = ctx.sw_breakpoints().ok_or(Errors::Unsupported)?;
+                match insert {
+                    true => breakpoints.add_sw_breakpoint(addr, size),
+                    false => breakpoints.remove_sw_breakpoint(addr, size),
+                }
+            }
+            BreakpointKind::Hardware => {
+                let breakpoints = ctx.hw_breakpoints().ok_or(Errors::Unsupported)?;
+                match insert {
+                    true => breakpoints.add_hw_breakpoint(addr, size),
+                    false => breakpoints.remove_hw_breakpoint(addr, size),
+                }
+            }
+            BreakpointKind::Watch(watch) => {
+                let watchpoints = ctx.watchpoints().ok_or(Errors::Unsupported)?;
+                match insert {
+                    true => watchpoints.add_watchpoint(addr, size, watch),
+                    false => watchpoints.remove_watchpoint(addr, size, watch),
+                }
+            }
+        }
+    }
+}
+
+/// the number of breakpoints a BreakpointList keeps track of
+pub const BREAKPOINT_LIST_LEN: usize = 16;
+
+/// The breakpoints gdb inserted and did not remove yet.
+/// The watchdog removes them before it detaches,
+/// breakpoints beyond BREAKPOINT_LIST_LEN are not tracked.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BreakpointList {
+    entries: [Option<Breakpoint>; BREAKPOINT_LIST_LEN],
+}
+
+impl BreakpointList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// fails with MemoryFilledInterupt once the list is full
+    pub fn add(&mut self, breakpoint: Breakpoint) -> Result<(), Errors> {
+        let free = self.entries.iter_mut().find(|entry| entry.is_none());
+        *free.ok_or(Errors::MemoryFilledInterupt)? = Some(breakpoint);
+        Ok(())
+    }
+
+    pub fn forget(&mut self, breakpoint: Breakpoint) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| **entry == Some(breakpoint))
+        {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = [None; BREAKPOINT_LIST_LEN];
+    }
+
+    /// removes all breakpoints from the target
+    /// one that fails to be removed does not keep the others in place
+    pub fn remove_all(&mut self, ctx: &mut dyn Target) {
+        for breakpoint in self.entries.iter_mut().filter_map(|entry| entry.take()) {
+            let _ = breakpoint.remove(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct CountTarget {
+        inserted: usize,
+    }
+
+    impl Target for CountTarget {
+        fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+            Some(self)
+        }
+    }
+
+    impl SwBreakpoints for CountTarget {
+        fn add_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            self.inserted += 1;
+            Ok(0)
+        }
+
+        fn remove_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            self.inserted -= 1;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_remove_tracked_breakpoints() {
+        let mut target = CountTarget::default();
+        let mut list = BreakpointList::new();
+        for addr in [0x10, 0x20, 0x30] {
+            let breakpoint = Breakpoint::new(BreakpointKind::Software, addr, 2);
+            breakpoint.insert(&mut target).unwrap();
+            list.add(breakpoint).unwrap();
+        }
+        list.forget(Breakpoint::new(BreakpointKind::Software, 0x20, 2));
+        assert_eq!(list.len(), 2);
+
+        list.remove_all(&mut target);
+        assert_eq!(target.inserted, 1);
+        assert!(list.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use super::error::Errors;
+use super::stream::Stream;
+use super::xml;
+
+/// the part of the branch trace gdb requests
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BtraceRead {
+    /// the complete trace
+    All,
+    /// the complete trace if it changed since the last read
+    /// otherwise nothing
+    New,
+    /// only the blocks recorded since the last read
+    Delta,
+}
+
+impl BtraceRead {
+    pub fn parse(annex: &[u8]) -> Result<Self, Errors> {
+        match annex {
+            b"all" => Ok(Self::All),
+            b"new" => Ok(Self::New),
+            b"delta" => Ok(Self::Delta),
+            _ => Err(Errors::Unsupported),
+        }
+    }
+}
+
+/// a sequence of instructions executed without a branch
+/// end is the address of the last instruction, not past it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BtraceBlock {
+    pub begin: usize,
+    pub end: usize,
+}
+
+impl BtraceBlock {
+    pub fn new(begin: usize, end: usize) -> Self {
+        Self { begin, end }
+    }
+
+    /// writes the block as an element of the btrace document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<block")?;
+        size += xml::write_attr_addr(stream, b"begin", self.begin)?;
+        size += xml::write_attr_addr(stream, b"end", self.end)?;
+        size += stream.write_all(b"/>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each block, the most recent one first
+pub type BtraceCallback<'a> = dyn FnMut(BtraceBlock) -> Result<usize, Errors> + 'a;
+
+/// writes the btrace xml document
+/// blocks is expected to call the callback once per block
+pub fn write_btrace(
+    stream: &mut dyn Stream,
+    blocks: &mut dyn FnMut(&mut BtraceCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(
+        b"<?xml version=\"1.0\"?><!DOCTYPE btrace SYSTEM \"btrace.dtd\"><btrace version=\"1.0\">",
+    )?;
+    size += blocks(&mut |block| block.write_xml(stream))?;
+    size += stream.write_all(b"</btrace>")?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_branch_traces() {
+        let mut s = BufferedStream::new();
+        let size = write_btrace(&mut s, &mut |f| {
+            Ok(f(BtraceBlock::new(0x100, 0x10c))? + f(BtraceBlock::new(0x80, 0x88))?)
+        })
+        .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE btrace SYSTEM \"btrace.dtd\"><btrace version=\"1.0\">\
+            <block begin=\"0x100\" end=\"0x10c\"/><block begin=\"0x80\" end=\"0x88\"/></btrace>"[..]
+        );
+        assert_eq!(BtraceRead::parse(b"delta"), Ok(BtraceRead::Delta));
+    }
+}
@@ -0,0 +1,170 @@
+use core::mem::size_of;
+
+use super::basic::control::ENVIRONMENT_LEN;
+use super::basic::query::{SEARCH_CHUNK, SEARCH_PATTERN_LEN, SYMBOL_NAME_LEN};
+use super::command::Commands;
+use super::session::Session;
+use super::stream::BufferedStream;
+
+/// when the memory of a part is in use
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Usage {
+    /// as long as the stub exists
+    Static,
+    /// on the stack while any packet is answered
+    Packet,
+    /// on the stack while a single command runs
+    /// the buffers of different commands never coexist
+    Command,
+    /// on the stack while a reply is compressed after its command returned
+    Compression,
+}
+
+/// memory used by a part of the stub
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Budget {
+    pub name: &'static str,
+    pub bytes: usize,
+    pub usage: Usage,
+}
+
+impl Budget {
+    pub const fn new(name: &'static str, bytes: usize, usage: Usage) -> Self {
+        Self { name, bytes, usage }
+    }
+}
+
+/// memory used by the subsystems enabled at compile time
+/// the reply streams of GdbStub are listed for the default of REPLY_LEN bytes
+/// reverse, fileio, btrace and arch write straight to the reply,
+/// they only add code and their variants of Commands
+pub const REPORT: &[Budget] = &[
+    Budget::new("Session", size_of::<Session>(), Usage::Static),
+    Budget::new(
+        "GdbStub last reply",
+        size_of::<BufferedStream>(),
+        Usage::Static,
+    ),
+    Budget::new("GdbStub reply", size_of::<BufferedStream>(), Usage::Packet),
+    Budget::new("Commands", size_of::<Commands>(), Usage::Packet),
+    Budget::new("qSymbol name buffer", SYMBOL_NAME_LEN, Usage::Command),
+    Budget::new(
+        "qSearch:memory pattern and window",
+        SEARCH_PATTERN_LEN + SEARCH_CHUNK + SEARCH_PATTERN_LEN,
+        Usage::Command,
+    ),
+    Budget::new("QEnvironment buffer", ENVIRONMENT_LEN, Usage::Command),
+    #[cfg(feature = "vfile")]
+    Budget::new(
+        "vFile path and data",
+        super::basic::vfile::VFILE_PATH_LEN + super::basic::vfile::VFILE_READ_CHUNK,
+        Usage::Command,
+    ),
+    #[cfg(feature = "flash")]
+    Budget::new(
+        "vFlashWrite chunk",
+        super::basic::flash::FLASH_WRITE_CHUNK,
+        Usage::Command,
+    ),
+    #[cfg(feature = "tracepoints")]
+    Budget::new(
+        "qTBuffer chunk",
+        super::basic::tracepoint::TRACE_BUFFER_CHUNK,
+        Usage::Command,
+    ),
+    #[cfg(feature = "xfer")]
+    Budget::new(
+        "qXfer write chunk",
+        super::basic::xfer::XFER_WRITE_CHUNK,
+        Usage::Command,
+    ),
+    // GdbStub sends the output one O packet at a time
+    #[cfg(feature = "monitor")]
+    Budget::new(
+        "qRcmd command and console buffer",
+        super::basic::query::MONITOR_CMD_LEN + super::stream::CONSOLE_FRAME_LEN,
+        Usage::Command,
+    ),
+    // gdbstub writes the registers as hex
+    #[cfg(feature = "gdbstub")]
+    Budget::new(
+        "gdbstub register buffer",
+        super::interop::MAX_REGISTER_BYTES * 2,
+        Usage::Command,
+    ),
+    #[cfg(feature = "compression")]
+    Budget::new(
+        "compression scratch",
+        super::stream::REPLY_LEN,
+        Usage::Compression,
+    ),
+    #[cfg(feature = "compression")]
+    Budget::new(
+        "compression hash table",
+        super::compress::COMPRESSION_TABLE_BYTES,
+        Usage::Compression,
+    ),
+    #[cfg(feature = "watchdog")]
+    Budget::new(
+        "Watchdog",
+        size_of::<super::watchdog::Watchdog>(),
+        Usage::Static,
+    ),
+];
+
+/// total memory of all parts with usage
+const fn sum(usage: Usage) -> usize {
+    let mut total = 0;
+    let mut i = 0;
+    while i < REPORT.len() {
+        if REPORT[i].usage as u8 == usage as u8 {
+            total += REPORT[i].bytes;
+        }
+        i += 1;
+    }
+    total
+}
+
+/// total static memory of all enabled subsystems
+pub const fn static_bytes() -> usize {
+    sum(Usage::Static)
+}
+
+/// worst case stack memory used while answering a command
+/// only the largest command buffer counts, they never coexist,
+/// and compression only starts once the command returned
+pub const fn stack_bytes() -> usize {
+    let mut largest = sum(Usage::Compression);
+    let mut i = 0;
+    while i < REPORT.len() {
+        if REPORT[i].usage as u8 == Usage::Command as u8 && REPORT[i].bytes > largest {
+            largest = REPORT[i].bytes;
+        }
+        i += 1;
+    }
+    sum(Usage::Packet) + largest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_sum_budgets() {
+        assert!(static_bytes() >= size_of::<BufferedStream>() + size_of::<Session>());
+        let largest = REPORT
+            .iter()
+            .filter(|b| b.usage == Usage::Command)
+            .map(|b| b.bytes)
+            .max()
+            .unwrap();
+        let compression: usize = REPORT
+            .iter()
+            .filter(|b| b.usage == Usage::Compression)
+            .map(|b| b.bytes)
+            .sum();
+        // the command buffers are not added up
+        let packet = size_of::<BufferedStream>() + size_of::<Commands>();
+        assert_eq!(stack_bytes(), packet + usize::max(largest, compression));
+    }
+}
@@ -0,0 +1,216 @@
+use super::parser::Parser;
+use super::stop::StopReason;
+use super::target::Target;
+use super::thread::ThreadId;
+
+/// An invariant a Target implementation broke.
+/// gdb tends to react to those with confusing behavior
+/// instead of a clear error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// a stop reply names a thread missing from the thread list
+    UnknownStopThread(usize),
+    /// the thread list contains an id more than once
+    DuplicateThread(usize),
+    /// id 0 is reserved for any thread
+    ReservedThreadId,
+    /// a stop reply could not be parsed
+    MalformedStopReply,
+}
+
+/// receives every violation found by a check
+pub type ViolationCallback<'a> = dyn FnMut(Violation) + 'a;
+
+/// returns the thread named by the thread field of a T stop reply
+/// the process of a multiprocess thread id is ignored
+/// or None for replies without one
+pub fn stop_thread(reply: &[u8]) -> Result<Option<usize>, Violation> {
+    match reply.first() {
+        Some(b'T') => (),
+        Some(b'S') | Some(b'W') | Some(b'X') if reply.len() >= 3 => return Ok(None),
+        _ => return Err(Violation::MalformedStopReply),
+    }
+
+    match reply.get(1..3) {
+        Some(signal) if signal.iter().all(|b| Parser::is_hex_digit(*b)) => (),
+        _ => return Err(Violation::MalformedStopReply),
+    }
+
+    for field in reply[3..].split(|b| *b == b';') {
+        let mut pair = field.splitn(2, |b| *b == b':');
+        if let (Some(b"thread"), Some(id)) = (pair.next(), pair.next()) {
+            return ThreadId::parse(id)
+                .map(|id| Some(id.tid))
+                .ok_or(Violation::MalformedStopReply);
+        }
+    }
+    Ok(None)
+}
+
+/// checks the thread list for reserved and duplicate ids
+pub fn check_threads(ctx: &dyn Target, report: &mut ViolationCallback) {
+    let threads = match ctx.thread_target() {
+        Some(threads) => threads,
+        None => return,
+    };
+    let mut index = 0;
+    let _ = threads.threads(&mut |info| {
+        if info.id == 0 {
+            report(Violation::ReservedThreadId);
+        }
+
+        // without allocations every thread is compared with its predecessors
+        let mut other = 0;
+        let _ = threads.threads(&mut |previous| {
+            if other < index && previous.id == info.id {
+                report(Violation::DuplicateThread(info.id));
+            }
+            other += 1;
+            Ok(0)
+        });
+        index += 1;
+        Ok(0)
+    });
+}
+
+/// checks that the thread of a stop reason exists
+pub fn check_stop_reason(ctx: &dyn Target, reason: &StopReason, report: &mut ViolationCallback) {
+    let id = match reason.thread() {
+        Some(id) => id,
+        None => return,
+    };
+
+    // nothing to compare against without a thread list
+    let threads = match ctx.thread_target() {
+        Some(threads) => threads,
+        None => return,
+    };
+    let mut found = false;
+    let _ = threads.threads(&mut |info| {
+        found |= info.id == id;
+        Ok(0)
+    });
+    if !found {
+        report(Violation::UnknownStopThread(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::TestCommands;
+    use crate::error::Errors;
+    use crate::stop::StopReply;
+    use crate::thread::{ThreadCallback, ThreadInfo, ThreadTarget};
+    use std::vec::Vec;
+
+    struct ListTarget(&'static [usize]);
+    impl Target for ListTarget {
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+    }
+
+    impl ThreadTarget for ListTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            for id in self.0 {
+                f(ThreadInfo::new(*id))?;
+            }
+            Ok(0)
+        }
+    }
+
+    fn stop(thread: usize) -> StopReason {
+        StopReason::Stop(StopReply::new(5).with_thread(thread))
+    }
+
+    fn collect(check: &dyn Fn(&mut ViolationCallback)) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        check(&mut |v| violations.push(v));
+        violations
+    }
+
+    #[test]
+    fn it_should_find_stop_threads() {
+        assert_eq!(stop_thread(b"S05"), Ok(None));
+        assert_eq!(stop_thread(b"T05"), Ok(None));
+        assert_eq!(
+            stop_thread(b"T05swbreak:;thread:1f;core:0;"),
+            Ok(Some(0x1f))
+        );
+        assert_eq!(stop_thread(b"T05thread:p1.2;"), Ok(Some(2)));
+        assert_eq!(stop_thread(b"T0"), Err(Violation::MalformedStopReply));
+        assert_eq!(
+            stop_thread(b"T05thread:;"),
+            Err(Violation::MalformedStopReply)
+        );
+        assert_eq!(stop_thread(b"OK"), Err(Violation::MalformedStopReply));
+    }
+
+    #[test]
+    fn it_should_report_unknown_stop_threads() {
+        let target = ListTarget(&[1, 2]);
+        assert!(collect(&|r| check_stop_reason(&target, &stop(2), r)).is_empty());
+        assert_eq!(
+            collect(&|r| check_stop_reason(&target, &stop(3), r)),
+            [Violation::UnknownStopThread(3)]
+        );
+
+        // nothing to compare against without a thread list
+        struct NoThreads;
+        impl Target for NoThreads {}
+        let target = NoThreads;
+        assert!(collect(&|r| check_stop_reason(&target, &stop(3), r)).is_empty());
+    }
+
+    #[test]
+    fn it_should_report_bad_thread_lists() {
+        assert!(collect(&|r| check_threads(&ListTarget(&[1, 2, 3]), r)).is_empty());
+        assert_eq!(
+            collect(&|r| check_threads(&ListTarget(&[0, 2, 2]), r)),
+            [Violation::ReservedThreadId, Violation::DuplicateThread(2)]
+        );
+    }
+
+    #[test]
+    fn it_should_check_stop_replies_in_debug_builds() {
+        use crate::command::Command;
+        use crate::stream::BufferedStream;
+        use core::cell::RefCell;
+
+        struct StaleTarget(RefCell<Vec<Violation>>);
+        impl Target for StaleTarget {
+            fn stop_reason(&self) -> StopReason {
+                stop(2)
+            }
+
+            fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+                Some(self)
+            }
+
+            fn violation(&self, violation: Violation) {
+                self.0.borrow_mut().push(violation);
+            }
+        }
+
+        impl ThreadTarget for StaleTarget {
+            fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+                f(ThreadInfo::new(1))
+            }
+        }
+
+        let mut target = StaleTarget(RefCell::new(Vec::new()));
+        let mut stream = BufferedStream::new();
+        let mut result = Parser::new(b"$?#3f").parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(&mut stream, &mut target)
+            .unwrap();
+
+        if cfg!(debug_assertions) {
+            assert_eq!(*target.0.borrow(), [Violation::UnknownStopThread(2)]);
+        }
+    }
+}
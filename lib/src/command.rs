@@ -1,6 +1,22 @@
+use super::basic::breakpoint::*;
+use super::basic::control::*;
+#[cfg(feature = "flash")]
+use super::basic::flash::*;
+#[cfg(feature = "lldb")]
+use super::basic::lldb::*;
+use super::basic::query::*;
+use super::basic::register::*;
 use super::basic::required::*;
+use super::basic::thread::*;
+#[cfg(feature = "tracepoints")]
+use super::basic::tracepoint::*;
+#[cfg(feature = "vfile")]
+use super::basic::vfile::*;
+#[cfg(feature = "xfer")]
+use super::basic::xfer::*;
 use super::error::Errors;
 use super::parser::{Parsed, Parser};
+use super::resume::ResumeKind;
 use super::stream::Stream;
 use super::target::Target;
 
@@ -20,6 +36,192 @@ pub trait SupportedCommands<'a> {
             )))),
             b"m" => Parsed::ack(Some(Commands::ReadMemory(ReadMemoryCommand::new(args)))),
             b"M" => Parsed::ack(Some(Commands::WriteMemory(WriteMemoryCommand::new(args)))),
+            b"p" => Parsed::ack(Some(Commands::ReadSingleRegister(
+                ReadRegisterCommand::new(args),
+            ))),
+            b"P" => Parsed::ack(Some(Commands::WriteSingleRegister(
+                WriteRegisterCommand::new(args),
+            ))),
+            b"D" => Parsed::ack(Some(Commands::Detach(DetachCommand::new(args)))),
+            b"!" => Parsed::ack(Some(Commands::ExtendedMode(ExtendedModeCommand::new(args)))),
+            #[cfg(feature = "reverse")]
+            b"b" => match args {
+                b"c" => Parsed::ack(Some(Commands::ReverseContinue(
+                    ReverseContinueCommand::new(args),
+                ))),
+                b"s" => Parsed::ack(Some(Commands::ReverseStep(ReverseStepCommand::new(args)))),
+                _ => Parsed::ack(Some(Commands::NotImplemented(NotImplemented::new()))),
+            },
+            b"H" => Parsed::ack(Some(Commands::SelectThread(SelectThreadCommand::new(args)))),
+            b"T" => Parsed::ack(Some(Commands::ThreadAlive(ThreadAliveCommand::new(args)))),
+            b"qfThreadInfo" => Parsed::ack(Some(Commands::ThreadInfo(ThreadInfoCommand::new(
+                args, true,
+            )))),
+            b"qsThreadInfo" => Parsed::ack(Some(Commands::ThreadInfo(ThreadInfoCommand::new(
+                args, false,
+            )))),
+            b"qC" => Parsed::ack(Some(Commands::CurrentThread(CurrentThreadCommand::new(
+                args,
+            )))),
+            b"qThreadExtraInfo" => Parsed::ack(Some(Commands::ThreadExtraInfo(
+                ThreadExtraInfoCommand::new(args),
+            ))),
+            b"Z" => Parsed::ack(Some(Commands::Breakpoint(BreakpointCommand::new(
+                args, true,
+            )))),
+            b"z" => Parsed::ack(Some(Commands::Breakpoint(BreakpointCommand::new(
+                args, false,
+            )))),
+            b"c" => Parsed::ack(Some(Commands::Resume(ResumeCommand::new(
+                args,
+                ResumeKind::Continue,
+                false,
+            )))),
+            b"C" => Parsed::ack(Some(Commands::Resume(ResumeCommand::new(
+                args,
+                ResumeKind::Continue,
+                true,
+            )))),
+            b"s" => Parsed::ack(Some(Commands::Resume(ResumeCommand::new(
+                args,
+                ResumeKind::Step,
+                false,
+            )))),
+            b"S" => Parsed::ack(Some(Commands::Resume(ResumeCommand::new(
+                args,
+                ResumeKind::Step,
+                true,
+            )))),
+            b"vCont" => Parsed::ack(Some(Commands::Resume(ResumeCommand::vcont(args)))),
+            b"vCont?" => Parsed::ack(Some(Commands::VContSupported(VContSupportedCommand::new(
+                args,
+            )))),
+            b"i" => Parsed::ack(Some(Commands::CycleStep(CycleStepCommand::new(
+                args, false,
+            )))),
+            b"I" => Parsed::ack(Some(Commands::CycleStep(CycleStepCommand::new(args, true)))),
+            b"vRun" => Parsed::ack(Some(Commands::Run(RunCommand::new(args)))),
+            b"R" => Parsed::ack(Some(Commands::Restart(RestartCommand::new(args)))),
+            #[cfg(feature = "fileio")]
+            b"F" => Parsed::ack(Some(Commands::FileIoReply(FileIoReplyCommand::new(args)))),
+            b"A" => Parsed::ack(Some(Commands::Arguments(ArgumentsCommand::new(args)))),
+            b"QEnvironmentHexEncoded" => Parsed::ack(Some(Commands::Environment(
+                EnvironmentCommand::new(args, EnvironmentOp::Set),
+            ))),
+            b"QEnvironmentUnset" => Parsed::ack(Some(Commands::Environment(
+                EnvironmentCommand::new(args, EnvironmentOp::Unset),
+            ))),
+            b"QEnvironmentReset" => Parsed::ack(Some(Commands::Environment(
+                EnvironmentCommand::new(args, EnvironmentOp::Reset),
+            ))),
+            b"vCtrlC" => Parsed::ack(Some(Commands::CtrlC(CtrlCCommand::new(args)))),
+            b"vKill" => Parsed::ack(Some(Commands::Kill(KillCommand::new(args)))),
+            b"vAttach" => Parsed::ack(Some(Commands::Attach(AttachCommand::new(args)))),
+            b"QNonStop" => Parsed::ack(Some(Commands::NonStop(NonStopCommand::new(args)))),
+            b"QThreadEvents" => {
+                Parsed::ack(Some(Commands::ThreadEvents(ThreadEventsCommand::new(args))))
+            }
+            #[cfg(feature = "btrace")]
+            b"Qbtrace" => Parsed::ack(Some(Commands::Btrace(BtraceCommand::new(args)))),
+            b"QPassSignals" => {
+                Parsed::ack(Some(Commands::Signals(SignalsCommand::new(args, false))))
+            }
+            b"QProgramSignals" => {
+                Parsed::ack(Some(Commands::Signals(SignalsCommand::new(args, true))))
+            }
+            b"QCatchSyscalls" => Parsed::ack(Some(Commands::CatchSyscalls(
+                CatchSyscallsCommand::new(args),
+            ))),
+            b"vStopped" => Parsed::ack(Some(Commands::Stopped(StoppedCommand::new(args)))),
+            #[cfg(feature = "flash")]
+            b"vFlashErase" => Parsed::ack(Some(Commands::FlashErase(FlashEraseCommand::new(args)))),
+            #[cfg(feature = "flash")]
+            b"vFlashWrite" => Parsed::ack(Some(Commands::FlashWrite(FlashWriteCommand::new(args)))),
+            #[cfg(feature = "flash")]
+            b"vFlashDone" => Parsed::ack(Some(Commands::FlashDone(FlashDoneCommand::new(args)))),
+            #[cfg(feature = "vfile")]
+            b"vFile" => Parsed::ack(Some(Commands::VFile(VFileCommand::new(args)))),
+            b"qSnapshotRead" => {
+                Parsed::ack(Some(Commands::SnapshotRead(SnapshotReadCommand::new(args))))
+            }
+            b"qCRC" => Parsed::ack(Some(Commands::Crc(CrcCommand::new(args)))),
+            b"qGetTLSAddr" => Parsed::ack(Some(Commands::TlsAddress(TlsAddressCommand::new(args)))),
+            b"qSearch" => Parsed::ack(Some(Commands::SearchMemory(SearchMemoryCommand::new(args)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTinit" => Parsed::ack(Some(Commands::TraceInit(TraceInitCommand::new(args)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTDP" => Parsed::ack(Some(Commands::TraceDefine(TraceDefineCommand::new(args)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTEnable" => Parsed::ack(Some(Commands::TraceEnable(TraceEnableCommand::new(
+                args, true,
+            )))),
+            #[cfg(feature = "tracepoints")]
+            b"QTDisable" => Parsed::ack(Some(Commands::TraceEnable(TraceEnableCommand::new(
+                args, false,
+            )))),
+            #[cfg(feature = "tracepoints")]
+            b"QTStart" => Parsed::ack(Some(Commands::TraceRun(TraceRunCommand::new(args, true)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTStop" => Parsed::ack(Some(Commands::TraceRun(TraceRunCommand::new(args, false)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTFrame" => Parsed::ack(Some(Commands::TraceFrame(TraceFrameCommand::new(args)))),
+            #[cfg(feature = "tracepoints")]
+            b"QTro" => Parsed::ack(Some(Commands::TraceReadOnly(TraceReadOnlyCommand::new(
+                args,
+            )))),
+            #[cfg(feature = "tracepoints")]
+            b"qTBuffer" => Parsed::ack(Some(Commands::TraceBuffer(TraceBufferCommand::new(args)))),
+            #[cfg(feature = "tracepoints")]
+            b"qTStatus" => Parsed::ack(Some(Commands::TraceStatus(TraceStatusCommand::new(args)))),
+            b"qSymbol" => Parsed::ack(Some(Commands::Symbol(SymbolCommand::new(args)))),
+            #[cfg(feature = "monitor")]
+            b"qRcmd" => Parsed::ack(Some(Commands::Monitor(MonitorCommand::new(args)))),
+            b"QStartNoAckMode" => Parsed::ack(Some(Commands::StartNoAckMode(
+                StartNoAckModeCommand::new(args),
+            ))),
+            b"qSupported" => Parsed::ack(Some(Commands::Supported(SupportedCommand::new(args)))),
+            #[cfg(feature = "lldb")]
+            b"_" => match args.split_first() {
+                Some((b'M', args)) => {
+                    Parsed::ack(Some(Commands::Allocate(AllocateCommand::new(args))))
+                }
+                Some((b'm', args)) => {
+                    Parsed::ack(Some(Commands::Deallocate(DeallocateCommand::new(args))))
+                }
+                _ => Parsed::ack(Some(Commands::NotImplemented(NotImplemented::new()))),
+            },
+            #[cfg(feature = "lldb")]
+            b"QSaveRegisterState" => Parsed::ack(Some(Commands::SaveRegisterState(
+                SaveRegisterStateCommand::new(args),
+            ))),
+            #[cfg(feature = "lldb")]
+            b"QRestoreRegisterState" => Parsed::ack(Some(Commands::RestoreRegisterState(
+                RestoreRegisterStateCommand::new(args),
+            ))),
+            #[cfg(feature = "compression")]
+            b"QEnableCompression" => Parsed::ack(Some(Commands::EnableCompression(
+                EnableCompressionCommand::new(args),
+            ))),
+            #[cfg(feature = "lldb")]
+            b"QListThreadsInStopReply" => Parsed::ack(Some(Commands::ListThreadsInStopReply(
+                ListThreadsInStopReplyCommand::new(args),
+            ))),
+            #[cfg(feature = "lldb")]
+            b"qHostInfo" => Parsed::ack(Some(Commands::HostInfo(HostInfoCommand::new(args)))),
+            #[cfg(feature = "lldb")]
+            b"qMemoryRegionInfo" => Parsed::ack(Some(Commands::MemoryRegionInfo(
+                MemoryRegionInfoCommand::new(args),
+            ))),
+            #[cfg(feature = "lldb")]
+            b"jThreadsInfo" => {
+                Parsed::ack(Some(Commands::ThreadsInfo(ThreadsInfoCommand::new(args))))
+            }
+            #[cfg(feature = "lldb")]
+            name if name.starts_with(b"qRegisterInfo") => Parsed::ack(Some(
+                Commands::RegisterInfo(RegisterInfoCommand::new(&name[b"qRegisterInfo".len()..])),
+            )),
+            #[cfg(feature = "xfer")]
+            b"qXfer" => Parsed::ack(Some(Commands::Xfer(XferCommand::new(args)))),
             _ => Parsed::ack(Some(Commands::NotImplemented(NotImplemented::new()))),
         }
     }
@@ -35,12 +237,102 @@ pub enum Commands<'a> {
     AcknowledgeLast, // this is returned if the packet received a +
     NotImplemented(NotImplemented<'a>),
     Retransmit(Retransmit<'a>),
+    Error(ErrorReply<'a>),
     Acknowledge(Acknowledge<'a>),
     Reason(ReasonCommand<'a>),
     ReadRegister(ReadRegistersCommand<'a>),
     WriteRegister(WriteRegistersCommand<'a>),
     ReadMemory(ReadMemoryCommand<'a>),
     WriteMemory(WriteMemoryCommand<'a>),
+    ReadSingleRegister(ReadRegisterCommand<'a>),
+    WriteSingleRegister(WriteRegisterCommand<'a>),
+    Detach(DetachCommand<'a>),
+    NonStop(NonStopCommand<'a>),
+    ThreadEvents(ThreadEventsCommand<'a>),
+    #[cfg(feature = "btrace")]
+    Btrace(BtraceCommand<'a>),
+    ExtendedMode(ExtendedModeCommand<'a>),
+    Run(RunCommand<'a>),
+    Attach(AttachCommand<'a>),
+    Kill(KillCommand<'a>),
+    Breakpoint(BreakpointCommand<'a>),
+    SelectThread(SelectThreadCommand<'a>),
+    ThreadAlive(ThreadAliveCommand<'a>),
+    ThreadInfo(ThreadInfoCommand<'a>),
+    CurrentThread(CurrentThreadCommand<'a>),
+    ThreadExtraInfo(ThreadExtraInfoCommand<'a>),
+    Resume(ResumeCommand<'a>),
+    VContSupported(VContSupportedCommand<'a>),
+    CycleStep(CycleStepCommand<'a>),
+    Interrupt(InterruptCommand<'a>),
+    CtrlC(CtrlCCommand<'a>),
+    Arguments(ArgumentsCommand<'a>),
+    Environment(EnvironmentCommand<'a>),
+    Restart(RestartCommand<'a>),
+    #[cfg(feature = "reverse")]
+    ReverseContinue(ReverseContinueCommand<'a>),
+    #[cfg(feature = "reverse")]
+    ReverseStep(ReverseStepCommand<'a>),
+    #[cfg(feature = "fileio")]
+    FileIoReply(FileIoReplyCommand<'a>),
+    Stopped(StoppedCommand<'a>),
+    Signals(SignalsCommand<'a>),
+    CatchSyscalls(CatchSyscallsCommand<'a>),
+    #[cfg(feature = "flash")]
+    FlashErase(FlashEraseCommand<'a>),
+    #[cfg(feature = "flash")]
+    FlashWrite(FlashWriteCommand<'a>),
+    #[cfg(feature = "flash")]
+    FlashDone(FlashDoneCommand<'a>),
+    #[cfg(feature = "vfile")]
+    VFile(VFileCommand<'a>),
+    SnapshotRead(SnapshotReadCommand<'a>),
+    Symbol(SymbolCommand<'a>),
+    Crc(CrcCommand<'a>),
+    SearchMemory(SearchMemoryCommand<'a>),
+    TlsAddress(TlsAddressCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceInit(TraceInitCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceDefine(TraceDefineCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceEnable(TraceEnableCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceRun(TraceRunCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceFrame(TraceFrameCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceStatus(TraceStatusCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceBuffer(TraceBufferCommand<'a>),
+    #[cfg(feature = "tracepoints")]
+    TraceReadOnly(TraceReadOnlyCommand<'a>),
+    #[cfg(feature = "monitor")]
+    Monitor(MonitorCommand<'a>),
+    Supported(SupportedCommand<'a>),
+    StartNoAckMode(StartNoAckModeCommand<'a>),
+    #[cfg(feature = "compression")]
+    EnableCompression(EnableCompressionCommand<'a>),
+    #[cfg(feature = "lldb")]
+    ListThreadsInStopReply(ListThreadsInStopReplyCommand<'a>),
+    #[cfg(feature = "lldb")]
+    HostInfo(HostInfoCommand<'a>),
+    #[cfg(feature = "lldb")]
+    SaveRegisterState(SaveRegisterStateCommand<'a>),
+    #[cfg(feature = "lldb")]
+    RestoreRegisterState(RestoreRegisterStateCommand<'a>),
+    #[cfg(feature = "lldb")]
+    Allocate(AllocateCommand<'a>),
+    #[cfg(feature = "lldb")]
+    Deallocate(DeallocateCommand<'a>),
+    #[cfg(feature = "lldb")]
+    RegisterInfo(RegisterInfoCommand<'a>),
+    #[cfg(feature = "lldb")]
+    MemoryRegionInfo(MemoryRegionInfoCommand<'a>),
+    #[cfg(feature = "lldb")]
+    ThreadsInfo(ThreadsInfoCommand<'a>),
+    #[cfg(feature = "xfer")]
+    Xfer(XferCommand<'a>),
 }
 
 impl Command for Commands<'_> {
@@ -51,12 +343,102 @@ impl Command for Commands<'_> {
             }
             Self::NotImplemented(c) => c.response(stream, ctx),
             Self::Retransmit(c) => c.response(stream, ctx),
+            Self::Error(c) => c.response(stream, ctx),
             Self::Acknowledge(c) => c.response(stream, ctx),
             Self::Reason(c) => c.response(stream, ctx),
             Self::ReadRegister(c) => c.response(stream, ctx),
             Self::WriteRegister(c) => c.response(stream, ctx),
             Self::ReadMemory(c) => c.response(stream, ctx),
             Self::WriteMemory(c) => c.response(stream, ctx),
+            Self::ReadSingleRegister(c) => c.response(stream, ctx),
+            Self::WriteSingleRegister(c) => c.response(stream, ctx),
+            Self::Detach(c) => c.response(stream, ctx),
+            Self::NonStop(c) => c.response(stream, ctx),
+            Self::ThreadEvents(c) => c.response(stream, ctx),
+            #[cfg(feature = "btrace")]
+            Self::Btrace(c) => c.response(stream, ctx),
+            Self::ExtendedMode(c) => c.response(stream, ctx),
+            Self::Run(c) => c.response(stream, ctx),
+            Self::Attach(c) => c.response(stream, ctx),
+            Self::Kill(c) => c.response(stream, ctx),
+            Self::Breakpoint(c) => c.response(stream, ctx),
+            Self::SelectThread(c) => c.response(stream, ctx),
+            Self::ThreadAlive(c) => c.response(stream, ctx),
+            Self::ThreadInfo(c) => c.response(stream, ctx),
+            Self::CurrentThread(c) => c.response(stream, ctx),
+            Self::ThreadExtraInfo(c) => c.response(stream, ctx),
+            Self::Resume(c) => c.response(stream, ctx),
+            Self::VContSupported(c) => c.response(stream, ctx),
+            Self::CycleStep(c) => c.response(stream, ctx),
+            Self::Interrupt(c) => c.response(stream, ctx),
+            Self::CtrlC(c) => c.response(stream, ctx),
+            Self::Arguments(c) => c.response(stream, ctx),
+            Self::Environment(c) => c.response(stream, ctx),
+            Self::Restart(c) => c.response(stream, ctx),
+            #[cfg(feature = "reverse")]
+            Self::ReverseContinue(c) => c.response(stream, ctx),
+            #[cfg(feature = "reverse")]
+            Self::ReverseStep(c) => c.response(stream, ctx),
+            #[cfg(feature = "fileio")]
+            Self::FileIoReply(c) => c.response(stream, ctx),
+            Self::Stopped(c) => c.response(stream, ctx),
+            Self::Signals(c) => c.response(stream, ctx),
+            Self::CatchSyscalls(c) => c.response(stream, ctx),
+            #[cfg(feature = "flash")]
+            Self::FlashErase(c) => c.response(stream, ctx),
+            #[cfg(feature = "flash")]
+            Self::FlashWrite(c) => c.response(stream, ctx),
+            #[cfg(feature = "flash")]
+            Self::FlashDone(c) => c.response(stream, ctx),
+            #[cfg(feature = "vfile")]
+            Self::VFile(c) => c.response(stream, ctx),
+            Self::SnapshotRead(c) => c.response(stream, ctx),
+            Self::Symbol(c) => c.response(stream, ctx),
+            Self::Crc(c) => c.response(stream, ctx),
+            Self::SearchMemory(c) => c.response(stream, ctx),
+            Self::TlsAddress(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceInit(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceDefine(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceEnable(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceRun(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceFrame(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceStatus(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceBuffer(c) => c.response(stream, ctx),
+            #[cfg(feature = "tracepoints")]
+            Self::TraceReadOnly(c) => c.response(stream, ctx),
+            #[cfg(feature = "monitor")]
+            Self::Monitor(c) => c.response(stream, ctx),
+            Self::Supported(c) => c.response(stream, ctx),
+            Self::StartNoAckMode(c) => c.response(stream, ctx),
+            #[cfg(feature = "compression")]
+            Self::EnableCompression(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::ListThreadsInStopReply(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::HostInfo(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::SaveRegisterState(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::RestoreRegisterState(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::Allocate(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::Deallocate(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::RegisterInfo(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::MemoryRegionInfo(c) => c.response(stream, ctx),
+            #[cfg(feature = "lldb")]
+            Self::ThreadsInfo(c) => c.response(stream, ctx),
+            #[cfg(feature = "xfer")]
+            Self::Xfer(c) => c.response(stream, ctx),
         }
     }
 }
@@ -75,24 +457,37 @@ pub trait Command {
 #[derive(Debug, PartialEq)]
 pub struct ResponseWriter<'a> {
     pub fields: &'a [u8],
+    chksm: u32,
 }
 
 impl<'a> ResponseWriter<'a> {
     pub fn new(fields: &'a [u8]) -> Self {
-        Self { fields }
+        Self { fields, chksm: 0 }
     }
 
     // starts a packet
+    // the stream checksum is remembered so that
+    // multiple packets can be written to the same stream
     pub fn start(&mut self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        self.chksm = stream.chksm();
         self.write_force(stream, b'$')
     }
 
+    // starts a notification packet
+    // unlike $ the % is not part of the stream checksum
+    // so it is excluded by taking the snapshot afterwards
+    pub fn start_notification(&mut self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let size = self.write_force(stream, b'%')?;
+        self.chksm = stream.chksm();
+        Ok(size)
+    }
+
     // ends a packet
     pub fn end(&mut self, stream: &mut dyn Stream) -> Result<usize, Errors> {
         let mut size = self.write_force(stream, b'#')?;
 
         // write checksum byte
-        let chksm = (stream.chksm() % 256) as u8;
+        let chksm = (stream.chksm().wrapping_sub(self.chksm) % 256) as u8;
 
         size += self.write_hex(stream, chksm)?;
 
@@ -110,9 +505,23 @@ impl<'a> ResponseWriter<'a> {
         self.write_all(stream, b"OK")
     }
 
-    pub fn error(&mut self, stream: &mut dyn Stream, _error: Errors) -> Result<usize, Errors> {
-        // TODO write error code
-        self.write_all(stream, b"E00")
+    /// writes an empty packet
+    /// telling gdb that a command is not supported
+    pub fn empty(&mut self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = self.start(stream)?;
+        size += self.end(stream)?;
+        Ok(size)
+    }
+
+    pub fn error(&mut self, stream: &mut dyn Stream, error: Errors) -> Result<usize, Errors> {
+        // TODO write error codes for the remaining errors
+        match error {
+            // EFAULT
+            Errors::MemoryFault => self.write_all(stream, b"E14"),
+            // EACCES
+            Errors::AccessDenied => self.write_all(stream, b"E13"),
+            _ => self.write_all(stream, b"E00"),
+        }
     }
 
     pub fn escape(byte: u8) -> u8 {
@@ -178,6 +587,39 @@ impl Command for Retransmit<'_> {
     }
 }
 
+/**
+ * Error reply for packets that could not be handled
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct ErrorReply<'a> {
+    state: ResponseWriter<'a>,
+    error: Errors,
+}
+
+impl<'a> ErrorReply<'a> {
+    pub fn new(error: Errors) -> Self {
+        Self {
+            state: ResponseWriter::new(&[]),
+            error,
+        }
+    }
+}
+
+impl Command for ErrorReply<'_> {
+    fn response(
+        &mut self,
+        stream: &mut dyn Stream,
+        _ctx: &mut dyn Target,
+    ) -> Result<usize, Errors> {
+        stream.reset();
+        self.state.start(stream)?;
+        self.state.error(stream, self.error)?;
+        self.state.end(stream)?;
+        Ok(stream.pos())
+    }
+}
+
 /**
  * Acknowledge
  */
@@ -242,20 +684,52 @@ impl Command for NotImplemented<'_> {
         _ctx: &mut dyn Target,
     ) -> Result<usize, Errors> {
         stream.reset();
-        let mut size = self.state.start(stream)?;
-        size += self.state.end(stream)?;
-        Ok(size)
+        self.state.empty(stream)
     }
 }
 
+/// helpers shared by the command tests
 #[cfg(test)]
+pub(crate) mod testing {
+    use super::{Command, SupportedCommands};
+    use crate::parser::Parser;
+    use crate::stream::{BufferedStream, Stream};
+    use crate::target::Target;
+    use std::vec::Vec;
+
+    /// supports all optional commands
+    pub(crate) struct TestCommands;
+    impl<'a> SupportedCommands<'a> for TestCommands {}
+
+    /// parses a packet and writes the response to stream
+    pub(crate) fn respond(
+        packet: &[u8],
+        stream: &mut dyn Stream,
+        target: &mut dyn Target,
+    ) -> usize {
+        let mut result = Parser::new(packet).parse_packet(&TestCommands);
+        result
+            .command
+            .as_mut()
+            .unwrap()
+            .response(stream, target)
+            .unwrap()
+    }
+
+    /// parses a packet and returns the response
+    pub(crate) fn exec(packet: &[u8], target: &mut dyn Target) -> Vec<u8> {
+        let mut stream = BufferedStream::new();
+        respond(packet, &mut stream, target);
+        stream.buffer[..stream.pos()].to_vec()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::assign_op_pattern)]
 mod tests {
     use super::*;
     use crate::stream::BufferedStream;
 
-    struct TestCommands;
-    impl<'a> SupportedCommands<'a> for TestCommands {}
-
     #[derive(Debug, Clone, PartialEq)]
     struct TestCtx;
     impl Target for TestCtx {}
@@ -0,0 +1,376 @@
+/*
+ * lldb packet compression
+ * replies are compressed into a caller provided buffer
+ * so no allocation is needed
+ */
+
+/// replies shorter than this are sent uncompressed
+pub const COMPRESSION_MIN_SIZE: usize = 384;
+
+// both encoders share a small hash table on the stack
+const HASH_BITS: u32 = 9;
+const MIN_MATCH: usize = 4;
+
+/// stack used by the match finder while compressing a reply
+pub const COMPRESSION_TABLE_BYTES: usize = core::mem::size_of::<Matcher>();
+
+const DEFLATE_MAX_DIST: usize = 32768;
+const DEFLATE_MAX_LEN: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// lz4 requires the last match to start 12 bytes before the end
+// and the last 5 bytes to be literals
+const LZ4_MFLIMIT: usize = 12;
+const LZ4_LAST_LITERALS: usize = 5;
+const LZ4_MAX_DIST: usize = 65535;
+const LZ4_BLOCK_MAGIC: &[u8; 4] = b"bv41";
+const LZ4_END_MAGIC: &[u8; 4] = b"bv4$";
+const LZ4_HEADER_LEN: usize = 12;
+
+/// compression algorithms lldb can negotiate with QEnableCompression
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    /// raw deflate stream without zlib header
+    ZlibDeflate,
+    /// lz4 blocks in the bv41 framing of apple's compression library
+    Lz4,
+}
+
+impl Compression {
+    pub fn parse(name: &[u8]) -> Option<Self> {
+        match name {
+            b"zlib-deflate" => Some(Self::ZlibDeflate),
+            b"lz4" => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Self::ZlibDeflate => b"zlib-deflate",
+            Self::Lz4 => b"lz4",
+        }
+    }
+
+    /// compresses input into out
+    /// returns None if out is too small
+    pub fn compress(&self, input: &[u8], out: &mut [u8]) -> Option<usize> {
+        match self {
+            Self::ZlibDeflate => deflate(input, out),
+            Self::Lz4 => lz4(input, out),
+        }
+    }
+}
+
+/// greedy match finder remembering the last position of each hash
+struct Matcher {
+    table: [u32; 1 << HASH_BITS],
+}
+
+impl Matcher {
+    fn new() -> Self {
+        Self {
+            table: [u32::MAX; 1 << HASH_BITS],
+        }
+    }
+
+    fn hash(input: &[u8], pos: usize) -> usize {
+        let v = u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]]);
+        (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// returns distance and length of a match at pos
+    /// the match never extends past limit
+    fn find(
+        &mut self,
+        input: &[u8],
+        pos: usize,
+        limit: usize,
+        max_dist: usize,
+        max_len: usize,
+    ) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > limit {
+            return None;
+        }
+        let hash = Self::hash(input, pos);
+        let candidate = self.table[hash];
+        self.table[hash] = pos as u32;
+        if candidate == u32::MAX {
+            return None;
+        }
+
+        let candidate = candidate as usize;
+        let dist = pos - candidate;
+        if dist > max_dist {
+            return None;
+        }
+        let mut len = 0;
+        while pos + len < limit && len < max_len && input[candidate + len] == input[pos + len] {
+            len += 1;
+        }
+        if len >= MIN_MATCH {
+            Some((dist, len))
+        } else {
+            None
+        }
+    }
+}
+
+struct SliceWriter<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl SliceWriter<'_> {
+    fn push(&mut self, byte: u8) -> Option<()> {
+        *self.out.get_mut(self.pos)? = byte;
+        self.pos += 1;
+        Some(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> Option<()> {
+        self.out
+            .get_mut(self.pos..self.pos + bytes.len())?
+            .copy_from_slice(bytes);
+        self.pos += bytes.len();
+        Some(())
+    }
+}
+
+/// deflate writes bits starting with the least significant bit
+struct BitWriter<'a> {
+    out: SliceWriter<'a>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter<'_> {
+    fn put(&mut self, value: u32, len: u32) -> Option<()> {
+        self.bits |= value << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8)?;
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+        Some(())
+    }
+
+    /// huffman codes are stored most significant bit first
+    fn put_code(&mut self, code: u32, len: u32) -> Option<()> {
+        self.put(code.reverse_bits() >> (32 - len), len)
+    }
+
+    /// writes a symbol of the fixed literal/length code
+    fn put_symbol(&mut self, symbol: u32) -> Option<()> {
+        match symbol {
+            0..=143 => self.put_code(0x30 + symbol, 8),
+            144..=255 => self.put_code(0x190 + symbol - 144, 9),
+            256..=279 => self.put_code(symbol - 256, 7),
+            _ => self.put_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn put_match(&mut self, dist: usize, len: usize) -> Option<()> {
+        let i = LENGTH_BASE.iter().rposition(|base| *base as usize <= len)?;
+        self.put_symbol(257 + i as u32)?;
+        self.put(
+            (len - LENGTH_BASE[i] as usize) as u32,
+            LENGTH_EXTRA[i] as u32,
+        )?;
+
+        let i = DIST_BASE.iter().rposition(|base| *base as usize <= dist)?;
+        self.put_code(i as u32, 5)?;
+        self.put((dist - DIST_BASE[i] as usize) as u32, DIST_EXTRA[i] as u32)
+    }
+
+    fn finish(mut self) -> Option<usize> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8)?;
+        }
+        Some(self.out.pos)
+    }
+}
+
+/// compresses input as a single raw deflate block with fixed huffman codes
+pub fn deflate(input: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut writer = BitWriter {
+        out: SliceWriter { out, pos: 0 },
+        bits: 0,
+        count: 0,
+    };
+    // BFINAL and BTYPE 01
+    writer.put(1, 1)?;
+    writer.put(1, 2)?;
+
+    let mut matcher = Matcher::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        match matcher.find(input, pos, input.len(), DEFLATE_MAX_DIST, DEFLATE_MAX_LEN) {
+            Some((dist, len)) => {
+                writer.put_match(dist, len)?;
+                pos += len;
+            }
+            None => {
+                writer.put_symbol(input[pos] as u32)?;
+                pos += 1;
+            }
+        }
+    }
+    writer.put_symbol(256)?;
+    writer.finish()
+}
+
+fn lz4_length(out: &mut SliceWriter, mut len: usize) -> Option<()> {
+    while len >= 255 {
+        out.push(255)?;
+        len -= 255;
+    }
+    out.push(len as u8)
+}
+
+fn lz4_sequence(out: &mut SliceWriter, literals: &[u8], m: Option<(usize, usize)>) -> Option<()> {
+    let match_len = m.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8)?;
+    if literals.len() >= 15 {
+        lz4_length(out, literals.len() - 15)?;
+    }
+    out.extend(literals)?;
+
+    if let Some((dist, _)) = m {
+        out.extend(&(dist as u16).to_le_bytes())?;
+        if match_len >= 15 {
+            lz4_length(out, match_len - 15)?;
+        }
+    }
+    Some(())
+}
+
+/// compresses input as a single framed lz4 block
+pub fn lz4(input: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut writer = SliceWriter {
+        out: out.get_mut(LZ4_HEADER_LEN..)?,
+        pos: 0,
+    };
+
+    let mut matcher = Matcher::new();
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + LZ4_MFLIMIT <= input.len() {
+        let limit = input.len() - LZ4_LAST_LITERALS;
+        match matcher.find(input, pos, limit, LZ4_MAX_DIST, usize::MAX) {
+            Some((dist, len)) => {
+                lz4_sequence(&mut writer, &input[anchor..pos], Some((dist, len)))?;
+                pos += len;
+                anchor = pos;
+            }
+            None => pos += 1,
+        }
+    }
+    lz4_sequence(&mut writer, &input[anchor..], None)?;
+    writer.extend(LZ4_END_MAGIC)?;
+    let size = LZ4_HEADER_LEN + writer.pos;
+    let encoded = writer.pos - LZ4_END_MAGIC.len();
+
+    out[..4].copy_from_slice(LZ4_BLOCK_MAGIC);
+    out[4..8].copy_from_slice(&(input.len() as u32).to_le_bytes());
+    out[8..12].copy_from_slice(&(encoded as u32).to_le_bytes());
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn lz4_decode(block: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        let read_len = |pos: &mut usize, mut len: usize| {
+            if len == 15 {
+                loop {
+                    let byte = block[*pos];
+                    *pos += 1;
+                    len += byte as usize;
+                    if byte != 255 {
+                        break;
+                    }
+                }
+            }
+            len
+        };
+        while pos < block.len() {
+            let token = block[pos];
+            pos += 1;
+            let literals = read_len(&mut pos, (token >> 4) as usize);
+            out.extend_from_slice(&block[pos..pos + literals]);
+            pos += literals;
+            if pos == block.len() {
+                break;
+            }
+            let dist = u16::from_le_bytes([block[pos], block[pos + 1]]) as usize;
+            pos += 2;
+            let len = read_len(&mut pos, (token & 0xf) as usize) + MIN_MATCH;
+            for _ in 0..len {
+                out.push(out[out.len() - dist]);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn it_should_parse_compression_names() {
+        assert_eq!(
+            Compression::parse(b"zlib-deflate"),
+            Some(Compression::ZlibDeflate)
+        );
+        assert_eq!(Compression::parse(b"lz4"), Some(Compression::Lz4));
+        assert_eq!(Compression::parse(b"lzfse"), None);
+        assert_eq!(Compression::Lz4.name(), b"lz4");
+    }
+
+    #[test]
+    fn it_should_deflate() {
+        let mut out = [0; 32];
+        let size = deflate(b"abcdabcdabcdabcd", &mut out).unwrap();
+        assert_eq!(out[..size], [0x4b, 0x4c, 0x4a, 0x4e, 0x41, 0xc6, 0x00][..]);
+
+        let size = deflate(b"", &mut out).unwrap();
+        assert_eq!(out[..size], [0x03, 0x00][..]);
+    }
+
+    #[test]
+    fn it_should_compress_lz4() {
+        let input = b"0123456789012345678901234567890123456789 end";
+        let mut out = [0; 64];
+        let size = lz4(input, &mut out).unwrap();
+        assert!(size < input.len());
+        assert_eq!(out[..4], b"bv41"[..]);
+        assert_eq!(out[4..8], (input.len() as u32).to_le_bytes());
+        let encoded = u32::from_le_bytes([out[8], out[9], out[10], out[11]]) as usize;
+        assert_eq!(out[12 + encoded..size], b"bv4$"[..]);
+        assert_eq!(lz4_decode(&out[12..12 + encoded]), input[..]);
+    }
+
+    #[test]
+    fn it_should_fail_on_small_buffers() {
+        let mut out = [0; 4];
+        assert_eq!(deflate(b"0123456789", &mut out), None);
+        assert_eq!(lz4(b"0123456789", &mut out), None);
+    }
+}
@@ -0,0 +1,95 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// initial value of gdb's memory checksum
+pub const CRC32_INIT: u32 = 0xffff_ffff;
+
+const CRC32_POLY: u32 = 0x04c1_1db7;
+
+/// updates a crc-32 with one byte
+/// this is the non-reflected variant gdb uses for qCRC
+/// it is computed bitwise to avoid a 1KiB table
+pub fn crc32_update(crc: u32, byte: u8) -> u32 {
+    let mut crc = crc ^ ((byte as u32) << 24);
+    for _ in 0..8 {
+        crc = if crc & 0x8000_0000 != 0 {
+            (crc << 1) ^ CRC32_POLY
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| crc32_update(crc, *byte))
+}
+
+/// Computes the checksum of hex encoded memory as it is written,
+/// so a memory range can be checked without buffering it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcStream {
+    crc: u32,
+    high: Option<u8>,
+    len: usize,
+}
+
+impl Default for CrcStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrcStream {
+    pub fn new() -> Self {
+        Self {
+            crc: CRC32_INIT,
+            high: None,
+            len: 0,
+        }
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// amount of bytes checked
+    pub fn checked(&self) -> usize {
+        self.len
+    }
+}
+
+impl Stream for CrcStream {
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        let nibble = Parser::from_hex(byte).ok_or(Errors::BadNumber)?;
+        match self.high.take() {
+            None => self.high = Some(nibble),
+            Some(high) => {
+                self.crc = crc32_update(self.crc, (high << 4) | nibble);
+                self.len += 1;
+            }
+        }
+        Ok(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_compute_gdb_crc() {
+        assert_eq!(crc32(CRC32_INIT, b"123456789"), 0x0376_e6e7);
+        assert_eq!(crc32(CRC32_INIT, b""), CRC32_INIT);
+    }
+
+    #[test]
+    fn it_should_checksum_hex_streams() {
+        let mut stream = CrcStream::new();
+        stream.write_all(b"313233343536373839").unwrap();
+        assert_eq!(stream.crc(), 0x0376_e6e7);
+        assert_eq!(stream.checked(), 9);
+        assert_eq!(stream.write(b'x'), Err(Errors::BadNumber));
+    }
+}
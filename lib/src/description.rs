@@ -0,0 +1,326 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+use super::xml;
+
+/// how the bits of a register are interpreted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegisterEncoding {
+    Uint,
+    Sint,
+    Ieee754,
+    Vector,
+}
+
+/// registers with a special meaning to the debugger
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenericRegister {
+    Pc,
+    Sp,
+    Fp,
+    Ra,
+    Flags,
+}
+
+impl GenericRegister {
+    fn name(&self) -> &'static [u8] {
+        match self {
+            Self::Pc => b"pc",
+            Self::Sp => b"sp",
+            Self::Fp => b"fp",
+            Self::Ra => b"ra",
+            Self::Flags => b"flags",
+        }
+    }
+}
+
+/// describes a single register in g packet order
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegisterDesc<'a> {
+    pub name: &'a [u8],
+    pub bitsize: usize,
+    pub encoding: RegisterEncoding,
+    /// register group, e.g. general or float
+    pub group: Option<&'a [u8]>,
+    pub dwarf: Option<usize>,
+    pub generic: Option<GenericRegister>,
+    /// the gdb feature of the register if it differs
+    /// from the one of the target description, e.g. org.gnu.gdb.arm.vfp
+    pub feature: Option<&'a [u8]>,
+    /// a gdb type overriding the one derived from encoding and bitsize,
+    /// e.g. i387_ext
+    pub type_name: Option<&'a [u8]>,
+}
+
+impl<'a> RegisterDesc<'a> {
+    pub const fn new(name: &'a [u8], bitsize: usize) -> Self {
+        Self {
+            name,
+            bitsize,
+            encoding: RegisterEncoding::Uint,
+            group: None,
+            dwarf: None,
+            generic: None,
+            feature: None,
+            type_name: None,
+        }
+    }
+
+    pub const fn with_encoding(mut self, encoding: RegisterEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub const fn with_group(mut self, group: &'a [u8]) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    pub const fn with_dwarf(mut self, dwarf: usize) -> Self {
+        self.dwarf = Some(dwarf);
+        self
+    }
+
+    pub const fn with_generic(mut self, generic: GenericRegister) -> Self {
+        self.generic = Some(generic);
+        self
+    }
+
+    pub const fn with_feature(mut self, feature: &'a [u8]) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+
+    pub const fn with_type(mut self, type_name: &'a [u8]) -> Self {
+        self.type_name = Some(type_name);
+        self
+    }
+
+    /// the gdb type of the register
+    fn xml_type(&self) -> &'a [u8] {
+        if let Some(type_name) = self.type_name {
+            return type_name;
+        }
+        match (self.generic, self.encoding, self.bitsize) {
+            (Some(GenericRegister::Pc | GenericRegister::Ra), ..) => b"code_ptr",
+            (Some(GenericRegister::Sp | GenericRegister::Fp), ..) => b"data_ptr",
+            (_, RegisterEncoding::Ieee754, 32) => b"ieee_single",
+            (_, RegisterEncoding::Ieee754, 64) => b"ieee_double",
+            // gdb sizes int to the bitsize
+            _ => b"int",
+        }
+    }
+
+    /// writes the register as a reg element of target.xml
+    pub fn write_xml(&self, stream: &mut dyn Stream, regnum: usize) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<reg")?;
+        size += xml::write_attr(stream, b"name", self.name)?;
+        size += stream.write_all(b" bitsize=\"")?;
+        size += Parser::to_decimal(self.bitsize, stream)?;
+        size += stream.write_all(b"\" regnum=\"")?;
+        size += Parser::to_decimal(regnum, stream)?;
+        size += stream.write(b'"')?;
+        size += xml::write_attr(stream, b"type", self.xml_type())?;
+        if let Some(group) = self.group {
+            size += xml::write_attr(stream, b"group", group)?;
+        }
+        size += stream.write_all(b"/>")?;
+        Ok(size)
+    }
+
+    /// writes the lldb qRegisterInfo key:value pairs
+    /// offset is the byte offset of the register in the g packet
+    pub fn write_lldb(&self, stream: &mut dyn Stream, offset: usize) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"name:")?;
+        size += stream.write_all(self.name)?;
+        size += stream.write_all(b";bitsize:")?;
+        size += Parser::to_decimal(self.bitsize, stream)?;
+        size += stream.write_all(b";offset:")?;
+        size += Parser::to_decimal(offset, stream)?;
+        size += stream.write_all(match self.encoding {
+            RegisterEncoding::Uint => b";encoding:uint;format:hex;",
+            RegisterEncoding::Sint => b";encoding:sint;format:decimal;",
+            RegisterEncoding::Ieee754 => b";encoding:ieee754;format:float;",
+            RegisterEncoding::Vector => b";encoding:vector;format:vector-uint8;",
+        })?;
+        size += stream.write_all(b"set:")?;
+        size += stream.write_all(self.group.unwrap_or(b"general"))?;
+        size += stream.write(b';')?;
+        if let Some(dwarf) = self.dwarf {
+            size += stream.write_all(b"gcc:")?;
+            size += Parser::to_decimal(dwarf, stream)?;
+            size += stream.write_all(b";dwarf:")?;
+            size += Parser::to_decimal(dwarf, stream)?;
+            size += stream.write(b';')?;
+        }
+        if let Some(generic) = self.generic {
+            size += stream.write_all(b"generic:")?;
+            size += stream.write_all(generic.name())?;
+            size += stream.write(b';')?;
+        }
+        Ok(size)
+    }
+}
+
+/// describes the target's registers
+/// used to generate target.xml and answer lldb's qRegisterInfo
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TargetDescription<'a> {
+    /// the bfd architecture name, e.g. riscv:rv32
+    pub architecture: Option<&'a [u8]>,
+    /// the gdb feature the registers belong to, e.g. org.gnu.gdb.riscv.cpu
+    pub feature: &'a [u8],
+    pub registers: &'a [RegisterDesc<'a>],
+    /// target.xml only names the architecture and gdb uses its own register layout
+    pub architecture_only: bool,
+}
+
+impl<'a> TargetDescription<'a> {
+    pub const fn new(feature: &'a [u8], registers: &'a [RegisterDesc<'a>]) -> Self {
+        Self {
+            architecture: None,
+            feature,
+            registers,
+            architecture_only: false,
+        }
+    }
+
+    pub const fn with_architecture(mut self, architecture: &'a [u8]) -> Self {
+        self.architecture = Some(architecture);
+        self
+    }
+
+    /// leaves the registers out of target.xml,
+    /// for architectures gdb only accepts with all of its mandatory features
+    pub const fn architecture_only(mut self, architecture: &'a [u8]) -> Self {
+        self.architecture = Some(architecture);
+        self.architecture_only = true;
+        self
+    }
+
+    /// returns register regnum and its byte offset in the g packet
+    pub fn register(&self, regnum: usize) -> Option<(&RegisterDesc<'a>, usize)> {
+        let reg = self.registers.get(regnum)?;
+        let offset = self.registers[..regnum].iter().map(|r| r.bitsize / 8).sum();
+        Some((reg, offset))
+    }
+
+    fn write_feature(stream: &mut dyn Stream, feature: &[u8]) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<feature")?;
+        size += xml::write_attr(stream, b"name", feature)?;
+        size += stream.write(b'>')?;
+        Ok(size)
+    }
+
+    /// writes the target.xml document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>",
+        )?;
+        if let Some(architecture) = self.architecture {
+            size += stream.write_all(b"<architecture>")?;
+            size += xml::write_escaped(stream, architecture)?;
+            size += stream.write_all(b"</architecture>")?;
+        }
+        if self.architecture_only {
+            size += stream.write_all(b"</target>")?;
+            return Ok(size);
+        }
+        // every feature is written once with all of its registers,
+        // the regnum attributes keep the g packet order
+        let feature = |reg: &RegisterDesc<'a>| reg.feature.unwrap_or(self.feature);
+        for (first, reg) in self.registers.iter().enumerate() {
+            let name = feature(reg);
+            if self.registers[..first].iter().any(|r| feature(r) == name) {
+                continue;
+            }
+            if first > 0 {
+                size += stream.write_all(b"</feature>")?;
+            }
+            size += Self::write_feature(stream, name)?;
+            for (regnum, reg) in self.registers.iter().enumerate().skip(first) {
+                if feature(reg) == name {
+                    size += reg.write_xml(stream, regnum)?;
+                }
+            }
+        }
+        if self.registers.is_empty() {
+            size += Self::write_feature(stream, self.feature)?;
+        }
+        size += stream.write_all(b"</feature></target>")?;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    const REGISTERS: [RegisterDesc; 2] = [
+        RegisterDesc::new(b"r0", 32).with_dwarf(0),
+        RegisterDesc::new(b"pc", 32)
+            .with_dwarf(15)
+            .with_generic(GenericRegister::Pc),
+    ];
+
+    const DESCRIPTION: TargetDescription =
+        TargetDescription::new(b"org.gnu.gdb.arm.m-profile", &REGISTERS).with_architecture(b"arm");
+
+    #[test]
+    fn it_should_write_target_xml() {
+        let mut s = BufferedStream::new();
+        let size = DESCRIPTION.write_xml(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <architecture>arm</architecture><feature name=\"org.gnu.gdb.arm.m-profile\">\
+            <reg name=\"r0\" bitsize=\"32\" regnum=\"0\" type=\"int\"/>\
+            <reg name=\"pc\" bitsize=\"32\" regnum=\"1\" type=\"code_ptr\"/>\
+            </feature></target>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_split_features() {
+        const REGISTERS: [RegisterDesc; 3] = [
+            RegisterDesc::new(b"pc", 32),
+            RegisterDesc::new(b"d0", 64)
+                .with_encoding(RegisterEncoding::Ieee754)
+                .with_feature(b"org.gnu.gdb.arm.vfp"),
+            RegisterDesc::new(b"fpscr", 32)
+                .with_group(b"float")
+                .with_feature(b"org.gnu.gdb.arm.vfp"),
+        ];
+        let mut s = BufferedStream::new();
+        let size = TargetDescription::new(b"org.gnu.gdb.arm.m-profile", &REGISTERS)
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+            <feature name=\"org.gnu.gdb.arm.m-profile\">\
+            <reg name=\"pc\" bitsize=\"32\" regnum=\"0\" type=\"int\"/></feature>\
+            <feature name=\"org.gnu.gdb.arm.vfp\">\
+            <reg name=\"d0\" bitsize=\"64\" regnum=\"1\" type=\"ieee_double\"/>\
+            <reg name=\"fpscr\" bitsize=\"32\" regnum=\"2\" type=\"int\" group=\"float\"/>\
+            </feature></target>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_write_lldb_register_info() {
+        let mut s = BufferedStream::new();
+        let (reg, offset) = DESCRIPTION.register(1).unwrap();
+        let size = reg.write_lldb(&mut s, offset).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"name:pc;bitsize:32;offset:4;encoding:uint;format:hex;set:general;\
+            gcc:15;dwarf:15;generic:pc;"[..]
+        );
+        assert_eq!(DESCRIPTION.register(2), None);
+    }
+}
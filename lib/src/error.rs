@@ -1,4 +1,4 @@
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Errors {
     MemoryFilledInterupt,
     NotTerminated,
@@ -9,5 +9,14 @@ pub enum Errors {
     BadNumber,
     InsufficientArguments,
     AddressOutOfRange,
+    MemoryFault,
+    AccessDenied,
     LengthMismatch,
+    Unsupported,
+    InvalidRegister,
+    PacketOverflow,
+    /// the connection to gdb was closed
+    Disconnected,
+    /// the input ends in the middle of a packet
+    NeedMoreData,
 }
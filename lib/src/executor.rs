@@ -0,0 +1,513 @@
+use super::arch::cortex_m::XPSR_REGNO;
+use super::error::Errors;
+use super::memory::{self, GuestAddress};
+use super::register::RegisterValue;
+use super::target::Target;
+use super::thread::{
+    StackFrame, ThreadCallback, ThreadId, ThreadInfo, ThreadOp, ALL_THREADS, ANY_THREAD,
+};
+
+/// the thread id of the task at index
+/// thread 0 is reserved, so tasks are numbered from 1
+pub const fn task_thread(index: usize) -> usize {
+    index + 1
+}
+
+/// the thread Hg selects, 0 and -1 select the running task
+fn selection(op: ThreadOp, thread: ThreadId, selected: Option<usize>) -> Option<usize> {
+    match (op, thread.tid) {
+        (ThreadOp::Continue, _) => selected,
+        (_, ANY_THREAD | ALL_THREADS) => None,
+        (_, tid) => Some(tid),
+    }
+}
+
+/// the task was spawned and has not finished yet
+pub const EMBASSY_SPAWNED: u32 = 1 << 0;
+/// the task is in the run queue of its executor
+pub const EMBASSY_RUN_QUEUED: u32 = 1 << 1;
+/// the task waits for a timer
+pub const EMBASSY_TIMER_QUEUED: u32 = 1 << 2;
+
+/// a task of an embassy executor
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AsyncTask<'a> {
+    pub name: &'a [u8],
+    /// address of the TaskHeader, its state word comes first
+    pub header: GuestAddress,
+}
+
+impl<'a> AsyncTask<'a> {
+    pub const fn new(name: &'a [u8], header: GuestAddress) -> Self {
+        Self { name, header }
+    }
+}
+
+/// Lists the spawned tasks of embassy executors as threads.
+/// The executor keeps no list of its tasks, so the task storages
+/// are registered up front.
+/// Saved contexts are not supported: tasks are futures without own stack,
+/// a waiting task keeps no registers, only the state of its future.
+/// Registers of any task but the running one are reported as unavailable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EmbassyTasks<'a> {
+    pub tasks: &'a [AsyncTask<'a>],
+    selected: Option<usize>,
+}
+
+impl<'a> EmbassyTasks<'a> {
+    pub const fn new(tasks: &'a [AsyncTask<'a>]) -> Self {
+        Self {
+            tasks,
+            selected: None,
+        }
+    }
+
+    /// forward Target::select_thread to it
+    pub fn select(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+        self.selected = selection(op, thread, self.selected);
+        Ok(0)
+    }
+
+    /// the task selected with Hg
+    pub fn selected_thread(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// enumerates the spawned tasks, the state flags are shown as extra info
+    pub fn threads(&self, ctx: &dyn Target, f: &mut ThreadCallback) -> Result<usize, Errors> {
+        let mut size = 0;
+        for (index, task) in self.tasks.iter().enumerate() {
+            let state = memory::read_word(ctx, task.header, 4)? as u32;
+            let extra: &[u8] = match state {
+                _ if state & EMBASSY_SPAWNED == 0 => continue,
+                _ if state & EMBASSY_RUN_QUEUED != 0 => b"Ready",
+                _ if state & EMBASSY_TIMER_QUEUED != 0 => b"Waiting for timer",
+                _ => b"Waiting",
+            };
+            size += f(ThreadInfo::new(task_thread(index))
+                .with_name(task.name)
+                .with_extra(extra))?;
+        }
+        Ok(size)
+    }
+}
+
+/// a hardware or software task of an rtic application on Cortex-M
+/// software tasks run in the handler of their dispatcher
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RticTask<'a> {
+    pub name: &'a [u8],
+    /// exception number of the bound interrupt, irq + 16
+    /// 0 is the idle task running in thread mode
+    pub exception: usize,
+    /// address of a word holding the stack pointer the core
+    /// stacked the exception frame of the task at when it was preempted
+    pub context: Option<GuestAddress>,
+}
+
+impl<'a> RticTask<'a> {
+    pub const fn new(name: &'a [u8], exception: usize) -> Self {
+        Self {
+            name,
+            exception,
+            context: None,
+        }
+    }
+
+    /// the application stores the main stack pointer at context
+    /// on entry of every handler that may preempt the task,
+    /// before the handler pushes anything itself
+    pub const fn with_context(mut self, context: GuestAddress) -> Self {
+        self.context = Some(context);
+        self
+    }
+}
+
+/// the frame the core stacks on exception entry
+/// r0-r3, r12, lr, pc and xPSR, without the extended fpu frame
+pub const EXCEPTION_FRAME: StackFrame = StackFrame {
+    slots: &[
+        Some(0),
+        Some(1),
+        Some(2),
+        Some(3),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(4),
+        None,
+        Some(5),
+        Some(6),
+        Some(7),
+    ],
+    slot_size: 4,
+    sp: 13,
+    len: 8,
+};
+
+/// the core aligned the stack with an extra word if set in the stacked xPSR
+const XPSR_STACK_ALIGNED: u128 = 1 << 9;
+
+/// NVIC interrupt set-pending registers
+pub const NVIC_ISPR: GuestAddress = 0xe000_e200;
+/// NVIC interrupt active bit registers
+pub const NVIC_IABR: GuestAddress = 0xe000_e300;
+
+/// Lists the tasks of an rtic application as threads.
+/// The running task is derived from the exception number in xPSR,
+/// preempted and pending tasks from the NVIC.
+/// Preempted tasks share the main stack, their registers are read
+/// from the exception frame found with RticTask::context.
+/// Registers the frame does not hold, r4-r11, are reported as unavailable
+/// as are all registers of tasks without context.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RticTasks<'a> {
+    pub tasks: &'a [RticTask<'a>],
+    selected: Option<usize>,
+}
+
+impl<'a> RticTasks<'a> {
+    pub const fn new(tasks: &'a [RticTask<'a>]) -> Self {
+        Self {
+            tasks,
+            selected: None,
+        }
+    }
+
+    /// forward Target::select_thread to it
+    pub fn select(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+        self.selected = selection(op, thread, self.selected);
+        Ok(0)
+    }
+
+    /// the task selected with Hg
+    pub fn selected_thread(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// the active exception, the low bits of xPSR
+    fn exception(ctx: &dyn Target) -> Option<usize> {
+        let xpsr = ctx.rd_register(XPSR_REGNO).ok()?.value()?;
+        Some((xpsr & 0x1ff) as usize)
+    }
+
+    /// reads the bit of an external interrupt from a bank of NVIC registers
+    fn nvic_bit(ctx: &dyn Target, bank: GuestAddress, exception: usize) -> Result<bool, Errors> {
+        let irq = exception - 16;
+        let word = memory::read_word(ctx, bank + (irq / 32 * 4) as GuestAddress, 4)?;
+        Ok(word & (1 << (irq % 32)) != 0)
+    }
+
+    /// the task bound to the active exception
+    pub fn current_thread(&self, ctx: &dyn Target) -> Option<usize> {
+        let exception = Self::exception(ctx)?;
+        self.tasks
+            .iter()
+            .position(|task| task.exception == exception)
+            .map(task_thread)
+    }
+
+    /// the state of task while exception is active
+    fn state(
+        ctx: &dyn Target,
+        task: &RticTask,
+        exception: Option<usize>,
+    ) -> Result<&'static [u8], Errors> {
+        Ok(match task.exception {
+            current if Some(current) == exception => b"Running",
+            // thread mode is below every handler
+            0 => b"Preempted",
+            // system exceptions are not tracked by the NVIC
+            1..=15 => b"Idle",
+            _ if Self::nvic_bit(ctx, NVIC_IABR, task.exception)? => b"Preempted",
+            _ if Self::nvic_bit(ctx, NVIC_ISPR, task.exception)? => b"Pending",
+            _ => b"Idle",
+        })
+    }
+
+    /// enumerates all tasks, their state is shown as extra info
+    pub fn threads(&self, ctx: &dyn Target, f: &mut ThreadCallback) -> Result<usize, Errors> {
+        let exception = Self::exception(ctx);
+        let mut size = 0;
+        for (index, task) in self.tasks.iter().enumerate() {
+            let extra = Self::state(ctx, task, exception)?;
+            size += f(ThreadInfo::new(task_thread(index))
+                .with_name(task.name)
+                .with_extra(extra))?;
+        }
+        Ok(size)
+    }
+
+    /// reads a register of a preempted task from its exception frame
+    /// pending and idle tasks have no saved context
+    pub fn rd_thread_register(
+        &self,
+        ctx: &dyn Target,
+        thread: usize,
+        regno: usize,
+    ) -> Result<RegisterValue<'static>, Errors> {
+        let task = thread
+            .checked_sub(1)
+            .and_then(|index| self.tasks.get(index))
+            .ok_or(Errors::CommandError)?;
+        let context = task.context.ok_or(Errors::Unsupported)?;
+        if Self::state(ctx, task, Self::exception(ctx))? != b"Preempted" {
+            return Err(Errors::Unsupported);
+        }
+        let top = memory::read_word(ctx, context, 4)? as GuestAddress;
+        let value = EXCEPTION_FRAME.rd_register(ctx, top, regno)?;
+        if regno != EXCEPTION_FRAME.sp {
+            return Ok(value);
+        }
+        let xpsr = EXCEPTION_FRAME.rd_register(ctx, top, XPSR_REGNO)?.value();
+        match (value, xpsr) {
+            (RegisterValue::U32(sp), Some(xpsr)) if xpsr & XPSR_STACK_ALIGNED != 0 => {
+                Ok(RegisterValue::U32(sp + 4))
+            }
+            _ => Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::cortex_m::CORTEX_M;
+    use crate::arch::Arch;
+    use crate::command::testing::exec;
+    use crate::parser::Parser;
+    use crate::register::RegisterValue;
+    use crate::stream::Stream;
+    use crate::thread::ThreadTarget;
+    use std::vec::Vec;
+
+    const EMBASSY: [AsyncTask; 3] = [
+        AsyncTask::new(b"blinky", 0x10),
+        AsyncTask::new(b"usb", 0x20),
+        AsyncTask::new(b"done", 0x30),
+    ];
+
+    const RTIC: [RticTask; 3] = [
+        RticTask::new(b"idle", 0),
+        RticTask::new(b"uart", 16 + 33).with_context(0x40),
+        RticTask::new(b"timer", 16 + 2),
+    ];
+
+    /// uart preempted idle and was preempted by timer
+    struct TaskTarget {
+        embassy: EmbassyTasks<'static>,
+        rtic: RticTasks<'static>,
+    }
+
+    impl TaskTarget {
+        fn new() -> Self {
+            Self {
+                embassy: EmbassyTasks::new(&EMBASSY),
+                rtic: RticTasks::new(&RTIC),
+            }
+        }
+    }
+
+    impl ThreadTarget for TaskTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            self.embassy.threads(self, f)
+        }
+
+        fn current_thread(&self) -> Option<usize> {
+            self.rtic.current_thread(self)
+        }
+
+        fn selected_thread(&self) -> Option<usize> {
+            self.embassy.selected_thread()
+        }
+    }
+
+    impl Target for TaskTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&CORTEX_M)
+        }
+
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            rd_word(start, size, stream)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            rd_register(regno)
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+
+        fn select_thread(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+            self.embassy.select(op, thread)
+        }
+    }
+
+    /// the memory both targets share
+    fn rd_word(start: GuestAddress, size: usize, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let word: u32 = match start {
+            0x10 => EMBASSY_SPAWNED | EMBASSY_RUN_QUEUED,
+            0x20 => EMBASSY_SPAWNED | EMBASSY_TIMER_QUEUED,
+            // the context of uart
+            0x40 => 0x2000_1000,
+            // the frame of uart with an alignment word
+            0x2000_101c => 0x0100_0200 | (16 + 33),
+            0x2000_1000..=0x2000_1018 => 0x500 + (start as u32 - 0x2000_1000) / 4,
+            // uart is irq 33 in the second bank
+            0xe000_e304 => 1 << 1,
+            _ => 0,
+        };
+        Parser::to_hexu(&word.to_le_bytes()[..size], stream)?;
+        Ok(size * 2)
+    }
+
+    /// timer is running
+    fn rd_register(regno: usize) -> Result<RegisterValue<'static>, Errors> {
+        match regno {
+            XPSR_REGNO => Ok(RegisterValue::U32(0x0100_0000 | (16 + 2))),
+            _ => Ok(RegisterValue::U32(0x100 + regno as u32)),
+        }
+    }
+
+    /// selects rtic tasks
+    struct RticTarget {
+        rtic: RticTasks<'static>,
+    }
+
+    impl ThreadTarget for RticTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            self.rtic.threads(self, f)
+        }
+
+        fn current_thread(&self) -> Option<usize> {
+            self.rtic.current_thread(self)
+        }
+
+        fn selected_thread(&self) -> Option<usize> {
+            self.rtic.selected_thread()
+        }
+
+        fn rd_thread_register(
+            &self,
+            thread: usize,
+            regno: usize,
+        ) -> Result<RegisterValue<'_>, Errors> {
+            self.rtic.rd_thread_register(self, thread, regno)
+        }
+    }
+
+    impl Target for RticTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&CORTEX_M)
+        }
+
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            rd_word(start, size, stream)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            rd_register(regno)
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+
+        fn select_thread(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+            self.rtic.select(op, thread)
+        }
+    }
+
+    fn collect(
+        target: &TaskTarget,
+        threads: &dyn Fn(&TaskTarget, &mut ThreadCallback) -> Result<usize, Errors>,
+    ) -> Vec<(usize, Vec<u8>, Vec<u8>)> {
+        let mut result = Vec::new();
+        threads(target, &mut |thread| {
+            result.push((
+                thread.id,
+                thread.name.unwrap().to_vec(),
+                thread.extra.unwrap().to_vec(),
+            ));
+            Ok(0)
+        })
+        .unwrap();
+        result
+    }
+
+    #[test]
+    fn it_should_list_spawned_embassy_tasks() {
+        let mut target = TaskTarget::new();
+        assert_eq!(
+            collect(&target, &|target, f| target.embassy.threads(target, f)),
+            [
+                (1, b"blinky".to_vec(), b"Ready".to_vec()),
+                (2, b"usb".to_vec(), b"Waiting for timer".to_vec()),
+            ]
+        );
+        assert_eq!(exec(b"$qfThreadInfo#bb", &mut target), b"$m1,2#fc");
+        assert_eq!(
+            exec(b"$qThreadExtraInfo,1#b6", &mut target),
+            b"$5265616479#13"
+        );
+    }
+
+    #[test]
+    fn it_should_derive_rtic_task_states() {
+        let target = TaskTarget::new();
+        assert_eq!(
+            collect(&target, &|target, f| target.rtic.threads(target, f)),
+            [
+                (1, b"idle".to_vec(), b"Preempted".to_vec()),
+                (2, b"uart".to_vec(), b"Preempted".to_vec()),
+                (3, b"timer".to_vec(), b"Running".to_vec()),
+            ]
+        );
+        assert_eq!(target.current_thread(), Some(3));
+    }
+
+    #[test]
+    fn it_should_hide_registers_of_suspended_tasks() {
+        let mut target = TaskTarget::new();
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$00010000#81");
+        assert_eq!(exec(b"$Hg1#e0", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$xxxxxxxx#c0");
+        assert_eq!(exec(b"$Hg3#e2", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$00010000#81");
+    }
+
+    #[test]
+    fn it_should_read_registers_of_preempted_rtic_tasks() {
+        let mut target = RticTarget {
+            rtic: RticTasks::new(&RTIC),
+        };
+        assert_eq!(exec(b"$Hg2#e1", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$00050000#85");
+        assert_eq!(exec(b"$pf#d6", &mut target), b"$06050000#8b");
+        // r4-r11 are not part of the frame
+        assert_eq!(exec(b"$p4#a4", &mut target), b"$xxxxxxxx#c0");
+        // past the frame and its alignment word
+        assert_eq!(exec(b"$pd#d4", &mut target), b"$24100020#89");
+
+        // idle has no context
+        assert_eq!(exec(b"$Hg1#e0", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$xxxxxxxx#c0");
+    }
+}
@@ -0,0 +1,54 @@
+/// the optional features gdb announced in qSupported
+/// stop reply extensions must only be sent if gdb supports them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct GdbFeatures {
+    pub multiprocess: bool,
+    pub swbreak: bool,
+    pub hwbreak: bool,
+    pub fork_events: bool,
+    pub vfork_events: bool,
+    pub exec_events: bool,
+    /// gdb understands the N stop reply
+    pub no_resumed: bool,
+}
+
+impl GdbFeatures {
+    /// parses the ; separated feature list of qSupported
+    /// unknown features are ignored
+    pub fn parse(list: &[u8]) -> Self {
+        let mut features = Self::default();
+        for feature in list.split(|b| *b == b';') {
+            let name = match feature.strip_suffix(b"+") {
+                Some(name) => name,
+                None => continue,
+            };
+            match name {
+                b"multiprocess" => features.multiprocess = true,
+                b"swbreak" => features.swbreak = true,
+                b"hwbreak" => features.hwbreak = true,
+                b"fork-events" => features.fork_events = true,
+                b"vfork-events" => features.vfork_events = true,
+                b"exec-events" => features.exec_events = true,
+                b"no-resumed" => features.no_resumed = true,
+                _ => (),
+            }
+        }
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_gdb_features() {
+        let features =
+            GdbFeatures::parse(b"multiprocess+;swbreak+;hwbreak-;xmlRegisters=i386;no-resumed+");
+        assert!(features.multiprocess);
+        assert!(features.swbreak);
+        assert!(!features.hwbreak);
+        assert!(features.no_resumed);
+        assert!(!features.exec_events);
+    }
+}
@@ -0,0 +1,217 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+// errno values of the File-I/O protocol
+pub const EPERM: usize = 1;
+pub const ENOENT: usize = 2;
+pub const EINTR: usize = 4;
+pub const EBADF: usize = 9;
+pub const EACCES: usize = 13;
+pub const EFAULT: usize = 14;
+pub const EBUSY: usize = 16;
+pub const EEXIST: usize = 17;
+pub const ENODEV: usize = 19;
+pub const ENOTDIR: usize = 20;
+pub const EISDIR: usize = 21;
+pub const EINVAL: usize = 22;
+pub const ENFILE: usize = 23;
+pub const EMFILE: usize = 24;
+pub const EFBIG: usize = 27;
+pub const ENOSPC: usize = 28;
+pub const ESPIPE: usize = 29;
+pub const EROFS: usize = 30;
+pub const ENAMETOOLONG: usize = 91;
+pub const EUNKNOWN: usize = 9999;
+
+// open flags of the File-I/O protocol
+pub const O_RDONLY: usize = 0x0;
+pub const O_WRONLY: usize = 0x1;
+pub const O_RDWR: usize = 0x2;
+pub const O_APPEND: usize = 0x8;
+pub const O_CREAT: usize = 0x200;
+pub const O_TRUNC: usize = 0x400;
+pub const O_EXCL: usize = 0x800;
+
+/// lseek whence values
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// A system call the target asks gdb to run on the host.
+/// Pointers refer to target memory which gdb accesses with m and M.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileIoCall {
+    /// path_len includes the trailing zero
+    Open {
+        path: usize,
+        path_len: usize,
+        flags: usize,
+        mode: usize,
+    },
+    Close {
+        fd: usize,
+    },
+    Read {
+        fd: usize,
+        buf: usize,
+        count: usize,
+    },
+    Write {
+        fd: usize,
+        buf: usize,
+        count: usize,
+    },
+    Lseek {
+        fd: usize,
+        offset: usize,
+        whence: usize,
+    },
+    IsATty {
+        fd: usize,
+    },
+    GetTimeOfDay {
+        tv: usize,
+        tz: usize,
+    },
+}
+
+impl FileIoCall {
+    /// writes the call as the contents of an F packet
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let (name, args): (&[u8], [Option<usize>; 4]) = match *self {
+            Self::Open {
+                path,
+                path_len,
+                flags,
+                mode,
+            } => (
+                b"open",
+                [Some(path), Some(path_len), Some(flags), Some(mode)],
+            ),
+            Self::Close { fd } => (b"close", [Some(fd), None, None, None]),
+            Self::Read { fd, buf, count } => (b"read", [Some(fd), Some(buf), Some(count), None]),
+            Self::Write { fd, buf, count } => (b"write", [Some(fd), Some(buf), Some(count), None]),
+            Self::Lseek { fd, offset, whence } => {
+                (b"lseek", [Some(fd), Some(offset), Some(whence), None])
+            }
+            Self::IsATty { fd } => (b"isatty", [Some(fd), None, None, None]),
+            Self::GetTimeOfDay { tv, tz } => (b"gettimeofday", [Some(tv), Some(tz), None, None]),
+        };
+
+        let mut size = stream.write(b'F')?;
+        size += stream.write_all(name)?;
+        for (i, arg) in args.iter().flatten().enumerate() {
+            // pointer and length of a string are separated by a slash
+            let separator = match (self, i) {
+                (Self::Open { .. }, 1) => b'/',
+                _ => b',',
+            };
+            size += stream.write(separator)?;
+            size += Parser::to_hex_number(*arg, stream)?;
+        }
+        Ok(size)
+    }
+}
+
+/// gdb's answer to a FileIoCall
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileIoReply {
+    /// return value of the call, -1 on errors
+    pub result: isize,
+    pub errno: Option<usize>,
+    /// the user pressed Ctrl-C during the call
+    /// the target has to stop with SIGINT instead of resuming
+    pub interrupted: bool,
+}
+
+impl FileIoReply {
+    /// parses the arguments of an F reply packet
+    /// retcode,errno,C
+    pub fn parse(args: &[u8]) -> Result<Self, Errors> {
+        let mut fields = args.split(|b| *b == b',');
+
+        let result = fields.next().ok_or(Errors::InsufficientArguments)?;
+        let result = match result.split_first() {
+            Some((b'-', digits)) if !digits.is_empty() => {
+                -(Parser::from_hexu(digits).ok_or(Errors::BadNumber)? as isize)
+            }
+            Some(_) => Parser::from_hexu(result).ok_or(Errors::BadNumber)? as isize,
+            None => return Err(Errors::InsufficientArguments),
+        };
+
+        let mut reply = Self {
+            result,
+            errno: None,
+            interrupted: false,
+        };
+        for field in fields {
+            match field {
+                b"C" => reply.interrupted = true,
+                _ if reply.errno.is_none() && !field.is_empty() => {
+                    reply.errno = Some(Parser::from_hexu(field).ok_or(Errors::BadNumber)?)
+                }
+                _ => return Err(Errors::BadNumber),
+            }
+        }
+        Ok(reply)
+    }
+
+    /// true if the call was aborted by Ctrl-C
+    pub fn is_eintr(&self) -> bool {
+        self.interrupted && self.errno == Some(EINTR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_calls() {
+        let mut s = BufferedStream::new();
+        FileIoCall::Write {
+            fd: 1,
+            buf: 0x2000,
+            count: 0xc,
+        }
+        .write(&mut s)
+        .unwrap();
+        s.write(b'|').unwrap();
+        FileIoCall::Open {
+            path: 0x100,
+            path_len: 9,
+            flags: O_CREAT | O_WRONLY,
+            mode: 0o644,
+        }
+        .write(&mut s)
+        .unwrap();
+        s.write(b'|').unwrap();
+        FileIoCall::GetTimeOfDay { tv: 0x40, tz: 0 }
+            .write(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"Fwrite,1,2000,c|Fopen,100/9,201,1a4|Fgettimeofday,40,0"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_parse_replies() {
+        assert_eq!(
+            FileIoReply::parse(b"c"),
+            Ok(FileIoReply {
+                result: 12,
+                errno: None,
+                interrupted: false
+            })
+        );
+        let reply = FileIoReply::parse(b"-1,4,C").unwrap();
+        assert_eq!(reply.result, -1);
+        assert!(reply.is_eintr());
+        assert!(FileIoReply::parse(b"5,C").unwrap().interrupted);
+        assert_eq!(FileIoReply::parse(b""), Err(Errors::InsufficientArguments));
+        assert_eq!(FileIoReply::parse(b"1,x"), Err(Errors::BadNumber));
+    }
+}
@@ -0,0 +1,19 @@
+use super::error::Errors;
+
+/// Programming of the flash regions in the memory map
+/// with vFlashErase, vFlashWrite and vFlashDone.
+pub trait Flash {
+    /// erases len bytes of flash starting at start
+    /// the range is always aligned to the block size of its memory-map region
+    fn erase(&mut self, start: usize, len: usize) -> Result<usize, Errors>;
+
+    /// programs data into previously erased flash
+    /// writes may be buffered until done
+    fn write(&mut self, start: usize, data: &[u8]) -> Result<usize, Errors>;
+
+    /// called once all flash writes of a load are sent
+    /// buffered writes have to be committed now
+    fn done(&mut self) -> Result<usize, Errors> {
+        Ok(0)
+    }
+}
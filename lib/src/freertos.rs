@@ -0,0 +1,477 @@
+use super::error::Errors;
+use super::memory::{self, GuestAddress};
+use super::register::RegisterValue;
+use super::target::Target;
+use super::thread::{
+    StackFrame, ThreadCallback, ThreadId, ThreadInfo, ThreadOp, ALL_THREADS, ANY_THREAD,
+};
+
+/// symbols gdb is asked for with qSymbol, in request order
+pub const SYMBOLS: [&[u8]; 8] = [
+    b"pxCurrentTCB",
+    b"pxReadyTasksLists",
+    b"xDelayedTaskList1",
+    b"xDelayedTaskList2",
+    b"xPendingReadyList",
+    b"xSuspendedTaskList",
+    b"xTasksWaitingTermination",
+    b"uxTopUsedPriority",
+];
+
+const CURRENT_TCB: usize = 0;
+const READY_LISTS: usize = 1;
+const TOP_USED_PRIORITY: usize = 7;
+
+/// the task lists that are walked and the state reported for their tasks
+/// the ready lists are walked separately, one list per priority
+const LISTS: [(usize, &[u8]); 5] = [
+    (2, b"Blocked"),
+    (3, b"Blocked"),
+    (4, b"Ready"),
+    (5, b"Suspended"),
+    (6, b"Deleted"),
+];
+
+/// callback receiving the task control block and state of a task
+type TaskCallback<'a> = dyn FnMut(usize, &[u8]) -> Result<usize, Errors> + 'a;
+
+/// task names are truncated to this length
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Sizes of the kernel types, they depend on the port and FreeRTOSConfig.h.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// size of pointers and UBaseType_t
+    pub pointer_size: usize,
+    /// size of TickType_t
+    pub tick_size: usize,
+    /// offset of pcTaskName in the task control block
+    pub name_offset: usize,
+    /// configMAX_TASK_NAME_LEN
+    pub name_len: usize,
+    /// configMAX_PRIORITIES, the number of ready lists
+    pub max_priorities: usize,
+}
+
+impl Layout {
+    /// the layout without MPU wrappers and list integrity checks
+    /// max_priorities defaults to 5 as in the FreeRTOSConfig.h template
+    pub const fn new(pointer_size: usize, tick_size: usize) -> Self {
+        // pxTopOfStack, xStateListItem, xEventListItem, uxPriority, pxStack
+        let list_item = tick_size + 4 * pointer_size;
+        Self {
+            pointer_size,
+            tick_size,
+            name_offset: 3 * pointer_size + 2 * list_item,
+            name_len: 16,
+            max_priorities: 5,
+        }
+    }
+
+    pub const fn with_name(mut self, offset: usize, len: usize) -> Self {
+        self.name_offset = offset;
+        self.name_len = len;
+        self
+    }
+
+    pub const fn with_max_priorities(mut self, max_priorities: usize) -> Self {
+        self.max_priorities = max_priorities;
+        self
+    }
+}
+
+/// 32 bit ports with 32 bit ticks, e.g. ARM_CM3 and RISC-V
+pub const LAYOUT_32: Layout = Layout::new(4, 4);
+
+/// the frame saved by the ARM_CM3 and ARM_CM0 ports
+/// r4-r11 are pushed by PendSV below the exception frame
+pub const CORTEX_M3_FRAME: StackFrame = StackFrame {
+    slots: &[
+        Some(8),
+        Some(9),
+        Some(10),
+        Some(11),
+        Some(0),
+        Some(1),
+        Some(2),
+        Some(3),
+        Some(4),
+        Some(5),
+        Some(6),
+        Some(7),
+        Some(12),
+        None,
+        Some(13),
+        Some(14),
+        Some(15),
+    ],
+    slot_size: 4,
+    sp: 13,
+    len: 16,
+};
+
+/// Lists FreeRTOS tasks as threads by walking the kernel's task lists.
+/// Forward Target::next_symbol and Target::symbol_value to it so gdb
+/// resolves the list addresses, and Target::select_thread to select.
+/// Threads are identified by the address of their task control block,
+/// registers of suspended tasks are read from their stack with frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FreeRtos {
+    pub layout: Layout,
+    pub frame: StackFrame<'static>,
+    addresses: [Option<usize>; SYMBOLS.len()],
+    next: usize,
+    selected: Option<usize>,
+}
+
+impl FreeRtos {
+    pub const fn new(layout: Layout, frame: StackFrame<'static>) -> Self {
+        Self {
+            layout,
+            frame,
+            addresses: [None; SYMBOLS.len()],
+            next: 0,
+            selected: None,
+        }
+    }
+
+    /// the next symbol gdb should look up
+    pub fn next_symbol(&self) -> Option<&'static [u8]> {
+        SYMBOLS.get(self.next).copied()
+    }
+
+    /// stores the address of a symbol, unresolved lists are skipped
+    pub fn symbol_value(&mut self, name: &[u8], value: Option<usize>) {
+        if let Some(index) = SYMBOLS.iter().position(|symbol| *symbol == name) {
+            self.addresses[index] = value;
+            self.next = usize::max(self.next, index + 1);
+        }
+    }
+
+    /// true once the kernel symbols are resolved
+    pub fn ready(&self) -> bool {
+        self.addresses[CURRENT_TCB].is_some() && self.addresses[READY_LISTS].is_some()
+    }
+
+    /// selects the task Hg accesses, 0 and -1 select the running task
+    /// tasks cannot be resumed on their own, Hc is accepted for any thread
+    pub fn select(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+        if op == ThreadOp::General {
+            self.selected = match thread.tid {
+                ANY_THREAD | ALL_THREADS => None,
+                tid => Some(tid),
+            };
+        }
+        Ok(0)
+    }
+
+    /// the task selected with Hg
+    pub fn selected_thread(&self) -> Option<usize> {
+        self.selected
+    }
+
+    fn read_pointer(&self, ctx: &dyn Target, addr: usize) -> Result<usize, Errors> {
+        memory::read_word(ctx, addr as GuestAddress, self.layout.pointer_size)
+            .map(|value| value as usize)
+    }
+
+    /// the running task, pxCurrentTCB
+    pub fn current_thread(&self, ctx: &dyn Target) -> Option<usize> {
+        let current = self.addresses[CURRENT_TCB]?;
+        match self.read_pointer(ctx, current) {
+            Ok(0) | Err(_) => None,
+            Ok(tcb) => Some(tcb),
+        }
+    }
+
+    /// calls f with the task control block of each task in the List_t at list
+    fn walk_list(
+        &self,
+        ctx: &dyn Target,
+        list: usize,
+        f: &mut dyn FnMut(usize) -> Result<usize, Errors>,
+    ) -> Result<usize, Errors> {
+        let pointer = self.layout.pointer_size;
+        let tick = self.layout.tick_size;
+        // uxNumberOfItems, pxIndex, xListEnd { xItemValue, pxNext, pxPrevious }
+        let count = self.read_pointer(ctx, list)?;
+        let end = list + 2 * pointer;
+        let mut item = self.read_pointer(ctx, end + tick)?;
+
+        // the count bounds the walk in case the list is corrupted
+        let mut size = 0;
+        for _ in 0..count {
+            if item == end || item == 0 {
+                break;
+            }
+            // ListItem_t { xItemValue, pxNext, pxPrevious, pvOwner, pxContainer }
+            let owner = self.read_pointer(ctx, item + tick + 2 * pointer)?;
+            size += f(owner)?;
+            item = self.read_pointer(ctx, item + tick)?;
+        }
+        Ok(size)
+    }
+
+    /// calls f with the task control block and state of every task
+    fn walk(&self, ctx: &dyn Target, f: &mut TaskCallback) -> Result<usize, Errors> {
+        let (Some(current), Some(ready)) =
+            (self.addresses[CURRENT_TCB], self.addresses[READY_LISTS])
+        else {
+            return Ok(0);
+        };
+        let current = self.read_pointer(ctx, current)?;
+        // uxTopUsedPriority is configMAX_PRIORITIES - 1, the layout bounds
+        // the walk if it is unresolved or corrupted
+        let priorities = match self.addresses[TOP_USED_PRIORITY] {
+            Some(top) => usize::min(
+                self.read_pointer(ctx, top)?.saturating_add(1),
+                self.layout.max_priorities,
+            ),
+            None => self.layout.max_priorities,
+        };
+        // uxNumberOfItems, pxIndex, xListEnd
+        let list_size = 4 * self.layout.pointer_size + self.layout.tick_size;
+
+        let mut size = 0;
+        for priority in 0..priorities {
+            size += self.walk_list(ctx, ready + priority * list_size, &mut |tcb| match tcb
+                == current
+            {
+                true => f(tcb, b"Running"),
+                false => f(tcb, b"Ready"),
+            })?;
+        }
+        for (symbol, state) in LISTS {
+            if let Some(list) = self.addresses[symbol] {
+                size += self.walk_list(ctx, list, &mut |tcb| f(tcb, state))?;
+            }
+        }
+        Ok(size)
+    }
+
+    /// enumerates all tasks, named after pcTaskName
+    pub fn threads(&self, ctx: &dyn Target, f: &mut ThreadCallback) -> Result<usize, Errors> {
+        self.walk(ctx, &mut |tcb, state| {
+            let mut name = [0; MAX_NAME_LEN];
+            let len = usize::min(self.layout.name_len, MAX_NAME_LEN);
+            let name = &mut name[..len];
+            memory::read_memory(ctx, (tcb + self.layout.name_offset) as GuestAddress, name)?;
+            let len = name.iter().position(|b| *b == 0).unwrap_or(len);
+
+            f(ThreadInfo::new(tcb)
+                .with_name(&name[..len])
+                .with_extra(state))
+        })
+    }
+
+    /// reads a register of a suspended task from its stack
+    /// the saved top of stack is the first member of the task control block
+    pub fn rd_thread_register(
+        &self,
+        ctx: &dyn Target,
+        thread: usize,
+        regno: usize,
+    ) -> Result<RegisterValue<'static>, Errors> {
+        let top = self.read_pointer(ctx, thread)?;
+        self.frame.rd_register(ctx, top as GuestAddress, regno)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::cortex_m::CORTEX_M;
+    use crate::arch::Arch;
+    use crate::command::testing::exec;
+    use crate::parser::Parser;
+    use crate::stream::Stream;
+    use crate::thread::ThreadTarget;
+    use std::vec::Vec;
+
+    const CURRENT: usize = 0x10;
+    const TOP: usize = 0x14;
+    const READY: usize = 0x20;
+    const DELAYED: usize = 0x48;
+    const IDLE: usize = 0x100;
+    const MAIN: usize = 0x180;
+
+    /// a kernel with two priorities, idle is ready and main is delayed
+    struct RtosTarget {
+        memory: [u8; 0x300],
+        rtos: FreeRtos,
+    }
+
+    impl RtosTarget {
+        fn word(&mut self, addr: usize, value: usize) {
+            self.memory[addr..addr + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        }
+
+        /// a list holding a single item, the item is placed behind the list
+        fn list(&mut self, list: usize, item: usize, tcb: usize) {
+            self.word(list, 1);
+            self.word(list + 12, item);
+            self.word(item + 4, list + 8);
+            self.word(item + 12, tcb);
+        }
+
+        fn tcb(&mut self, tcb: usize, name: &[u8], top: usize) {
+            self.word(tcb, top);
+            let name_offset = tcb + LAYOUT_32.name_offset;
+            self.memory[name_offset..name_offset + name.len()].copy_from_slice(name);
+        }
+
+        fn new() -> Self {
+            let mut target = Self {
+                memory: [0; 0x300],
+                rtos: FreeRtos::new(LAYOUT_32, CORTEX_M3_FRAME),
+            };
+            target.word(CURRENT, IDLE);
+            target.word(TOP, 1);
+            // the lists are 20 bytes, the items are placed in the gaps
+            target.word(READY, 0);
+            target.word(READY + 12, READY + 8);
+            target.list(READY + 20, 0x60, IDLE);
+            target.list(DELAYED, 0x80, MAIN);
+            target.tcb(IDLE, b"IDLE\0", 0x200);
+            target.tcb(MAIN, b"main\0", 0x240);
+            // r0 and pc of main
+            target.word(0x240 + 8 * 4, 0xaa);
+            target.word(0x240 + 14 * 4, 0x1234);
+
+            for (name, value) in [
+                (SYMBOLS[0], Some(CURRENT)),
+                (SYMBOLS[1], Some(READY)),
+                (SYMBOLS[2], Some(DELAYED)),
+                (SYMBOLS[3], None),
+                (SYMBOLS[7], Some(TOP)),
+            ] {
+                target.rtos.symbol_value(name, value);
+            }
+            target
+        }
+    }
+
+    impl ThreadTarget for RtosTarget {
+        fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+            self.rtos.threads(self, f)
+        }
+
+        fn current_thread(&self) -> Option<usize> {
+            self.rtos.current_thread(self)
+        }
+
+        fn selected_thread(&self) -> Option<usize> {
+            self.rtos.selected_thread()
+        }
+
+        fn rd_thread_register(
+            &self,
+            thread: usize,
+            regno: usize,
+        ) -> Result<RegisterValue<'_>, Errors> {
+            self.rtos.rd_thread_register(self, thread, regno)
+        }
+    }
+
+    impl Target for RtosTarget {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&CORTEX_M)
+        }
+
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            let start = start as usize;
+            Parser::to_hexu(&self.memory[start..start + size], stream)?;
+            Ok(size * 2)
+        }
+
+        fn rd_register(&self, _regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(0))
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(self)
+        }
+
+        fn select_thread(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+            self.rtos.select(op, thread)
+        }
+    }
+
+    #[test]
+    fn it_should_request_symbols() {
+        let mut rtos = FreeRtos::new(LAYOUT_32, CORTEX_M3_FRAME);
+        assert_eq!(rtos.next_symbol(), Some(&b"pxCurrentTCB"[..]));
+        assert!(!rtos.ready());
+        rtos.symbol_value(b"pxCurrentTCB", Some(0x10));
+        rtos.symbol_value(b"pxReadyTasksLists", Some(0x20));
+        assert_eq!(rtos.next_symbol(), Some(&b"xDelayedTaskList1"[..]));
+        assert!(rtos.ready());
+    }
+
+    #[test]
+    fn it_should_walk_the_task_lists() {
+        let target = RtosTarget::new();
+        let mut threads = Vec::new();
+        target
+            .threads(&mut |thread| {
+                threads.push((
+                    thread.id,
+                    thread.name.unwrap().to_vec(),
+                    thread.extra.unwrap().to_vec(),
+                ));
+                Ok(0)
+            })
+            .unwrap();
+        assert_eq!(
+            threads,
+            [
+                (IDLE, b"IDLE".to_vec(), b"Running".to_vec()),
+                (MAIN, b"main".to_vec(), b"Blocked".to_vec())
+            ]
+        );
+        assert_eq!(target.current_thread(), Some(IDLE));
+    }
+
+    fn thread_ids(target: &RtosTarget) -> Vec<usize> {
+        let mut threads = Vec::new();
+        target
+            .threads(&mut |thread| {
+                threads.push(thread.id);
+                Ok(0)
+            })
+            .unwrap();
+        threads
+    }
+
+    #[test]
+    fn it_should_walk_max_priorities_without_top_used_priority() {
+        let mut target = RtosTarget::new();
+        target.rtos.layout = LAYOUT_32.with_max_priorities(2);
+        target.rtos.symbol_value(SYMBOLS[7], None);
+        assert_eq!(thread_ids(&target), [IDLE, MAIN]);
+    }
+
+    #[test]
+    fn it_should_cap_top_used_priority() {
+        let mut target = RtosTarget::new();
+        target.rtos.layout = LAYOUT_32.with_max_priorities(2);
+        target.word(TOP, 0xffffffff);
+        assert_eq!(thread_ids(&target), [IDLE, MAIN]);
+    }
+
+    #[test]
+    fn it_should_read_saved_task_registers() {
+        let mut target = RtosTarget::new();
+        assert_eq!(exec(b"$Hg180#48", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$aa000000#e2");
+        assert_eq!(exec(b"$pf#d6", &mut target), b"$34120000#8a");
+        assert_eq!(exec(b"$pd#d4", &mut target), b"$80020000#8a");
+        assert_eq!(exec(b"$Hg0#df", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$pf#d6", &mut target), b"$00000000#80");
+    }
+}
@@ -0,0 +1,343 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// console output is decoded in chunks of this size
+pub const CONSOLE_CHUNK: usize = 64;
+
+/// execution state of the remote target as seen by the host
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TargetState {
+    Running,
+    Stopped,
+    Exited,
+}
+
+/// how the remote process ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// W packet
+    Code(u8),
+    /// X packet
+    Signal(u8),
+}
+
+impl ExitStatus {
+    /// writes the W or X stop reply
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let (kind, number) = match *self {
+            Self::Code(code) => (b'W', code),
+            Self::Signal(signal) => (b'X', signal),
+        };
+        stream.write(kind)?;
+        Parser::to_hex8(number, stream)?;
+        Ok(3)
+    }
+}
+
+/// Callbacks for frontends driving a stub from the host side.
+/// All hooks are optional.
+pub trait SessionEvents {
+    /// the target stopped with signal
+    /// reply is the data of the S or T stop reply for further inspection
+    fn on_stop(&mut self, _signal: u8, _reply: &[u8]) {}
+
+    /// decoded program output from O packets
+    /// may be called several times per packet
+    fn on_console(&mut self, _text: &[u8]) {}
+
+    /// the stub answered with an E packet
+    fn on_error(&mut self, _code: u8) {}
+
+    /// the process exited or was terminated
+    fn on_exit(&mut self, _status: ExitStatus) {}
+
+    /// called whenever the target state changes
+    fn on_state(&mut self, _state: TargetState) {}
+}
+
+/// Tracks the state of a remote target
+/// and turns stub replies into SessionEvents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostSession {
+    state: TargetState,
+}
+
+impl Default for HostSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostSession {
+    pub fn new() -> Self {
+        Self {
+            state: TargetState::Stopped,
+        }
+    }
+
+    pub fn state(&self) -> TargetState {
+        self.state
+    }
+
+    fn set_state(&mut self, state: TargetState, events: &mut dyn SessionEvents) {
+        if self.state != state {
+            self.state = state;
+            events.on_state(state);
+        }
+    }
+
+    /// call after sending a packet that resumes the target
+    pub fn resumed(&mut self, events: &mut dyn SessionEvents) {
+        self.set_state(TargetState::Running, events);
+    }
+
+    /// checks a $data#cs reply to a packet that does not resume the target
+    /// or a %name:data#cs notification and reports the events it contains,
+    /// E packets and %Stop notifications
+    /// returns the packet data for replies that are no events
+    /// so the caller can process them, e.g. T0;tnotrun:0 of qTStatus
+    pub fn handle_reply<'a>(
+        &mut self,
+        packet: &'a [u8],
+        events: &mut dyn SessionEvents,
+    ) -> Result<Option<&'a [u8]>, Errors> {
+        match Self::verify(packet)? {
+            (b'%', data) => self.notification(data, events),
+            (_, data) if data.len() == 3 && data[0] == b'E' => {
+                let code = Parser::from_hexu(&data[1..]).ok_or(Errors::BadNumber)?;
+                events.on_error(code as u8);
+                Ok(None)
+            }
+            (_, data) => Ok(Some(data)),
+        }
+    }
+
+    /// like handle_reply for the replies to packets that resume the target
+    /// or ask for a stop, e.g. c, s, vCont, ? and vStopped
+    /// O packets are reported with on_console,
+    /// stop replies with on_stop or on_exit
+    pub fn handle_stop_reply<'a>(
+        &mut self,
+        packet: &'a [u8],
+        events: &mut dyn SessionEvents,
+    ) -> Result<Option<&'a [u8]>, Errors> {
+        match Self::verify(packet)? {
+            (b'%', data) => self.notification(data, events),
+            (_, data) => self.stop_reply(data, events),
+        }
+    }
+
+    /// reports a %Stop notification of a non-stop session
+    /// other notifications are returned
+    fn notification<'a>(
+        &mut self,
+        data: &'a [u8],
+        events: &mut dyn SessionEvents,
+    ) -> Result<Option<&'a [u8]>, Errors> {
+        match data.strip_prefix(b"Stop:") {
+            Some(reply) => self.stop_reply(reply, events),
+            None => Ok(Some(data)),
+        }
+    }
+
+    fn stop_reply<'a>(
+        &mut self,
+        data: &'a [u8],
+        events: &mut dyn SessionEvents,
+    ) -> Result<Option<&'a [u8]>, Errors> {
+        let number = || {
+            data.get(1..3)
+                .and_then(Parser::from_hexu)
+                .map(|n| n as u8)
+                .ok_or(Errors::BadNumber)
+        };
+
+        match data.first() {
+            Some(b'O') if data != b"OK" => {
+                let mut chunk = [0; CONSOLE_CHUNK];
+                for hex in data[1..].chunks(CONSOLE_CHUNK * 2) {
+                    let len = Parser::from_hex_bytes(hex, &mut chunk).ok_or(Errors::BadNumber)?;
+                    events.on_console(&chunk[..len]);
+                }
+                Ok(None)
+            }
+            Some(b'S') | Some(b'T') => {
+                let signal = number()?;
+                self.set_state(TargetState::Stopped, events);
+                events.on_stop(signal, data);
+                Ok(None)
+            }
+            Some(b'W') | Some(b'X') => {
+                let status = match data[0] {
+                    b'W' => ExitStatus::Code(number()?),
+                    _ => ExitStatus::Signal(number()?),
+                };
+                self.set_state(TargetState::Exited, events);
+                events.on_exit(status);
+                Ok(None)
+            }
+            Some(b'E') if data.len() == 3 => {
+                events.on_error(number()?);
+                Ok(None)
+            }
+            _ => Ok(Some(data)),
+        }
+    }
+
+    /// returns the introduction and the data of a well formed packet
+    /// or notification
+    fn verify(packet: &[u8]) -> Result<(u8, &[u8]), Errors> {
+        let intro = match packet.first() {
+            Some(intro @ (b'$' | b'%')) => *intro,
+            _ => return Err(Errors::UnexpectedIntroduction),
+        };
+        let end = packet
+            .iter()
+            .position(|b| *b == b'#')
+            .ok_or(Errors::NotTerminated)?;
+        let chksm = packet
+            .get(end + 1..end + 3)
+            .and_then(Parser::from_hexu)
+            .ok_or(Errors::InvalidChecksum)?;
+        if chksm as u32 != Parser::chksm(&packet[1..]) {
+            return Err(Errors::InvalidChecksum);
+        }
+        Ok((intro, &packet[1..end]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct Recorder {
+        stops: Vec<u8>,
+        console: Vec<u8>,
+        errors: Vec<u8>,
+        exits: Vec<ExitStatus>,
+        states: Vec<TargetState>,
+    }
+
+    impl SessionEvents for Recorder {
+        fn on_stop(&mut self, signal: u8, _reply: &[u8]) {
+            self.stops.push(signal);
+        }
+
+        fn on_console(&mut self, text: &[u8]) {
+            self.console.extend_from_slice(text);
+        }
+
+        fn on_error(&mut self, code: u8) {
+            self.errors.push(code);
+        }
+
+        fn on_exit(&mut self, status: ExitStatus) {
+            self.exits.push(status);
+        }
+
+        fn on_state(&mut self, state: TargetState) {
+            self.states.push(state);
+        }
+    }
+
+    #[test]
+    fn it_should_report_stops_and_output() {
+        let mut session = HostSession::new();
+        let mut events = Recorder::default();
+
+        session.resumed(&mut events);
+        assert_eq!(
+            session.handle_stop_reply(b"$O68690a#bd", &mut events),
+            Ok(None)
+        );
+        assert_eq!(
+            session.handle_stop_reply(b"$T05thread:01;#07", &mut events),
+            Ok(None)
+        );
+        assert_eq!(session.state(), TargetState::Stopped);
+        assert_eq!(events.console, b"hi\n");
+        assert_eq!(events.stops, [5]);
+        assert_eq!(events.states, [TargetState::Running, TargetState::Stopped]);
+    }
+
+    #[test]
+    fn it_should_report_errors_and_exits() {
+        let mut session = HostSession::new();
+        let mut events = Recorder::default();
+
+        assert_eq!(session.handle_reply(b"$E16#ac", &mut events), Ok(None));
+        assert_eq!(session.handle_stop_reply(b"$X0b#ea", &mut events), Ok(None));
+        assert_eq!(events.errors, [0x16]);
+        assert_eq!(events.exits, [ExitStatus::Signal(11)]);
+        assert_eq!(session.state(), TargetState::Exited);
+    }
+
+    #[test]
+    fn it_should_pass_on_other_replies() {
+        let mut session = HostSession::new();
+        let mut events = Recorder::default();
+
+        assert_eq!(
+            session.handle_reply(b"$OK#9a", &mut events),
+            Ok(Some(&b"OK"[..]))
+        );
+        assert_eq!(
+            session.handle_reply(b"$OK#00", &mut events),
+            Err(Errors::InvalidChecksum)
+        );
+        assert!(events.console.is_empty());
+    }
+
+    #[test]
+    fn it_should_not_parse_other_replies_as_stops() {
+        let mut session = HostSession::new();
+        let mut events = Recorder::default();
+
+        // qTStatus and qOffsets
+        assert_eq!(
+            session.handle_reply(b"$T0;tnotrun:0#43", &mut events),
+            Ok(Some(&b"T0;tnotrun:0"[..]))
+        );
+        assert_eq!(
+            session.handle_reply(b"$Text=0;Data=0;Bss=0#04", &mut events),
+            Ok(Some(&b"Text=0;Data=0;Bss=0"[..]))
+        );
+        assert!(events.stops.is_empty());
+        assert!(events.states.is_empty());
+    }
+
+    #[test]
+    fn it_should_report_stop_notifications() {
+        let mut session = HostSession::new();
+        let mut events = Recorder::default();
+
+        session.resumed(&mut events);
+        // notifications may arrive while another reply is awaited
+        assert_eq!(
+            session.handle_reply(b"%Stop:T05thread:1;#b7", &mut events),
+            Ok(None)
+        );
+        assert_eq!(events.stops, [5]);
+        assert_eq!(session.state(), TargetState::Stopped);
+
+        // vStopped answers with the next stop or OK
+        assert_eq!(
+            session.handle_stop_reply(b"$T05thread:1;#d7", &mut events),
+            Ok(None)
+        );
+        assert_eq!(
+            session.handle_stop_reply(b"$OK#9a", &mut events),
+            Ok(Some(&b"OK"[..]))
+        );
+        assert_eq!(events.stops, [5, 5]);
+
+        assert_eq!(
+            session.handle_stop_reply(b"%Stop:W00#97", &mut events),
+            Ok(None)
+        );
+        assert_eq!(events.exits, [ExitStatus::Code(0)]);
+    }
+}
@@ -0,0 +1,132 @@
+use super::error::Errors;
+use super::fileio::{EINVAL, EUNKNOWN};
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// result of a file system operation
+/// errors are File-I/O errno values, see fileio
+pub type FsResult<T> = Result<T, usize>;
+
+/// file information in the layout of the File-I/O struct stat
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FileStat {
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+}
+
+impl FileStat {
+    /// size of the encoded structure
+    pub const SIZE: usize = 64;
+
+    /// returns the big endian encoding gdb expects
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let mut pos = 0;
+        let mut put = |field: &[u8]| {
+            bytes[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        };
+        for field in [
+            self.dev, self.ino, self.mode, self.nlink, self.uid, self.gid, self.rdev,
+        ] {
+            put(&field.to_be_bytes());
+        }
+        for field in [self.size, self.blksize, self.blocks] {
+            put(&field.to_be_bytes());
+        }
+        for field in [self.atime, self.mtime, self.ctime] {
+            put(&field.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Access to the target's file system for vFile packets.
+/// This lets gdb push and pull files with remote get and remote put.
+/// Optional operations fail with EUNKNOWN by default.
+pub trait HostFs {
+    /// path is not zero terminated
+    /// returns the new file descriptor
+    fn open(&mut self, path: &[u8], flags: usize, mode: usize) -> FsResult<usize>;
+
+    fn close(&mut self, fd: usize) -> FsResult<()>;
+
+    /// reads up to buf.len() bytes at offset
+    /// returns the amount of bytes read, 0 at the end of the file
+    fn pread(&mut self, fd: usize, offset: usize, buf: &mut [u8]) -> FsResult<usize>;
+
+    /// returns the amount of bytes written
+    fn pwrite(&mut self, fd: usize, offset: usize, data: &[u8]) -> FsResult<usize>;
+
+    fn fstat(&mut self, _fd: usize) -> FsResult<FileStat> {
+        Err(EUNKNOWN)
+    }
+
+    fn unlink(&mut self, _path: &[u8]) -> FsResult<()> {
+        Err(EUNKNOWN)
+    }
+
+    /// writes the link target into buf
+    /// returns its length
+    fn readlink(&mut self, _path: &[u8], _buf: &mut [u8]) -> FsResult<usize> {
+        Err(EUNKNOWN)
+    }
+
+    /// selects the file system of process pid, 0 for the stub's own
+    fn setfs(&mut self, pid: usize) -> FsResult<()> {
+        match pid {
+            0 => Ok(()),
+            _ => Err(EINVAL),
+        }
+    }
+}
+
+/// writes F<result> or F-1,<errno>
+pub fn write_fs_result(result: FsResult<usize>, stream: &mut dyn Stream) -> Result<usize, Errors> {
+    let mut size = stream.write(b'F')?;
+    match result {
+        Ok(value) => size += Parser::to_hex_number(value, stream)?,
+        Err(errno) => {
+            size += stream.write_all(b"-1,")?;
+            size += Parser::to_hex_number(errno, stream)?;
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_encode_stat() {
+        let stat = FileStat {
+            mode: 0o100644,
+            size: 0x1234,
+            ..Default::default()
+        };
+        let bytes = stat.to_bytes();
+        assert_eq!(bytes[8..12], [0, 0, 0x81, 0xa4]);
+        assert_eq!(bytes[28..36], [0, 0, 0, 0, 0, 0, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn it_should_write_results() {
+        let mut s = BufferedStream::new();
+        write_fs_result(Ok(0x10), &mut s).unwrap();
+        write_fs_result(Err(crate::fileio::ENOENT), &mut s).unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"F10F-1,2"[..]);
+    }
+}
@@ -0,0 +1,455 @@
+use super::arch;
+use super::error::Errors;
+use super::memory::{self, GuestAddress};
+use super::parser::Parser;
+use super::stream::Stream;
+use super::target::Target;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use gdbstub::arch::{Arch, Registers};
+use gdbstub::target::ext::base::singlethread::SingleThreadBase;
+use gdbstub::target::ext::base::BaseOps;
+use gdbstub::target::{TargetError, TargetResult};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+/// largest register file in bytes that is bridged
+pub const MAX_REGISTER_BYTES: usize = 256;
+
+/// memory is converted in chunks of this many bytes
+const CHUNK: usize = 64;
+
+/// Decodes the hex digits written to it into out.
+/// x digits of unavailable registers decode to 0.
+struct HexDecoder<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+    high: Option<u8>,
+}
+
+impl<'a> HexDecoder<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Self {
+            out,
+            pos: 0,
+            high: None,
+        }
+    }
+}
+
+impl Stream for HexDecoder<'_> {
+    fn len(&self) -> usize {
+        self.out.len()
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        let nibble = match byte {
+            b'x' => 0,
+            _ => Parser::from_hex(byte).ok_or(Errors::BadNumber)?,
+        };
+        match self.high.take() {
+            None => self.high = Some(nibble),
+            Some(high) => {
+                let slot = self.out.get_mut(self.pos).ok_or(Errors::LengthMismatch)?;
+                *slot = (high << 4) | nibble;
+                self.pos += 1;
+            }
+        }
+        Ok(1)
+    }
+}
+
+/// converts an error of a gdbstub target
+fn from_target_error<E>(_err: TargetError<E>) -> Errors {
+    Errors::CommandError
+}
+
+/// converts an error of an embedgdb target
+/// gdb sees them as error replies, the session goes on
+fn to_target_error(_err: Errors) -> TargetError<Errors> {
+    TargetError::NonFatal
+}
+
+/// Serves a target written for the gdbstub crate as embedgdb Target.
+/// Only single threaded gdbstub targets are supported and only
+/// registers and memory are bridged, resuming is left to the caller.
+pub struct GdbstubTarget<T> {
+    inner: RefCell<T>,
+}
+
+impl<T> GdbstubTarget<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: RefCell::new(inner),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: gdbstub::target::Target> GdbstubTarget<T> {
+    /// runs f with the single threaded base ops of the wrapped target
+    fn base<R>(
+        &self,
+        f: impl FnOnce(&mut dyn SingleThreadBase<Arch = T::Arch, Error = T::Error>) -> R,
+    ) -> Result<R, Errors> {
+        let mut inner = self.inner.borrow_mut();
+        match inner.base_ops() {
+            BaseOps::SingleThread(base) => Ok(f(base)),
+            BaseOps::MultiThread(_) => Err(Errors::Unsupported),
+        }
+    }
+
+    fn address(addr: GuestAddress) -> Result<<T::Arch as Arch>::Usize, Errors> {
+        <T::Arch as Arch>::Usize::from_u64(addr).ok_or(Errors::AddressOutOfRange)
+    }
+}
+
+impl<T: gdbstub::target::Target> Target for GdbstubTarget<T> {
+    fn rd_registers(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut regs = <T::Arch as Arch>::Registers::default();
+        self.base(|base| base.read_registers(&mut regs))?
+            .map_err(from_target_error)?;
+
+        let start = stream.pos();
+        let mut result = Ok(());
+        regs.gdb_serialize(|byte| {
+            if result.is_ok() {
+                result = match byte {
+                    Some(byte) => Parser::to_hex8(byte, stream),
+                    None => stream
+                        .write(b'x')
+                        .and_then(|_| stream.write(b'x'))
+                        .map(|_| ()),
+                };
+            }
+        });
+        result?;
+        Ok(stream.pos() - start)
+    }
+
+    fn wr_registers(&mut self, data: &[u8]) -> Result<usize, Errors> {
+        let mut bytes = [0; MAX_REGISTER_BYTES];
+        let len = Parser::from_hex_bytes(data, &mut bytes).ok_or(Errors::LengthMismatch)?;
+        let mut regs = <T::Arch as Arch>::Registers::default();
+        regs.gdb_deserialize(&bytes[..len])
+            .map_err(|_| Errors::LengthMismatch)?;
+        self.base(|base| base.write_registers(&regs))?
+            .map_err(from_target_error)?;
+        Ok(0)
+    }
+
+    fn rd_memory(
+        &self,
+        start: GuestAddress,
+        size: usize,
+        stream: &mut dyn Stream,
+    ) -> Result<usize, Errors> {
+        let stream_start = stream.pos();
+        let mut chunk = [0; CHUNK];
+        let mut offset = 0;
+        while offset < size {
+            let len = usize::min(size - offset, CHUNK);
+            let addr = Self::address(start + offset as GuestAddress)?;
+            let read = self
+                .base(|base| base.read_addrs(addr, &mut chunk[..len]))?
+                .map_err(from_target_error)?;
+            for byte in chunk[..read].iter() {
+                Parser::to_hex8(*byte, stream)?;
+            }
+            // a short read ends at unreadable memory
+            if read < len {
+                break;
+            }
+            offset += len;
+        }
+        Ok(stream.pos() - stream_start)
+    }
+
+    fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+        let mut chunk = [0; CHUNK];
+        for (i, hex) in data.chunks(CHUNK * 2).enumerate() {
+            let len = Parser::from_hex_bytes(hex, &mut chunk).ok_or(Errors::CommandError)?;
+            let addr = Self::address(start + (i * CHUNK) as GuestAddress)?;
+            self.base(|base| base.write_addrs(addr, &chunk[..len]))?
+                .map_err(from_target_error)?;
+        }
+        Ok(0)
+    }
+
+    fn features(&self, annex: &[u8]) -> Option<&[u8]> {
+        match annex {
+            b"target.xml" => <T::Arch as Arch>::target_description_xml().map(str::as_bytes),
+            _ => None,
+        }
+    }
+}
+
+/// Serves an embedgdb Target as single threaded target of the gdbstub crate.
+/// A describes the register layout of the target.
+/// Only registers and memory are bridged.
+pub struct EmbedgdbTarget<T, A> {
+    inner: T,
+    arch: PhantomData<A>,
+}
+
+impl<T, A> EmbedgdbTarget<T, A> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            arch: PhantomData,
+        }
+    }
+
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Target, A: Arch> gdbstub::target::Target for EmbedgdbTarget<T, A> {
+    type Arch = A;
+    type Error = Errors;
+
+    fn base_ops(&mut self) -> BaseOps<'_, A, Errors> {
+        BaseOps::SingleThread(self)
+    }
+}
+
+impl<T: Target, A: Arch> SingleThreadBase for EmbedgdbTarget<T, A> {
+    fn read_registers(&mut self, regs: &mut A::Registers) -> TargetResult<(), Self> {
+        let mut bytes = [0; MAX_REGISTER_BYTES];
+        let mut decoder = HexDecoder::new(&mut bytes);
+        arch::read_registers(&self.inner, &mut decoder).map_err(to_target_error)?;
+        let len = decoder.pos();
+        regs.gdb_deserialize(&bytes[..len])
+            .map_err(|_| TargetError::Fatal(Errors::LengthMismatch))
+    }
+
+    fn write_registers(&mut self, regs: &A::Registers) -> TargetResult<(), Self> {
+        let mut hex = [0; MAX_REGISTER_BYTES * 2];
+        let mut len = 0;
+        let mut overflow = false;
+        regs.gdb_serialize(|byte| {
+            let (high, low) = match byte {
+                Some(byte) => Parser::to_hex_tuple(byte),
+                None => (b'x', b'x'),
+            };
+            match hex.get_mut(len..len + 2) {
+                Some(slot) => {
+                    slot.copy_from_slice(&[high, low]);
+                    len += 2;
+                }
+                None => overflow = true,
+            }
+        });
+        if overflow {
+            return Err(TargetError::Fatal(Errors::LengthMismatch));
+        }
+        arch::write_registers(&mut self.inner, &hex[..len]).map_err(to_target_error)?;
+        Ok(())
+    }
+
+    fn read_addrs(&mut self, start_addr: A::Usize, data: &mut [u8]) -> TargetResult<usize, Self> {
+        let start = start_addr.to_u64().ok_or(TargetError::NonFatal)?;
+        memory::read_memory(&self.inner, start, data).map_err(to_target_error)
+    }
+
+    fn write_addrs(&mut self, start_addr: A::Usize, data: &[u8]) -> TargetResult<(), Self> {
+        let start = start_addr.to_u64().ok_or(TargetError::NonFatal)?;
+        memory::write_memory(&mut self.inner, start, data).map_err(to_target_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::mips::MIPS32_BE;
+    use crate::arch::Arch as _;
+    use crate::command::testing::exec;
+    use crate::register::RegisterValue;
+    use crate::stream::BufferedStream;
+    use crate::target::VirtualTarget;
+    use std::vec::Vec;
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct TestRegisters {
+        pc: u32,
+    }
+
+    impl Registers for TestRegisters {
+        type ProgramCounter = u32;
+
+        fn pc(&self) -> u32 {
+            self.pc
+        }
+
+        fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+            for byte in self.pc.to_le_bytes() {
+                write_byte(Some(byte));
+            }
+        }
+
+        fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+            self.pc = u32::from_le_bytes(bytes.try_into().map_err(|_| ())?);
+            Ok(())
+        }
+    }
+
+    enum TestArch {}
+
+    impl Arch for TestArch {
+        type Usize = u32;
+        type Registers = TestRegisters;
+        type BreakpointKind = usize;
+        type RegId = ();
+
+        fn target_description_xml() -> Option<&'static str> {
+            Some("<target><architecture>test</architecture></target>")
+        }
+    }
+
+    /// keeps the registers in an embedgdb target
+    #[derive(Default)]
+    struct PcTarget {
+        pc: [u8; 4],
+    }
+
+    impl Target for PcTarget {
+        fn rd_registers(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+            Parser::to_hexu(&self.pc, stream)?;
+            Ok(stream.pos())
+        }
+
+        fn wr_registers(&mut self, data: &[u8]) -> Result<usize, Errors> {
+            Parser::from_hex_bytes(data, &mut self.pc).ok_or(Errors::LengthMismatch)?;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_bridge_registers_both_ways() {
+        let mut target = EmbedgdbTarget::<_, TestArch>::new(PcTarget::default());
+        assert!(target
+            .write_registers(&TestRegisters { pc: 0x80001234 })
+            .is_ok());
+        assert_eq!(target.inner().pc, [0x34, 0x12, 0x00, 0x80]);
+
+        let mut regs = TestRegisters::default();
+        assert!(target.read_registers(&mut regs).is_ok());
+        assert_eq!(regs.pc, 0x80001234);
+
+        let mut target = GdbstubTarget::new(target);
+        let mut stream = BufferedStream::new();
+        target.rd_registers(&mut stream).unwrap();
+        assert_eq!(&stream.buffer[..stream.pos()], b"34120080");
+        target.wr_registers(b"78563412").unwrap();
+        assert_eq!(
+            target.into_inner().into_inner().pc,
+            [0x78, 0x56, 0x34, 0x12]
+        );
+    }
+
+    /// the raw register file of the mips VirtualTarget
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct MipsRegisters {
+        bytes: Vec<u8>,
+    }
+
+    impl Registers for MipsRegisters {
+        type ProgramCounter = u32;
+
+        fn pc(&self) -> u32 {
+            0
+        }
+
+        fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+            for byte in self.bytes.iter() {
+                write_byte(Some(*byte));
+            }
+        }
+
+        fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+            if bytes.len() != MIPS32_BE.registers_size() {
+                return Err(());
+            }
+            self.bytes = bytes.to_vec();
+            Ok(())
+        }
+    }
+
+    enum MipsArch {}
+
+    impl Arch for MipsArch {
+        type Usize = u32;
+        type Registers = MipsRegisters;
+        type BreakpointKind = usize;
+        type RegId = ();
+    }
+
+    #[test]
+    fn it_should_bridge_registers_of_an_arch() {
+        let mut target = EmbedgdbTarget::<_, MipsArch>::new(VirtualTarget::new());
+        let mut regs = MipsRegisters::default();
+        assert!(target.read_registers(&mut regs).is_ok());
+        // the same bytes as a g reply
+        let g = exec(b"$g#67", target.inner());
+        let mut expected = [0; MAX_REGISTER_BYTES];
+        let len = Parser::from_hex_bytes(&g[1..g.len() - 3], &mut expected).unwrap();
+        assert_eq!(regs.bytes, expected[..len]);
+
+        regs.bytes[8..12].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        assert!(target.write_registers(&regs).is_ok());
+        assert_eq!(
+            target.inner().rd_register(2),
+            Ok(RegisterValue::U32(0x12345678))
+        );
+    }
+
+    #[test]
+    fn it_should_bridge_memory_both_ways() {
+        let mut target = EmbedgdbTarget::<_, TestArch>::new(VirtualTarget::new());
+        let data: [u8; 100] = core::array::from_fn(|i| i as u8);
+        assert!(target.write_addrs(0x10, &data).is_ok());
+
+        let mut read = [0; 100];
+        assert_eq!(target.read_addrs(0x10, &mut read).ok(), Some(100));
+        assert_eq!(read, data);
+
+        let target = GdbstubTarget::new(target);
+        let mut stream = BufferedStream::new();
+        target.rd_memory(0x12, 3, &mut stream).unwrap();
+        assert_eq!(&stream.buffer[..stream.pos()], b"020304");
+    }
+
+    #[test]
+    fn it_should_check_bridged_memory_accesses() {
+        let mut target = EmbedgdbTarget::<_, TestArch>::new(VirtualTarget::new());
+        // reads are cut at the end of the memory map
+        let mut read = [0; 4];
+        assert_eq!(target.read_addrs(0x1fe, &mut read).ok(), Some(2));
+        assert!(target.read_addrs(0x1000, &mut read).is_err());
+        // writes must fit completely
+        assert!(target.write_addrs(0x1fe, &[1, 2, 3, 4]).is_err());
+        assert!(target.write_addrs(0x1000, &[1]).is_err());
+    }
+
+    #[test]
+    fn it_should_forward_the_target_description() {
+        let target = GdbstubTarget::new(EmbedgdbTarget::<_, TestArch>::new(PcTarget::default()));
+        assert_eq!(
+            target.features(b"target.xml"),
+            Some(&b"<target><architecture>test</architecture></target>"[..])
+        );
+        assert_eq!(target.features(b"other.xml"), None);
+    }
+}
@@ -0,0 +1,122 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// Minimal streaming json writer.
+/// Commas are inserted automatically between values,
+/// nesting is not validated.
+pub struct JsonWriter<'a> {
+    stream: &'a mut dyn Stream,
+    needs_comma: bool,
+}
+
+impl<'a> JsonWriter<'a> {
+    pub fn new(stream: &'a mut dyn Stream) -> Self {
+        Self {
+            stream,
+            needs_comma: false,
+        }
+    }
+
+    fn separate(&mut self) -> Result<usize, Errors> {
+        match core::mem::replace(&mut self.needs_comma, true) {
+            true => self.stream.write(b','),
+            false => Ok(0),
+        }
+    }
+
+    pub fn begin_object(&mut self) -> Result<usize, Errors> {
+        let size = self.separate()? + self.stream.write(b'{')?;
+        self.needs_comma = false;
+        Ok(size)
+    }
+
+    pub fn end_object(&mut self) -> Result<usize, Errors> {
+        self.needs_comma = true;
+        self.stream.write(b'}')
+    }
+
+    pub fn begin_array(&mut self) -> Result<usize, Errors> {
+        let size = self.separate()? + self.stream.write(b'[')?;
+        self.needs_comma = false;
+        Ok(size)
+    }
+
+    pub fn end_array(&mut self) -> Result<usize, Errors> {
+        self.needs_comma = true;
+        self.stream.write(b']')
+    }
+
+    /// writes an object key, the value has to follow
+    pub fn key(&mut self, key: &[u8]) -> Result<usize, Errors> {
+        let size = self.string(key)? + self.stream.write(b':')?;
+        self.needs_comma = false;
+        Ok(size)
+    }
+
+    /// writes a numeric object key, e.g. a register number
+    pub fn key_number(&mut self, key: usize) -> Result<usize, Errors> {
+        let mut size = self.separate()? + self.stream.write(b'"')?;
+        size += Parser::to_decimal(key, self.stream)?;
+        size += self.stream.write_all(b"\":")?;
+        self.needs_comma = false;
+        Ok(size)
+    }
+
+    pub fn string(&mut self, value: &[u8]) -> Result<usize, Errors> {
+        let mut size = self.separate()? + self.stream.write(b'"')?;
+        for byte in value {
+            size += match byte {
+                b'"' | b'\\' => self.stream.write(b'\\')? + self.stream.write(*byte)?,
+                0..=0x1f => {
+                    self.stream.write_all(b"\\u00")?;
+                    Parser::to_hex8(*byte, self.stream)?;
+                    6
+                }
+                _ => self.stream.write(*byte)?,
+            };
+        }
+        size += self.stream.write(b'"')?;
+        Ok(size)
+    }
+
+    pub fn number(&mut self, value: usize) -> Result<usize, Errors> {
+        Ok(self.separate()? + Parser::to_decimal(value, self.stream)?)
+    }
+
+    /// writes a value that f encodes itself
+    pub fn raw(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn Stream) -> Result<usize, Errors>,
+    ) -> Result<usize, Errors> {
+        Ok(self.separate()? + f(self.stream)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_json() {
+        let mut s = BufferedStream::new();
+        let mut json = JsonWriter::new(&mut s);
+        json.begin_array().unwrap();
+        for tid in 1..3 {
+            json.begin_object().unwrap();
+            json.key(b"tid").unwrap();
+            json.number(tid).unwrap();
+            json.key(b"name").unwrap();
+            json.string(b"a\"b\n").unwrap();
+            json.end_object().unwrap();
+        }
+        json.begin_array().unwrap();
+        json.end_array().unwrap();
+        json.end_array().unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"[{\"tid\":1,\"name\":\"a\\\"b\\u000a\"},{\"tid\":2,\"name\":\"a\\\"b\\u000a\"},[]]"[..]
+        );
+    }
+}
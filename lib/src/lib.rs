@@ -3,16 +3,115 @@
 #[cfg(test)]
 #[macro_use]
 extern crate std;
+#[cfg(all(feature = "std", not(test)))]
+extern crate std;
 
+pub use arch::*;
+pub use argv::*;
+pub use assembler::*;
+pub use breakpoint::*;
+#[cfg(feature = "btrace")]
+pub use btrace::*;
 pub use command::*;
+#[cfg(feature = "compression")]
+pub use compress::*;
+pub use description::*;
 pub use error::*;
+#[cfg(feature = "executor")]
+pub use executor::*;
+pub use features::*;
+#[cfg(feature = "fileio")]
+pub use fileio::{FileIoCall, FileIoReply};
+#[cfg(feature = "flash")]
+pub use flash::*;
+#[cfg(feature = "freertos")]
+pub use freertos::*;
+pub use host::*;
+#[cfg(feature = "vfile")]
+pub use hostfs::*;
+#[cfg(feature = "gdbstub")]
+pub use interop::*;
+pub use library::*;
+#[cfg(feature = "lldb")]
+pub use lldb::*;
+pub use memory::*;
+#[cfg(feature = "monitor")]
+pub use monitor::*;
+pub use osdata::*;
 pub use parser::*;
+pub use register::*;
+#[cfg(feature = "reverse")]
+pub use replay::*;
+pub use resume::*;
+pub use session::*;
+pub use signal::*;
+pub use smp::*;
+pub use stop::*;
 pub use stream::*;
+pub use stub::*;
+pub use syscall::*;
 pub use target::*;
+pub use thread::*;
+#[cfg(feature = "tracepoints")]
+pub use tracepoint::*;
+#[cfg(feature = "watchdog")]
+pub use watchdog::*;
 
+pub mod arch;
+pub mod argv;
+pub mod assembler;
 pub mod basic;
+pub mod breakpoint;
+#[cfg(feature = "btrace")]
+pub mod btrace;
+pub mod budget;
+pub mod check;
 pub mod command;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod crc;
+pub mod description;
 pub mod error;
+#[cfg(feature = "executor")]
+pub mod executor;
+pub mod features;
+#[cfg(feature = "fileio")]
+pub mod fileio;
+#[cfg(feature = "flash")]
+pub mod flash;
+#[cfg(feature = "freertos")]
+pub mod freertos;
+pub mod host;
+#[cfg(feature = "vfile")]
+pub mod hostfs;
+#[cfg(feature = "gdbstub")]
+pub mod interop;
+pub mod json;
+pub mod library;
+#[cfg(feature = "lldb")]
+pub mod lldb;
+pub mod memory;
+#[cfg(feature = "monitor")]
+pub mod monitor;
+pub mod osdata;
 pub mod parser;
+pub mod register;
+#[cfg(feature = "reverse")]
+pub mod replay;
+pub mod resume;
+pub mod sanitize;
+pub mod session;
+pub mod signal;
+pub mod smp;
+pub mod step;
+pub mod stop;
 pub mod stream;
+pub mod stub;
+pub mod syscall;
 pub mod target;
+pub mod thread;
+#[cfg(feature = "tracepoints")]
+pub mod tracepoint;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
+pub mod xml;
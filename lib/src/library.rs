@@ -0,0 +1,130 @@
+use super::error::Errors;
+use super::stream::Stream;
+use super::xml;
+
+/// a shared library loaded by the dynamic linker
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LibraryInfo<'a> {
+    pub name: &'a [u8],
+    /// address of the link_map entry
+    pub lm: usize,
+    /// load bias of the library
+    pub l_addr: usize,
+    /// address of the dynamic section
+    pub l_ld: usize,
+}
+
+impl<'a> LibraryInfo<'a> {
+    pub fn new(name: &'a [u8], lm: usize, l_addr: usize, l_ld: usize) -> Self {
+        Self {
+            name,
+            lm,
+            l_addr,
+            l_ld,
+        }
+    }
+
+    /// writes the library as an element of the library-list-svr4 document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<library")?;
+        size += xml::write_attr(stream, b"name", self.name)?;
+        size += xml::write_attr_addr(stream, b"lm", self.lm)?;
+        size += xml::write_attr_addr(stream, b"l_addr", self.l_addr)?;
+        size += xml::write_attr_addr(stream, b"l_ld", self.l_ld)?;
+        size += stream.write_all(b"/>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each library during enumeration
+pub type LibraryCallback<'a> = dyn FnMut(LibraryInfo) -> Result<usize, Errors> + 'a;
+
+/// a module loaded at runtime without a dynamic linker, e.g. an overlay
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ModuleInfo<'a> {
+    pub name: &'a [u8],
+    /// load address of the first segment
+    pub segment: usize,
+}
+
+impl<'a> ModuleInfo<'a> {
+    pub fn new(name: &'a [u8], segment: usize) -> Self {
+        Self { name, segment }
+    }
+
+    /// writes the module as an element of the library-list document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<library")?;
+        size += xml::write_attr(stream, b"name", self.name)?;
+        size += stream.write_all(b"><segment")?;
+        size += xml::write_attr_addr(stream, b"address", self.segment)?;
+        size += stream.write_all(b"/></library>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each module during enumeration
+pub type ModuleCallback<'a> = dyn FnMut(ModuleInfo) -> Result<usize, Errors> + 'a;
+
+/// writes the library-list-svr4 xml document
+/// libraries is expected to call the callback once per library
+pub fn write_library_list_svr4(
+    stream: &mut dyn Stream,
+    libraries: &mut dyn FnMut(&mut LibraryCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(b"<library-list-svr4 version=\"1.0\">")?;
+    size += libraries(&mut |library| library.write_xml(stream))?;
+    size += stream.write_all(b"</library-list-svr4>")?;
+    Ok(size)
+}
+
+/// writes the library-list xml document
+/// modules is expected to call the callback once per module
+pub fn write_library_list(
+    stream: &mut dyn Stream,
+    modules: &mut dyn FnMut(&mut ModuleCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(b"<library-list>")?;
+    size += modules(&mut |module| module.write_xml(stream))?;
+    size += stream.write_all(b"</library-list>")?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_libraries() {
+        let mut s = BufferedStream::new();
+        write_library_list_svr4(&mut s, &mut |f| {
+            f(LibraryInfo::new(
+                b"/lib/libc.so.6",
+                0xe4f8f8,
+                0xe2d000,
+                0xe4eefc,
+            ))
+        })
+        .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<library-list-svr4 version=\"1.0\"><library name=\"/lib/libc.so.6\" lm=\"0xe4f8f8\" l_addr=\"0xe2d000\" l_ld=\"0xe4eefc\"/></library-list-svr4>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_write_modules() {
+        let mut s = BufferedStream::new();
+        let size = write_library_list(&mut s, &mut |f| {
+            Ok(f(ModuleInfo::new(b"overlay1", 0x8000))?
+                + f(ModuleInfo::new(b"app.elf", 0x20010000))?)
+        })
+        .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<library-list><library name=\"overlay1\"><segment address=\"0x8000\"/></library><library name=\"app.elf\"><segment address=\"0x20010000\"/></library></library-list>"[..]
+        );
+    }
+}
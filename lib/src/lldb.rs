@@ -0,0 +1,163 @@
+use super::error::Errors;
+use super::parser::{Endianness, Parser};
+use super::stream::Stream;
+
+/// describes the target for lldb's qHostInfo
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HostInfo<'a> {
+    /// llvm target triple, e.g. thumbv7em-none-eabihf
+    pub triple: &'a [u8],
+    /// size of a pointer in bytes
+    pub ptr_size: usize,
+    pub os_type: Option<&'a [u8]>,
+    pub vendor: Option<&'a [u8]>,
+}
+
+impl<'a> HostInfo<'a> {
+    pub fn new(triple: &'a [u8], ptr_size: usize) -> Self {
+        Self {
+            triple,
+            ptr_size,
+            os_type: None,
+            vendor: None,
+        }
+    }
+
+    pub fn with_os_type(mut self, os_type: &'a [u8]) -> Self {
+        self.os_type = Some(os_type);
+        self
+    }
+
+    pub fn with_vendor(mut self, vendor: &'a [u8]) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    /// writes the ; terminated key:value pairs of the qHostInfo reply
+    pub fn write(&self, endianness: Endianness, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"triple:")?;
+        Parser::to_hexu(self.triple, stream)?;
+        size += self.triple.len() * 2;
+        size += stream.write_all(b";ptrsize:")?;
+        size += Parser::to_decimal(self.ptr_size, stream)?;
+        size += stream.write_all(match endianness {
+            Endianness::Little => b";endian:little;",
+            Endianness::Big => b";endian:big;",
+        })?;
+        if let Some(os_type) = self.os_type {
+            size += stream.write_all(b"ostype:")?;
+            size += stream.write_all(os_type)?;
+            size += stream.write(b';')?;
+        }
+        if let Some(vendor) = self.vendor {
+            size += stream.write_all(b"vendor:")?;
+            size += stream.write_all(vendor)?;
+            size += stream.write(b';')?;
+        }
+        Ok(size)
+    }
+}
+
+/// amount of blocks a ScratchAllocator can hand out at once
+pub const SCRATCH_BLOCKS: usize = 8;
+
+/// allocation sizes are rounded up to this
+pub const SCRATCH_ALIGN: usize = 8;
+
+/// Hands out blocks of a ram region the program does not use,
+/// e.g. for lldb's _M when it calls functions in expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScratchAllocator {
+    start: usize,
+    len: usize,
+    /// start and length of each block, sorted by address
+    blocks: [(usize, usize); SCRATCH_BLOCKS],
+    count: usize,
+}
+
+impl ScratchAllocator {
+    pub fn new(start: usize, len: usize) -> Self {
+        Self {
+            start,
+            len,
+            blocks: [(0, 0); SCRATCH_BLOCKS],
+            count: 0,
+        }
+    }
+
+    /// returns the address of a free block of at least size bytes
+    pub fn allocate(&mut self, size: usize) -> Result<usize, Errors> {
+        if size == 0 || self.count == SCRATCH_BLOCKS {
+            return Err(Errors::AddressOutOfRange);
+        }
+        let size = size
+            .checked_add(SCRATCH_ALIGN - 1)
+            .ok_or(Errors::AddressOutOfRange)?
+            & !(SCRATCH_ALIGN - 1);
+
+        // first fit between the allocated blocks
+        let mut free = self.start;
+        let mut index = 0;
+        while index < self.count {
+            let (start, len) = self.blocks[index];
+            if start - free >= size {
+                break;
+            }
+            free = start + len;
+            index += 1;
+        }
+        if self.start + self.len - free < size {
+            return Err(Errors::AddressOutOfRange);
+        }
+
+        self.blocks.copy_within(index..self.count, index + 1);
+        self.blocks[index] = (free, size);
+        self.count += 1;
+        Ok(free)
+    }
+
+    /// frees the block starting at addr
+    pub fn deallocate(&mut self, addr: usize) -> Result<(), Errors> {
+        let index = self.blocks[..self.count]
+            .iter()
+            .position(|(start, _)| *start == addr)
+            .ok_or(Errors::AddressOutOfRange)?;
+        self.blocks.copy_within(index + 1..self.count, index);
+        self.count -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_host_info() {
+        let mut s = BufferedStream::new();
+        let size = HostInfo::new(b"riscv32", 4)
+            .with_os_type(b"none")
+            .write(Endianness::Little, &mut s)
+            .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"triple:72697363763332;ptrsize:4;endian:little;ostype:none;"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_allocate_scratch_memory() {
+        let mut scratch = ScratchAllocator::new(0x1000, 0x20);
+        assert_eq!(scratch.allocate(4), Ok(0x1000));
+        assert_eq!(scratch.allocate(0x10), Ok(0x1008));
+        assert_eq!(scratch.allocate(9), Err(Errors::AddressOutOfRange));
+
+        // freed blocks are reused
+        scratch.deallocate(0x1000).unwrap();
+        assert_eq!(scratch.allocate(8), Ok(0x1000));
+        assert_eq!(scratch.allocate(8), Ok(0x1018));
+        assert_eq!(scratch.deallocate(0x1004), Err(Errors::AddressOutOfRange));
+    }
+}
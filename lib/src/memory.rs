@@ -0,0 +1,541 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::register::{RegisterValue, MAX_REGISTER_SIZE};
+use super::stream::Stream;
+use super::target::Target;
+
+/// an address in the target's address space
+/// it is not a host pointer and may exceed the host's usize
+pub type GuestAddress = u64;
+
+/// a physical address, the result of Target::translate
+/// the memory map and the memory hooks work with physical addresses
+pub type PhysAddr = u64;
+
+/// Splits an access into chunks that do not cross a page boundary.
+/// Each chunk can be translated on its own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pages {
+    addr: GuestAddress,
+    len: usize,
+    page_size: Option<usize>,
+}
+
+impl Pages {
+    /// without page size the access is a single chunk
+    pub fn new(addr: GuestAddress, len: usize, page_size: Option<usize>) -> Self {
+        Self {
+            addr,
+            len,
+            page_size: page_size.filter(|size| *size != 0),
+        }
+    }
+}
+
+impl Iterator for Pages {
+    /// the virtual address and length of a chunk
+    type Item = (GuestAddress, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let len = match self.page_size {
+            Some(size) => {
+                let size = size as GuestAddress;
+                let left = (size - self.addr % size) as usize;
+                usize::min(left, self.len)
+            }
+            None => self.len,
+        };
+        let chunk = (self.addr, len);
+        self.addr = self.addr.wrapping_add(len as GuestAddress);
+        self.len -= len;
+        Some(chunk)
+    }
+}
+
+/// the kind of memory in a region
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryKind {
+    Ram,
+    Rom,
+    /// flash has to be erased in blocks of block_size bytes
+    Flash {
+        block_size: usize,
+    },
+}
+
+/// access permissions of a piece of memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl Permissions {
+    pub const fn new(read: bool, write: bool, exec: bool) -> Self {
+        Self { read, write, exec }
+    }
+
+    /// writes the permissions as a combination of r, w and x
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = 0;
+        for (set, perm) in [(self.read, b'r'), (self.write, b'w'), (self.exec, b'x')] {
+            if set {
+                size += stream.write(perm)?;
+            }
+        }
+        Ok(size)
+    }
+
+    /// parses a combination of r, w and x, e.g. rx
+    pub fn parse(perms: &[u8]) -> Result<Self, Errors> {
+        let mut result = Self::default();
+        for perm in perms {
+            match perm {
+                b'r' => result.read = true,
+                b'w' => result.write = true,
+                b'x' => result.exec = true,
+                _ => return Err(Errors::BadNumber),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// describes a single region of target memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: GuestAddress,
+    pub len: GuestAddress,
+    /// checked before any memory access reaches the target
+    pub perms: Permissions,
+    pub kind: MemoryKind,
+}
+
+impl MemoryRegion {
+    /// ram is readable and writable, rom and flash readable and executable
+    pub const fn new(start: GuestAddress, len: GuestAddress, kind: MemoryKind) -> Self {
+        let perms = match kind {
+            MemoryKind::Ram => Permissions::new(true, true, false),
+            MemoryKind::Rom | MemoryKind::Flash { .. } => Permissions::new(true, false, true),
+        };
+        Self {
+            start,
+            len,
+            perms,
+            kind,
+        }
+    }
+
+    pub const fn with_perms(mut self, perms: Permissions) -> Self {
+        self.perms = perms;
+        self
+    }
+
+    pub fn contains(&self, addr: GuestAddress) -> bool {
+        addr >= self.start && addr - self.start < self.len
+    }
+
+    /// writes the region as a memory-map xml element
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<memory type=\"")?;
+        size += stream.write_all(match self.kind {
+            MemoryKind::Ram => b"ram",
+            MemoryKind::Rom => b"rom",
+            MemoryKind::Flash { .. } => b"flash",
+        })?;
+        size += stream.write_all(b"\" start=\"0x")?;
+        size += Parser::to_hex_address(self.start, stream)?;
+        size += stream.write_all(b"\" length=\"0x")?;
+        size += Parser::to_hex_address(self.len, stream)?;
+
+        match self.kind {
+            MemoryKind::Flash { block_size } => {
+                size += stream.write_all(b"\"><property name=\"blocksize\">0x")?;
+                size += Parser::to_hex_number(block_size, stream)?;
+                size += stream.write_all(b"</property></memory>")?;
+            }
+            _ => {
+                size += stream.write_all(b"\"/>")?;
+            }
+        }
+        Ok(size)
+    }
+}
+
+/// reads memory starting at an address into a chunk
+pub type ReadHook<'a> = dyn FnMut(usize, &mut [u8]) -> Result<(), Errors> + 'a;
+
+/// Frozen copy of target memory stored in a user provided buffer.
+/// Capture it when the target stops so gdb reads a consistent image
+/// even if peripherals keep changing ram behind the halted cpu.
+pub struct Snapshot<'a> {
+    buffer: &'a mut [u8],
+    start: usize,
+    len: usize,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// copies memory starting at address start
+    /// at most the size of the buffer is captured
+    pub fn capture(&mut self, start: usize, memory: &[u8]) -> usize {
+        let len = usize::min(memory.len(), self.buffer.len());
+        self.buffer[..len].copy_from_slice(&memory[..len]);
+        self.start = start;
+        self.len = len;
+        len
+    }
+
+    /// captures len bytes starting at address start using a read hook
+    /// read receives an address and a chunk to fill
+    pub fn capture_with(
+        &mut self,
+        start: usize,
+        len: usize,
+        read: &mut ReadHook,
+    ) -> Result<usize, Errors> {
+        let len = usize::min(len, self.buffer.len());
+        self.len = 0;
+        read(start, &mut self.buffer[..len])?;
+        self.start = start;
+        self.len = len;
+        Ok(len)
+    }
+
+    /// address the snapshot was taken at
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+/// writes a memory-map xml document for the given regions
+pub fn write_memory_map(
+    stream: &mut dyn Stream,
+    regions: &[MemoryRegion],
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(b"<?xml version=\"1.0\"?><!DOCTYPE memory-map PUBLIC \"+//IDN gnu.org//DTD GDB Memory Map V1.0//EN\" \"http://sourceware.org/gdb/gdb-memory-map.dtd\"><memory-map>")?;
+    for region in regions {
+        size += region.write_xml(stream)?;
+    }
+    size += stream.write_all(b"</memory-map>")?;
+    Ok(size)
+}
+
+/// raw bytes converted to hex at once by write_memory
+pub const MEMORY_WRITE_CHUNK: usize = 16;
+
+/// captures hex encoded memory into a byte buffer
+struct CaptureStream<'a> {
+    buffer: &'a mut [u8],
+    pos: usize,
+    high: Option<u8>,
+}
+
+impl Stream for CaptureStream<'_> {
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        let nibble = Parser::from_hex(byte).ok_or(Errors::BadNumber)?;
+        match self.high.take() {
+            None => self.high = Some(nibble),
+            Some(high) => {
+                let out = self
+                    .buffer
+                    .get_mut(self.pos)
+                    .ok_or(Errors::MemoryFilledInterupt)?;
+                *out = (high << 4) | nibble;
+                self.pos += 1;
+            }
+        }
+        Ok(1)
+    }
+}
+
+/// checks an access of len bytes at addr against the memory map
+/// returns how many bytes from addr on are mapped with the permission,
+/// the access may span adjacent regions
+/// if not even the first byte is accessible it fails with MemoryFault
+/// for unmapped memory and AccessDenied for missing permissions
+/// targets without a memory map check accesses themselves
+pub fn check_access(
+    map: &[MemoryRegion],
+    addr: GuestAddress,
+    len: usize,
+    write: bool,
+) -> Result<usize, Errors> {
+    if map.is_empty() || len == 0 {
+        return Ok(len);
+    }
+    let mut checked = 0;
+    while checked < len {
+        let region = addr
+            .checked_add(checked as GuestAddress)
+            .and_then(|next| map.iter().find(|region| region.contains(next)));
+        let permitted = region.map(|region| match write {
+            true => region.perms.write,
+            false => region.perms.read,
+        });
+        match (region, permitted) {
+            (Some(region), Some(true)) => {
+                // a region may end at the top of the address space
+                let end = region.start.checked_add(region.len);
+                let remaining = match end {
+                    Some(end) => end - addr,
+                    None => (GuestAddress::MAX - addr).saturating_add(1),
+                };
+                checked = usize::try_from(remaining).map_or(len, |n| usize::min(len, n));
+            }
+            _ if checked != 0 => return Ok(checked),
+            (Some(_), _) => return Err(Errors::AccessDenied),
+            (None, _) => return Err(Errors::MemoryFault),
+        }
+    }
+    Ok(len)
+}
+
+/// reads len bytes of virtual memory hex encoded into stream as m does
+/// every page is translated with Target::translate and the read is cut
+/// at the first byte Target::check_memory_access does not permit,
+/// it fails if not even the first byte may be read
+/// returns the amount of bytes read
+pub fn read_hex(
+    target: &dyn Target,
+    start: GuestAddress,
+    len: usize,
+    stream: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let mut read = 0;
+    for (addr, len) in Pages::new(start, len, target.page_size()) {
+        let access = target
+            .translate(addr)
+            .ok_or(Errors::MemoryFault)
+            .and_then(|phys| Ok((phys, target.check_memory_access(phys, len, false)?)));
+        match access {
+            Ok((phys, readable)) => {
+                target.rd_memory(phys, readable, stream)?;
+                read += readable;
+                if readable < len {
+                    break;
+                }
+            }
+            Err(err) if read == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(read)
+}
+
+/// reads raw virtual memory into out, see read_hex
+/// returns the amount of bytes read
+pub fn read_memory(
+    target: &dyn Target,
+    start: GuestAddress,
+    out: &mut [u8],
+) -> Result<usize, Errors> {
+    let len = out.len();
+    let mut capture = CaptureStream {
+        buffer: out,
+        pos: 0,
+        high: None,
+    };
+    read_hex(target, start, len, &mut capture)?;
+    Ok(capture.pos)
+}
+
+/// reads an integer of size bytes in the byte order of the target
+pub fn read_word(target: &dyn Target, start: GuestAddress, size: usize) -> Result<u128, Errors> {
+    let mut bytes = [0; MAX_REGISTER_SIZE];
+    let bytes = bytes.get_mut(..size).ok_or(Errors::LengthMismatch)?;
+    if read_memory(target, start, bytes)? != size {
+        return Err(Errors::MemoryFault);
+    }
+    RegisterValue::from_bytes(bytes, target.endianess())
+        .and_then(|value| value.value())
+        .ok_or(Errors::LengthMismatch)
+}
+
+/// checks that every page of a write of len bytes may be written
+fn check_write(target: &dyn Target, start: GuestAddress, len: usize) -> Result<(), Errors> {
+    for (addr, len) in Pages::new(start, len, target.page_size()) {
+        let phys = target.translate(addr).ok_or(Errors::MemoryFault)?;
+        if target.check_memory_access(phys, len, true)? != len {
+            return Err(Errors::MemoryFault);
+        }
+    }
+    Ok(())
+}
+
+/// writes hex encoded data to the translated pages of a checked write
+fn write_pages(target: &mut dyn Target, start: GuestAddress, hex: &[u8]) -> Result<(), Errors> {
+    let mut offset = 0;
+    for (addr, len) in Pages::new(start, hex.len() / 2, target.page_size()) {
+        let phys = target.translate(addr).ok_or(Errors::MemoryFault)?;
+        target.wr_memory(phys, &hex[offset * 2..(offset + len) * 2])?;
+        offset += len;
+    }
+    Ok(())
+}
+
+/// writes hex encoded data to virtual memory as M does
+/// nothing is written unless Target::check_memory_access
+/// permits all of it on every translated page
+pub fn write_hex(
+    target: &mut dyn Target,
+    start: GuestAddress,
+    hex: &[u8],
+) -> Result<usize, Errors> {
+    check_write(target, start, hex.len() / 2)?;
+    write_pages(target, start, hex)?;
+    Ok(0)
+}
+
+/// writes raw bytes to virtual memory, see write_hex
+pub fn write_memory(
+    target: &mut dyn Target,
+    start: GuestAddress,
+    data: &[u8],
+) -> Result<usize, Errors> {
+    check_write(target, start, data.len())?;
+    let mut hex = [0; MEMORY_WRITE_CHUNK * 2];
+    for (chunk_index, chunk) in data.chunks(MEMORY_WRITE_CHUNK).enumerate() {
+        for (i, byte) in chunk.iter().enumerate() {
+            let (high, low) = Parser::to_hex_tuple(*byte);
+            hex[i * 2] = high;
+            hex[i * 2 + 1] = low;
+        }
+        let address = start + (chunk_index * MEMORY_WRITE_CHUNK) as GuestAddress;
+        write_pages(target, address, &hex[..chunk.len() * 2])?;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+    use std::vec::Vec;
+
+    #[test]
+    fn it_should_enforce_the_map_on_raw_accesses() {
+        let mut target = crate::target::VirtualTarget::new();
+        let mut out = [0; 4];
+        assert_eq!(read_memory(&target, 0x1fe, &mut out), Ok(2));
+        assert_eq!(
+            write_memory(&mut target, 0x1fe, &[1, 2, 3, 4]),
+            Err(Errors::MemoryFault)
+        );
+        assert_eq!(
+            read_memory(&target, 0x1000, &mut out),
+            Err(Errors::MemoryFault)
+        );
+    }
+
+    #[test]
+    fn it_should_write_ram_regions() {
+        let mut s = BufferedStream::new();
+        MemoryRegion::new(0x80000000, 0x400000, MemoryKind::Ram)
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<memory type=\"ram\" start=\"0x80000000\" length=\"0x400000\"/>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_write_flash_regions() {
+        let mut s = BufferedStream::new();
+        MemoryRegion::new(0, 0x10000, MemoryKind::Flash { block_size: 0x400 })
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<memory type=\"flash\" start=\"0x0\" length=\"0x10000\"><property name=\"blocksize\">0x400</property></memory>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_capture_snapshots() {
+        let mut buffer = [0; 4];
+        let mut snapshot = Snapshot::new(&mut buffer);
+        assert_eq!(snapshot.capture(0x80, &[1, 2, 3, 4, 5]), 4);
+        assert_eq!(snapshot.start(), 0x80);
+        assert_eq!(snapshot.data(), &[1, 2, 3, 4]);
+
+        snapshot
+            .capture_with(0x10, 2, &mut |addr, chunk| {
+                chunk.fill(addr as u8);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(snapshot.data(), &[0x10, 0x10]);
+    }
+
+    #[test]
+    fn it_should_split_pages() {
+        let pages: Vec<_> = Pages::new(0xff8, 0x1010, Some(0x1000)).collect();
+        assert_eq!(pages, [(0xff8, 8), (0x1000, 0x1000), (0x2000, 8)]);
+        let pages: Vec<_> = Pages::new(0xff8, 0x10, None).collect();
+        assert_eq!(pages, [(0xff8, 0x10)]);
+        assert_eq!(Pages::new(0x10, 0, Some(0x1000)).next(), None);
+    }
+
+    #[test]
+    fn it_should_check_accesses() {
+        const MAP: [MemoryRegion; 3] = [
+            MemoryRegion::new(0, 0x100, MemoryKind::Rom),
+            MemoryRegion::new(0x100, 0x100, MemoryKind::Ram),
+            MemoryRegion::new(0x1000, 0x10, MemoryKind::Ram)
+                .with_perms(Permissions::new(false, true, false)),
+        ];
+        assert_eq!(check_access(&MAP, 0x80, 0x100, false), Ok(0x100));
+        assert_eq!(check_access(&MAP, 0x80, 4, true), Err(Errors::AccessDenied));
+        assert_eq!(check_access(&MAP, 0xfe, 4, true), Err(Errors::AccessDenied));
+        assert_eq!(check_access(&MAP, 0x100, 0x100, true), Ok(0x100));
+        assert_eq!(check_access(&MAP, 0x1fe, 4, false), Ok(2));
+        assert_eq!(
+            check_access(&MAP, 0x200, 4, false),
+            Err(Errors::MemoryFault)
+        );
+        assert_eq!(
+            check_access(&MAP, 0x1000, 4, false),
+            Err(Errors::AccessDenied)
+        );
+        assert_eq!(check_access(&MAP, 0x1000, 4, true), Ok(4));
+        assert_eq!(
+            check_access(&MAP, u64::MAX, 2, false),
+            Err(Errors::MemoryFault)
+        );
+        assert_eq!(check_access(&[], 0x5000, 4, true), Ok(4));
+
+        let top = [MemoryRegion::new(
+            GuestAddress::MAX - 0xff,
+            0x100,
+            MemoryKind::Ram,
+        )];
+        let last = GuestAddress::MAX - 1;
+        assert_eq!(check_access(&top, last, 4, false), Ok(2));
+    }
+
+    #[test]
+    fn it_should_check_bounds() {
+        let region = MemoryRegion::new(0x100, 0x10, MemoryKind::Rom);
+        assert!(region.contains(0x100));
+        assert!(region.contains(0x10f));
+        assert!(!region.contains(0x110));
+        assert!(!region.contains(0xff));
+    }
+}
@@ -0,0 +1,360 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// handler of a monitor command
+/// receives everything after the command name
+pub type MonitorHandler<T> = fn(&mut T, &[u8], &mut dyn Stream) -> Result<usize, Errors>;
+
+/// a named monitor command with a one line description
+/// e.g. monitor reset halt runs the subcommand halt of reset
+pub struct MonitorEntry<T: ?Sized + 'static> {
+    pub name: &'static [u8],
+    pub help: &'static [u8],
+    /// runs if no subcommand matches
+    pub run: MonitorHandler<T>,
+    pub subcommands: &'static [MonitorEntry<T>],
+}
+
+impl<T: ?Sized + 'static> MonitorEntry<T> {
+    pub const fn new(name: &'static [u8], help: &'static [u8], run: MonitorHandler<T>) -> Self {
+        Self {
+            name,
+            help,
+            run,
+            subcommands: &[],
+        }
+    }
+
+    /// a command that only groups subcommands
+    /// running it without subcommand lists them
+    pub const fn group(
+        name: &'static [u8],
+        help: &'static [u8],
+        subcommands: &'static [MonitorEntry<T>],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            run: |_, _, _| Err(Errors::InsufficientArguments),
+            subcommands,
+        }
+    }
+
+    pub const fn with_subcommands(mut self, subcommands: &'static [MonitorEntry<T>]) -> Self {
+        self.subcommands = subcommands;
+        self
+    }
+}
+
+/// Splits the arguments of a monitor command into words.
+pub struct Args<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a [u8]) -> Self {
+        Self {
+            rest: args.trim_ascii(),
+        }
+    }
+
+    /// the next word as a decimal or 0x prefixed hex number
+    pub fn number(&mut self) -> Result<usize, Errors> {
+        let word = self.next().ok_or(Errors::InsufficientArguments)?;
+        let number = match word.strip_prefix(b"0x") {
+            Some(hex) if !hex.is_empty() => Parser::from_hexu(hex),
+            Some(_) => None,
+            None if word.iter().all(u8::is_ascii_digit) => word.iter().try_fold(0usize, |n, b| {
+                n.checked_mul(10)?.checked_add((b - b'0') as usize)
+            }),
+            None => None,
+        };
+        number.ok_or(Errors::BadNumber)
+    }
+
+    /// everything that was not consumed yet
+    pub fn rest(&self) -> &'a [u8] {
+        self.rest
+    }
+
+    /// fails with CommandError if words are left over
+    pub fn end(&self) -> Result<(), Errors> {
+        match self.rest {
+            b"" => Ok(()),
+            _ => Err(Errors::CommandError),
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a [u8];
+
+    /// the next word or None if all words were consumed
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (word, rest) = split_command(self.rest);
+        self.rest = rest;
+        Some(word)
+    }
+}
+
+/// splits a command into its name and the remaining arguments
+pub fn split_command(cmd: &[u8]) -> (&[u8], &[u8]) {
+    let cmd = cmd.trim_ascii();
+    match cmd.iter().position(|b| b.is_ascii_whitespace()) {
+        Some(i) => (&cmd[..i], cmd[i..].trim_ascii_start()),
+        None => (cmd, &[]),
+    }
+}
+
+/// writes one line per command, subcommands are prefixed with their parent
+fn write_entries<T: ?Sized + 'static>(
+    prefix: &[&[u8]],
+    entries: &[MonitorEntry<T>],
+    out: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let mut size = 0;
+    for entry in entries {
+        for parent in prefix {
+            size += out.write_all(parent)?;
+            size += out.write(b' ')?;
+        }
+        size += out.write_all(entry.name)?;
+        size += out.write_all(b" - ")?;
+        size += out.write_all(entry.help)?;
+        size += out.write(b'\n')?;
+
+        if !entry.subcommands.is_empty() {
+            // commands are nested a few levels at most
+            let mut path: [&[u8]; 4] = [&[]; 4];
+            let depth = usize::min(prefix.len(), path.len() - 1);
+            path[..depth].copy_from_slice(&prefix[..depth]);
+            path[depth] = entry.name;
+            size += write_entries(&path[..=depth], entry.subcommands, out)?;
+        }
+    }
+    Ok(size)
+}
+
+/// writes one line per registered command
+pub fn write_help<T: ?Sized + 'static>(
+    entries: &[MonitorEntry<T>],
+    out: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let size = out.write_all(b"help - lists all monitor commands\n")?;
+    Ok(size + write_entries(&[], entries, out)?)
+}
+
+/// finds the command named by the leading words of cmd
+/// returns the command and the remaining arguments
+pub fn find<'a, 'e, T: ?Sized + 'static>(
+    entries: &'e [MonitorEntry<T>],
+    cmd: &'a [u8],
+) -> Option<(&'e MonitorEntry<T>, &'a [u8])> {
+    let (name, args) = split_command(cmd);
+    let entry = entries.iter().find(|entry| entry.name == name)?;
+    let (sub, _) = split_command(args);
+    match entry.subcommands.iter().any(|entry| entry.name == sub) {
+        true => find(entry.subcommands, args),
+        false => Some((entry, args)),
+    }
+}
+
+/// runs the registered command matching cmd
+/// help is answered automatically from the registered descriptions,
+/// help <command> only lists the command and its subcommands
+/// returns Unsupported if no command matches
+pub fn dispatch<T: ?Sized + 'static>(
+    target: &mut T,
+    entries: &[MonitorEntry<T>],
+    cmd: &[u8],
+    out: &mut dyn Stream,
+) -> Result<usize, Errors> {
+    let (name, args) = split_command(cmd);
+    if name == b"help" {
+        return match args {
+            b"" => write_help(entries, out),
+            _ => match find(entries, args) {
+                Some((entry, _)) => write_entries(&[], core::slice::from_ref(entry), out),
+                None => Err(Errors::Unsupported),
+            },
+        };
+    }
+
+    match find(entries, cmd) {
+        Some((entry, args)) if entry.subcommands.is_empty() || !args.is_empty() => {
+            (entry.run)(target, args, out)
+        }
+        // a group without subcommand lists what it offers
+        Some((entry, _)) => match (entry.run)(target, args, out) {
+            Err(Errors::InsufficientArguments) => {
+                write_entries(&[entry.name], entry.subcommands, out)
+            }
+            result => result,
+        },
+        None => Err(Errors::Unsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    struct Counter(usize);
+
+    const COMMANDS: [MonitorEntry<Counter>; 1] = [MonitorEntry::new(
+        b"add",
+        b"increments the counter",
+        |counter, args, _out| {
+            counter.0 += args.len();
+            Ok(0)
+        },
+    )];
+
+    struct Probe {
+        halted: bool,
+        rtt: Option<usize>,
+    }
+
+    const RESET: [MonitorEntry<Probe>; 1] = [MonitorEntry::new(
+        b"halt",
+        b"resets and halts",
+        |probe, args, _out| {
+            Args::new(args).end()?;
+            probe.halted = true;
+            Ok(0)
+        },
+    )];
+
+    const RTT: [MonitorEntry<Probe>; 2] = [
+        MonitorEntry::new(b"start", b"starts rtt at address", |probe, args, _out| {
+            let mut args = Args::new(args);
+            let addr = args.number()?;
+            args.end()?;
+            probe.rtt = Some(addr);
+            Ok(0)
+        }),
+        MonitorEntry::new(b"stop", b"stops rtt", |probe, _args, _out| {
+            probe.rtt = None;
+            Ok(0)
+        }),
+    ];
+
+    const PROBE: [MonitorEntry<Probe>; 2] = [
+        MonitorEntry::new(
+            b"reset",
+            b"resets the target",
+            |probe: &mut Probe, args, _out| {
+                Args::new(args).end()?;
+                probe.halted = false;
+                Ok(0)
+            },
+        )
+        .with_subcommands(&RESET),
+        MonitorEntry::group(b"rtt", b"real time transfer", &RTT),
+    ];
+
+    #[test]
+    fn it_should_parse_arguments() {
+        let mut args = Args::new(b" 0x2000 42  x ");
+        assert_eq!(args.number(), Ok(0x2000));
+        assert_eq!(args.number(), Ok(42));
+        assert_eq!(args.rest(), b"x");
+        assert_eq!(args.end(), Err(Errors::CommandError));
+        assert_eq!(args.number(), Err(Errors::BadNumber));
+        assert_eq!(args.next(), None);
+        assert_eq!(args.number(), Err(Errors::InsufficientArguments));
+        assert_eq!(Args::new(b"0x").number(), Err(Errors::BadNumber));
+    }
+
+    #[test]
+    fn it_should_dispatch_subcommands() {
+        let mut probe = Probe {
+            halted: false,
+            rtt: None,
+        };
+        let mut s = BufferedStream::new();
+        dispatch(&mut probe, &PROBE, b"reset halt", &mut s).unwrap();
+        assert!(probe.halted);
+        dispatch(&mut probe, &PROBE, b"reset", &mut s).unwrap();
+        assert!(!probe.halted);
+        let err = dispatch(&mut probe, &PROBE, b"reset run", &mut s).unwrap_err();
+        assert_eq!(err, Errors::CommandError);
+        assert!(!probe.halted);
+
+        dispatch(&mut probe, &PROBE, b"rtt start 0x20000000", &mut s).unwrap();
+        assert_eq!(probe.rtt, Some(0x20000000));
+        let err = dispatch(&mut probe, &PROBE, b"rtt start", &mut s).unwrap_err();
+        assert_eq!(err, Errors::InsufficientArguments);
+        let err = dispatch(&mut probe, &PROBE, b"rtt restart", &mut s).unwrap_err();
+        assert_eq!(err, Errors::InsufficientArguments);
+    }
+
+    #[test]
+    fn it_should_list_subcommands() {
+        let mut probe = Probe {
+            halted: false,
+            rtt: None,
+        };
+        let mut s = BufferedStream::new();
+        dispatch(&mut probe, &PROBE, b"help", &mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"help - lists all monitor commands\n\
+              reset - resets the target\n\
+              reset halt - resets and halts\n\
+              rtt - real time transfer\n\
+              rtt start - starts rtt at address\n\
+              rtt stop - stops rtt\n"[..]
+        );
+
+        let mut s = BufferedStream::new();
+        dispatch(&mut probe, &PROBE, b"rtt", &mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"rtt start - starts rtt at address\nrtt stop - stops rtt\n"[..]
+        );
+
+        let mut s = BufferedStream::new();
+        dispatch(&mut probe, &PROBE, b"help reset", &mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"reset - resets the target\nreset halt - resets and halts\n"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_split_commands() {
+        assert_eq!(
+            split_command(b" reset  halt "),
+            (&b"reset"[..], &b"halt"[..])
+        );
+        assert_eq!(split_command(b"reset"), (&b"reset"[..], &b""[..]));
+    }
+
+    #[test]
+    fn it_should_dispatch_commands() {
+        let mut counter = Counter(0);
+        let mut s = BufferedStream::new();
+        dispatch(&mut counter, &COMMANDS, b"add abc", &mut s).unwrap();
+        assert_eq!(counter.0, 3);
+
+        let err = dispatch(&mut counter, &COMMANDS, b"sub", &mut s).unwrap_err();
+        assert_eq!(err, Errors::Unsupported);
+    }
+
+    #[test]
+    fn it_should_generate_help() {
+        let mut counter = Counter(0);
+        let mut s = BufferedStream::new();
+        dispatch(&mut counter, &COMMANDS, b"help", &mut s).unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"help - lists all monitor commands\nadd - increments the counter\n"[..]
+        );
+    }
+}
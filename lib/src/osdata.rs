@@ -0,0 +1,110 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+use super::xml;
+
+/// value of a single os data cell
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OsValue<'a> {
+    Text(&'a [u8]),
+    /// written as a decimal number
+    Number(usize),
+}
+
+/// a named cell of an os data row
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OsColumn<'a> {
+    pub name: &'a [u8],
+    pub value: OsValue<'a>,
+}
+
+impl<'a> OsColumn<'a> {
+    pub fn text(name: &'a [u8], value: &'a [u8]) -> Self {
+        Self {
+            name,
+            value: OsValue::Text(value),
+        }
+    }
+
+    pub fn number(name: &'a [u8], value: usize) -> Self {
+        Self {
+            name,
+            value: OsValue::Number(value),
+        }
+    }
+
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<column")?;
+        size += xml::write_attr(stream, b"name", self.name)?;
+        size += stream.write(b'>')?;
+        size += match self.value {
+            OsValue::Text(text) => xml::write_escaped(stream, text)?,
+            OsValue::Number(n) => Parser::to_decimal(n, stream)?,
+        };
+        size += stream.write_all(b"</column>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each row of a table
+pub type OsDataCallback<'a> = dyn FnMut(&[OsColumn]) -> Result<usize, Errors> + 'a;
+
+/// writes an osdata xml document of the given type
+/// rows is expected to call the callback once per row
+pub fn write_osdata(
+    stream: &mut dyn Stream,
+    kind: &[u8],
+    rows: &mut dyn FnMut(&mut OsDataCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream
+        .write_all(b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"osdata.dtd\"><osdata")?;
+    size += xml::write_attr(
+        stream,
+        b"type",
+        if kind.is_empty() { b"types" } else { kind },
+    )?;
+    size += stream.write(b'>')?;
+    size += rows(&mut |columns| {
+        let mut size = stream.write_all(b"<item>")?;
+        for column in columns {
+            size += column.write_xml(stream)?;
+        }
+        size += stream.write_all(b"</item>")?;
+        Ok(size)
+    })?;
+    size += stream.write_all(b"</osdata>")?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_tables() {
+        let mut s = BufferedStream::new();
+        write_osdata(&mut s, b"tasks", &mut |f| {
+            f(&[
+                OsColumn::text(b"name", b"idle"),
+                OsColumn::number(b"priority", 0),
+            ])?;
+            f(&[
+                OsColumn::text(b"name", b"<main>"),
+                OsColumn::number(b"priority", 12),
+            ])
+        })
+        .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"osdata.dtd\"><osdata type=\"tasks\"><item><column name=\"name\">idle</column><column name=\"priority\">0</column></item><item><column name=\"name\">&lt;main&gt;</column><column name=\"priority\">12</column></item></osdata>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_name_type_listings() {
+        let mut s = BufferedStream::new();
+        write_osdata(&mut s, b"", &mut |_| Ok(0)).unwrap();
+        assert!(s.buffer[..s.pos()].ends_with(b"<osdata type=\"types\"></osdata>"));
+    }
+}
@@ -1,5 +1,8 @@
+use super::assembler::INTERRUPT;
+use super::basic::control::InterruptCommand;
 use super::command::*;
 use super::error::Errors;
+use super::memory::GuestAddress;
 use super::stream::Stream;
 
 // Holds the Acknowledge Packet and command packet
@@ -20,7 +23,10 @@ impl<'a> Parsed<'a> {
     }
 }
 
-#[derive(Copy, Clone)]
+/// receives the offset and data of an unescaped chunk
+pub type ChunkCallback<'a> = dyn FnMut(usize, &[u8]) -> Result<usize, Errors> + 'a;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Endianness {
     Big,
     Little,
@@ -44,11 +50,17 @@ pub enum Endianness {
 pub struct Parser<'a> {
     packet: &'a [u8],
     current: usize,
+    /// start of the packet the checksum covers
+    frame: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(packet: &'a [u8]) -> Self {
-        Self { packet, current: 0 }
+        Self {
+            packet,
+            current: 0,
+            frame: 0,
+        }
     }
 
     fn retransmit(error: Errors) -> Parsed<'a> {
@@ -60,19 +72,29 @@ impl<'a> Parser<'a> {
     // if this function causes an error
     // a retransmit packet should be sent
     pub fn parse_packet(&mut self, cmds: &'a dyn SupportedCommands<'a>) -> Parsed<'a> {
-        // there are 2 special cases where there is no checksum
+        // there are 3 special cases where there is no checksum
         if self.is_match(b'-') {
             return Parsed::new(Some(Commands::RetransmitLast), None);
         } else if self.is_match(b'+') {
             return Parsed::new(Some(Commands::AcknowledgeLast), None);
+        } else if self.is_match(INTERRUPT) {
+            // the interrupt is not acknowledged
+            return Parsed::new(None, Some(Commands::Interrupt(InterruptCommand::new())));
         }
 
         // first char needs to be $
+        self.frame = self.current;
         if !self.is_match(b'$') {
-            // bail
+            // bail, skipping the stray byte
+            self.advance();
             return Self::retransmit(Errors::UnexpectedIntroduction);
         }
 
+        // the input ended before the packet did
+        if self.is_at_end() {
+            return Self::retransmit(Errors::NotTerminated);
+        }
+
         // read packet name
         // packet names either are terminated by #, space, comma or semicolon
         let name = self.parse_name();
@@ -93,6 +115,8 @@ impl<'a> Parser<'a> {
         }
         // is checksum ok?
         if !self.verify_chksm() {
+            // skip to the next packet
+            self.current = usize::min(self.frame_end(), self.packet.len());
             return Self::retransmit(Errors::InvalidChecksum);
         }
         // get to end
@@ -101,6 +125,51 @@ impl<'a> Parser<'a> {
         cmds.commands(name, args)
     }
 
+    /// the number of bytes parse_packet consumed so far
+    /// gdb may batch several packets into one read, e.g. +$Hg0#df+$?#3f,
+    /// call try_parse_packet until it returns NeedMoreData to handle all of them
+    /// and keep the input from consumed on for the next read
+    pub fn consumed(&self) -> usize {
+        usize::min(self.current, self.packet.len())
+    }
+
+    /// the position after the checksum of the current packet
+    fn frame_end(&self) -> usize {
+        match self.packet[self.frame..].iter().position(|b| *b == b'#') {
+            Some(end) => self.frame + end + 3,
+            None => self.packet.len(),
+        }
+    }
+
+    /// parses the input of a read that may end in the middle of a packet,
+    /// tcp and uart reads routinely split $...#xx frames
+    /// returns NeedMoreData until the checksum arrived,
+    /// keep the input and retry once more bytes were read
+    pub fn try_parse_packet(
+        &mut self,
+        cmds: &'a dyn SupportedCommands<'a>,
+    ) -> Result<Parsed<'a>, Errors> {
+        if !self.is_complete() {
+            return Err(Errors::NeedMoreData);
+        }
+        Ok(self.parse_packet(cmds))
+    }
+
+    /// true if the input holds a whole packet at the current position
+    /// acks, naks, Ctrl-C and stray bytes are complete on their own
+    pub fn is_complete(&self) -> bool {
+        let rest = self.packet.get(self.current..).unwrap_or(&[]);
+        match rest.first() {
+            None => false,
+            // escaped data never contains a raw #
+            Some(b'$') => match rest.iter().position(|b| *b == b'#') {
+                Some(end) => rest.len() > end + 2,
+                None => false,
+            },
+            Some(_) => true,
+        }
+    }
+
     // parses a single token
     pub fn next_token(&mut self) -> Option<&'a [u8]> {
         if !self.is_at_end() {
@@ -114,8 +183,11 @@ impl<'a> Parser<'a> {
 
     pub fn parse_name(&mut self) -> &'a [u8] {
         match self.peek() {
-            b'v' | b'q' => self.parse_token(),
-            _ => &self.packet[self.current..self.current + 1],
+            b'v' | b'q' | b'Q' | b'j' => self.parse_token(),
+            _ => self
+                .packet
+                .get(self.current..self.current + 1)
+                .unwrap_or(&[]),
         }
     }
 
@@ -179,7 +251,7 @@ impl<'a> Parser<'a> {
         if let (Some(b0), Some(b1)) = (b0, b1) {
             // now we have a sum, calculate based on data and see!
             let sum = (b0 << 4) | b1;
-            let calc = Self::chksm(self.packet) as u8;
+            let calc = Self::chksm(&self.packet[self.frame..]) as u8;
             sum == calc
         } else {
             // in all other cases bail with bad checksum!
@@ -208,7 +280,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn is_digit(b: u8) -> bool {
-        (b'0'..=b'9').contains(&b)
+        b.is_ascii_digit()
     }
 
     pub fn is_hex(b: u8) -> bool {
@@ -220,7 +292,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn from_hex(b: u8) -> Option<u8> {
-        if (b'0'..=b'9').contains(&b) {
+        if b.is_ascii_digit() {
             Some(b - b'0')
         } else if (b'A'..=b'F').contains(&b) {
             Some(b - b'A' + 10)
@@ -239,14 +311,94 @@ impl<'a> Parser<'a> {
         let end_index = b.iter().position(|&v| v == 0).unwrap_or(b.len());
         let b = &b[0..end_index];
 
-        let mut result = 0;
-        let shift_len = (b.len() - 1) * 4;
-        for (i, byte) in b.iter().enumerate() {
-            let val = Self::from_hex(*byte)? as usize;
+        // None if empty or wider than usize
+        if b.is_empty() || b.len() > (usize::BITS / 4) as usize {
+            return None;
+        }
+        b.iter().try_fold(0, |result: usize, byte| {
+            Some(result << 4 | Self::from_hex(*byte)? as usize)
+        })
+    }
 
-            result |= val << (shift_len - 4 * i);
+    /// parses a hex address, None if empty or wider than 64 bits
+    pub fn from_hex_address(b: &[u8]) -> Option<GuestAddress> {
+        if b.is_empty() || b.len() > 16 {
+            return None;
         }
-        Some(result)
+        b.iter().try_fold(0, |address: GuestAddress, byte| {
+            Some(address << 4 | Self::from_hex(*byte)? as GuestAddress)
+        })
+    }
+
+    /// parses a decimal number, None if empty or on overflow
+    pub fn from_decimal(b: &[u8]) -> Option<usize> {
+        if b.is_empty() {
+            return None;
+        }
+        b.iter().try_fold(0usize, |result, byte| {
+            if !Self::is_digit(*byte) {
+                return None;
+            }
+            result.checked_mul(10)?.checked_add((byte - b'0') as usize)
+        })
+    }
+
+    /// decodes a string of hex pairs into out
+    /// returns the amount of bytes written
+    /// or None if the input is not valid hex or does not fit
+    pub fn from_hex_bytes(b: &[u8], out: &mut [u8]) -> Option<usize> {
+        let pairs = b.chunks_exact(2);
+        if !pairs.remainder().is_empty() || pairs.len() > out.len() {
+            return None;
+        }
+
+        for (i, pair) in pairs.enumerate() {
+            out[i] = (Self::from_hex(pair[0])? << 4) | Self::from_hex(pair[1])?;
+        }
+        Some(b.len() / 2)
+    }
+
+    /// decodes binary packet data
+    /// bytes following } are xored with 0x20
+    pub fn unescape(data: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+        let mut escaped = false;
+        data.iter().filter_map(move |byte| {
+            if escaped {
+                escaped = false;
+                Some(byte ^ 0x20)
+            } else if *byte == b'}' {
+                escaped = true;
+                None
+            } else {
+                Some(*byte)
+            }
+        })
+    }
+
+    /// unescapes binary packet data in chunks of chunk.len() bytes
+    /// so no packet sized buffer is required
+    /// f is called with the offset of each chunk and returns the bytes it consumed
+    /// empty data still calls f once
+    /// returns the total amount of bytes consumed
+    pub fn unescape_chunks(
+        data: &[u8],
+        chunk: &mut [u8],
+        f: &mut ChunkCallback,
+    ) -> Result<usize, Errors> {
+        let mut len = 0;
+        let mut total = 0;
+        for byte in Parser::unescape(data) {
+            chunk[len] = byte;
+            len += 1;
+            if len == chunk.len() {
+                total += f(total, chunk)?;
+                len = 0;
+            }
+        }
+        if len > 0 || data.is_empty() {
+            total += f(total, &chunk[..len])?;
+        }
+        Ok(total)
     }
 
     pub fn to_hex(b: u8) -> Option<u8> {
@@ -281,17 +433,66 @@ impl<'a> Parser<'a> {
         }
         Ok(())
     }
+
+    /// writes a number in decimal
+    pub fn to_decimal(n: usize, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut digits = [0; 20];
+        let mut len = 0;
+        let mut n = n;
+        loop {
+            digits[len] = b'0' + (n % 10) as u8;
+            len += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+
+        let mut size = 0;
+        for digit in digits[..len].iter().rev() {
+            size += stream.write(*digit)?;
+        }
+        Ok(size)
+    }
+
+    /// writes an address as hex without leading zeros
+    pub fn to_hex_address(addr: GuestAddress, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let digits = usize::max(
+            1,
+            (GuestAddress::BITS - addr.leading_zeros()).div_ceil(4) as usize,
+        );
+        let mut size = 0;
+        for i in (0..digits).rev() {
+            size += stream.write(Self::to_hex(((addr >> (i * 4)) & 0xF) as u8).unwrap())?;
+        }
+        Ok(size)
+    }
+
+    /// writes a number as hex without leading zeros
+    pub fn to_hex_number(n: usize, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let digits = usize::max(1, (usize::BITS - n.leading_zeros()).div_ceil(4) as usize);
+        let mut size = 0;
+        for i in (0..digits).rev() {
+            // we can unwrap here because a nibble is always valid
+            size += stream.write(Self::to_hex(((n >> (i * 4)) & 0xF) as u8).unwrap())?;
+        }
+        Ok(size)
+    }
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::byte_char_slices,
+    clippy::mixed_case_hex_literals,
+    clippy::unnecessary_cast
+)]
 mod tests {
     use super::*;
+    use crate::basic::query::*;
     use crate::basic::required::*;
+    use crate::command::testing::TestCommands;
     use crate::stream::BufferedStream;
 
-    struct TestCommands;
-    impl<'a> SupportedCommands<'a> for TestCommands {}
-
     #[test]
     fn it_should_parse_hex() {
         assert_eq!(Parser::to_hex(15), Some(b'f'));
@@ -325,6 +526,52 @@ mod tests {
         assert_eq!(&s.buffer[..8], b"eedd12af");
     }
 
+    #[test]
+    fn it_should_write_hex_numbers() {
+        let mut s = BufferedStream::new();
+        Parser::to_hex_number(0, &mut s).unwrap();
+        Parser::to_hex_number(0x1f, &mut s).unwrap();
+        Parser::to_hex_number(0x400, &mut s).unwrap();
+        assert_eq!(&s.buffer[..s.pos()], b"01f400");
+    }
+
+    #[test]
+    fn it_should_write_hex_addresses() {
+        let mut s = BufferedStream::new();
+        Parser::to_hex_address(0, &mut s).unwrap();
+        s.write(b',').unwrap();
+        Parser::to_hex_address(0x1_0000_0000, &mut s).unwrap();
+        assert_eq!(&s.buffer[..s.pos()], b"0,100000000");
+    }
+
+    #[test]
+    fn it_should_write_decimal_numbers() {
+        let mut s = BufferedStream::new();
+        Parser::to_decimal(0, &mut s).unwrap();
+        s.write(b',').unwrap();
+        Parser::to_decimal(1234567890, &mut s).unwrap();
+        assert_eq!(&s.buffer[..s.pos()], b"0,1234567890");
+    }
+
+    #[test]
+    fn it_should_read_decimal_numbers() {
+        assert_eq!(Parser::from_decimal(b"1234"), Some(1234));
+        assert_eq!(Parser::from_decimal(b""), None);
+        assert_eq!(Parser::from_decimal(b"12a"), None);
+    }
+
+    #[test]
+    fn it_should_read_addresses() {
+        assert_eq!(Parser::from_hex_address(b"20001000"), Some(0x20001000));
+        assert_eq!(
+            Parser::from_hex_address(b"ffffffff80001000"),
+            Some(0xffffffff80001000)
+        );
+        assert_eq!(Parser::from_hex_address(b"1ffffffff80001000"), None);
+        assert_eq!(Parser::from_hex_address(b""), None);
+        assert_eq!(Parser::from_hex_address(b"12g"), None);
+    }
+
     #[test]
     fn it_should_read_hex8() {
         assert_eq!(Parser::from_hexu(&[b'A', b'B']).unwrap(), 0xAB);
@@ -351,6 +598,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_should_reject_empty_or_wide_hex() {
+        assert_eq!(Parser::from_hexu(b""), None);
+        assert_eq!(Parser::from_hexu(&[0, 0]), None);
+        assert_eq!(Parser::from_hexu(b"11111111111111111"), None);
+        assert_eq!(Parser::from_hexu(b"ffffffffffffffff"), Some(usize::MAX));
+    }
+
     #[test]
     fn it_should_read_hex32_with_padding() {
         assert_eq!(
@@ -359,6 +614,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_should_read_hex_bytes() {
+        let mut out = [0; 4];
+        assert_eq!(Parser::from_hex_bytes(b"6d61696e", &mut out), Some(4));
+        assert_eq!(&out, b"main");
+        assert_eq!(Parser::from_hex_bytes(b"6d616", &mut out), None);
+        assert_eq!(Parser::from_hex_bytes(b"6d61696e00", &mut out), None);
+    }
+
     #[test]
     fn it_should_read_hex_be() {
         let mut s = BufferedStream::new();
@@ -409,6 +673,87 @@ mod tests {
         assert!(parser.is_at_end());
     }
 
+    #[test]
+    fn it_should_parse_batched_packets() {
+        let mut parser = Parser::new(b"+$Hg0#df+x$m0#00$?#3f");
+        let mut consumed = [0; 6];
+        let mut parsed = [const { None }; 6];
+        for i in 0..6 {
+            parsed[i] = Some(parser.parse_packet(&TestCommands));
+            consumed[i] = parser.consumed();
+        }
+        assert!(parser.is_at_end());
+        assert_eq!(consumed, [1, 8, 9, 10, 16, 21]);
+
+        let ack = Some(Commands::Acknowledge(Acknowledge::new()));
+        let responses = parsed.map(|parsed| parsed.unwrap().response);
+        assert_eq!(responses[0], Some(Commands::AcknowledgeLast));
+        assert_eq!(responses[1], ack);
+        assert_eq!(
+            responses[3],
+            Some(Commands::Retransmit(Retransmit::new(
+                Errors::UnexpectedIntroduction
+            )))
+        );
+        assert_eq!(
+            responses[4],
+            Some(Commands::Retransmit(Retransmit::new(
+                Errors::InvalidChecksum
+            )))
+        );
+        assert_eq!(responses[5], ack);
+    }
+
+    #[test]
+    fn it_should_stop_batches_at_partial_packets() {
+        for input in [&b"+$Hg0#df+$"[..], b"+$Hg0#df+$?", b"+$Hg0#df+$?#3"] {
+            let mut parser = Parser::new(input);
+            let mut parsed = 0;
+            while parser.try_parse_packet(&TestCommands).is_ok() {
+                parsed += 1;
+            }
+            assert_eq!(parsed, 3);
+            assert_eq!(parser.consumed(), 9);
+        }
+
+        let mut parser = Parser::new(b"$");
+        assert_eq!(
+            parser.parse_packet(&TestCommands),
+            Parser::retransmit(Errors::NotTerminated)
+        );
+        assert!(parser.is_at_end());
+    }
+
+    #[test]
+    fn it_should_wait_for_partial_packets() {
+        let packet = b"$vMustReplyEmpty#3a";
+        for len in 0..packet.len() {
+            let mut parser = Parser::new(&packet[..len]);
+            let parsed = parser.try_parse_packet(&TestCommands);
+            assert_eq!(parsed, Err(Errors::NeedMoreData));
+        }
+
+        let mut parser = Parser::new(packet);
+        let parsed = parser.try_parse_packet(&TestCommands);
+        assert_eq!(
+            parsed,
+            Ok(Parsed::new(
+                Some(Commands::Acknowledge(Acknowledge::new())),
+                Some(Commands::NotImplemented(NotImplemented::new()))
+            ))
+        );
+
+        let mut parser = Parser::new(b"+$g");
+        assert_eq!(
+            parser.try_parse_packet(&TestCommands),
+            Ok(Parsed::new(Some(Commands::AcknowledgeLast), None))
+        );
+        assert_eq!(
+            parser.try_parse_packet(&TestCommands),
+            Err(Errors::NeedMoreData)
+        );
+    }
+
     #[test]
     fn it_should_read_name() {
         let chksm = "$g#67".as_bytes();
@@ -456,7 +801,7 @@ mod tests {
             parsed,
             Parsed::new(
                 Some(Commands::Acknowledge(Acknowledge::new())),
-                Some(Commands::NotImplemented(NotImplemented::new()))
+                Some(Commands::Supported(SupportedCommand::new(b"multiprocess+;swbreak+;hwbreak+;qRelocInsn+;fork-events+;vfork-events+;exec-events+;vContSupported+;QThreadEvents+;no-resumed+;xmlRegisters=i386")))
             )
         );
     }
@@ -0,0 +1,147 @@
+use super::error::Errors;
+use super::parser::{Endianness, Parser};
+use super::stream::Stream;
+
+/// largest register value supported
+/// as an integer
+pub const MAX_REGISTER_SIZE: usize = 16;
+
+/// The value of a single register
+/// Integers are serialized in the target's byte order
+/// while opaque bytes are always sent as is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegisterValue<'a> {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> RegisterValue<'a> {
+    /// size of the register in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::U8(_) => 1,
+            Self::U16(_) => 2,
+            Self::U32(_) => 4,
+            Self::U64(_) => 8,
+            Self::U128(_) => 16,
+            Self::Bytes(b) => b.len(),
+        }
+    }
+
+    /// returns the integer value
+    /// or None for opaque bytes
+    pub fn value(&self) -> Option<u128> {
+        match *self {
+            Self::U8(v) => Some(v as u128),
+            Self::U16(v) => Some(v as u128),
+            Self::U32(v) => Some(v as u128),
+            Self::U64(v) => Some(v as u128),
+            Self::U128(v) => Some(v),
+            Self::Bytes(_) => None,
+        }
+    }
+
+    /// writes the hex encoded value in the requested byte order
+    /// returns the amount of bytes written
+    pub fn write(&self, endianness: Endianness, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let size = self.size();
+        match (*self, endianness) {
+            (Self::Bytes(bytes), _) => Parser::to_hexu(bytes, stream)?,
+            (_, Endianness::Big) => {
+                let bytes = self.value().unwrap_or(0).to_be_bytes();
+                Parser::to_hexu(&bytes[MAX_REGISTER_SIZE - size..], stream)?
+            }
+            (_, Endianness::Little) => {
+                let bytes = self.value().unwrap_or(0).to_le_bytes();
+                Parser::to_hexu(&bytes[..size], stream)?
+            }
+        }
+        Ok(size * 2)
+    }
+
+    /// parses a hex encoded integer register in the requested byte order
+    /// the register size is derived from the input length
+    /// returns None if the size does not match an integer register
+    pub fn from_hex(data: &[u8], endianness: Endianness) -> Option<Self> {
+        let mut bytes = [0; MAX_REGISTER_SIZE];
+        let size = Parser::from_hex_bytes(data, &mut bytes)?;
+        Self::from_bytes(&bytes[..size], endianness)
+    }
+
+    /// converts raw bytes in the requested byte order to an integer register
+    /// returns None if the size does not match an integer register
+    pub fn from_bytes(bytes: &[u8], endianness: Endianness) -> Option<Self> {
+        let fold = |value: u128, byte: &u8| (value << 8) | *byte as u128;
+        let value = match endianness {
+            Endianness::Big => bytes.iter().fold(0, fold),
+            Endianness::Little => bytes.iter().rev().fold(0, fold),
+        };
+
+        match bytes.len() {
+            1 => Some(Self::U8(value as u8)),
+            2 => Some(Self::U16(value as u16)),
+            4 => Some(Self::U32(value as u32)),
+            8 => Some(Self::U64(value as u64)),
+            16 => Some(Self::U128(value)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_big_endian() {
+        let mut s = BufferedStream::new();
+        let size = RegisterValue::U32(0xBFC00000)
+            .write(Endianness::Big, &mut s)
+            .unwrap();
+        assert_eq!(size, 8);
+        assert_eq!(&s.buffer[..8], b"bfc00000");
+    }
+
+    #[test]
+    fn it_should_write_little_endian() {
+        let mut s = BufferedStream::new();
+        RegisterValue::U16(0x1234)
+            .write(Endianness::Little, &mut s)
+            .unwrap();
+        RegisterValue::U128(1)
+            .write(Endianness::Little, &mut s)
+            .unwrap();
+        assert_eq!(&s.buffer[..36], b"341201000000000000000000000000000000");
+    }
+
+    #[test]
+    fn it_should_write_bytes() {
+        let mut s = BufferedStream::new();
+        RegisterValue::Bytes(&[1, 2, 3])
+            .write(Endianness::Little, &mut s)
+            .unwrap();
+        assert_eq!(&s.buffer[..6], b"010203");
+    }
+
+    #[test]
+    fn it_should_parse_registers() {
+        assert_eq!(
+            RegisterValue::from_hex(b"bfc00000", Endianness::Big),
+            Some(RegisterValue::U32(0xBFC00000))
+        );
+        assert_eq!(
+            RegisterValue::from_hex(b"0000c0bf", Endianness::Little),
+            Some(RegisterValue::U32(0xBFC00000))
+        );
+        assert_eq!(
+            RegisterValue::from_hex(b"ff", Endianness::Little),
+            Some(RegisterValue::U8(0xFF))
+        );
+        assert_eq!(RegisterValue::from_hex(b"ffffff", Endianness::Big), None);
+    }
+}
@@ -0,0 +1,36 @@
+use super::error::Errors;
+
+/// where a reverse execution request stopped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplayStop {
+    /// stopped for the target's regular reason
+    /// e.g. a breakpoint or the end of the step
+    Stopped,
+    /// ran out of recorded history going backwards
+    Begin,
+    /// reached the live end of the recording
+    End,
+}
+
+impl ReplayStop {
+    /// returns the stop reply for a history boundary
+    /// regular stops are reported with the target's reason instead
+    pub fn reply(&self) -> Option<&'static [u8]> {
+        match self {
+            Self::Stopped => None,
+            Self::Begin => Some(b"T05replaylog:begin;"),
+            Self::End => Some(b"T05replaylog:end;"),
+        }
+    }
+}
+
+/// Reverse execution for record/replay capable targets, e.g. emulators
+/// keeping an instruction trace.
+/// Supporting it advertises ReverseStep+ and ReverseContinue+.
+pub trait Replay {
+    /// runs backwards until a breakpoint or the start of the recording
+    fn reverse_continue(&mut self) -> Result<ReplayStop, Errors>;
+
+    /// undoes exactly one instruction
+    fn reverse_step(&mut self) -> Result<ReplayStop, Errors>;
+}
@@ -0,0 +1,382 @@
+use super::assembler::INTERRUPT;
+use super::error::Errors;
+use super::parser::Parser;
+use super::stop::StopReason;
+use super::stream::{GdbConsole, Stream};
+use super::target::Target;
+use super::thread::{ThreadId, ALL_THREADS};
+
+/// how a thread should run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResumeKind {
+    /// c, run until something stops the target
+    Continue,
+    /// s, execute a single instruction
+    Step,
+    /// r, step while the pc stays within start..end
+    RangeStep { start: usize, end: usize },
+    /// t, stop the thread, only used in non-stop mode
+    Stop,
+}
+
+/// A single action of c, C, s, S or vCont.
+/// Without thread it applies to every thread
+/// not named by an earlier action.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResumeAction {
+    pub kind: ResumeKind,
+    /// signal delivered to the thread when it resumes
+    pub signal: Option<u8>,
+    pub thread: Option<ThreadId>,
+}
+
+impl ResumeAction {
+    pub const fn new(kind: ResumeKind) -> Self {
+        Self {
+            kind,
+            signal: None,
+            thread: None,
+        }
+    }
+
+    pub const fn with_signal(mut self, signal: u8) -> Self {
+        self.signal = Some(signal);
+        self
+    }
+
+    pub const fn with_thread(mut self, thread: ThreadId) -> Self {
+        self.thread = Some(thread);
+        self
+    }
+
+    /// parses a vCont action, e.g. c, C05, s:2 or r1000,1010:p1.2
+    pub fn parse(action: &[u8]) -> Option<Self> {
+        let mut parts = action.splitn(2, |b| *b == b':');
+        let action = parts.next()?;
+        let thread = match parts.next() {
+            Some(thread) => Some(ThreadId::parse(thread)?),
+            None => None,
+        };
+        let (&kind, args) = action.split_first()?;
+
+        let mut result = match (kind, args) {
+            (b'c', b"") => Self::new(ResumeKind::Continue),
+            (b's', b"") => Self::new(ResumeKind::Step),
+            (b't', b"") => Self::new(ResumeKind::Stop),
+            (b'C', sig) => Self::new(ResumeKind::Continue).with_signal(Self::parse_signal(sig)?),
+            (b'S', sig) => Self::new(ResumeKind::Step).with_signal(Self::parse_signal(sig)?),
+            (b'r', range) => {
+                let mut parts = range.splitn(2, |b| *b == b',');
+                let start = Self::parse_number(parts.next()?)?;
+                let end = Self::parse_number(parts.next()?)?;
+                Self::new(ResumeKind::RangeStep { start, end })
+            }
+            _ => return None,
+        };
+        result.thread = thread;
+        Some(result)
+    }
+
+    fn parse_number(number: &[u8]) -> Option<usize> {
+        match number {
+            b"" => None,
+            _ => Parser::from_hexu(number),
+        }
+    }
+
+    fn parse_signal(signal: &[u8]) -> Option<u8> {
+        Self::parse_number(signal).and_then(|signal| u8::try_from(signal).ok())
+    }
+
+    /// true if the action applies to thread
+    pub fn applies_to(&self, thread: ThreadId) -> bool {
+        let selected = match self.thread {
+            Some(selected) => selected,
+            None => return true,
+        };
+        let pid = match (selected.pid, thread.pid) {
+            (Some(selected), Some(pid)) => selected == ALL_THREADS || selected == pid,
+            _ => true,
+        };
+        pid && (selected.tid == ALL_THREADS || selected.tid == thread.tid)
+    }
+}
+
+/// The actions of a resume request.
+/// vCont actions are parsed lazily, the packet is validated
+/// before it reaches the target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResumeActions<'a> {
+    /// c and s may continue at a new pc
+    pub addr: Option<usize>,
+    action: Option<ResumeAction>,
+    vcont: &'a [u8],
+}
+
+impl<'a> ResumeActions<'a> {
+    /// c, C, s or S, the action applies to all threads
+    pub const fn single(action: ResumeAction, addr: Option<usize>) -> Self {
+        Self {
+            addr,
+            action: Some(action),
+            vcont: &[],
+        }
+    }
+
+    /// parses the fields of vCont;action[:thread];...
+    /// the separator after vCont is already stripped by the parser
+    pub fn parse_vcont(vcont: &'a [u8]) -> Option<Self> {
+        if vcont.is_empty() {
+            return None;
+        }
+        for action in vcont.split(|b| *b == b';') {
+            ResumeAction::parse(action)?;
+        }
+        Some(Self {
+            addr: None,
+            action: None,
+            vcont,
+        })
+    }
+
+    /// all actions in packet order
+    pub fn iter(&self) -> impl Iterator<Item = ResumeAction> + 'a {
+        self.action.into_iter().chain(
+            self.vcont
+                .split(|b| *b == b';')
+                .filter(|action| !action.is_empty())
+                .filter_map(ResumeAction::parse),
+        )
+    }
+
+    /// the action for thread, the leftmost matching action wins
+    /// threads without action keep their state
+    pub fn action_for(&self, thread: ThreadId) -> Option<ResumeAction> {
+        self.iter().find(|action| action.applies_to(thread))
+    }
+}
+
+/// Execution control for c, C, s, S and vCont.
+/// The vCont? reply is derived from the supported actions.
+/// Targets sharing the cpu with other firmware implement start and poll
+/// so the stub does not block while the target runs.
+pub trait Execution {
+    /// runs the threads as requested
+    /// returns once the target halted again, the stub writes the stop reply
+    /// cores without hardware single-step can use step::software_step
+    fn resume(&mut self, actions: &ResumeActions) -> Result<StopReason, Errors>;
+
+    /// starts the threads and returns right away
+    /// None means the target is running, the stub then calls poll
+    /// the default runs resume to completion
+    fn start(&mut self, actions: &ResumeActions) -> Result<Option<StopReason>, Errors> {
+        self.resume(actions).map(Some)
+    }
+
+    /// called periodically while the target runs
+    /// feed watchdogs and service the rest of the firmware here
+    /// text written to console is shown by gdb right away,
+    /// in non-stop mode gdb takes no O packets and it is dropped
+    /// returns the stop reason once the target halted
+    fn poll(&mut self, _console: &mut GdbConsole) -> Result<Option<StopReason>, Errors> {
+        Ok(None)
+    }
+
+    /// true if s and S actions are handled
+    fn single_step(&self) -> bool {
+        true
+    }
+
+    /// true if r actions are handled, gdb steps ranges itself otherwise
+    fn range_step(&self) -> bool {
+        false
+    }
+}
+
+/// reads a byte gdb sent while the target runs, None if nothing arrived
+/// fails once the connection is lost
+pub type InputPoll<'a> = dyn FnMut() -> Result<Option<u8>, Errors> + 'a;
+
+/// polls a running target once
+/// its console output is written to output as O packets and flushed
+pub fn poll(ctx: &mut dyn Target, output: &mut dyn Stream) -> Result<Option<StopReason>, Errors> {
+    let execution = ctx.execution().ok_or(Errors::Unsupported)?;
+    let result = execution.poll(&mut GdbConsole::new(output));
+    output.flush();
+    result
+}
+
+/// resumes the target and polls it until it halts
+/// a Ctrl-C read from input halts the target with Target::interrupt
+/// console output of the target is written to output while it runs
+/// the target keeps being polled until it reports the stop
+/// or input fails, the target is left running then
+pub fn run_until_halt(
+    ctx: &mut dyn Target,
+    actions: &ResumeActions,
+    input: &mut InputPoll,
+    output: &mut dyn Stream,
+) -> Result<StopReason, Errors> {
+    let execution = ctx.execution().ok_or(Errors::Unsupported)?;
+    if let Some(reason) = execution.start(actions)? {
+        return Ok(reason);
+    }
+    loop {
+        if let Some(reason) = poll(ctx, output)? {
+            return Ok(reason);
+        }
+        if input()? == Some(INTERRUPT) {
+            ctx.interrupt()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::SIGINT;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_parse_actions() {
+        assert_eq!(
+            ResumeAction::parse(b"c"),
+            Some(ResumeAction::new(ResumeKind::Continue))
+        );
+        assert_eq!(
+            ResumeAction::parse(b"S0b:2"),
+            Some(
+                ResumeAction::new(ResumeKind::Step)
+                    .with_signal(0xb)
+                    .with_thread(ThreadId::new(2))
+            )
+        );
+        assert_eq!(
+            ResumeAction::parse(b"r1000,1010:p1.-1"),
+            Some(
+                ResumeAction::new(ResumeKind::RangeStep {
+                    start: 0x1000,
+                    end: 0x1010
+                })
+                .with_thread(ThreadId::new(ALL_THREADS).with_pid(1))
+            )
+        );
+        assert_eq!(ResumeAction::parse(b"C"), None);
+        assert_eq!(ResumeAction::parse(b"C100"), None);
+        assert_eq!(ResumeAction::parse(b"cx"), None);
+        assert_eq!(ResumeAction::parse(b"r1000"), None);
+    }
+
+    #[test]
+    fn it_should_pick_the_leftmost_action() {
+        let actions = ResumeActions::parse_vcont(b"s:2;c").unwrap();
+        assert_eq!(actions.iter().count(), 2);
+        assert_eq!(
+            actions.action_for(ThreadId::new(2)).map(|a| a.kind),
+            Some(ResumeKind::Step)
+        );
+        assert_eq!(
+            actions.action_for(ThreadId::new(3)).map(|a| a.kind),
+            Some(ResumeKind::Continue)
+        );
+
+        let actions = ResumeActions::parse_vcont(b"s:2").unwrap();
+        assert_eq!(actions.action_for(ThreadId::new(3)), None);
+
+        assert_eq!(ResumeActions::parse_vcont(b""), None);
+        assert_eq!(ResumeActions::parse_vcont(b";"), None);
+        assert_eq!(ResumeActions::parse_vcont(b"c;x"), None);
+    }
+
+    /// runs until polled five times or interrupted
+    #[derive(Default)]
+    struct PollTarget {
+        polls: usize,
+        interrupted: bool,
+    }
+
+    impl Target for PollTarget {
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+
+        fn interrupt(&mut self) -> Result<usize, Errors> {
+            self.interrupted = true;
+            Ok(0)
+        }
+    }
+
+    impl Execution for PollTarget {
+        fn resume(&mut self, _actions: &ResumeActions) -> Result<StopReason, Errors> {
+            Err(Errors::Unsupported)
+        }
+
+        fn start(&mut self, actions: &ResumeActions) -> Result<Option<StopReason>, Errors> {
+            match actions.iter().next().map(|a| a.kind) {
+                Some(ResumeKind::Step) => Ok(Some(StopReason::Signal(5))),
+                _ => Ok(None),
+            }
+        }
+
+        fn poll(&mut self, console: &mut GdbConsole) -> Result<Option<StopReason>, Errors> {
+            self.polls += 1;
+            console.write_str("poll")?;
+            match (self.interrupted, self.polls) {
+                (true, _) => Ok(Some(StopReason::Signal(SIGINT))),
+                (_, 5) => Ok(Some(StopReason::SwBreak)),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_poll_until_halted() {
+        let mut target = PollTarget::default();
+        let step = ResumeActions::single(ResumeAction::new(ResumeKind::Step), None);
+        let mut console = BufferedStream::new();
+        let reason = run_until_halt(&mut target, &step, &mut || Ok(None), &mut console).unwrap();
+        assert_eq!(reason, StopReason::Signal(5));
+        assert_eq!(target.polls, 0);
+
+        let resume = ResumeActions::single(ResumeAction::new(ResumeKind::Continue), None);
+        let reason = run_until_halt(&mut target, &resume, &mut || Ok(None), &mut console).unwrap();
+        assert_eq!(reason, StopReason::SwBreak);
+        assert_eq!(target.polls, 5);
+    }
+
+    #[test]
+    fn it_should_print_while_polling() {
+        let mut target = PollTarget::default();
+        let mut console = BufferedStream::new();
+        let resume = ResumeActions::single(ResumeAction::new(ResumeKind::Continue), None);
+        run_until_halt(&mut target, &resume, &mut || Ok(None), &mut console).unwrap();
+        assert_eq!(
+            console.buffer[..console.pos()],
+            b"$O706f6c6c#84".repeat(5)[..]
+        );
+    }
+
+    #[test]
+    fn it_should_interrupt_while_polling() {
+        let mut target = PollTarget::default();
+        let mut input = [b'+', INTERRUPT].into_iter();
+        let resume = ResumeActions::single(ResumeAction::new(ResumeKind::Continue), None);
+        let mut console = BufferedStream::new();
+        let reason =
+            run_until_halt(&mut target, &resume, &mut || Ok(input.next()), &mut console).unwrap();
+        assert_eq!(reason, StopReason::Signal(SIGINT));
+        assert!(target.interrupted);
+        assert_eq!(target.polls, 3);
+    }
+
+    #[test]
+    fn it_should_stop_polling_once_input_fails() {
+        let mut target = PollTarget::default();
+        let resume = ResumeActions::single(ResumeAction::new(ResumeKind::Continue), None);
+        let mut console = BufferedStream::new();
+        let input = &mut || Err(Errors::Disconnected);
+        let result = run_until_halt(&mut target, &resume, input, &mut console);
+        assert_eq!(result, Err(Errors::Disconnected));
+        assert_eq!(target.polls, 1);
+    }
+}
@@ -0,0 +1,252 @@
+use super::error::Errors;
+use super::stream::Connection;
+
+/// telnet interpret as command
+pub const IAC: u8 = 0xFF;
+pub const SE: u8 = 0xF0;
+pub const SB: u8 = 0xFA;
+pub const WILL: u8 = 0xFB;
+pub const DONT: u8 = 0xFE;
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FilterState {
+    Data,
+    Iac,
+    Option,
+    Sub,
+    SubIac,
+}
+
+/// Removes telnet command sequences and optionally xon/xoff bytes
+/// from the input before it is parsed.
+/// This is needed when the stub is reached through
+/// telnet to serial bridges like ser2net.
+/// The filter keeps its state between calls so sequences
+/// that are split across reads are handled correctly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelnetFilter {
+    state: FilterState,
+    strip_flow_control: bool,
+}
+
+impl Default for TelnetFilter {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl TelnetFilter {
+    pub fn new(strip_flow_control: bool) -> Self {
+        Self {
+            state: FilterState::Data,
+            strip_flow_control,
+        }
+    }
+
+    /// filters a single byte
+    /// returns the byte if it is packet data
+    pub fn filter_byte(&mut self, byte: u8) -> Option<u8> {
+        let (state, out) = match (self.state, byte) {
+            (FilterState::Data, IAC) => (FilterState::Iac, None),
+            (FilterState::Data, XON | XOFF) if self.strip_flow_control => (FilterState::Data, None),
+            (FilterState::Data, _) => (FilterState::Data, Some(byte)),
+            // escaped 0xFF data byte
+            (FilterState::Iac, IAC) => (FilterState::Data, Some(IAC)),
+            (FilterState::Iac, SB) => (FilterState::Sub, None),
+            (FilterState::Iac, WILL..=DONT) => (FilterState::Option, None),
+            // all other commands are 2 bytes long
+            (FilterState::Iac, _) => (FilterState::Data, None),
+            (FilterState::Option, _) => (FilterState::Data, None),
+            (FilterState::Sub, IAC) => (FilterState::SubIac, None),
+            (FilterState::Sub, _) => (FilterState::Sub, None),
+            (FilterState::SubIac, SE) => (FilterState::Data, None),
+            (FilterState::SubIac, _) => (FilterState::Sub, None),
+        };
+        self.state = state;
+        out
+    }
+
+    /// filters a buffer in place
+    /// returns the length of the remaining data
+    pub fn filter(&mut self, buffer: &mut [u8]) -> usize {
+        let mut len = 0;
+        for i in 0..buffer.len() {
+            if let Some(byte) = self.filter_byte(buffer[i]) {
+                buffer[len] = byte;
+                len += 1;
+            }
+        }
+        len
+    }
+}
+
+/// Connection that passes its input through a TelnetFilter
+/// and escapes 0xFF in its output as telnet requires.
+/// Wrap the connection of a GdbStub with it when the stub
+/// is reached through a telnet to serial bridge.
+pub struct TelnetConnection<C: Connection> {
+    inner: C,
+    filter: TelnetFilter,
+    /// the next data byte, read ahead by peek
+    peeked: Option<u8>,
+}
+
+impl<C: Connection> TelnetConnection<C> {
+    pub fn new(inner: C, filter: TelnetFilter) -> Self {
+        Self {
+            inner,
+            filter,
+            peeked: None,
+        }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// reads until a data byte arrives or no input is left
+    fn next_byte(&mut self) -> Result<Option<u8>, Errors> {
+        while let Some(byte) = self.inner.read()? {
+            if let Some(byte) = self.filter.filter_byte(byte) {
+                return Ok(Some(byte));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<C: Connection> Connection for TelnetConnection<C> {
+    fn read(&mut self) -> Result<Option<u8>, Errors> {
+        match self.peeked.take() {
+            Some(byte) => Ok(Some(byte)),
+            None => self.next_byte(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Errors> {
+        if self.peeked.is_none() {
+            self.peeked = self.next_byte()?;
+        }
+        Ok(self.peeked)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+        for chunk in bytes.split_inclusive(|byte| *byte == IAC) {
+            self.inner.write_all(chunk)?;
+            if chunk.last() == Some(&IAC) {
+                self.inner.write_all(&[IAC])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Errors> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn it_should_strip_ser2net_negotiation() {
+        // written by hand, see fixtures/README.md
+        let mut buffer = *include_bytes!("../fixtures/ser2net-telnet.bin");
+        let mut filter = TelnetFilter::default();
+        let len = filter.filter(&mut buffer);
+        assert_eq!(buffer[..len], b"+$g#67"[..]);
+
+        // the same input split at every byte
+        let mut filter = TelnetFilter::default();
+        let mut output = Vec::new();
+        for byte in include_bytes!("../fixtures/ser2net-telnet.bin") {
+            let mut chunk = [*byte];
+            let len = filter.filter(&mut chunk);
+            output.extend_from_slice(&chunk[..len]);
+        }
+        assert_eq!(output, b"+$g#67");
+    }
+
+    #[test]
+    fn it_should_handle_split_sequences() {
+        let mut filter = TelnetFilter::default();
+        let mut first = [b'$', b'g', 0xff, 0xfa];
+        let mut second = [0x2c, 0xff, 0xf0, b'#', b'6', b'7'];
+
+        let len = filter.filter(&mut first);
+        assert_eq!(first[..len], b"$g"[..]);
+        let len = filter.filter(&mut second);
+        assert_eq!(second[..len], b"#67"[..]);
+    }
+
+    #[test]
+    fn it_should_unescape_data() {
+        let mut buffer = [b'$', b'X', 0xff, 0xff, 0xf1, b'#'];
+        let len = TelnetFilter::default().filter(&mut buffer);
+        assert_eq!(buffer[..len], [b'$', b'X', 0xff, 0xf1, b'#']);
+    }
+
+    #[test]
+    fn it_should_strip_flow_control() {
+        let mut buffer = [XOFF, b'$', b'g', XON, b'#', b'6', b'7'];
+        let len = TelnetFilter::new(true).filter(&mut buffer);
+        assert_eq!(buffer[..len], b"$g#67"[..]);
+
+        let mut buffer = [XOFF, b'$'];
+        let len = TelnetFilter::new(false).filter(&mut buffer);
+        assert_eq!(len, 2);
+    }
+
+    /// replays input and records the output
+    struct Replay<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    impl Connection for Replay<'_> {
+        fn read(&mut self) -> Result<Option<u8>, Errors> {
+            let byte = self.peek()?;
+            if byte.is_some() {
+                self.input = &self.input[1..];
+            }
+            Ok(byte)
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, Errors> {
+            Ok(self.input.first().copied())
+        }
+
+        fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+            self.output.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_filter_a_connection() {
+        let input = [
+            0xff, 0xfb, 0x01, b'$', 0xff, 0xfb, 0x03, b'g', b'#', b'6', b'7',
+        ];
+        let replay = Replay {
+            input: &input,
+            output: Vec::new(),
+        };
+        let mut connection = TelnetConnection::new(replay, TelnetFilter::default());
+        assert_eq!(connection.peek(), Ok(Some(b'$')));
+        let mut buffer = [0; 8];
+        assert_eq!(connection.read_slice(&mut buffer), Ok(5));
+        assert_eq!(buffer[..5], b"$g#67"[..]);
+
+        // 0xff in binary replies is escaped
+        connection.write_all(&[b'$', 0xff, b'#']).unwrap();
+        assert_eq!(connection.into_inner().output, [b'$', 0xff, 0xff, b'#']);
+    }
+}
@@ -0,0 +1,391 @@
+#[cfg(feature = "watchdog")]
+use super::breakpoint::BreakpointList;
+use super::command::{Commands, ResponseWriter};
+#[cfg(feature = "compression")]
+use super::compress::Compression;
+use super::error::Errors;
+use super::parser::Parsed;
+#[cfg(feature = "compression")]
+use super::parser::Parser;
+use super::stop::{StopQueue, StopReason};
+use super::stream::Stream;
+use super::target::Target;
+
+/// Protocol state that outlives a single packet.
+/// Every parsed packet has to be passed through apply
+/// before its responses are sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    no_ack: bool,
+    extended: bool,
+    non_stop: bool,
+    notified: bool,
+    stops: StopQueue,
+    packet_size: Option<usize>,
+    #[cfg(feature = "watchdog")]
+    breakpoints: BreakpointList,
+    #[cfg(feature = "compression")]
+    compression: Option<(Compression, usize)>,
+    #[cfg(feature = "compression")]
+    pending_compression: Option<(Compression, usize)>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// advertises PacketSize in the reply to qSupported
+    /// gdb neither sends packets nor requests replies larger than size
+    pub fn with_packet_size(mut self, size: usize) -> Self {
+        self.packet_size = Some(size);
+        self
+    }
+
+    pub fn packet_size(&self) -> Option<usize> {
+        self.packet_size
+    }
+
+    /// true once QStartNoAckMode was accepted
+    /// neither side sends + or - anymore
+    pub fn no_ack(&self) -> bool {
+        self.no_ack
+    }
+
+    /// true once ! was accepted
+    /// gdb may then restart the program and stays connected when it exits
+    pub fn extended(&self) -> bool {
+        self.extended
+    }
+
+    /// true once the target accepted QNonStop:1
+    /// resumes are answered with OK, stops are reported with %Stop
+    pub fn non_stop(&self) -> bool {
+        self.non_stop
+    }
+
+    /// the breakpoints gdb inserted with Z and did not remove yet
+    #[cfg(feature = "watchdog")]
+    pub fn breakpoints_mut(&mut self) -> &mut BreakpointList {
+        &mut self.breakpoints
+    }
+
+    /// stops the stub received from the target in non-stop mode
+    /// they are reported before the stops queued by the target itself
+    pub fn stop_queue(&mut self) -> &mut StopQueue {
+        &mut self.stops
+    }
+
+    /// removes the oldest stop that was not reported yet
+    pub fn next_stop(&mut self, ctx: &mut dyn Target) -> Option<StopReason> {
+        self.stops.pop().or_else(|| ctx.next_stop())
+    }
+
+    /// true while a %Stop notification waits for vStopped
+    pub fn notified(&self) -> bool {
+        self.notified
+    }
+
+    /// the negotiated compression and the smallest reply it applies to
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<(Compression, usize)> {
+        self.compression
+    }
+
+    /// rewrites a single reply packet for the negotiated compression
+    /// as $C<decimal length>:<compressed>#cs or $N<payload>#cs.
+    /// scratch receives the compressed payload, replies that do not fit
+    /// or do not get smaller are sent as N packets.
+    /// Without compression or for anything but one packet
+    /// the input is copied unchanged.
+    #[cfg(feature = "compression")]
+    pub fn compress(
+        &self,
+        packet: &[u8],
+        scratch: &mut [u8],
+        stream: &mut dyn Stream,
+    ) -> Result<usize, Errors> {
+        let single = packet.len() >= 4
+            && packet[0] == b'$'
+            && packet.iter().position(|b| *b == b'#') == Some(packet.len() - 3);
+        let (compression, min_size) = match self.compression {
+            Some(mode) if single => mode,
+            _ => return stream.write_all(packet),
+        };
+        let payload = &packet[1..packet.len() - 3];
+
+        let compressed = if payload.len() >= min_size {
+            compression
+                .compress(payload, scratch)
+                .filter(|size| *size < payload.len())
+        } else {
+            None
+        };
+
+        let mut state = ResponseWriter::new(&[]);
+        let mut size = state.start(stream)?;
+        match compressed {
+            Some(compressed) => {
+                size += state.write_force(stream, b'C')?;
+                size += Parser::to_decimal(payload.len(), stream)?;
+                size += state.write_force(stream, b':')?;
+                size += state.write_all(stream, &scratch[..compressed])?;
+            }
+            None => {
+                size += state.write_force(stream, b'N')?;
+                // the payload is already escaped
+                size += stream.write_all(payload)?;
+            }
+        }
+        size += state.end(stream)?;
+        Ok(size)
+    }
+
+    /// sends the oldest queued stop of the target as %Stop notification
+    /// gdb acknowledges it with vStopped and fetches the remaining stops
+    /// with further vStopped packets, so nothing is sent until then.
+    /// Notifications are not acknowledged with + and not retransmitted.
+    pub fn notify_stop(
+        &mut self,
+        stream: &mut dyn Stream,
+        ctx: &mut dyn Target,
+    ) -> Result<usize, Errors> {
+        if self.notified {
+            return Ok(0);
+        }
+        let reason = match self.next_stop(ctx) {
+            Some(reason) => reason,
+            None => return Ok(0),
+        };
+
+        let mut state = ResponseWriter::new(&[]);
+        let mut size = state.start_notification(stream)?;
+        size += state.write_all(stream, b"Stop:")?;
+        size += reason.write(ctx, stream)?;
+        size += state.end(stream)?;
+        self.notified = true;
+        Ok(size)
+    }
+
+    /// updates the session with a command once it was answered
+    /// modes only change if the target accepted them.
+    /// The notification sequence only ends once vStopped found no stop left,
+    /// a stop reply means more vStopped packets follow
+    pub fn answered(&mut self, command: &Commands) {
+        match command {
+            Commands::NonStop(c) => self.non_stop = c.enabled().unwrap_or(self.non_stop),
+            Commands::Stopped(c) if c.drained() => self.notified = false,
+            // breakpoints that do not fit the list stay in place on a timeout
+            #[cfg(feature = "watchdog")]
+            Commands::Breakpoint(c) => match (c.applied(), c.inserts()) {
+                (Some(breakpoint), true) => {
+                    let _ = self.breakpoints.add(breakpoint);
+                }
+                (Some(breakpoint), false) => self.breakpoints.forget(breakpoint),
+                (None, _) => (),
+            },
+            // gdb removed its breakpoints before it detached
+            #[cfg(feature = "watchdog")]
+            Commands::Detach(c) if c.detached() => self.breakpoints.clear(),
+            _ => (),
+        }
+    }
+
+    /// updates the session with a parsed packet
+    /// and drops the parts of it that do not apply to the current mode
+    pub fn apply<'a>(&mut self, mut parsed: Parsed<'a>) -> Parsed<'a> {
+        // the reply to QEnableCompression itself was sent uncompressed
+        #[cfg(feature = "compression")]
+        if let Some(mode) = self.pending_compression.take() {
+            self.compression = Some(mode);
+        }
+
+        if self.no_ack {
+            // packets with a bad checksum are silently dropped
+            // since gdb will not retransmit them
+            if let Some(Commands::Retransmit(_)) = parsed.response {
+                parsed.command = None;
+            }
+            parsed.response = None;
+        }
+
+        // the packet itself is still acknowledged
+        match parsed.command {
+            Some(Commands::StartNoAckMode(_)) => self.no_ack = true,
+            Some(Commands::ExtendedMode(_)) => self.extended = true,
+            Some(Commands::Supported(ref mut c)) => c.set_packet_size(self.packet_size),
+            #[cfg(feature = "compression")]
+            Some(Commands::EnableCompression(ref c)) => self.pending_compression = c.mode(),
+            // restarting is only allowed in extended mode
+            Some(Commands::Restart(_)) if !self.extended => parsed.command = None,
+            _ => (),
+        }
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::control::StoppedCommand;
+    use crate::command::testing::TestCommands;
+    use crate::command::Command;
+    use crate::parser::Parser;
+    use crate::stop::StopReply;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_stop_acknowledging() {
+        let mut session = Session::new();
+
+        let parsed = session.apply(Parser::new(b"$?#3f").parse_packet(&TestCommands));
+        assert!(parsed.response.is_some());
+
+        let parsed = session.apply(Parser::new(b"$QStartNoAckMode#b0").parse_packet(&TestCommands));
+        assert!(parsed.response.is_some());
+        assert!(session.no_ack());
+
+        let parsed = session.apply(Parser::new(b"$?#3f").parse_packet(&TestCommands));
+        assert_eq!(parsed.response, None);
+        assert!(parsed.command.is_some());
+
+        let parsed = session.apply(Parser::new(b"-").parse_packet(&TestCommands));
+        assert_eq!(parsed.response, None);
+
+        let parsed = session.apply(Parser::new(b"$?#00").parse_packet(&TestCommands));
+        assert_eq!(parsed.response, None);
+        assert_eq!(parsed.command, None);
+    }
+
+    #[test]
+    fn it_should_enter_extended_mode() {
+        let mut session = Session::new();
+        assert!(!session.extended());
+
+        session.apply(Parser::new(b"$!#21").parse_packet(&TestCommands));
+        assert!(session.extended());
+    }
+
+    #[test]
+    fn it_should_only_restart_in_extended_mode() {
+        let mut session = Session::new();
+
+        let parsed = session.apply(Parser::new(b"$R00#b2").parse_packet(&TestCommands));
+        assert!(parsed.response.is_some());
+        assert_eq!(parsed.command, None);
+
+        session.apply(Parser::new(b"$!#21").parse_packet(&TestCommands));
+        let parsed = session.apply(Parser::new(b"$R00#b2").parse_packet(&TestCommands));
+        assert!(parsed.command.is_some());
+    }
+
+    struct QueueTarget {
+        stops: &'static [StopReason],
+    }
+
+    impl Target for QueueTarget {
+        fn next_stop(&mut self) -> Option<StopReason> {
+            let (first, rest) = self.stops.split_first()?;
+            self.stops = rest;
+            Some(*first)
+        }
+
+        fn set_non_stop(&mut self, _enabled: bool) -> Result<usize, Errors> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_switch_to_non_stop_once_accepted() {
+        let mut session = Session::new();
+        let mut target = QueueTarget {
+            stops: &[StopReason::Signal(2)],
+        };
+
+        let mut parsed = session.apply(Parser::new(b"$QNonStop:1#8d").parse_packet(&TestCommands));
+        let command = parsed.command.as_mut().unwrap();
+        session.answered(command);
+        assert!(!session.non_stop());
+        command
+            .response(&mut BufferedStream::new(), &mut target)
+            .unwrap();
+        session.answered(command);
+        assert!(session.non_stop());
+
+        // stops of the stub are reported first
+        session.stop_queue().push(StopReason::SwBreak).unwrap();
+        assert_eq!(session.next_stop(&mut target), Some(StopReason::SwBreak));
+        assert_eq!(session.next_stop(&mut target), Some(StopReason::Signal(2)));
+        assert_eq!(session.next_stop(&mut target), None);
+    }
+
+    #[test]
+    fn it_should_notify_stops_once() {
+        const STOPS: [StopReason; 2] = [
+            StopReason::Stop(StopReply::new(5).with_thread(1)),
+            StopReason::Stop(StopReply::new(5).with_thread(2)),
+        ];
+        let mut session = Session::new();
+        let mut target = QueueTarget { stops: &STOPS };
+
+        let mut stream = BufferedStream::new();
+        session.notify_stop(&mut stream, &mut target).unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"%Stop:T05thread:1;#b7"[..]);
+        assert!(session.notified());
+
+        // the second stop is fetched with vStopped
+        let mut stream = BufferedStream::new();
+        assert_eq!(session.notify_stop(&mut stream, &mut target), Ok(0));
+
+        let mut stopped = StoppedCommand::new(&[]);
+        let stop = session.next_stop(&mut target);
+        stopped.reply(&mut stream, &mut target, stop).unwrap();
+        session.answered(&Commands::Stopped(stopped));
+        assert_eq!(stream.buffer[..stream.pos()], b"$T05thread:2;#d8"[..]);
+        assert!(session.notified());
+
+        let mut stopped = StoppedCommand::new(&[]);
+        let stop = session.next_stop(&mut target);
+        stopped.reply(&mut stream, &mut target, stop).unwrap();
+        session.answered(&Commands::Stopped(stopped));
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+        assert!(!session.notified());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn it_should_compress_after_enabling() {
+        let mut session = Session::new();
+        let mut scratch = [0; 64];
+
+        let mut stream = BufferedStream::new();
+        session
+            .compress(b"$OK#9a", &mut scratch, &mut stream)
+            .unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$OK#9a"[..]);
+
+        session.apply(
+            Parser::new(b"$QEnableCompression:type:zlib-deflate;minsize:8;#fa")
+                .parse_packet(&TestCommands),
+        );
+        assert_eq!(session.compression(), None);
+        session.apply(Parser::new(b"$?#3f").parse_packet(&TestCommands));
+        assert_eq!(session.compression(), Some((Compression::ZlibDeflate, 8)));
+
+        let mut stream = BufferedStream::new();
+        let size = session
+            .compress(b"$OK#9a", &mut scratch, &mut stream)
+            .unwrap();
+        assert_eq!(size, stream.pos());
+        assert_eq!(stream.buffer[..stream.pos()], b"$NOK#e8"[..]);
+
+        let mut stream = BufferedStream::new();
+        session
+            .compress(b"$abcdabcdabcdabcd#28", &mut scratch, &mut stream)
+            .unwrap();
+        assert_eq!(
+            stream.buffer[..stream.pos()],
+            b"$C16:\x4b\x4c\x4a\x4e\x41\xc6\x00#1a"[..]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use super::error::Errors;
+use super::parser::Parser;
+
+// gdb signal numbers used in stop replies
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGABRT: u8 = 6;
+pub const SIGFPE: u8 = 8;
+pub const SIGKILL: u8 = 9;
+pub const SIGBUS: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+pub const SIGTERM: u8 = 15;
+
+/// number of gdb signal numbers a SignalSet can hold
+pub const SIGNAL_COUNT: usize = 256;
+
+/// a set of gdb signal numbers
+/// as sent by QPassSignals and QProgramSignals
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SignalSet {
+    bits: [u32; SIGNAL_COUNT / 32],
+}
+
+impl SignalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parses a ; separated list of hex signal numbers
+    pub fn parse(list: &[u8]) -> Result<Self, Errors> {
+        let mut set = Self::new();
+        for signal in list.split(|b| *b == b';').filter(|s| !s.is_empty()) {
+            let signal = Parser::from_hexu(signal).ok_or(Errors::BadNumber)?;
+            if signal >= SIGNAL_COUNT {
+                return Err(Errors::BadNumber);
+            }
+            set.insert(signal as u8);
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, signal: u8) {
+        self.bits[signal as usize / 32] |= 1 << (signal % 32);
+    }
+
+    pub fn contains(&self, signal: u8) -> bool {
+        self.bits[signal as usize / 32] & (1 << (signal % 32)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|b| *b == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_signal_lists() {
+        let set = SignalSet::parse(b"e;14;ff").unwrap();
+        assert!(set.contains(0x0e));
+        assert!(set.contains(0x14));
+        assert!(set.contains(0xff));
+        assert!(!set.contains(0x05));
+
+        assert!(SignalSet::parse(b"").unwrap().is_empty());
+        assert_eq!(SignalSet::parse(b"100"), Err(Errors::BadNumber));
+    }
+}
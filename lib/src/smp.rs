@@ -0,0 +1,154 @@
+use super::error::Errors;
+use super::resume::{ResumeAction, ResumeActions};
+use super::stop::StopReply;
+use super::thread::{ThreadCallback, ThreadId, ThreadInfo, ThreadTarget, ALL_THREADS, ANY_THREAD};
+
+/// the thread id gdb uses for core
+/// thread 0 is reserved, so cores are numbered from 1
+pub const fn core_thread(core: usize) -> usize {
+    core + 1
+}
+
+/// the core of a thread id, None for -1 and 0
+pub fn thread_core(thread: ThreadId) -> Option<usize> {
+    match thread.tid {
+        ALL_THREADS | ANY_THREAD => None,
+        tid => Some(tid - 1),
+    }
+}
+
+/// the vCont action for core
+pub fn core_action(actions: &ResumeActions, core: usize) -> Option<ResumeAction> {
+    actions.action_for(ThreadId::new(core_thread(core)))
+}
+
+/// a stop of core, reported as its thread together with the core field
+pub const fn core_stop(signal: u8, core: usize) -> StopReply {
+    StopReply::new(signal)
+        .with_thread(core_thread(core))
+        .with_core(core)
+}
+
+/// Lists the cores of a multi-core target as threads.
+/// Return it from Target::thread_target and switch the core
+/// the register and memory hooks access in Target::select_thread.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CoreThreads {
+    pub cores: usize,
+}
+
+impl CoreThreads {
+    pub const fn new(cores: usize) -> Self {
+        Self { cores }
+    }
+}
+
+impl ThreadTarget for CoreThreads {
+    fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+        let mut size = 0;
+        for core in 0..self.cores {
+            size += f(ThreadInfo::new(core_thread(core)).with_core(core))?;
+        }
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::exec;
+    use crate::register::RegisterValue;
+    use crate::resume::{Execution, ResumeKind};
+    use crate::stop::StopReason;
+    use crate::target::Target;
+    use crate::thread::ThreadOp;
+    use std::vec::Vec;
+
+    const CORES: CoreThreads = CoreThreads::new(2);
+
+    struct SmpTarget {
+        pcs: [u32; 2],
+        current: usize,
+        stepped: Option<usize>,
+    }
+
+    impl Target for SmpTarget {
+        fn rd_register(&self, _regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U32(self.pcs[self.current]))
+        }
+
+        fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+            Some(&CORES)
+        }
+
+        fn select_thread(&mut self, op: ThreadOp, thread: ThreadId) -> Result<usize, Errors> {
+            match (op, thread.tid, thread_core(thread)) {
+                (ThreadOp::General, ANY_THREAD, _) => Ok(0),
+                (ThreadOp::General, _, Some(core)) if core < CORES.cores => {
+                    self.current = core;
+                    Ok(0)
+                }
+                _ => Err(Errors::CommandError),
+            }
+        }
+
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+    }
+
+    impl Execution for SmpTarget {
+        fn resume(&mut self, actions: &ResumeActions) -> Result<StopReason, Errors> {
+            self.stepped = (0..CORES.cores)
+                .find(|core| core_action(actions, *core).map(|a| a.kind) == Some(ResumeKind::Step));
+            let core = self.stepped.unwrap_or(0);
+            Ok(StopReason::Stop(core_stop(5, core)))
+        }
+    }
+
+    #[test]
+    fn it_should_map_cores_to_threads() {
+        assert_eq!(core_thread(0), 1);
+        assert_eq!(thread_core(ThreadId::new(2)), Some(1));
+        assert_eq!(thread_core(ThreadId::new(ALL_THREADS)), None);
+
+        let mut cores = Vec::new();
+        CORES
+            .threads(&mut |info| {
+                cores.push((info.id, info.core));
+                Ok(0)
+            })
+            .unwrap();
+        assert_eq!(cores, [(1, Some(0)), (2, Some(1))]);
+    }
+
+    #[test]
+    fn it_should_switch_cores() {
+        let mut target = SmpTarget {
+            pcs: [0x100, 0x200],
+            current: 0,
+            stepped: None,
+        };
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$00010000#81");
+        assert_eq!(exec(b"$Hg2#e1", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$p0#a0", &mut target), b"$00020000#82");
+        assert_eq!(exec(b"$Hg0#df", &mut target), b"$OK#9a");
+        assert_eq!(target.current, 1);
+        assert_eq!(exec(b"$Hg3#e2", &mut target), b"$E00#a5");
+        assert_eq!(exec(b"$Hc-1#09", &mut target), b"$E00#a5");
+    }
+
+    #[test]
+    fn it_should_resume_cores() {
+        let mut target = SmpTarget {
+            pcs: [0x100, 0x200],
+            current: 0,
+            stepped: None,
+        };
+        assert_eq!(
+            exec(b"$vCont;s:2;c#c2", &mut target),
+            b"$T05thread:2;core:1;#27"
+        );
+        assert_eq!(target.stepped, Some(1));
+    }
+}
@@ -0,0 +1,296 @@
+use super::error::Errors;
+use super::memory::{read_memory, write_memory, GuestAddress};
+use super::stop::StopReason;
+use super::target::Target;
+
+pub mod riscv;
+
+/// an instruction can continue at most at two places
+/// the next instruction or a branch target
+pub const MAX_STEP_TARGETS: usize = 2;
+
+/// largest breakpoint instruction supported
+pub const MAX_BREAKPOINT_SIZE: usize = 4;
+
+/// Possible addresses of the instruction
+/// executed after the current one
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct NextPcs {
+    pcs: [usize; MAX_STEP_TARGETS],
+    len: usize,
+}
+
+impl NextPcs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds an address unless it is already known
+    pub fn push(&mut self, pc: usize) {
+        if !self.as_slice().contains(&pc) && self.len < MAX_STEP_TARGETS {
+            self.pcs[self.len] = pc;
+            self.len += 1;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.pcs[..self.len]
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+struct Patch {
+    address: GuestAddress,
+    original: [u8; MAX_BREAKPOINT_SIZE],
+    len: usize,
+}
+
+/// Step emulation for cores without hardware single-step.
+/// Temporary breakpoints are patched into memory at every
+/// possible next pc and removed again once the target stops.
+#[derive(Debug, Default)]
+pub struct SoftStep {
+    patches: [Patch; MAX_STEP_TARGETS],
+    len: usize,
+}
+
+impl SoftStep {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// true while temporary breakpoints are inserted
+    pub fn is_active(&self) -> bool {
+        self.len != 0
+    }
+
+    /// saves the original code at each address in next
+    /// and replaces it with breakpoint
+    pub fn insert(
+        &mut self,
+        target: &mut dyn Target,
+        next: &NextPcs,
+        breakpoint: &[u8],
+    ) -> Result<usize, Errors> {
+        if self.is_active() {
+            return Err(Errors::CommandError);
+        }
+        if breakpoint.len() > MAX_BREAKPOINT_SIZE {
+            return Err(Errors::LengthMismatch);
+        }
+
+        for address in next.as_slice() {
+            let mut patch = Patch {
+                address: *address as GuestAddress,
+                len: breakpoint.len(),
+                ..Default::default()
+            };
+            let read = read_memory(target, patch.address, &mut patch.original[..patch.len]);
+            if read != Ok(patch.len) {
+                self.remove(target)?;
+                return Err(read.err().unwrap_or(Errors::AddressOutOfRange));
+            }
+
+            self.patches[self.len] = patch;
+            self.len += 1;
+            if let Err(err) = write_memory(target, patch.address, breakpoint) {
+                self.remove(target)?;
+                return Err(err);
+            }
+        }
+        Ok(self.len)
+    }
+
+    /// restores the original code
+    /// patches are undone in reverse order so overlapping breakpoints
+    /// leave the original bytes behind
+    pub fn remove(&mut self, target: &mut dyn Target) -> Result<usize, Errors> {
+        while self.len > 0 {
+            self.len -= 1;
+            let patch = self.patches[self.len];
+            write_memory(target, patch.address, &patch.original[..patch.len])?;
+        }
+        Ok(0)
+    }
+}
+
+/// single steps a target without hardware single-step
+/// temporary breakpoints are planted at every address of Target::next_pcs,
+/// resume runs the target until it stops at one of them
+/// and the original code is restored before returning
+pub fn software_step<T: Target>(
+    target: &mut T,
+    breakpoint: &[u8],
+    resume: impl FnOnce(&mut T) -> Result<StopReason, Errors>,
+) -> Result<StopReason, Errors> {
+    let next = target.next_pcs()?;
+    let mut step = SoftStep::new();
+    step.insert(target, &next, breakpoint)?;
+    let result = resume(target);
+    step.remove(target)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::PhysAddr;
+    use crate::parser::Parser;
+    use crate::resume::{Execution, ResumeAction, ResumeActions, ResumeKind};
+    use crate::stream::Stream;
+    use crate::target::VirtualTarget;
+
+    #[test]
+    fn it_should_patch_and_restore_code() {
+        let mut target = VirtualTarget::new();
+        write_memory(&mut target, 0x10, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+        let mut next = NextPcs::new();
+        next.push(0x10);
+        next.push(0x12);
+        next.push(0x10);
+        assert_eq!(next.as_slice(), &[0x10, 0x12]);
+
+        let mut step = SoftStep::new();
+        assert_eq!(step.insert(&mut target, &next, &[0xaa, 0xbb]), Ok(2));
+        assert!(step.is_active());
+
+        let mut code = [0; 6];
+        read_memory(&target, 0x10, &mut code).unwrap();
+        assert_eq!(code, [0xaa, 0xbb, 0xaa, 0xbb, 5, 6]);
+
+        step.remove(&mut target).unwrap();
+        assert!(!step.is_active());
+        read_memory(&target, 0x10, &mut code).unwrap();
+        assert_eq!(code, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn it_should_undo_partial_inserts() {
+        let mut target = VirtualTarget::new();
+        write_memory(&mut target, 0x10, &[1, 2]).unwrap();
+
+        let mut next = NextPcs::new();
+        next.push(0x10);
+        next.push(0x1000);
+
+        let mut step = SoftStep::new();
+        assert_eq!(
+            step.insert(&mut target, &next, &[0xaa, 0xbb]),
+            Err(Errors::MemoryFault)
+        );
+        assert!(!step.is_active());
+
+        let mut code = [0; 2];
+        read_memory(&target, 0x10, &mut code).unwrap();
+        assert_eq!(code, [1, 2]);
+    }
+
+    /// the virtual page at 0x8000 is the memory at 0x40
+    struct MappedTarget(VirtualTarget);
+
+    impl Target for MappedTarget {
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            self.0.rd_memory(start, size, stream)
+        }
+
+        fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+            self.0.wr_memory(start, data)
+        }
+
+        fn translate(&self, addr: GuestAddress) -> Option<PhysAddr> {
+            match addr {
+                0x8000..=0x803f => Some(addr - 0x8000 + 0x40),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_patch_translated_code() {
+        let mut target = MappedTarget(VirtualTarget::new());
+        write_memory(&mut target, 0x8010, &[1, 2]).unwrap();
+
+        let mut next = NextPcs::new();
+        next.push(0x8010);
+        let mut step = SoftStep::new();
+        assert_eq!(step.insert(&mut target, &next, &[0xaa, 0xbb]), Ok(1));
+
+        let mut code = [0; 2];
+        read_memory(&target.0, 0x50, &mut code).unwrap();
+        assert_eq!(code, [0xaa, 0xbb]);
+
+        step.remove(&mut target).unwrap();
+        read_memory(&target.0, 0x50, &mut code).unwrap();
+        assert_eq!(code, [1, 2]);
+    }
+
+    struct StepTarget {
+        memory: [u8; 0x40],
+        pc: usize,
+    }
+
+    impl Target for StepTarget {
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            let start = start as usize;
+            for byte in &self.memory[start..start + size] {
+                Parser::to_hex8(*byte, stream)?;
+            }
+            Ok(size * 2)
+        }
+
+        fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+            Parser::from_hex_bytes(data, &mut self.memory[start as usize..])
+                .ok_or(Errors::BadNumber)
+        }
+
+        // every instruction is a branch to 0x20
+        fn next_pcs(&self) -> Result<NextPcs, Errors> {
+            let mut next = NextPcs::new();
+            next.push(self.pc + 2);
+            next.push(0x20);
+            Ok(next)
+        }
+
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+    }
+
+    impl Execution for StepTarget {
+        fn resume(&mut self, _actions: &ResumeActions) -> Result<StopReason, Errors> {
+            software_step(self, &[0xaa, 0xbb], |target| {
+                // runs until the branch hits a breakpoint
+                match target.memory[0x20..0x22] {
+                    [0xaa, 0xbb] => target.pc = 0x20,
+                    _ => return Err(Errors::CommandError),
+                }
+                Ok(StopReason::Signal(5))
+            })
+        }
+    }
+
+    #[test]
+    fn it_should_step_in_software() {
+        let mut target = StepTarget {
+            memory: [0; 0x40],
+            pc: 0x10,
+        };
+        target.memory[0x12] = 1;
+        let step = ResumeActions::single(ResumeAction::new(ResumeKind::Step), None);
+        assert_eq!(target.resume(&step), Ok(StopReason::Signal(5)));
+        assert_eq!(target.pc, 0x20);
+        assert_eq!(target.memory[0x12..0x14], [1, 0]);
+        assert_eq!(target.memory[0x20..0x22], [0, 0]);
+    }
+}
@@ -0,0 +1,256 @@
+use super::{NextPcs, SoftStep};
+use crate::error::Errors;
+use crate::memory::{read_memory, GuestAddress};
+use crate::target::Target;
+
+/// gdb register number of the program counter
+pub const PC_REGNO: usize = 32;
+
+/// ebreak in little endian byte order
+pub const EBREAK: [u8; 4] = [0x73, 0x00, 0x10, 0x00];
+
+/// c.ebreak in little endian byte order
+/// prefer it on cores with the C extension so that
+/// breakpoints never cover more than one instruction
+pub const C_EBREAK: [u8; 2] = [0x02, 0x90];
+
+/// base integer register width
+/// changes how some compressed instructions decode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Xlen {
+    Rv32,
+    Rv64,
+}
+
+/// reads integer register x1..x31
+pub type RegisterHook<'a> = dyn FnMut(usize) -> Result<usize, Errors> + 'a;
+
+/// size of the instruction starting with the given 16 bit parcel
+pub fn insn_len(parcel: u16) -> usize {
+    if parcel & 3 == 3 {
+        4
+    } else {
+        2
+    }
+}
+
+fn bits(insn: u32, hi: u32, lo: u32) -> u32 {
+    (insn >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// sign extends the lowest width bits of value
+fn sext(value: u32, width: u32) -> usize {
+    let shift = 32 - width;
+    (((value << shift) as i32) >> shift) as isize as usize
+}
+
+fn reg(read: &mut RegisterHook, regno: u32) -> Result<usize, Errors> {
+    match regno {
+        0 => Ok(0),
+        n => read(n as usize),
+    }
+}
+
+/// decodes the instruction insn located at pc
+/// and returns every address execution may continue at
+/// compressed instructions only use the lower 16 bits
+pub fn next_pcs(
+    xlen: Xlen,
+    pc: usize,
+    insn: u32,
+    read: &mut RegisterHook,
+) -> Result<NextPcs, Errors> {
+    let len = insn_len(insn as u16);
+    let mut next = NextPcs::new();
+    let mut branch = None;
+
+    if len == 4 {
+        match bits(insn, 6, 0) {
+            // jal
+            0x6f => {
+                let imm = (bits(insn, 31, 31) << 20)
+                    | (bits(insn, 19, 12) << 12)
+                    | (bits(insn, 20, 20) << 11)
+                    | (bits(insn, 30, 21) << 1);
+                branch = Some(pc.wrapping_add(sext(imm, 21)));
+            }
+            // jalr
+            0x67 => {
+                let base = reg(read, bits(insn, 19, 15))?;
+                branch = Some(base.wrapping_add(sext(bits(insn, 31, 20), 12)) & !1);
+            }
+            // beq, bne, blt, bge, bltu, bgeu
+            0x63 => {
+                let imm = (bits(insn, 31, 31) << 12)
+                    | (bits(insn, 7, 7) << 11)
+                    | (bits(insn, 30, 25) << 5)
+                    | (bits(insn, 11, 8) << 1);
+                next.push(pc.wrapping_add(len));
+                branch = Some(pc.wrapping_add(sext(imm, 13)));
+            }
+            _ => {}
+        }
+    } else {
+        let rs1 = bits(insn, 11, 7);
+        let rs2 = bits(insn, 6, 2);
+        match (bits(insn, 1, 0), bits(insn, 15, 13)) {
+            // c.j and c.jal, which is c.addiw on rv64
+            (1, 5) | (1, 1) if xlen == Xlen::Rv32 || bits(insn, 15, 13) == 5 => {
+                let imm = (bits(insn, 12, 12) << 11)
+                    | (bits(insn, 8, 8) << 10)
+                    | (bits(insn, 10, 9) << 8)
+                    | (bits(insn, 6, 6) << 7)
+                    | (bits(insn, 7, 7) << 6)
+                    | (bits(insn, 2, 2) << 5)
+                    | (bits(insn, 11, 11) << 4)
+                    | (bits(insn, 5, 3) << 1);
+                branch = Some(pc.wrapping_add(sext(imm, 12)));
+            }
+            // c.beqz and c.bnez
+            (1, 6) | (1, 7) => {
+                let imm = (bits(insn, 12, 12) << 8)
+                    | (bits(insn, 6, 5) << 6)
+                    | (bits(insn, 2, 2) << 5)
+                    | (bits(insn, 11, 10) << 3)
+                    | (bits(insn, 4, 3) << 1);
+                next.push(pc.wrapping_add(len));
+                branch = Some(pc.wrapping_add(sext(imm, 9)));
+            }
+            // c.jr and c.jalr
+            (2, 4) if rs2 == 0 && rs1 != 0 => {
+                branch = Some(reg(read, rs1)? & !1);
+            }
+            _ => {}
+        }
+    }
+
+    match branch {
+        Some(target) => next.push(target),
+        None => next.push(pc.wrapping_add(len)),
+    }
+
+    if xlen == Xlen::Rv32 {
+        let mut wrapped = NextPcs::new();
+        for pc in next.as_slice() {
+            wrapped.push(*pc as u32 as usize);
+        }
+        next = wrapped;
+    }
+    Ok(next)
+}
+
+fn rd_register(target: &dyn Target, regno: usize) -> Result<usize, Errors> {
+    target
+        .rd_register(regno)?
+        .value()
+        .map(|value| value as usize)
+        .ok_or(Errors::LengthMismatch)
+}
+
+/// decodes the instruction at pc and returns its possible successors
+/// suitable for Target::next_pcs
+pub fn next_pcs_at_pc(target: &dyn Target, xlen: Xlen) -> Result<NextPcs, Errors> {
+    let pc = rd_register(target, PC_REGNO)?;
+
+    let mut code = [0; 4];
+    if read_memory(target, pc as GuestAddress, &mut code[..2])? != 2 {
+        return Err(Errors::AddressOutOfRange);
+    }
+    let len = insn_len(u16::from_le_bytes([code[0], code[1]]));
+    if len == 4 && read_memory(target, (pc + 2) as GuestAddress, &mut code[2..])? != 2 {
+        return Err(Errors::AddressOutOfRange);
+    }
+
+    next_pcs(xlen, pc, u32::from_le_bytes(code), &mut |regno| {
+        rd_register(target, regno)
+    })
+}
+
+/// prepares a single step on cores without the debug extension
+/// decodes the instruction at pc and patches breakpoint into
+/// every possible successor
+/// resume the target afterwards and call SoftStep::remove once it stops
+pub fn prepare_step(
+    step: &mut SoftStep,
+    target: &mut dyn Target,
+    xlen: Xlen,
+    breakpoint: &[u8],
+) -> Result<NextPcs, Errors> {
+    let next = next_pcs_at_pc(target, xlen)?;
+    step.insert(target, &next, breakpoint)?;
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::write_memory;
+    use crate::register::RegisterValue;
+    use crate::target::VirtualTarget;
+
+    fn decode(xlen: Xlen, pc: usize, insn: u32) -> NextPcs {
+        next_pcs(xlen, pc, insn, &mut |regno| Ok(regno * 0x100 + 1)).unwrap()
+    }
+
+    #[test]
+    fn it_should_follow_jumps() {
+        // addi a0, a0, 1
+        assert_eq!(decode(Xlen::Rv32, 0x100, 0x00150513).as_slice(), &[0x104]);
+        // jal ra, -16
+        assert_eq!(decode(Xlen::Rv32, 0x100, 0xff1ff0ef).as_slice(), &[0xf0]);
+        // jalr zero, 8(a5)
+        assert_eq!(decode(Xlen::Rv64, 0x100, 0x00878067).as_slice(), &[0xf08]);
+        // c.j 32
+        assert_eq!(decode(Xlen::Rv32, 0x100, 0xa005).as_slice(), &[0x120]);
+        // c.jr ra
+        assert_eq!(decode(Xlen::Rv32, 0x100, 0x8082).as_slice(), &[0x100]);
+    }
+
+    #[test]
+    fn it_should_consider_both_branch_paths() {
+        // beq a0, a1, -8
+        assert_eq!(
+            decode(Xlen::Rv32, 0x100, 0xfeb50ce3).as_slice(),
+            &[0x104, 0xf8]
+        );
+        // c.beqz a0, 8
+        assert_eq!(
+            decode(Xlen::Rv32, 0x100, 0xc501).as_slice(),
+            &[0x102, 0x108]
+        );
+    }
+
+    #[test]
+    fn it_should_decode_c_addiw_on_rv64() {
+        // c.jal 4 on rv32, c.addiw s0, 1 on rv64
+        assert_eq!(decode(Xlen::Rv32, 0x100, 0x2011).as_slice(), &[0x104]);
+        assert_eq!(decode(Xlen::Rv64, 0x100, 0x2405).as_slice(), &[0x102]);
+    }
+
+    #[test]
+    fn it_should_wrap_rv32_addresses() {
+        // jal zero, -4
+        assert_eq!(decode(Xlen::Rv32, 0, 0xffdff06f).as_slice(), &[0xfffffffc]);
+    }
+
+    #[test]
+    fn it_should_prepare_step() {
+        let mut target = VirtualTarget::new();
+        // beq a0, a1, 16 at 0x40
+        write_memory(&mut target, 0x40, &[0x63, 0x08, 0xb5, 0x00]).unwrap();
+        target
+            .wr_register(PC_REGNO, RegisterValue::U32(0x40))
+            .unwrap();
+
+        let mut step = SoftStep::new();
+        let next = prepare_step(&mut step, &mut target, Xlen::Rv32, &C_EBREAK).unwrap();
+        assert_eq!(next.as_slice(), &[0x44, 0x50]);
+
+        let mut code = [0; 2];
+        read_memory(&target, 0x50, &mut code).unwrap();
+        assert_eq!(code, C_EBREAK);
+        step.remove(&mut target).unwrap();
+        read_memory(&target, 0x50, &mut code).unwrap();
+        assert_eq!(code, [0, 0]);
+    }
+}
@@ -0,0 +1,585 @@
+use super::description::GenericRegister;
+use super::error::Errors;
+use super::features::GdbFeatures;
+use super::host::ExitStatus;
+use super::parser::Parser;
+use super::signal::SIGTRAP;
+use super::stream::Stream;
+use super::target::Target;
+use super::thread::ThreadId;
+
+/// why a thread stopped, written as reason field of a T stop reply
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopCause {
+    /// the thread was just created, needs QThreadEvents
+    Create,
+    /// a software breakpoint, the pc points at the breakpoint instruction
+    SwBreak,
+    /// a hardware breakpoint
+    HwBreak,
+    /// modules were loaded or unloaded, gdb re-reads qXfer:libraries
+    Library,
+    /// a watchpoint hit at the data address
+    Watch { kind: WatchKind, addr: usize },
+}
+
+/// the access that triggered a watchpoint
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchKind {
+    /// watch, the value was written
+    Write,
+    /// rwatch, the value was read
+    Read,
+    /// awatch, the value was read or written
+    Access,
+}
+
+impl WatchKind {
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Self::Write => b"watch",
+            Self::Read => b"rwatch",
+            Self::Access => b"awatch",
+        }
+    }
+}
+
+impl StopCause {
+    /// true if gdb announced support for the cause
+    pub fn is_supported(&self, features: &GdbFeatures) -> bool {
+        match self {
+            Self::Create => true,
+            Self::SwBreak => features.swbreak,
+            Self::HwBreak => features.hwbreak,
+            Self::Library | Self::Watch { .. } => true,
+        }
+    }
+
+    /// writes the reason:value; field
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        match self {
+            Self::Create => stream.write_all(b"create:;"),
+            Self::SwBreak => stream.write_all(b"swbreak:;"),
+            Self::HwBreak => stream.write_all(b"hwbreak:;"),
+            Self::Library => stream.write_all(b"library:;"),
+            Self::Watch { kind, addr } => {
+                let mut size = stream.write_all(kind.name())?;
+                size += stream.write(b':')?;
+                size += Parser::to_hex_number(*addr, stream)?;
+                size += stream.write(b';')?;
+                Ok(size)
+            }
+        }
+    }
+}
+
+/// A T stop reply.
+/// The pc, sp and fp registers of the target description are expedited
+/// so gdb does not need to read all registers after every stop.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StopReply {
+    pub signal: u8,
+    pub thread: Option<usize>,
+    /// the process of the thread, written with the multiprocess extension
+    pub pid: Option<usize>,
+    /// the core the thread stopped on
+    pub core: Option<usize>,
+    pub cause: Option<StopCause>,
+}
+
+impl StopReply {
+    pub const fn new(signal: u8) -> Self {
+        Self {
+            signal,
+            thread: None,
+            pid: None,
+            core: None,
+            cause: None,
+        }
+    }
+
+    pub const fn with_thread(mut self, thread: usize) -> Self {
+        self.thread = Some(thread);
+        self
+    }
+
+    pub const fn with_pid(mut self, pid: usize) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    pub const fn with_core(mut self, core: usize) -> Self {
+        self.core = Some(core);
+        self
+    }
+
+    pub const fn with_cause(mut self, cause: StopCause) -> Self {
+        self.cause = Some(cause);
+        self
+    }
+
+    /// writes T<signal><regno>:<value>;...<cause>thread:<tid>;core:<core>;
+    /// registers that cannot be read are left out
+    pub fn write(&self, ctx: &dyn Target, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write(b'T')?;
+        Parser::to_hex8(self.signal, stream)?;
+        size += 2;
+
+        if let Some(description) = ctx.description() {
+            for (regnum, reg) in description.registers.iter().enumerate() {
+                if !matches!(
+                    reg.generic,
+                    Some(GenericRegister::Pc | GenericRegister::Sp | GenericRegister::Fp)
+                ) {
+                    continue;
+                }
+                if let Ok(value) = ctx.rd_register(regnum) {
+                    size += Parser::to_hex_number(regnum, stream)?;
+                    size += stream.write(b':')?;
+                    size += value.write(ctx.endianess(), stream)?;
+                    size += stream.write(b';')?;
+                }
+            }
+        }
+
+        // gdb rejects stop replies with reasons it did not announce
+        if let Some(cause) = self.cause.filter(|c| c.is_supported(&ctx.gdb_features())) {
+            size += cause.write(stream)?;
+        }
+        if let Some(thread) = self.thread {
+            size += stream.write_all(b"thread:")?;
+            size += ThreadId {
+                pid: self.pid,
+                tid: thread,
+            }
+            .write(stream)?;
+            size += stream.write(b';')?;
+        }
+        if let Some(core) = self.core {
+            size += stream.write_all(b"core:")?;
+            size += Parser::to_hex_number(core, stream)?;
+            size += stream.write(b';')?;
+        }
+        #[cfg(feature = "lldb")]
+        if ctx.threads_in_stop_reply() {
+            size += write_thread_list(ctx, stream)?;
+        }
+        Ok(size)
+    }
+}
+
+/// writes threads:<tid>,...; and thread-pcs:<pc>,...; for lldb
+/// the pcs are left out unless all threads report one
+#[cfg(feature = "lldb")]
+fn write_thread_list(ctx: &dyn Target, stream: &mut dyn Stream) -> Result<usize, Errors> {
+    let threads = match ctx.thread_target() {
+        Some(threads) => threads,
+        None => return Ok(0),
+    };
+
+    let mut size = stream.write_all(b"threads:")?;
+    let mut count = 0;
+    let mut pcs = 0;
+    threads.threads(&mut |thread| {
+        if count > 0 {
+            size += stream.write(b',')?;
+        }
+        count += 1;
+        pcs += thread.pc.is_some() as usize;
+        size += Parser::to_hex_number(thread.id, stream)?;
+        Ok(0)
+    })?;
+    size += stream.write(b';')?;
+
+    if count > 0 && pcs == count {
+        size += stream.write_all(b"thread-pcs:")?;
+        let mut first = true;
+        threads.threads(&mut |thread| {
+            if !first {
+                size += stream.write(b',')?;
+            }
+            first = false;
+            size += Parser::to_hex_number(thread.pc.unwrap_or(0), stream)?;
+            Ok(0)
+        })?;
+        size += stream.write(b';')?;
+    }
+    Ok(size)
+}
+
+/// a thread that exited while its process keeps running
+/// only reported after QThreadEvents:1
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ThreadExit {
+    pub thread: ThreadId,
+    pub status: u8,
+}
+
+impl ThreadExit {
+    /// writes the stop reply, e.g. w00;p1.2
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write(b'w')?;
+        Parser::to_hex8(self.status, stream)?;
+        size += 2;
+        size += stream.write(b';')?;
+        size += self.thread.write(stream)?;
+        Ok(size)
+    }
+}
+
+/// a process event reported as SIGTRAP stop
+/// new processes are reported as p<pid>.<tid>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProcessEvent<'a> {
+    Fork {
+        pid: usize,
+        tid: usize,
+    },
+    VFork {
+        pid: usize,
+        tid: usize,
+    },
+    /// the vfork child exec'd or exited and the parent resumes
+    VForkDone,
+    /// the absolute path of the new program
+    Exec(&'a [u8]),
+}
+
+impl ProcessEvent<'_> {
+    /// writes the stop reply, e.g. T05fork:p2a.2a;
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"T05")?;
+        match *self {
+            Self::Fork { pid, tid } => {
+                size += stream.write_all(b"fork:")?;
+                size += ThreadId::new(tid).with_pid(pid).write(stream)?;
+            }
+            Self::VFork { pid, tid } => {
+                size += stream.write_all(b"vfork:")?;
+                size += ThreadId::new(tid).with_pid(pid).write(stream)?;
+            }
+            Self::VForkDone => size += stream.write_all(b"vforkdone:")?,
+            Self::Exec(path) => {
+                size += stream.write_all(b"exec:")?;
+                Parser::to_hexu(path, stream)?;
+                size += path.len() * 2;
+            }
+        }
+        size += stream.write(b';')?;
+        Ok(size)
+    }
+}
+
+/// Why the target halted.
+/// The stub writes the matching stop reply,
+/// breakpoint causes are left out unless gdb announced them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// S<signal>, stopped without further details
+    Signal(u8),
+    /// a software breakpoint was hit
+    SwBreak,
+    /// a hardware breakpoint was hit
+    HwBreak,
+    /// a watchpoint hit at the data address
+    Watch { kind: WatchKind, addr: usize },
+    /// W, the program exited with code
+    Exited { code: u8 },
+    /// X, the program was terminated by signal
+    Terminated { signal: u8 },
+    /// T<signal> with thread and cause
+    Stop(StopReply),
+    /// N, no thread is left that could be resumed
+    NoResumed,
+}
+
+impl StopReason {
+    /// the signal the target stopped with, None once it is gone
+    pub fn signal(&self) -> Option<u8> {
+        match self {
+            Self::Signal(signal) => Some(*signal),
+            Self::SwBreak | Self::HwBreak | Self::Watch { .. } => Some(SIGTRAP),
+            Self::Stop(reply) => Some(reply.signal),
+            Self::Exited { .. } | Self::Terminated { .. } | Self::NoResumed => None,
+        }
+    }
+
+    /// the thread that stopped if it is known
+    pub fn thread(&self) -> Option<usize> {
+        match self {
+            Self::Stop(reply) => reply.thread,
+            _ => None,
+        }
+    }
+
+    pub fn write(&self, ctx: &dyn Target, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let trap = StopReply::new(SIGTRAP);
+        match *self {
+            Self::Signal(signal) => {
+                stream.write(b'S')?;
+                Parser::to_hex8(signal, stream)?;
+                Ok(3)
+            }
+            Self::SwBreak => trap.with_cause(StopCause::SwBreak).write(ctx, stream),
+            Self::HwBreak => trap.with_cause(StopCause::HwBreak).write(ctx, stream),
+            Self::Watch { kind, addr } => trap
+                .with_cause(StopCause::Watch { kind, addr })
+                .write(ctx, stream),
+            Self::Exited { code } => ExitStatus::Code(code).write(stream),
+            Self::Terminated { signal } => ExitStatus::Signal(signal).write(stream),
+            Self::Stop(reply) => reply.write(ctx, stream),
+            Self::NoResumed => stream.write(b'N'),
+        }
+    }
+}
+
+/// stops a non-stop session holds until gdb fetched them
+pub const STOP_QUEUE_LEN: usize = 8;
+
+/// Stops of a non-stop session that were not reported yet, oldest first.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct StopQueue {
+    stops: [Option<StopReason>; STOP_QUEUE_LEN],
+    len: usize,
+}
+
+impl StopQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// fails with MemoryFilledInterupt once STOP_QUEUE_LEN stops are queued
+    pub fn push(&mut self, reason: StopReason) -> Result<(), Errors> {
+        let slot = self
+            .stops
+            .get_mut(self.len)
+            .ok_or(Errors::MemoryFilledInterupt)?;
+        *slot = Some(reason);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// removes the oldest stop
+    pub fn pop(&mut self) -> Option<StopReason> {
+        let oldest = self.stops.first_mut()?.take()?;
+        self.stops.rotate_left(1);
+        self.len -= 1;
+        Some(oldest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::description::{RegisterDesc, TargetDescription};
+    use crate::register::RegisterValue;
+    use crate::signal::SIGTRAP;
+    use crate::stream::BufferedStream;
+
+    const REGISTERS: [RegisterDesc; 3] = [
+        RegisterDesc::new(b"r0", 32),
+        RegisterDesc::new(b"sp", 32).with_generic(GenericRegister::Sp),
+        RegisterDesc::new(b"pc", 32).with_generic(GenericRegister::Pc),
+    ];
+
+    const DESCRIPTION: TargetDescription = TargetDescription::new(b"org.gnu.gdb.test", &REGISTERS);
+
+    struct StopTarget;
+    impl Target for StopTarget {
+        fn description(&self) -> Option<&TargetDescription<'_>> {
+            Some(&DESCRIPTION)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            match regno {
+                1 => Ok(RegisterValue::U32(0x2000_0100)),
+                _ => Err(Errors::Unsupported),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_queue_stops_in_order() {
+        let mut queue = StopQueue::new();
+        assert_eq!(queue.pop(), None);
+        for signal in 0..STOP_QUEUE_LEN as u8 {
+            queue.push(StopReason::Signal(signal)).unwrap();
+        }
+        assert_eq!(
+            queue.push(StopReason::SwBreak),
+            Err(Errors::MemoryFilledInterupt)
+        );
+        assert_eq!(queue.pop(), Some(StopReason::Signal(0)));
+        queue.push(StopReason::SwBreak).unwrap();
+        assert_eq!(queue.len(), STOP_QUEUE_LEN);
+        assert_eq!(queue.pop(), Some(StopReason::Signal(1)));
+    }
+
+    #[test]
+    fn it_should_write_t_replies() {
+        let mut s = BufferedStream::new();
+        let size = StopReply::new(SIGTRAP)
+            .with_thread(0x1f)
+            .write(&StopTarget, &mut s)
+            .unwrap();
+        assert_eq!(size, s.pos());
+        // pc cannot be read and is left out
+        assert_eq!(s.buffer[..s.pos()], b"T051:00010020;thread:1f;"[..]);
+
+        let mut s = BufferedStream::new();
+        StopReply::new(SIGTRAP)
+            .with_thread(2)
+            .with_pid(1)
+            .write(&StopTarget, &mut s)
+            .unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"T051:00010020;thread:p1.2;"[..]);
+    }
+
+    struct BreakTarget {
+        features: GdbFeatures,
+    }
+
+    impl Target for BreakTarget {
+        fn gdb_features(&self) -> GdbFeatures {
+            self.features
+        }
+    }
+
+    #[test]
+    fn it_should_write_watchpoint_causes() {
+        let mut s = BufferedStream::new();
+        let size = StopReply::new(SIGTRAP)
+            .with_cause(StopCause::Watch {
+                kind: WatchKind::Access,
+                addr: 0x20001000,
+            })
+            .with_thread(1)
+            .write(
+                &BreakTarget {
+                    features: GdbFeatures::default(),
+                },
+                &mut s,
+            )
+            .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"T05awatch:20001000;thread:1;"[..]);
+
+        let mut s = BufferedStream::new();
+        StopCause::Watch {
+            kind: WatchKind::Read,
+            addr: 0x10,
+        }
+        .write(&mut s)
+        .unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"rwatch:10;"[..]);
+    }
+
+    #[test]
+    fn it_should_write_library_causes() {
+        let mut s = BufferedStream::new();
+        StopReply::new(SIGTRAP)
+            .with_cause(StopCause::Library)
+            .write(
+                &BreakTarget {
+                    features: GdbFeatures::default(),
+                },
+                &mut s,
+            )
+            .unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"T05library:;"[..]);
+    }
+
+    #[test]
+    fn it_should_write_negotiated_break_causes() {
+        let reply = StopReply::new(SIGTRAP).with_cause(StopCause::SwBreak);
+        let mut target = BreakTarget {
+            features: GdbFeatures::default(),
+        };
+
+        let mut s = BufferedStream::new();
+        reply.write(&target, &mut s).unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"T05"[..]);
+
+        target.features.swbreak = true;
+        let mut s = BufferedStream::new();
+        let size = reply.write(&target, &mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"T05swbreak:;"[..]);
+    }
+
+    #[test]
+    fn it_should_write_stop_reasons() {
+        let target = BreakTarget {
+            features: GdbFeatures {
+                hwbreak: true,
+                ..GdbFeatures::default()
+            },
+        };
+        let write = |reason: StopReason| {
+            let mut s = BufferedStream::new();
+            let size = reason.write(&target, &mut s).unwrap();
+            assert_eq!(size, s.pos());
+            s.buffer[..s.pos()].to_vec()
+        };
+
+        assert_eq!(write(StopReason::Signal(2)), b"S02");
+        assert_eq!(write(StopReason::SwBreak), b"T05");
+        assert_eq!(write(StopReason::HwBreak), b"T05hwbreak:;");
+        assert_eq!(
+            write(StopReason::Watch {
+                kind: WatchKind::Write,
+                addr: 0x100
+            }),
+            b"T05watch:100;"
+        );
+        assert_eq!(write(StopReason::Exited { code: 1 }), b"W01");
+        assert_eq!(write(StopReason::Terminated { signal: 9 }), b"X09");
+        assert_eq!(write(StopReason::NoResumed), b"N");
+        assert_eq!(StopReason::Exited { code: 0 }.signal(), None);
+        assert_eq!(StopReason::HwBreak.signal(), Some(SIGTRAP));
+    }
+
+    #[test]
+    fn it_should_write_thread_events() {
+        let mut s = BufferedStream::new();
+        StopReply::new(SIGTRAP)
+            .with_thread(3)
+            .with_cause(StopCause::Create)
+            .write(&StopTarget, &mut s)
+            .unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"T051:00010020;create:;thread:3;"[..]);
+
+        let mut s = BufferedStream::new();
+        let size = ThreadExit {
+            thread: ThreadId::new(2).with_pid(1),
+            status: 0,
+        }
+        .write(&mut s)
+        .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"w00;p1.2"[..]);
+    }
+
+    #[test]
+    fn it_should_write_process_events() {
+        let mut s = BufferedStream::new();
+        let size = ProcessEvent::VFork { pid: 0x2a, tid: 1 }
+            .write(&mut s)
+            .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"T05vfork:p2a.1;"[..]);
+
+        let mut s = BufferedStream::new();
+        let size = ProcessEvent::Exec(b"/bin/sh").write(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"T05exec:2f62696e2f7368;"[..]);
+    }
+}
@@ -1,3 +1,4 @@
+use super::command::ResponseWriter;
 use super::error::Errors;
 
 /// basic stream implementation
@@ -37,32 +38,134 @@ pub trait Stream {
 
     /// write to the next byte
     fn write(&mut self, byte: u8) -> Result<usize, Errors>;
+
+    /// write all bytes without escaping them
+    fn write_all(&mut self, bytes: &[u8]) -> Result<usize, Errors> {
+        let mut size = 0;
+        for byte in bytes {
+            size += self.write(*byte)?;
+        }
+        Ok(size)
+    }
 }
 
-/// Sample stream with a fixed buffer
-pub struct BufferedStream {
-    pub buffer: [u8; 512],
+/// The transport to gdb, e.g. tcp, a uart, usb or rtt.
+/// Reads never block, they return None if nothing arrived.
+pub trait Connection {
+    /// reads the next byte
+    /// returns None if nothing arrived and Errors::Disconnected once closed
+    fn read(&mut self) -> Result<Option<u8>, Errors>;
+
+    /// returns the next byte without consuming it
+    fn peek(&mut self) -> Result<Option<u8>, Errors>;
+
+    /// reads the available bytes into buffer
+    /// returns how many bytes were read
+    fn read_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Errors> {
+        for (i, slot) in buffer.iter_mut().enumerate() {
+            match self.read()? {
+                Some(byte) => *slot = byte,
+                None => return Ok(i),
+            }
+        }
+        Ok(buffer.len())
+    }
+
+    /// writes all bytes
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors>;
+
+    /// sends buffered output
+    fn flush(&mut self) -> Result<(), Errors> {
+        Ok(())
+    }
+}
+
+/// Tcp connections to gdb.
+/// The socket has to be non-blocking or have a read timeout,
+/// otherwise reads wait for input.
+#[cfg(feature = "std")]
+mod tcp {
+    use super::Connection;
+    use crate::error::Errors;
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpStream;
+
+    /// maps io errors, nothing to read is not an error
+    fn received(result: std::io::Result<usize>, byte: u8) -> Result<Option<u8>, Errors> {
+        match result {
+            Ok(0) => Err(Errors::Disconnected),
+            Ok(_) => Ok(Some(byte)),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(_) => Err(Errors::Disconnected),
+        }
+    }
+
+    impl Connection for TcpStream {
+        fn read(&mut self) -> Result<Option<u8>, Errors> {
+            let mut byte = [0];
+            loop {
+                match Read::read(self, &mut byte) {
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    result => return received(result, byte[0]),
+                }
+            }
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, Errors> {
+            let mut byte = [0];
+            received(TcpStream::peek(self, &mut byte), byte[0])
+        }
+
+        fn read_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Errors> {
+            match Read::read(self, buffer) {
+                Ok(0) if !buffer.is_empty() => Err(Errors::Disconnected),
+                Ok(len) => Ok(len),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    Ok(0)
+                }
+                Err(_) => Err(Errors::Disconnected),
+            }
+        }
+
+        fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+            Write::write_all(self, bytes).map_err(|_| Errors::Disconnected)
+        }
+
+        fn flush(&mut self) -> Result<(), Errors> {
+            Write::flush(self).map_err(|_| Errors::Disconnected)
+        }
+    }
+}
+
+/// the default buffer size of BufferedStream and the reply buffers of the stubs
+pub const REPLY_LEN: usize = 512;
+
+/// Sample stream with a fixed buffer of N bytes
+pub struct BufferedStream<const N: usize = REPLY_LEN> {
+    pub buffer: [u8; N],
     pub pos: usize,
     chksm: u32,
 }
 
-impl Default for BufferedStream {
+impl<const N: usize> Default for BufferedStream<N> {
     fn default() -> Self {
-        Self::new()
+        Self {
+            buffer: [0; N],
+            pos: 0,
+            chksm: 0,
+        }
     }
 }
 
 impl BufferedStream {
     pub fn new() -> Self {
-        Self {
-            buffer: [0; 512],
-            pos: 0,
-            chksm: 0,
-        }
+        Self::default()
     }
 }
 
-impl Stream for BufferedStream {
+impl<const N: usize> Stream for BufferedStream<N> {
     fn len(&self) -> usize {
         self.buffer.len()
     }
@@ -101,3 +204,542 @@ impl Stream for BufferedStream {
         Ok(self.pos - start)
     }
 }
+
+/// Stream adapter that writes everything
+/// as hex encoded console output packets
+/// The O packet is only started once the first byte is written
+/// and has to be closed by calling finish
+/// Every CONSOLE_PACKET_LEN bytes the packet is ended and inner flushed,
+/// so inner only has to hold a single packet if it sends on flush
+pub struct ConsoleStream<'a> {
+    inner: &'a mut dyn Stream,
+    state: ResponseWriter<'a>,
+    open: bool,
+    written: usize,
+}
+
+impl<'a> ConsoleStream<'a> {
+    pub fn new(inner: &'a mut dyn Stream) -> Self {
+        Self {
+            inner,
+            state: ResponseWriter::new(&[]),
+            open: false,
+            written: 0,
+        }
+    }
+
+    /// true if any output was written
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// ends the current O packet if one was started
+    pub fn finish(&mut self) -> Result<usize, Errors> {
+        if !self.open {
+            return Ok(0);
+        }
+        self.open = false;
+        self.written = 0;
+        let size = self.state.end(self.inner)?;
+        self.inner.flush();
+        Ok(size)
+    }
+}
+
+impl Stream for ConsoleStream<'_> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn pos(&self) -> usize {
+        self.inner.pos()
+    }
+
+    fn chksm(&self) -> u32 {
+        self.inner.chksm()
+    }
+
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        let mut size = 0;
+        if self.written == CONSOLE_PACKET_LEN {
+            size += self.finish()?;
+        }
+        if !self.open {
+            self.open = true;
+            size += self.state.start(self.inner)?;
+            size += self.state.write(self.inner, b'O')?;
+        }
+        size += self.state.write_hex(self.inner, byte)?;
+        self.written += 1;
+        Ok(size)
+    }
+}
+
+/// longest text sent in a single O packet
+pub const CONSOLE_PACKET_LEN: usize = 64;
+
+/// size of an O packet holding CONSOLE_PACKET_LEN bytes of text
+pub const CONSOLE_FRAME_LEN: usize = 2 * CONSOLE_PACKET_LEN + 5;
+
+/// Prints to the gdb console with O packets.
+/// Output is only allowed while the target is running,
+/// i.e. after a resume and before the stop reply.
+/// The stubs hand one to Execution::poll.
+/// Each call sends complete packets so nothing has to be finished.
+pub struct GdbConsole<'a> {
+    inner: &'a mut dyn Stream,
+}
+
+impl<'a> GdbConsole<'a> {
+    pub fn new(inner: &'a mut dyn Stream) -> Self {
+        Self { inner }
+    }
+
+    /// sends text in packets of at most CONSOLE_PACKET_LEN bytes
+    pub fn write_bytes(&mut self, text: &[u8]) -> Result<usize, Errors> {
+        let mut console = ConsoleStream::new(self.inner);
+        let size = console.write_all(text)?;
+        Ok(size + console.finish()?)
+    }
+
+    pub fn write_str(&mut self, text: &str) -> Result<usize, Errors> {
+        self.write_bytes(text.as_bytes())
+    }
+}
+
+/// every write! fragment becomes its own packet
+impl core::fmt::Write for GdbConsole<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes())
+            .map(|_| ())
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+/// Stream adapter that escapes the bytes
+/// gdb reserves for framing, e.g. the braces of json replies
+pub struct EscapeStream<'a> {
+    inner: &'a mut dyn Stream,
+    state: ResponseWriter<'a>,
+}
+
+impl<'a> EscapeStream<'a> {
+    pub fn new(inner: &'a mut dyn Stream) -> Self {
+        Self {
+            inner,
+            state: ResponseWriter::new(&[]),
+        }
+    }
+}
+
+impl Stream for EscapeStream<'_> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn pos(&self) -> usize {
+        self.inner.pos()
+    }
+
+    fn chksm(&self) -> u32 {
+        self.inner.chksm()
+    }
+
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        self.state.write(self.inner, byte)
+    }
+}
+
+/// selects how response bytes are buffered
+/// before they are handed to the transport
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BufferStrategy {
+    /// the entire response has to fit into the buffer
+    #[default]
+    Single,
+    /// a full buffer is handed to the transport and writing goes on
+    /// with a Transfer the buffer is split into two halves,
+    /// one half is sent (e.g. by dma) while the other one is written
+    Double,
+    /// every byte is handed to the sink as soon as it is written
+    /// nothing is kept, so a reply can not be sent again
+    WriteThrough,
+}
+
+/// Sends buffers in the background, e.g. with dma.
+/// The transfer owns a buffer from start until finish hands it back,
+/// so it can keep sending from it after start returned.
+pub trait Transfer<'a> {
+    /// starts to send the first len bytes of buffer
+    fn start(&mut self, buffer: &'a mut [u8], len: usize) -> Result<(), Errors>;
+
+    /// waits until the last started transfer completed and returns its buffer
+    /// the buffer is returned even if start failed
+    fn finish(&mut self) -> Result<&'a mut [u8], Errors>;
+}
+
+enum Sink<'a> {
+    /// copies the bytes before it returns
+    Copy(&'a mut dyn FnMut(&[u8]) -> Result<(), Errors>),
+    /// the spare half is None while it is sent
+    Transfer {
+        transfer: &'a mut dyn Transfer<'a>,
+        spare: Option<&'a mut [u8]>,
+    },
+}
+
+/// Stream that hands its data to a sink
+/// according to a buffering strategy
+/// The checksum is tracked across flushes so the
+/// packet trailer is correct in every mode.
+/// Pending data has to be flushed after each command
+/// because reset discards anything that was not sent yet.
+/// The sink only borrows the bytes for the duration of the call,
+/// use with_transfer to send them asynchronously.
+pub struct SinkStream<'a> {
+    strategy: BufferStrategy,
+    /// the half that is written in double buffered mode
+    buffer: &'a mut [u8],
+    sink: Sink<'a>,
+    pos: usize,
+    chksm: u32,
+    error: Option<Errors>,
+}
+
+impl<'a> SinkStream<'a> {
+    pub fn new(
+        strategy: BufferStrategy,
+        buffer: &'a mut [u8],
+        sink: &'a mut dyn FnMut(&[u8]) -> Result<(), Errors>,
+    ) -> Self {
+        Self {
+            strategy,
+            buffer,
+            sink: Sink::Copy(sink),
+            pos: 0,
+            chksm: 0,
+            error: None,
+        }
+    }
+
+    /// double buffered stream that hands the filled half to transfer
+    /// and waits for it to complete before the half is written again
+    pub fn with_transfer(buffer: &'a mut [u8], transfer: &'a mut dyn Transfer<'a>) -> Self {
+        let (buffer, spare) = buffer.split_at_mut(buffer.len() / 2);
+        Self {
+            strategy: BufferStrategy::Double,
+            buffer,
+            sink: Sink::Transfer {
+                transfer,
+                spare: Some(spare),
+            },
+            pos: 0,
+            chksm: 0,
+            error: None,
+        }
+    }
+
+    pub fn strategy(&self) -> BufferStrategy {
+        self.strategy
+    }
+
+    /// returns the last error reported by the sink during a flush
+    pub fn take_error(&mut self) -> Option<Errors> {
+        self.error.take()
+    }
+
+    /// hands the written bytes to the sink
+    fn send(&mut self) -> Result<(), Errors> {
+        let len = core::mem::replace(&mut self.pos, 0);
+        match &mut self.sink {
+            Sink::Copy(sink) => sink(&self.buffer[..len]),
+            Sink::Transfer { transfer, spare } => {
+                // the other half is free again once its transfer completed
+                let next = match spare.take() {
+                    Some(next) => next,
+                    None => transfer.finish()?,
+                };
+                let filled = core::mem::replace(&mut self.buffer, next);
+                transfer.start(filled, len)
+            }
+        }
+    }
+
+    /// waits for the half that is still being sent
+    fn complete(&mut self) -> Result<(), Errors> {
+        if let Sink::Transfer { transfer, spare } = &mut self.sink {
+            if spare.is_none() {
+                *spare = Some(transfer.finish()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stream for SinkStream<'_> {
+    fn len(&self) -> usize {
+        match self.strategy {
+            BufferStrategy::Single | BufferStrategy::Double => self.buffer.len(),
+            BufferStrategy::WriteThrough => 0,
+        }
+    }
+
+    /// in write-through mode the bytes written since the last reset
+    /// in double buffered mode the bytes in the current half
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// sends the pending bytes and waits until they were sent
+    fn flush(&mut self) {
+        let mut result = Ok(());
+        if self.strategy != BufferStrategy::WriteThrough && self.pos > 0 {
+            result = self.send();
+        }
+        if let Err(err) = result.and_then(|_| self.complete()) {
+            self.error = Some(err);
+        }
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.chksm = 0;
+    }
+
+    /// sends the full half in double buffered mode
+    fn resize(&mut self) -> bool {
+        match self.strategy {
+            BufferStrategy::Double if self.pos > 0 => match self.send() {
+                Ok(()) => true,
+                Err(err) => {
+                    self.error = Some(err);
+                    false
+                }
+            },
+            _ => false,
+        }
+    }
+
+    fn chksm(&self) -> u32 {
+        self.chksm
+    }
+
+    fn write(&mut self, byte: u8) -> Result<usize, Errors> {
+        // add all bytes but start and end
+        if byte != b'$' && byte != b'#' {
+            self.chksm += byte as u32;
+        }
+
+        match self.strategy {
+            BufferStrategy::Single | BufferStrategy::Double => {
+                if self.len() < self.pos() + 1 && !self.resize() {
+                    return Err(self.error.take().unwrap_or(Errors::MemoryFilledInterupt));
+                }
+                self.buffer[self.pos] = byte;
+            }
+            BufferStrategy::WriteThrough => match &mut self.sink {
+                Sink::Copy(sink) => sink(&[byte])?,
+                Sink::Transfer { .. } => return Err(Errors::MemoryFilledInterupt),
+            },
+        }
+        self.pos += 1;
+        Ok(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn write_packet(stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut state = ResponseWriter::new(&[]);
+        let mut size = state.start(stream)?;
+        size += state.write_all(stream, b"0123456789")?;
+        size += state.end(stream)?;
+        Ok(size)
+    }
+
+    fn write_with(strategy: BufferStrategy, buffer: &mut [u8]) -> Result<Vec<u8>, Errors> {
+        let mut out = Vec::new();
+        let mut sink = |data: &[u8]| {
+            out.extend_from_slice(data);
+            Ok(())
+        };
+        let mut stream = SinkStream::new(strategy, buffer, &mut sink);
+        write_packet(&mut stream)?;
+        stream.flush();
+        Ok(out)
+    }
+
+    #[test]
+    fn it_should_write_console_packets() {
+        let mut stream = BufferedStream::new();
+        let mut console = ConsoleStream::new(&mut stream);
+        console.write(b'H').unwrap();
+        console.write(b'i').unwrap();
+        console.finish().unwrap();
+
+        assert_eq!(stream.buffer[..stream.pos()], b"$O4869#2a"[..]);
+    }
+
+    #[test]
+    fn it_should_print_to_the_gdb_console() {
+        use core::fmt::Write;
+
+        let mut stream = BufferedStream::new();
+        let mut console = GdbConsole::new(&mut stream);
+        console.write_str("Hi").unwrap();
+        write!(console, "{}", 7).unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"$O4869#2a$O37#b9"[..]);
+
+        let mut stream = BufferedStream::new();
+        GdbConsole::new(&mut stream)
+            .write_bytes(&[b'a'; CONSOLE_PACKET_LEN + 1])
+            .unwrap();
+        // the text is split into two packets
+        let packets = stream.buffer[..stream.pos()]
+            .iter()
+            .filter(|b| **b == b'$')
+            .count();
+        assert_eq!(packets, 2);
+    }
+
+    #[test]
+    fn it_should_not_write_empty_console_packets() {
+        let mut stream = BufferedStream::new();
+        let mut console = ConsoleStream::new(&mut stream);
+        assert_eq!(console.finish().unwrap(), 0);
+        assert_eq!(stream.pos(), 0);
+    }
+
+    #[test]
+    fn it_should_buffer_single() {
+        let out = write_with(BufferStrategy::Single, &mut [0; 16]).unwrap();
+        assert_eq!(out, b"$0123456789#0d");
+    }
+
+    #[test]
+    fn it_should_fail_single_if_buffer_is_full() {
+        let err = write_with(BufferStrategy::Single, &mut [0; 8]).unwrap_err();
+        assert_eq!(err, Errors::MemoryFilledInterupt);
+    }
+
+    /// sends a half once it is finished, like a dma that is polled for completion
+    struct Dma<'a, 'o> {
+        in_flight: Option<(&'a mut [u8], usize)>,
+        sent: &'o mut Vec<Vec<u8>>,
+    }
+
+    impl<'a> Transfer<'a> for Dma<'a, '_> {
+        fn start(&mut self, buffer: &'a mut [u8], len: usize) -> Result<(), Errors> {
+            // only one half may be sent at a time
+            assert!(self.in_flight.is_none());
+            self.in_flight = Some((buffer, len));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<&'a mut [u8], Errors> {
+            let (buffer, len) = self.in_flight.take().unwrap();
+            self.sent.push(buffer[..len].to_vec());
+            Ok(buffer)
+        }
+    }
+
+    #[test]
+    fn it_should_buffer_double() {
+        let mut sent = Vec::new();
+        let mut buffer = [0; 8];
+        let mut dma = Dma {
+            in_flight: None,
+            sent: &mut sent,
+        };
+        let mut stream = SinkStream::with_transfer(&mut buffer, &mut dma);
+        assert_eq!(stream.len(), 4);
+        // the reply is longer than a half, flush waits for the last transfer
+        write_packet(&mut stream).unwrap();
+        stream.flush();
+        assert_eq!(stream.take_error(), None);
+        assert_eq!(sent, [&b"$012"[..], b"3456", b"789#", b"0d"]);
+
+        let out = write_with(BufferStrategy::Double, &mut [0; 4]).unwrap();
+        assert_eq!(out, b"$0123456789#0d");
+    }
+
+    #[test]
+    fn it_should_write_through() {
+        let out = write_with(BufferStrategy::WriteThrough, &mut []).unwrap();
+        assert_eq!(out, b"$0123456789#0d");
+
+        let mut sink = |_: &[u8]| Ok(());
+        let mut stream = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut sink);
+        write_packet(&mut stream).unwrap();
+        assert_eq!(stream.pos(), 14);
+    }
+
+    #[test]
+    fn it_should_pass_sink_errors_through() {
+        let mut sink = |_: &[u8]| Err(Errors::Disconnected);
+        let mut stream = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut sink);
+        assert_eq!(write_packet(&mut stream), Err(Errors::Disconnected));
+
+        let mut buffer = [0; 16];
+        let mut stream = SinkStream::new(BufferStrategy::Single, &mut buffer, &mut sink);
+        write_packet(&mut stream).unwrap();
+        stream.flush();
+        assert_eq!(stream.take_error(), Some(Errors::Disconnected));
+    }
+
+    struct SliceConnection<'a>(&'a [u8]);
+
+    impl Connection for SliceConnection<'_> {
+        fn read(&mut self) -> Result<Option<u8>, Errors> {
+            let byte = self.peek()?;
+            if byte.is_some() {
+                self.0 = &self.0[1..];
+            }
+            Ok(byte)
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, Errors> {
+            Ok(self.0.first().copied())
+        }
+
+        fn write_all(&mut self, _bytes: &[u8]) -> Result<(), Errors> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_read_available_bytes() {
+        let mut connection = SliceConnection(b"$?#3f");
+        let mut buffer = [0; 4];
+        assert_eq!(connection.read_slice(&mut buffer), Ok(4));
+        assert_eq!(buffer, *b"$?#3");
+        assert_eq!(connection.peek(), Ok(Some(b'f')));
+        assert_eq!(connection.read_slice(&mut buffer), Ok(1));
+        assert_eq!(connection.read_slice(&mut buffer), Ok(0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_should_connect_over_tcp() {
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server.set_nonblocking(true).unwrap();
+
+        assert_eq!(Connection::read(&mut server), Ok(None));
+        Connection::write_all(&mut client, b"+").unwrap();
+        Connection::flush(&mut client).unwrap();
+        while Connection::peek(&mut server) == Ok(None) {}
+        assert_eq!(Connection::read(&mut server), Ok(Some(b'+')));
+
+        drop(client);
+        while Connection::read(&mut server) == Ok(None) {}
+        assert_eq!(Connection::read(&mut server), Err(Errors::Disconnected));
+    }
+}
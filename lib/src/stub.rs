@@ -0,0 +1,1199 @@
+use super::assembler::{Frame, PacketAssembler, INTERRUPT};
+use super::basic::control::{write_halt, StoppedCommand};
+use super::command::{Command, Commands, ErrorReply, SupportedCommands};
+use super::error::Errors;
+use super::parser::{Parsed, Parser};
+use super::session::Session;
+#[cfg(feature = "monitor")]
+use super::stream::CONSOLE_FRAME_LEN;
+use super::stream::{
+    BufferStrategy, BufferedStream, Connection, GdbConsole, SinkStream, Stream, REPLY_LEN,
+};
+use super::target::Target;
+#[cfg(feature = "watchdog")]
+use super::watchdog::{Clock, Watchdog};
+use core::cell::{Cell, RefCell};
+
+/// the default command set
+pub struct DefaultCommands;
+impl<'a> SupportedCommands<'a> for DefaultCommands {}
+
+/// $, # and the two checksum digits around a payload
+const FRAMING: usize = 4;
+
+/// the PacketSize advertised in qSupported
+/// gdb limits both its packets and the replies it requests to it
+fn packet_size(buffer: &[u8], reply: usize) -> usize {
+    usize::min(buffer.len(), reply).saturating_sub(FRAMING)
+}
+
+/// the state of the session after the available input was handled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StubState {
+    /// waiting for more input from gdb
+    Idle,
+    /// gdb detached or killed the target
+    /// or the watchdog detached it after gdb was silent for too long
+    Detached,
+}
+
+/// Runs a debug session over a connection.
+/// It assembles packets, acknowledges them, applies the session state,
+/// sends the replies and watches for Ctrl-C while the target runs.
+/// Replies are buffered in N bytes, the g packet of large register files
+/// e.g. riscv:rv64 needs more than the default.
+pub struct GdbStub<'b, T: Target, C: Connection, const N: usize = REPLY_LEN> {
+    target: T,
+    connection: C,
+    assembler: PacketAssembler<'b>,
+    session: Session,
+    commands: &'b dyn for<'p> SupportedCommands<'p>,
+    /// the last reply, sent again if gdb answers with -
+    last: BufferedStream<N>,
+    strategy: BufferStrategy,
+    #[cfg(feature = "watchdog")]
+    watchdog: Option<(Watchdog, &'b dyn Clock)>,
+    /// true while the target runs in non-stop mode
+    running: bool,
+}
+
+impl<'b, T: Target, C: Connection> GdbStub<'b, T, C> {
+    /// packets larger than buffer are rejected
+    pub fn new(target: T, connection: C, buffer: &'b mut [u8]) -> Self {
+        Self::sized(target, connection, buffer)
+    }
+}
+
+impl<'b, T: Target, C: Connection, const N: usize> GdbStub<'b, T, C, N> {
+    /// like new with replies of up to N bytes
+    /// e.g. GdbStub::<_, _, 1024>::sized(target, connection, &mut buffer)
+    pub fn sized(target: T, connection: C, buffer: &'b mut [u8]) -> Self {
+        let session = Session::new().with_packet_size(packet_size(buffer, N));
+        Self {
+            target,
+            connection,
+            assembler: PacketAssembler::new(buffer),
+            session,
+            commands: &DefaultCommands,
+            last: BufferedStream::default(),
+            strategy: BufferStrategy::Single,
+            #[cfg(feature = "watchdog")]
+            watchdog: None,
+            running: false,
+        }
+    }
+
+    /// replaces the default command set
+    pub fn with_commands(mut self, commands: &'b dyn for<'p> SupportedCommands<'p>) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// selects how replies are buffered
+    /// with BufferStrategy::WriteThrough or Double only stop replies are limited to N bytes
+    /// Double hands every N bytes to Connection::write_all
+    pub fn with_strategy(mut self, strategy: BufferStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// removes the breakpoints and detaches once gdb was silent for timeout
+    /// milliseconds while the target halted, run then returns Detached
+    #[cfg(feature = "watchdog")]
+    pub fn with_watchdog(mut self, timeout: u64, clock: &'b dyn Clock) -> Self {
+        self.watchdog = Some((Watchdog::new(timeout, clock), clock));
+        self
+    }
+
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    pub fn target_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    pub fn connection_mut(&mut self) -> &mut C {
+        &mut self.connection
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// borrows the target and the connection at the same time
+    pub fn parts_mut(&mut self) -> (&mut T, &mut C) {
+        (&mut self.target, &mut self.connection)
+    }
+
+    /// releases the target and the connection
+    pub fn into_parts(self) -> (T, C) {
+        (self.target, self.connection)
+    }
+
+    /// handles all input that is available without blocking
+    /// call it from a superloop, the target is only resumed
+    /// while a continue or step is handled
+    /// in non-stop mode it keeps running and is polled on every call
+    pub fn run(&mut self) -> Result<StubState, Errors> {
+        if self.running {
+            self.running = poll_non_stop(&mut self.session, &mut self.target)?;
+        }
+        notify::<N>(&mut self.session, &mut self.target, &mut self.connection)?;
+        while let Some(byte) = self.connection.read()? {
+            self.watch(true)?;
+            let parsed = match frame(&mut self.assembler, self.commands, byte) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            let outcome = respond(
+                &mut self.session,
+                &mut self.target,
+                &mut self.connection,
+                &mut self.last,
+                parsed,
+                self.strategy,
+                true,
+            )?;
+            notify::<N>(&mut self.session, &mut self.target, &mut self.connection)?;
+            self.connection.flush()?;
+            // the time a blocking resume took is not silence
+            self.watch(true)?;
+            match outcome {
+                Outcome::Answered => (),
+                Outcome::Running => self.running = true,
+                Outcome::Detached => {
+                    self.assembler.reset();
+                    return Ok(StubState::Detached);
+                }
+            }
+        }
+        if self.watch(false)? {
+            self.assembler.reset();
+            return Ok(StubState::Detached);
+        }
+        Ok(StubState::Idle)
+    }
+
+    /// returns true if the watchdog detached the target
+    fn watch(&mut self, fed: bool) -> Result<bool, Errors> {
+        #[cfg(feature = "watchdog")]
+        let detached = watch(
+            &mut self.watchdog,
+            &mut self.session,
+            &mut self.target,
+            fed || self.running,
+        )?;
+        #[cfg(not(feature = "watchdog"))]
+        let detached = {
+            let _ = fed;
+            false
+        };
+        Ok(detached)
+    }
+
+    /// runs until gdb detaches or kills the target
+    /// fails with Errors::Disconnected if the connection is closed first
+    pub fn run_blocking(&mut self) -> Result<(), Errors> {
+        loop {
+            if self.run()? == StubState::Detached {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// what happened after a byte was fed to GdbStubStateMachine
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// a packet was answered
+    Answered,
+    /// the target runs, call poll until it halted
+    Resumed,
+    /// the target halted and the stop reply was sent
+    /// in non-stop mode it is sent as %Stop notification instead
+    Halted,
+    /// gdb detached or killed the target
+    Detached,
+    /// the reply could not be written
+    Error(Errors),
+}
+
+/// writes the replies of GdbStubStateMachine
+struct Output<'f, F: FnMut(&[u8])>(&'f mut F);
+
+impl<F: FnMut(&[u8])> Connection for Output<'_, F> {
+    fn read(&mut self) -> Result<Option<u8>, Errors> {
+        Ok(None)
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Errors> {
+        Ok(None)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+        (self.0)(bytes);
+        Ok(())
+    }
+}
+
+/// Runs a debug session from bytes handed over one at a time,
+/// e.g. from a uart receive interrupt. Replies are passed to output.
+/// Continue and step only start the target, poll it from the main loop
+/// until it halted. Ctrl-C interrupts the running target.
+/// In non-stop mode packets are answered while the target runs.
+/// Replies are buffered in N bytes as with GdbStub.
+pub struct GdbStubStateMachine<'b, T: Target, F: FnMut(&[u8]), const N: usize = REPLY_LEN> {
+    target: T,
+    output: F,
+    assembler: PacketAssembler<'b>,
+    session: Session,
+    commands: &'b dyn for<'p> SupportedCommands<'p>,
+    last: BufferedStream<N>,
+    strategy: BufferStrategy,
+    #[cfg(feature = "watchdog")]
+    watchdog: Option<(Watchdog, &'b dyn Clock)>,
+    running: bool,
+}
+
+impl<'b, T: Target, F: FnMut(&[u8])> GdbStubStateMachine<'b, T, F> {
+    /// packets larger than buffer are rejected
+    pub fn new(target: T, buffer: &'b mut [u8], output: F) -> Self {
+        Self::sized(target, buffer, output)
+    }
+}
+
+impl<'b, T: Target, F: FnMut(&[u8]), const N: usize> GdbStubStateMachine<'b, T, F, N> {
+    /// like new with replies of up to N bytes
+    pub fn sized(target: T, buffer: &'b mut [u8], output: F) -> Self {
+        let session = Session::new().with_packet_size(packet_size(buffer, N));
+        Self {
+            target,
+            output,
+            assembler: PacketAssembler::new(buffer),
+            session,
+            commands: &DefaultCommands,
+            last: BufferedStream::default(),
+            strategy: BufferStrategy::Single,
+            #[cfg(feature = "watchdog")]
+            watchdog: None,
+            running: false,
+        }
+    }
+
+    /// replaces the default command set
+    pub fn with_commands(mut self, commands: &'b dyn for<'p> SupportedCommands<'p>) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// selects how replies are buffered
+    /// with BufferStrategy::WriteThrough or Double only stop replies are limited to N bytes
+    /// Double hands every N bytes to Connection::write_all
+    pub fn with_strategy(mut self, strategy: BufferStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// removes the breakpoints and detaches once gdb was silent for timeout
+    /// milliseconds while the target halted, poll then returns Detached
+    #[cfg(feature = "watchdog")]
+    pub fn with_watchdog(mut self, timeout: u64, clock: &'b dyn Clock) -> Self {
+        self.watchdog = Some((Watchdog::new(timeout, clock), clock));
+        self
+    }
+
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    pub fn target_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// true while the target runs
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// releases the target and the output
+    pub fn into_parts(self) -> (T, F) {
+        (self.target, self.output)
+    }
+
+    /// handles a byte received from gdb
+    /// returns None while a packet is incomplete
+    pub fn incoming(&mut self, byte: u8) -> Option<Event> {
+        #[cfg(feature = "watchdog")]
+        let _ = watch(
+            &mut self.watchdog,
+            &mut self.session,
+            &mut self.target,
+            true,
+        );
+        let parsed = frame(&mut self.assembler, self.commands, byte)?;
+        let mut output = Output(&mut self.output);
+        if self.running && !self.session.non_stop() {
+            // gdb only sends Ctrl-C while the target runs
+            return match parsed.command {
+                Some(Commands::Interrupt(_)) => match self.target.interrupt() {
+                    Ok(_) => None,
+                    Err(err) => Some(Event::Error(err)),
+                },
+                _ => None,
+            };
+        }
+
+        let outcome = respond(
+            &mut self.session,
+            &mut self.target,
+            &mut output,
+            &mut self.last,
+            parsed,
+            self.strategy,
+            false,
+        )
+        .and_then(|outcome| {
+            notify::<N>(&mut self.session, &mut self.target, &mut output).map(|_| outcome)
+        });
+        match outcome {
+            Ok(Outcome::Answered) => Some(Event::Answered),
+            Ok(Outcome::Running) => {
+                self.running = true;
+                Some(Event::Resumed)
+            }
+            Ok(Outcome::Detached) => {
+                self.assembler.reset();
+                Some(Event::Detached)
+            }
+            Err(err) => Some(Event::Error(err)),
+        }
+    }
+
+    /// checks if the running target halted and sends the stop reply
+    /// returns None while the target runs or if it was not resumed
+    /// and Detached once the watchdog detached the halted target
+    pub fn poll(&mut self) -> Option<Event> {
+        #[cfg(feature = "watchdog")]
+        match watch(
+            &mut self.watchdog,
+            &mut self.session,
+            &mut self.target,
+            self.running,
+        ) {
+            Ok(true) => {
+                self.assembler.reset();
+                return Some(Event::Detached);
+            }
+            Ok(false) => (),
+            Err(err) => return Some(Event::Error(err)),
+        }
+        if !self.running {
+            return None;
+        }
+        if self.session.non_stop() {
+            let mut output = Output(&mut self.output);
+            let polled = poll_non_stop(&mut self.session, &mut self.target).and_then(|running| {
+                notify::<N>(&mut self.session, &mut self.target, &mut output).map(|_| running)
+            });
+            return match polled {
+                Ok(true) => None,
+                Ok(false) => {
+                    self.running = false;
+                    Some(Event::Halted)
+                }
+                Err(err) => Some(Event::Error(err)),
+            };
+        }
+        let result = match self.target.execution() {
+            Some(execution) => {
+                let mut output = Output(&mut self.output);
+                let mut sink = |bytes: &[u8]| output.write_all(bytes);
+                let mut console = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut sink);
+                execution
+                    .poll(&mut GdbConsole::new(&mut console))
+                    .transpose()?
+            }
+            None => Err(Errors::Unsupported),
+        };
+        self.running = false;
+
+        let mut reply = BufferedStream::<N>::default();
+        let mut output = Output(&mut self.output);
+        let sent = write_halt(&mut reply, &mut self.target, result)
+            .and_then(|_| send(&self.session, &mut output, &reply, &mut self.last));
+        match sent {
+            Ok(()) => Some(Event::Halted),
+            Err(err) => Some(Event::Error(err)),
+        }
+    }
+}
+
+/// how a packet was handled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+    Answered,
+    /// the target was started without waiting for it to halt
+    Running,
+    Detached,
+}
+
+/// pushes a byte into the assembler
+/// returns the packet once a frame that needs a reply is complete
+fn frame<'p>(
+    assembler: &'p mut PacketAssembler,
+    commands: &'p dyn for<'c> SupportedCommands<'c>,
+    byte: u8,
+) -> Option<Parsed<'p>> {
+    match assembler.push(byte)? {
+        Frame::Packet(_) => Some(Parser::new(assembler.packet()).parse_packet(commands)),
+        Frame::Interrupt => Some(Parser::new(&[INTERRUPT]).parse_packet(commands)),
+        Frame::Overflow => Some(PacketAssembler::overflow_reply()),
+        Frame::Nak => Some(Parser::new(b"-").parse_packet(commands)),
+        Frame::Ack => None,
+    }
+}
+
+/// feeds the watchdog or lets it detach once gdb was silent for too long
+/// a running target does not wait for gdb, so only halted time counts
+/// returns true if the target was detached
+#[cfg(feature = "watchdog")]
+fn watch(
+    watchdog: &mut Option<(Watchdog, &dyn Clock)>,
+    session: &mut Session,
+    target: &mut dyn Target,
+    fed: bool,
+) -> Result<bool, Errors> {
+    match watchdog {
+        Some((watchdog, clock)) if fed => {
+            watchdog.feed(*clock);
+            Ok(false)
+        }
+        Some((watchdog, clock)) => watchdog.poll(*clock, target, session.breakpoints_mut()),
+        None => Ok(false),
+    }
+}
+
+/// polls a target resumed in non-stop mode and queues its stop
+/// returns true while it runs
+/// gdb takes no O packets in non-stop mode, console output is dropped
+fn poll_non_stop(session: &mut Session, target: &mut dyn Target) -> Result<bool, Errors> {
+    let execution = target.execution().ok_or(Errors::Unsupported)?;
+    let mut discard = |_: &[u8]| Ok(());
+    let mut console = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut discard);
+    match execution.poll(&mut GdbConsole::new(&mut console))? {
+        Some(reason) => session.stop_queue().push(reason).map(|_| false),
+        None => Ok(true),
+    }
+}
+
+/// sends the oldest queued stop as %Stop notification in non-stop mode
+/// notifications are never retransmitted, so they are not kept in last
+fn notify<const N: usize>(
+    session: &mut Session,
+    target: &mut dyn Target,
+    connection: &mut dyn Connection,
+) -> Result<(), Errors> {
+    if !session.non_stop() {
+        return Ok(());
+    }
+    let mut notification = BufferedStream::<N>::default();
+    session.notify_stop(&mut notification, target)?;
+    if notification.pos() > 0 {
+        connection.write_all(&notification.buffer[..notification.pos()])?;
+    }
+    Ok(())
+}
+
+/// writes a reply to the connection and keeps it in last for retransmission
+fn send<const N: usize>(
+    session: &Session,
+    connection: &mut dyn Connection,
+    reply: &BufferedStream<N>,
+    last: &mut BufferedStream<N>,
+) -> Result<(), Errors> {
+    let reply = &reply.buffer[..reply.pos()];
+    last.reset();
+    #[cfg(feature = "compression")]
+    if session.compression().is_some() {
+        // a reply never grows beyond the reply buffer once compressed
+        let mut scratch = [0; N];
+        session.compress(reply, &mut scratch, last)?;
+        return connection.write_all(&last.buffer[..last.pos()]);
+    }
+    #[cfg(not(feature = "compression"))]
+    let _ = session;
+    last.write_all(reply)?;
+    connection.write_all(&last.buffer[..last.pos()])
+}
+
+/// true if the reply of a command is streamed to the connection
+/// compressed replies are prefixed with their length, so they are always buffered
+fn streams(strategy: BufferStrategy, session: &Session) -> bool {
+    #[cfg(feature = "compression")]
+    if session.compression().is_some() {
+        return false;
+    }
+    #[cfg(not(feature = "compression"))]
+    let _ = session;
+    strategy != BufferStrategy::Single
+}
+
+/// runs a command and streams its reply to the connection
+/// WriteThrough writes every byte, Double writes whenever buffer is full
+/// replies longer than the reply buffer e.g. large qXfer chunks still work,
+/// but a - from gdb can not be answered anymore.
+/// A command that fails after it sent a part of its reply
+/// leaves a broken packet behind, gdb discards it once the error reply starts
+fn stream(
+    strategy: BufferStrategy,
+    buffer: &mut [u8],
+    target: &mut dyn Target,
+    connection: &mut dyn Connection,
+    command: &mut Commands,
+) -> Result<(), Errors> {
+    let mut sink = |bytes: &[u8]| connection.write_all(bytes);
+    let mut reply = SinkStream::new(strategy, buffer, &mut sink);
+    if let Err(err) = command.response(&mut reply, target) {
+        // fails again if the connection itself was lost
+        ErrorReply::new(err).response(&mut reply, target)?;
+    }
+    reply.flush();
+    reply.take_error().map_or(Ok(()), Err)
+}
+
+/// acknowledges a packet, runs its command and sends the reply
+/// blocking waits for a resumed target to halt, reading Ctrl-C from the connection
+/// a - from gdb sends the last reply again
+fn respond<const N: usize>(
+    session: &mut Session,
+    target: &mut dyn Target,
+    connection: &mut dyn Connection,
+    last: &mut BufferedStream<N>,
+    parsed: Parsed,
+    strategy: BufferStrategy,
+    blocking: bool,
+) -> Result<Outcome, Errors> {
+    let parsed = session.apply(parsed);
+    let mut reply = BufferedStream::<N>::default();
+
+    // without acks the - is dropped by the session
+    if let Some(Commands::RetransmitLast) = parsed.response {
+        connection.write_all(&last.buffer[..last.pos()])?;
+        return Ok(Outcome::Answered);
+    }
+
+    // the returned sizes are not reliable, the stream position is
+    if let Some(mut response) = parsed.response {
+        response.response(&mut reply, target)?;
+        connection.write_all(&reply.buffer[..reply.pos()])?;
+        reply.reset();
+    }
+
+    let mut command = match parsed.command {
+        Some(command) => command,
+        None => return Ok(Outcome::Answered),
+    };
+    let mut outcome = Outcome::Answered;
+    let result = match command {
+        // the stop is reported with %Stop once the target halted
+        Commands::Resume(ref mut resume) if session.non_stop() => {
+            match resume.start_non_stop(&mut reply, target, session.stop_queue()) {
+                Ok(running) => {
+                    if running {
+                        outcome = Outcome::Running;
+                    }
+                    Ok(reply.pos())
+                }
+                Err(err) => Err(err),
+            }
+        }
+        // the target runs until it halts, gdb may interrupt it meanwhile
+        Commands::Resume(ref mut resume) if blocking => {
+            // the console writes O packets to gdb between the reads
+            let connection = RefCell::new(&mut *connection);
+            let lost = Cell::new(None);
+            let mut sink = |bytes: &[u8]| {
+                let written = connection.borrow_mut().write_all(bytes);
+                written.inspect_err(|err| lost.set(Some(*err)))
+            };
+            let mut console = SinkStream::new(BufferStrategy::WriteThrough, &mut [], &mut sink);
+            let mut input = || {
+                let read = connection.borrow_mut().read();
+                read.inspect_err(|err| lost.set(Some(*err)))
+            };
+            let result = resume.run(&mut reply, target, &mut input, &mut console);
+            // nobody is left to read the reply
+            if let Some(err) = lost.get() {
+                return Err(err);
+            }
+            result
+        }
+        Commands::Resume(ref mut resume) => match resume.start(&mut reply, target) {
+            Ok(false) => return Ok(Outcome::Running),
+            Ok(true) => Ok(reply.pos()),
+            Err(err) => Err(err),
+        },
+        Commands::Stopped(ref mut stopped) => {
+            let stop = session.next_stop(target);
+            stopped.reply(&mut reply, target, stop)
+        }
+        // gdb fetches the stops of all threads as with vStopped
+        Commands::Reason(_) if session.non_stop() => {
+            let stop = session.next_stop(target);
+            StoppedCommand::new(&[]).reply(&mut reply, target, stop)
+        }
+        // the output may exceed the reply buffer, each O packet is sent once written
+        #[cfg(feature = "monitor")]
+        Commands::Monitor(ref mut monitor) => {
+            let mut sink = |bytes: &[u8]| connection.write_all(bytes);
+            let mut buffer = [0; CONSOLE_FRAME_LEN];
+            let mut console = SinkStream::new(BufferStrategy::Single, &mut buffer, &mut sink);
+            let result = monitor.run(&mut reply, target, &mut console);
+            // nobody is left to read the reply
+            if let Some(err) = console.take_error() {
+                return Err(err);
+            }
+            result
+        }
+        // stop replies above are short, only the other replies are streamed
+        ref mut command if streams(strategy, session) => {
+            last.reset();
+            stream(strategy, &mut reply.buffer, target, connection, command)?;
+            Ok(0)
+        }
+        ref mut command => command.response(&mut reply, target),
+    };
+    if let Err(err) = result {
+        // commands fail before writing a complete reply
+        ErrorReply::new(err).response(&mut reply, target)?;
+    }
+    session.answered(&command);
+    if reply.pos() > 0 {
+        send(session, connection, &reply, last)?;
+    }
+    match command {
+        Commands::Detach(ref detach) if detach.detached() => Ok(Outcome::Detached),
+        // gdb stays connected in extended mode to run the program again
+        Commands::Kill(ref kill) if kill.killed() && !session.extended() => Ok(Outcome::Detached),
+        _ => Ok(outcome),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "arch")]
+    use crate::arch::riscv::RV64;
+    #[cfg(feature = "arch")]
+    use crate::arch::Arch;
+    #[cfg(feature = "watchdog")]
+    use crate::breakpoint::SwBreakpoints;
+    #[cfg(feature = "watchdog")]
+    use crate::memory::GuestAddress;
+    #[cfg(feature = "arch")]
+    use crate::register::RegisterValue;
+    use crate::resume::{Execution, ResumeActions};
+    use crate::signal::SIGINT;
+    use crate::stop::StopReason;
+    use crate::target::VirtualTarget;
+    use std::vec::Vec;
+
+    /// replays input and records the output
+    struct TestConnection {
+        input: Vec<u8>,
+        pos: usize,
+        output: Vec<u8>,
+    }
+
+    impl TestConnection {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: input.to_vec(),
+                pos: 0,
+                output: Vec::new(),
+            }
+        }
+
+        /// replaces the input once the previous one was read
+        fn feed(&mut self, input: &[u8]) {
+            self.input = input.to_vec();
+            self.pos = 0;
+        }
+    }
+
+    impl Connection for TestConnection {
+        fn read(&mut self) -> Result<Option<u8>, Errors> {
+            let byte = self.input.get(self.pos).copied();
+            self.pos += 1;
+            match byte {
+                Some(byte) => Ok(Some(byte)),
+                None if self.pos > self.input.len() + 1 => Err(Errors::Disconnected),
+                None => Ok(None),
+            }
+        }
+
+        fn peek(&mut self) -> Result<Option<u8>, Errors> {
+            Ok(self.input.get(self.pos).copied())
+        }
+
+        fn write_all(&mut self, bytes: &[u8]) -> Result<(), Errors> {
+            self.output.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    /// runs until interrupted
+    #[derive(Default)]
+    struct RunTarget {
+        running: bool,
+    }
+
+    impl Target for RunTarget {
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+
+        fn interrupt(&mut self) -> Result<usize, Errors> {
+            self.running = false;
+            Ok(0)
+        }
+
+        fn set_non_stop(&mut self, _enabled: bool) -> Result<usize, Errors> {
+            Ok(0)
+        }
+    }
+
+    impl Execution for RunTarget {
+        fn resume(&mut self, _actions: &ResumeActions) -> Result<StopReason, Errors> {
+            Err(Errors::Unsupported)
+        }
+
+        fn start(&mut self, _actions: &ResumeActions) -> Result<Option<StopReason>, Errors> {
+            self.running = true;
+            Ok(None)
+        }
+
+        fn poll(&mut self, _console: &mut GdbConsole) -> Result<Option<StopReason>, Errors> {
+            match self.running {
+                true => Ok(None),
+                false => Ok(Some(StopReason::Signal(SIGINT))),
+            }
+        }
+    }
+
+    /// prints once while running and halts on the next poll
+    #[derive(Default)]
+    struct PrintTarget {
+        printed: bool,
+    }
+
+    impl Target for PrintTarget {
+        fn execution(&mut self) -> Option<&mut dyn Execution> {
+            Some(self)
+        }
+    }
+
+    impl Execution for PrintTarget {
+        fn resume(&mut self, _actions: &ResumeActions) -> Result<StopReason, Errors> {
+            Err(Errors::Unsupported)
+        }
+
+        fn start(&mut self, _actions: &ResumeActions) -> Result<Option<StopReason>, Errors> {
+            self.printed = false;
+            Ok(None)
+        }
+
+        fn poll(&mut self, console: &mut GdbConsole) -> Result<Option<StopReason>, Errors> {
+            if self.printed {
+                return Ok(Some(StopReason::Signal(5)));
+            }
+            self.printed = true;
+            console.write_str("hi")?;
+            Ok(None)
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    struct TestClock(core::cell::Cell<u64>);
+
+    #[cfg(feature = "watchdog")]
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[derive(Default)]
+    struct WatchedTarget {
+        breakpoints: usize,
+        detached: bool,
+    }
+
+    #[cfg(feature = "watchdog")]
+    impl Target for WatchedTarget {
+        fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+            Some(self)
+        }
+
+        fn detach(&mut self, _pid: Option<usize>) -> Result<usize, Errors> {
+            self.detached = true;
+            Ok(0)
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    impl SwBreakpoints for WatchedTarget {
+        fn add_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            self.breakpoints += 1;
+            Ok(0)
+        }
+
+        fn remove_sw_breakpoint(
+            &mut self,
+            _addr: GuestAddress,
+            _kind: usize,
+        ) -> Result<usize, Errors> {
+            self.breakpoints -= 1;
+            Ok(0)
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn it_should_detach_once_gdb_is_silent() {
+        let clock = TestClock(core::cell::Cell::new(0));
+        let connection = TestConnection::new(b"$Z0,10,2#75");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(WatchedTarget::default(), connection, &mut buffer)
+            .with_watchdog(50, &clock);
+        assert_eq!(stub.run(), Ok(StubState::Idle));
+        assert_eq!(stub.target().breakpoints, 1);
+
+        clock.0.set(49);
+        stub.connection_mut().feed(b"");
+        assert_eq!(stub.run(), Ok(StubState::Idle));
+
+        clock.0.set(50);
+        stub.connection_mut().feed(b"");
+        assert_eq!(stub.run(), Ok(StubState::Detached));
+        assert_eq!(stub.target().breakpoints, 0);
+        assert!(stub.target().detached);
+    }
+
+    #[test]
+    fn it_should_answer_packets() {
+        let connection = TestConnection::new(b"+$?#3f+$m0,2#fb-$QStartNoAckMode#b0+$?#3f");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        assert_eq!(stub.run(), Ok(StubState::Idle));
+        assert!(stub.session().no_ack());
+        assert_eq!(
+            stub.connection_mut().output,
+            b"+$S05#b8+$0000#c0$0000#c0+$OK#9a$S05#b8"
+        );
+    }
+
+    #[cfg(feature = "arch")]
+    /// the 33 registers of riscv:rv64 take 528 hex digits
+    struct Rv64Target;
+
+    #[cfg(feature = "arch")]
+    impl Target for Rv64Target {
+        fn arch(&self) -> Option<&dyn Arch> {
+            Some(&RV64)
+        }
+
+        fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+            Ok(RegisterValue::U64(regno as u64))
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arch")]
+    fn it_should_reply_with_large_register_files() {
+        let connection = TestConnection::new(b"$qSupported#37+$g#67+");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::<_, _, 1024>::sized(Rv64Target, connection, &mut buffer);
+        stub.run().unwrap();
+
+        let output = &stub.connection_mut().output;
+        assert!(output.starts_with(b"+$PacketSize=3c;QStartNoAckMode+"));
+        let registers = &output[output.len() - (33 * 16 + 3)..output.len() - 3];
+        assert_eq!(registers[..32], b"00000000000000000100000000000000"[..]);
+        assert_eq!(registers[32 * 16..], b"2000000000000000"[..]);
+    }
+
+    #[test]
+    fn it_should_answer_through_a_telnet_bridge() {
+        use crate::sanitize::{TelnetConnection, TelnetFilter};
+
+        // the bridge negotiates options before and between packets
+        let input = b"\xff\xfb\x01\xff\xfb\x03$?#3f\xff\xfa\x2c\x01\x00\x01\xc2\x00\xff\xf0+";
+        let connection = TelnetConnection::new(TestConnection::new(input), TelnetFilter::default());
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        stub.run().unwrap();
+        assert_eq!(stub.connection_mut().inner_mut().output, b"+$S05#b8");
+    }
+
+    #[test]
+    #[cfg(feature = "arch")]
+    fn it_should_write_replies_through() {
+        let connection = TestConnection::new(b"$g#67-$?#3f");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::<_, _, 64>::sized(Rv64Target, connection, &mut buffer)
+            .with_strategy(BufferStrategy::WriteThrough);
+        stub.run().unwrap();
+
+        // the register file does not fit the reply buffer and is not sent again
+        let output = &stub.connection_mut().output;
+        assert_eq!(output.len(), 1 + 33 * 16 + 4 + 1 + 7);
+        assert!(output.ends_with(b"#d6+$S05#b8"));
+    }
+
+    #[test]
+    #[cfg(feature = "arch")]
+    fn it_should_write_double_buffered_replies() {
+        let connection = TestConnection::new(b"$g#67$?#3f");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::<_, _, 64>::sized(Rv64Target, connection, &mut buffer)
+            .with_strategy(BufferStrategy::Double);
+        stub.run().unwrap();
+
+        // the register file is sent in pieces of the reply buffer
+        let output = &stub.connection_mut().output;
+        assert_eq!(output.len(), 1 + 33 * 16 + 4 + 1 + 7);
+        assert!(output.ends_with(b"#d6+$S05#b8"));
+    }
+
+    /// prints more than a reply buffer holds
+    #[cfg(feature = "monitor")]
+    struct DumpTarget;
+
+    #[cfg(feature = "monitor")]
+    impl Target for DumpTarget {
+        fn monitor(&mut self, _cmd: &[u8], out: &mut dyn Stream) -> Result<usize, Errors> {
+            out.write_all(&[b'a'; 300])
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "monitor")]
+    fn it_should_send_long_monitor_output() {
+        let connection = TestConnection::new(b"$qRcmd,64756d70#fa");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::<_, _, 64>::sized(DumpTarget, connection, &mut buffer);
+        stub.run().unwrap();
+
+        // the output is split into O packets of at most CONSOLE_PACKET_LEN bytes
+        let output = &stub.connection_mut().output;
+        let packets: Vec<&[u8]> = output[1..].split(|b| *b == b'$').skip(1).collect();
+        assert_eq!(packets.len(), 6);
+        assert!(packets[..4]
+            .iter()
+            .all(|p| p.len() == CONSOLE_FRAME_LEN - 1));
+        assert_eq!(packets[4].len(), 2 * 44 + 4);
+        assert_eq!(packets[5], b"OK#9a");
+    }
+
+    #[test]
+    fn it_should_reply_to_malformed_packets() {
+        let connection = TestConnection::new(b"$mzz#61$m0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0#5d");
+        let mut buffer = [0; 16];
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        stub.run().unwrap();
+        assert_eq!(stub.connection_mut().output, b"+$E00#a5+$E00#a5");
+    }
+
+    #[test]
+    fn it_should_interrupt_a_running_target() {
+        let connection = TestConnection::new(b"$c#63\x03$D#44");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(RunTarget::default(), connection, &mut buffer);
+        assert_eq!(stub.run(), Ok(StubState::Detached));
+        assert_eq!(stub.connection_mut().output, b"+$S02#b5+$OK#9a");
+        assert!(!stub.target().running);
+    }
+
+    #[test]
+    fn it_should_print_while_the_target_runs() {
+        let connection = TestConnection::new(b"$c#63+$D#44");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(PrintTarget::default(), connection, &mut buffer);
+        assert_eq!(stub.run(), Ok(StubState::Detached));
+        assert_eq!(stub.connection_mut().output, b"+$O6869#2c$S05#b8+$OK#9a");
+    }
+
+    /// refuses to detach
+    struct KillTarget;
+
+    impl Target for KillTarget {
+        fn detach(&mut self, _pid: Option<usize>) -> Result<usize, Errors> {
+            Err(Errors::CommandError)
+        }
+
+        fn kill(&mut self, _pid: usize) -> Result<usize, Errors> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_only_end_the_session_once_detached_or_killed() {
+        let connection = TestConnection::new(b"$D#44$!#21$vKill;1#6e");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(KillTarget, connection, &mut buffer);
+        assert_eq!(stub.run(), Ok(StubState::Idle));
+        assert_eq!(stub.connection_mut().output, b"+$E00#a5+$OK#9a+$OK#9a");
+
+        let connection = TestConnection::new(b"$vKill;1#6e");
+        let mut stub = GdbStub::new(KillTarget, connection, &mut buffer);
+        assert_eq!(stub.run(), Ok(StubState::Detached));
+    }
+
+    #[test]
+    fn it_should_stop_waiting_once_disconnected() {
+        let connection = TestConnection::new(b"$c#63");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(RunTarget::default(), connection, &mut buffer);
+        assert_eq!(stub.run(), Err(Errors::Disconnected));
+        assert_eq!(stub.connection_mut().output, b"+");
+        assert!(stub.target().running);
+    }
+
+    #[test]
+    fn it_should_queue_stops_in_non_stop_mode() {
+        let connection = TestConnection::new(b"$QNonStop:1#8d$c#63$?#3f");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(RunTarget::default(), connection, &mut buffer);
+        stub.run().unwrap();
+        assert!(stub.session().non_stop());
+        assert!(stub.target().running);
+
+        // the target halts while gdb is idle
+        stub.target_mut().running = false;
+        stub.connection_mut().feed(b"$vStopped#55$vStopped#55");
+        stub.run().unwrap();
+        assert_eq!(
+            stub.connection_mut().output,
+            b"+$OK#9a+$OK#9a+$OK#9a%Stop:S02#95+$OK#9a+$OK#9a"
+        );
+        assert!(!stub.session().notified());
+    }
+
+    #[test]
+    fn it_should_answer_bytes_one_at_a_time() {
+        let mut output = Vec::new();
+        let mut buffer = [0; 64];
+        let mut stub =
+            GdbStubStateMachine::new(VirtualTarget::new(), &mut buffer, |bytes: &[u8]| {
+                output.extend_from_slice(bytes)
+            });
+        let events: Vec<Event> = b"+$?#3f+"
+            .iter()
+            .filter_map(|b| stub.incoming(*b))
+            .collect();
+        assert_eq!(events, [Event::Answered]);
+        assert_eq!(stub.poll(), None);
+        assert_eq!(output, b"+$S05#b8");
+    }
+
+    #[test]
+    fn it_should_poll_a_resumed_target() {
+        let mut output = Vec::new();
+        let mut buffer = [0; 64];
+        let mut stub =
+            GdbStubStateMachine::new(RunTarget::default(), &mut buffer, |bytes: &[u8]| {
+                output.extend_from_slice(bytes)
+            });
+        let events: Vec<Event> = b"$c#63".iter().filter_map(|b| stub.incoming(*b)).collect();
+        assert_eq!(events, [Event::Resumed]);
+        assert!(stub.running());
+        assert_eq!(stub.poll(), None);
+
+        assert_eq!(stub.incoming(INTERRUPT), None);
+        assert_eq!(stub.poll(), Some(Event::Halted));
+        assert!(!stub.running());
+
+        let events: Vec<Event> = b"$D#44".iter().filter_map(|b| stub.incoming(*b)).collect();
+        assert_eq!(events, [Event::Detached]);
+        assert_eq!(output, b"+$S02#b5+$OK#9a");
+    }
+
+    #[test]
+    fn it_should_print_while_polling_a_resumed_target() {
+        let mut output = Vec::new();
+        let mut buffer = [0; 64];
+        let mut stub =
+            GdbStubStateMachine::new(PrintTarget::default(), &mut buffer, |bytes: &[u8]| {
+                output.extend_from_slice(bytes)
+            });
+        let events: Vec<Event> = b"$c#63".iter().filter_map(|b| stub.incoming(*b)).collect();
+        assert_eq!(events, [Event::Resumed]);
+        assert_eq!(stub.poll(), None);
+        assert_eq!(stub.poll(), Some(Event::Halted));
+        assert_eq!(output, b"+$O6869#2c$S05#b8");
+    }
+
+    #[test]
+    fn it_should_answer_packets_while_running_in_non_stop_mode() {
+        let mut output = Vec::new();
+        let mut buffer = [0; 64];
+        let mut stub =
+            GdbStubStateMachine::new(RunTarget::default(), &mut buffer, |bytes: &[u8]| {
+                output.extend_from_slice(bytes)
+            });
+        let events: Vec<Event> = b"$QNonStop:1#8d$c#63$vStopped#55"
+            .iter()
+            .filter_map(|b| stub.incoming(*b))
+            .collect();
+        assert_eq!(events, [Event::Answered, Event::Resumed, Event::Answered]);
+        assert_eq!(stub.poll(), None);
+
+        stub.target_mut().running = false;
+        assert_eq!(stub.poll(), Some(Event::Halted));
+        assert!(!stub.running());
+        let events: Vec<Event> = b"$vStopped#55"
+            .iter()
+            .filter_map(|b| stub.incoming(*b))
+            .collect();
+        assert_eq!(events, [Event::Answered]);
+        assert_eq!(output, b"+$OK#9a+$OK#9a+$OK#9a%Stop:S02#95+$OK#9a");
+    }
+
+    #[test]
+    fn it_should_retransmit_the_last_reply() {
+        let connection = TestConnection::new(b"-$?#3f+-$m0,2#fb-");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        stub.run().unwrap();
+        assert_eq!(
+            stub.connection_mut().output,
+            b"+$S05#b8$S05#b8+$0000#c0$0000#c0"
+        );
+
+        let connection = TestConnection::new(b"$QStartNoAckMode#b0+$?#3f-");
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        stub.run().unwrap();
+        assert_eq!(stub.connection_mut().output, b"+$OK#9a$S05#b8");
+    }
+
+    #[test]
+    fn it_should_report_closed_connections() {
+        let connection = TestConnection::new(b"$?#3f");
+        let mut buffer = [0; 64];
+        let mut stub = GdbStub::new(VirtualTarget::new(), connection, &mut buffer);
+        assert_eq!(stub.run_blocking(), Err(Errors::Disconnected));
+        let (_, connection) = stub.into_parts();
+        assert_eq!(connection.output, b"+$S05#b8");
+    }
+}
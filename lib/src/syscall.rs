@@ -0,0 +1,95 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// ; separated list of hex syscall numbers
+/// sent by QCatchSyscalls
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SyscallList<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> SyscallList<'a> {
+    /// fails if any entry is not a hex number
+    pub fn parse(raw: &'a [u8]) -> Result<Self, Errors> {
+        let list = Self { raw };
+        for syscall in list.entries() {
+            Parser::from_hexu(syscall).ok_or(Errors::BadNumber)?;
+        }
+        Ok(list)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.raw.split(|b| *b == b';').filter(|s| !s.is_empty())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + 'a {
+        self.entries().filter_map(Parser::from_hexu)
+    }
+
+    pub fn contains(&self, syscall: usize) -> bool {
+        self.iter().any(|s| s == syscall)
+    }
+}
+
+/// which syscalls should stop the target
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CatchSyscalls<'a> {
+    Disabled,
+    All,
+    Only(SyscallList<'a>),
+}
+
+impl CatchSyscalls<'_> {
+    pub fn catches(&self, syscall: usize) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::All => true,
+            Self::Only(list) => list.contains(syscall),
+        }
+    }
+}
+
+/// a stop at a caught syscall
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyscallStop {
+    Entry(usize),
+    Return(usize),
+}
+
+impl SyscallStop {
+    /// writes the stop reply, e.g. T05syscall_entry:3f;
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let (kind, syscall): (&[u8], _) = match *self {
+            Self::Entry(syscall) => (b"T05syscall_entry:", syscall),
+            Self::Return(syscall) => (b"T05syscall_return:", syscall),
+        };
+        let mut size = stream.write_all(kind)?;
+        size += Parser::to_hex_number(syscall, stream)?;
+        size += stream.write(b';')?;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_parse_syscall_lists() {
+        let catch = CatchSyscalls::Only(SyscallList::parse(b"3f;e7").unwrap());
+        assert!(catch.catches(0x3f));
+        assert!(catch.catches(0xe7));
+        assert!(!catch.catches(0x01));
+        assert_eq!(SyscallList::parse(b"3f;xx"), Err(Errors::BadNumber));
+    }
+
+    #[test]
+    fn it_should_write_syscall_stops() {
+        let mut s = BufferedStream::new();
+        let size = SyscallStop::Return(0x3f).write(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"T05syscall_return:3f;"[..]);
+    }
+}
@@ -1,17 +1,93 @@
+use super::arch::{mips, Arch};
+use super::argv::Argv;
+use super::breakpoint::{HwBreakpoints, SwBreakpoints, Watchpoints};
+#[cfg(feature = "btrace")]
+use super::btrace::{BtraceCallback, BtraceRead};
+use super::check::Violation;
+use super::description::TargetDescription;
 use super::error::Errors;
+use super::features::GdbFeatures;
+#[cfg(feature = "fileio")]
+use super::fileio::{FileIoCall, FileIoReply};
+#[cfg(feature = "flash")]
+use super::flash::Flash;
+use super::host::ExitStatus;
+#[cfg(feature = "vfile")]
+use super::hostfs::HostFs;
+use super::library::{LibraryCallback, ModuleCallback};
+#[cfg(feature = "lldb")]
+use super::lldb::HostInfo;
+#[cfg(feature = "lldb")]
+use super::memory::Permissions;
+use super::memory::{self, GuestAddress, MemoryKind, MemoryRegion, PhysAddr};
+#[cfg(feature = "monitor")]
+use super::monitor::{self, MonitorEntry};
+use super::osdata::OsDataCallback;
 use super::parser::Parser;
+#[cfg(feature = "reverse")]
+use super::replay::Replay;
+use super::resume::Execution;
+use super::signal::{SignalSet, SIGTRAP};
+use super::step::NextPcs;
+use super::stop::{ProcessEvent, StopReason, ThreadExit};
 use super::stream::Stream;
+use super::syscall::{CatchSyscalls, SyscallStop};
 use crate::parser::Endianness;
+use crate::register::RegisterValue;
+use crate::thread::{ThreadCallback, ThreadId, ThreadInfo, ThreadOp, ThreadTarget};
+#[cfg(feature = "tracepoints")]
+use crate::tracepoint::TraceTarget;
 
 /// This is the cpu architecture specific
 /// This is the cpu architecture specific
 /// response data and io handling
 /// The target context should be cheap to clone!
 pub trait Target {
-    /// returns the halt reason
-    /// as a slice of bytes
-    fn reason(&self) -> &[u8] {
-        b"S05" // sigtrap
+    /// returns why the target halted
+    /// watchpoint hits carry the data address, StopReason::Stop
+    /// reports the thread along with the cause
+    fn stop_reason(&self) -> StopReason {
+        StopReason::Signal(SIGTRAP)
+    }
+
+    /// returns how the program ended once it exited
+    /// it is reported as W or X stop reply instead of the halt reason
+    fn exit_status(&self) -> Option<ExitStatus> {
+        None
+    }
+
+    /// true once all threads exited while the process is still attached
+    /// sent as N, only return true if gdb announced no-resumed
+    fn no_resumed(&self) -> bool {
+        false
+    }
+
+    /// receives the features gdb announced in qSupported
+    /// called before the stub replies with its own features
+    fn set_gdb_features(&mut self, _features: GdbFeatures) {}
+
+    /// returns the features passed to set_gdb_features
+    /// stop reply fields gdb did not announce are left out
+    fn gdb_features(&self) -> GdbFeatures {
+        GdbFeatures::default()
+    }
+
+    /// returns the thread that exited, reported instead of the stop reason
+    /// only used after QThreadEvents:1
+    fn thread_exit(&self) -> Option<ThreadExit> {
+        None
+    }
+
+    /// enables reporting thread creation with StopCause::Create
+    /// and thread exits with thread_exit
+    fn set_thread_events(&mut self, _enabled: bool) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_thread_events is implemented
+    /// advertises QThreadEvents in qSupported
+    fn thread_events(&self) -> bool {
+        false
     }
 
     fn rd_registers(&self, _stream: &mut dyn Stream) -> Result<usize, Errors> {
@@ -23,32 +99,521 @@ pub trait Target {
         Ok(0)
     }
 
-    /// reads memory
-    /// evil raw pointers are being used to represent the start address!
+    /// reads a single register
+    /// returns Unsupported to make gdb fall back to reading all registers
+    fn rd_register(&self, _regno: usize) -> Result<RegisterValue<'_>, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// writes a single register
+    fn wr_register(&mut self, _regno: usize, _value: RegisterValue) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// reads a control and status register of an arch that describes csrs,
+    /// e.g. mstatus of a RISC-V core
+    fn rd_csr(&self, _csr: usize) -> Result<RegisterValue<'_>, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// writes a control and status register
+    fn wr_csr(&mut self, _csr: usize, _value: RegisterValue) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// false if register regno is absent at runtime,
+    /// e.g. the fpu registers of a core that has none
+    /// g and p send it as x, G skips it and P fails
+    fn register_available(&self, _regno: usize) -> bool {
+        true
+    }
+
+    /// reads memory and writes it hex encoded to stream
     fn rd_memory(
         &self,
-        _start: *const u8,
+        _start: GuestAddress,
         _size: usize,
         _stream: &mut dyn Stream,
     ) -> Result<usize, Errors> {
         Ok(0)
     }
 
-    /// write to registers
-    /// evil raw pointers are being used to represent the start address!
-    fn wr_memory(&mut self, _start: *const u8, _data: &[u8]) -> Result<usize, Errors> {
+    /// writes hex encoded data to memory
+    fn wr_memory(&mut self, _start: GuestAddress, _data: &[u8]) -> Result<usize, Errors> {
         Ok(0)
     }
 
     fn endianess(&self) -> Endianness {
-        Endianness::Little
+        self.arch()
+            .map_or(Endianness::Little, |arch| arch.endianness())
+    }
+
+    /// returns the flash programmer used by the vFlash packets
+    #[cfg(feature = "flash")]
+    fn flash(&mut self) -> Option<&mut dyn Flash> {
+        None
+    }
+
+    /// switches between all-stop and non-stop mode
+    /// in non-stop mode only the thread that stopped is halted
+    fn set_non_stop(&mut self, _enabled: bool) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_non_stop is implemented
+    /// advertises QNonStop in qSupported
+    fn non_stop(&self) -> bool {
+        false
+    }
+
+    /// sets the signals that are passed to the program without stopping
+    fn set_pass_signals(&mut self, _signals: &SignalSet) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_pass_signals is implemented
+    /// advertises QPassSignals in qSupported
+    fn pass_signals(&self) -> bool {
+        false
+    }
+
+    /// sets the signals the program may receive at all
+    /// all others are discarded when they are passed on
+    fn set_program_signals(&mut self, _signals: &SignalSet) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_program_signals is implemented
+    /// advertises QProgramSignals in qSupported
+    fn program_signals(&self) -> bool {
+        false
+    }
+
+    /// selects the syscalls that stop the target
+    fn catch_syscalls(&mut self, _catch: CatchSyscalls) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if catch_syscalls is implemented
+    /// advertises QCatchSyscalls in qSupported
+    fn syscall_catching(&self) -> bool {
+        false
+    }
+
+    /// returns the fork, vfork or exec event the target halted at
+    /// it is sent to gdb instead of the stop reason
+    fn process_event(&self) -> Option<ProcessEvent<'_>> {
+        None
+    }
+
+    /// true if the target reports fork, vfork and exec events
+    /// advertised in qSupported
+    fn process_events(&self) -> bool {
+        false
+    }
+
+    /// returns the caught syscall the target halted at
+    /// it is sent to gdb instead of the stop reason
+    fn syscall_stop(&self) -> Option<SyscallStop> {
+        None
+    }
+
+    /// removes the oldest stop the target queued and returns it
+    /// in non-stop mode threads also stop while no resume is handled,
+    /// the stub reports them with %Stop and gdb fetches them with vStopped
+    /// returns None once all stops were reported
+    fn next_stop(&mut self) -> Option<StopReason> {
+        None
+    }
+
+    /// starts the program argv.get(0) with the remaining arguments
+    /// the program has to stop before its first instruction
+    /// only used in extended mode
+    /// targets that can only reset ignore argv and restart by default
+    fn run(&mut self, _argv: &Argv) -> Result<usize, Errors> {
+        self.restart()
+    }
+
+    /// prepares argc program arguments sent with an A packet
+    /// they replace the previous arguments
+    fn set_argc(&mut self, _argc: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the buffer argument index is decoded into
+    /// len is the decoded length, None rejects the argument
+    fn arg_buffer(&mut self, _index: usize, _len: usize) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// sets an environment variable for programs started by vRun
+    fn set_env(&mut self, _name: &[u8], _value: &[u8]) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    fn unset_env(&mut self, _name: &[u8]) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// restores the initial environment
+    fn reset_env(&mut self) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_env, unset_env and reset_env are implemented
+    /// advertises the QEnvironment packets in qSupported
+    fn environment(&self) -> bool {
+        false
+    }
+
+    /// restarts the current program from the beginning
+    /// called for R packets and by default for vRun
+    fn restart(&mut self) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if the target uses p<pid>.<tid> thread ids
+    /// advertises multiprocess+ in qSupported
+    fn multiprocess(&self) -> bool {
+        false
+    }
+
+    /// attaches to the running process or core pid and stops it
+    /// the stop is reported with the current reason
+    fn attach(&mut self, _pid: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the software breakpoints used by Z0
+    fn sw_breakpoints(&mut self) -> Option<&mut dyn SwBreakpoints> {
+        None
+    }
+
+    /// returns the hardware breakpoints used by Z1
+    fn hw_breakpoints(&mut self) -> Option<&mut dyn HwBreakpoints> {
+        None
+    }
+
+    /// returns the watchpoints used by Z2, Z3 and Z4
+    fn watchpoints(&mut self) -> Option<&mut dyn Watchpoints> {
+        None
+    }
+
+    /// returns the execution control used by c, s and vCont
+    fn execution(&mut self) -> Option<&mut dyn Execution> {
+        None
+    }
+
+    /// the addresses the instruction at pc may continue at,
+    /// e.g. from step::riscv::next_pcs_at_pc
+    /// step::software_step plants its temporary breakpoints there
+    fn next_pcs(&self) -> Result<NextPcs, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// steps cycles clock cycles, starting at addr if given
+    /// signal is delivered first, only used by simulators
+    fn cycle_step(
+        &mut self,
+        _signal: Option<u8>,
+        _addr: Option<usize>,
+        _cycles: usize,
+    ) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// terminates the process or core pid
+    /// called for vKill, an empty reply makes gdb fall back to k
+    fn kill(&mut self, _pid: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// halts the running target on Ctrl-C or vCtrlC
+    /// the stop reason should report SIGINT afterwards
+    fn interrupt(&mut self) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the host system call the halted target waits for
+    #[cfg(feature = "fileio")]
+    /// e.g. a semihosted printf
+    /// it is sent to gdb instead of the stop reason
+    fn file_io_request(&self) -> Option<FileIoCall> {
+        None
+    }
+
+    /// receives the result of the pending file_io_request
+    #[cfg(feature = "fileio")]
+    /// the target should resume and run until it stops again
+    /// or stop with SIGINT if the reply is interrupted
+    fn file_io_reply(&mut self, _reply: FileIoReply) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// ends the debug session
+    /// the target should remove all breakpoints and resume
+    /// with the multiprocess extension only process pid is detached
+    fn detach(&mut self, _pid: Option<usize>) -> Result<usize, Errors> {
+        Ok(0)
+    }
+
+    /// returns the name of the next symbol
+    /// gdb should look up for the target
+    /// or None if no more symbols are required
+    fn next_symbol(&mut self) -> Option<&[u8]> {
+        None
+    }
+
+    /// called with the address of a symbol requested by next_symbol
+    /// value is None if gdb could not resolve the symbol
+    fn symbol_value(&mut self, _name: &[u8], _value: Option<usize>) {}
+
+    /// returns a target description document
+    /// annex is the requested file name, starting with target.xml
+    /// returning a description for target.xml enables qXfer:features:read
+    fn features(&self, _annex: &[u8]) -> Option<&[u8]> {
+        None
+    }
+
+    /// the architecture whose register layout g, G, p and P use
+    /// without one the register packets are left to rd_registers and wr_registers
+    /// a preset from arch or a RegisterMap owned by the target
+    fn arch(&self) -> Option<&dyn Arch> {
+        None
+    }
+
+    /// describes the registers of the target
+    /// target.xml is generated from it unless features provides one
+    fn description(&self) -> Option<&TargetDescription<'_>> {
+        self.arch().map(|arch| arch.description())
+    }
+
+    /// describes the memory regions of the target
+    /// a non-empty map enables qXfer:memory-map:read
+    /// m and M outside of the map are answered with E14,
+    /// without permission with E13
+    fn memory_map(&self) -> &[MemoryRegion] {
+        &[]
+    }
+
+    /// translates a virtual address to the physical address
+    /// the memory hooks are called with, e.g. by walking the page tables
+    /// every memory access of the stub is translated, see memory::read_hex
+    /// None faults the access, the default maps addresses one to one
+    fn translate(&self, addr: GuestAddress) -> Option<PhysAddr> {
+        Some(addr)
+    }
+
+    /// accesses are translated in chunks of page_size bytes
+    /// None if the translation of a page start holds for the whole access
+    fn page_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// checks an access of len bytes at the physical addr before memory is touched
+    /// returns how many bytes from addr on may be accessed,
+    /// writes are only performed if all of them may be written
+    /// the default checks the memory map, targets can add their own rules
+    /// e.g. deny peripheral reads with side effects while running
+    fn check_memory_access(
+        &self,
+        addr: GuestAddress,
+        len: usize,
+        write: bool,
+    ) -> Result<usize, Errors> {
+        memory::check_access(self.memory_map(), addr, len, write)
+    }
+
+    /// returns the threads of the target
+    fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+        None
+    }
+
+    /// selects the thread for op, 0 picks any thread and -1 all of them
+    /// multi-core targets switch the core the register and memory hooks access
+    fn select_thread(&mut self, _op: ThreadOp, _thread: ThreadId) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// enumerates all modules loaded at runtime by calling f once per module
+    /// returning anything but Unsupported enables qXfer:libraries:read
+    /// report StopCause::Library after loading or unloading a module
+    fn libraries(&self, _f: &mut ModuleCallback) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// enumerates all loaded shared libraries by calling f once per library
+    /// returning anything but Unsupported enables qXfer:libraries-svr4:read
+    fn libraries_svr4(&self, _f: &mut LibraryCallback) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// starts or stops recording branches for Qbtrace:bts and Qbtrace:off
+    #[cfg(feature = "btrace")]
+    fn set_btrace(&mut self, _enabled: bool) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true if set_btrace is implemented
+    #[cfg(feature = "btrace")]
+    /// advertises Qbtrace:bts and Qbtrace:off in qSupported
+    fn branch_tracing(&self) -> bool {
+        false
+    }
+
+    /// enumerates the recorded branch trace by calling f once per block
+    #[cfg(feature = "btrace")]
+    /// returning anything but Unsupported for All enables qXfer:btrace:read
+    fn btrace(&self, _read: BtraceRead, _f: &mut BtraceCallback) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the address of the thread local variable at offset
+    /// in the TLS block of the object with the link map at lm
+    fn tls_address(&self, _thread: ThreadId, _offset: usize, _lm: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the auxiliary vector of the debugged process
+    /// in target byte order
+    /// returning Some enables qXfer:auxv:read
+    fn auxv(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// returns the path of the executable
+    /// of process pid or of the current process if pid is None
+    /// returning Some for None enables qXfer:exec-file:read
+    fn exec_file(&self, _pid: Option<usize>) -> Option<&[u8]> {
+        None
+    }
+
+    /// writes the rows of the os data table annex by calling f once per row
+    /// the empty annex lists the available tables with Type and Description columns
+    /// returning anything but Unsupported for it enables qXfer:osdata:read
+    fn osdata(&self, _annex: &[u8], _f: &mut OsDataCallback) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the file system gdb accesses with vFile packets
+    #[cfg(feature = "vfile")]
+    fn host_fs(&mut self) -> Option<&mut dyn HostFs> {
+        None
+    }
+
+    /// returns the recording used for bc and bs
+    #[cfg(feature = "reverse")]
+    fn replay(&mut self) -> Option<&mut dyn Replay> {
+        None
+    }
+
+    /// returns the tracepoints for the QT packets
+    #[cfg(feature = "tracepoints")]
+    fn tracepoints(&mut self) -> Option<&mut dyn TraceTarget> {
+        None
+    }
+
+    /// returns the siginfo of the last stop in the target's layout
+    /// e.g. fault address and status registers
+    /// returning Some enables qXfer:siginfo:read
+    fn siginfo(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// overwrites part of the siginfo starting at offset
+    /// returns the amount of bytes written
+    /// it is called with empty data to detect support
+    /// returning anything but Unsupported enables qXfer:siginfo:write
+    fn wr_siginfo(&mut self, _offset: usize, _data: &[u8]) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// returns the memory snapshot taken when the target stopped
+    /// see memory::Snapshot
+    fn snapshot(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// called in debug builds when the stub detects
+    /// an inconsistency in the data the target provided
+    fn violation(&self, _violation: Violation) {}
+
+    /// allocates size bytes of scratch memory for lldb's _M
+    /// see ScratchAllocator for a simple implementation
+    #[cfg(feature = "lldb")]
+    fn allocate(&mut self, _size: usize, _perms: Permissions) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// frees memory returned by allocate for lldb's _m
+    #[cfg(feature = "lldb")]
+    fn deallocate(&mut self, _addr: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// saves all registers before lldb calls a function
+    /// returns an id for restore_registers
+    #[cfg(feature = "lldb")]
+    fn save_registers(&mut self) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// restores and drops the registers saved with id
+    #[cfg(feature = "lldb")]
+    fn restore_registers(&mut self, _id: usize) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// enables QListThreadsInStopReply
+    /// it is called with false to detect support
+    #[cfg(feature = "lldb")]
+    fn set_threads_in_stop_reply(&mut self, _enabled: bool) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// true once set_threads_in_stop_reply was enabled
+    /// stop replies then list all threads and, if every thread has one, their pcs
+    #[cfg(feature = "lldb")]
+    fn threads_in_stop_reply(&self) -> bool {
+        false
+    }
+
+    /// describes the target for lldb
+    #[cfg(feature = "lldb")]
+    fn host_info(&self) -> Option<HostInfo<'_>> {
+        None
+    }
+
+    /// runs a monitor command
+    /// anything written to out is shown on the gdb console
+    /// returns Unsupported for unknown commands
+    fn monitor(&mut self, _cmd: &[u8], _out: &mut dyn Stream) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
     }
 }
 
+const VIRTUAL_MEMORY_MAP: [MemoryRegion; 1] = [MemoryRegion::new(0, 512, MemoryKind::Ram)];
+
+#[cfg(feature = "monitor")]
+const VIRTUAL_MONITOR: [MonitorEntry<VirtualTarget>; 2] = [
+    MonitorEntry::new(
+        b"reset",
+        b"resets registers and memory",
+        |target, _, out| {
+            *target = VirtualTarget::new();
+            out.write_all(b"target reset\n")
+        },
+    ),
+    // the virtual target never runs
+    MonitorEntry::new(b"halt", b"halts the target", |_, _, _| Ok(0)),
+];
+
+const MIPS_REGISTERS: usize = mips::PC_REGNO + 1;
+
 /// This is a demo implementation
 /// simulating a mips cpu
 pub struct VirtualTarget {
-    registers: [u32; 38],
+    registers: [u32; MIPS_REGISTERS],
     memory: [u8; 512],
 }
 
@@ -63,7 +628,7 @@ impl VirtualTarget {
         let mut registers = [(1_u32).to_be(); 38];
 
         // set PC to reset vector
-        registers[37] = (0xBFC00000_u32).to_be();
+        registers[mips::PC_REGNO] = (0xBFC00000_u32).to_be();
         Self {
             memory: [0; 512],
             registers,
@@ -72,42 +637,38 @@ impl VirtualTarget {
 }
 
 impl Target for VirtualTarget {
-    fn endianess(&self) -> Endianness {
-        Endianness::Big
+    fn arch(&self) -> Option<&dyn Arch> {
+        Some(&mips::MIPS32_BE)
     }
 
-    fn rd_registers(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
-        let stream_start = stream.pos();
-        for reg in self.registers {
-            Parser::to_hexu(&reg.to_be_bytes(), stream)?;
+    fn rd_register(&self, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+        match self.registers.get(regno) {
+            Some(reg) => Ok(RegisterValue::U32(*reg)),
+            None => Err(Errors::InvalidRegister),
         }
-        Ok(stream.pos() - stream_start)
     }
 
-    fn wr_registers(&mut self, data: &[u8]) -> Result<usize, Errors> {
-        // is there enough data?
-        if data.len() % 8 != 0 || data.len() != self.registers.len() * 8 {
-            Err(Errors::CommandError)
-        } else {
-            let c = data.chunks(8);
-            for (i, bytes) in c.enumerate() {
-                match Parser::from_hexu(bytes) {
-                    Some(value) => self.registers[i] = value as u32,
-                    _ => return Err(Errors::CommandError),
-                }
+    fn wr_register(&mut self, regno: usize, value: RegisterValue) -> Result<usize, Errors> {
+        match (self.registers.get_mut(regno), value) {
+            (Some(reg), RegisterValue::U32(value)) => {
+                *reg = value;
+                Ok(0)
             }
-            Ok(0)
+            (None, _) => Err(Errors::InvalidRegister),
+            _ => Err(Errors::LengthMismatch),
         }
     }
 
     fn rd_memory(
         &self,
-        start: *const u8,
+        start: GuestAddress,
         size: usize,
         stream: &mut dyn Stream,
     ) -> Result<usize, Errors> {
-        let start = usize::min(start as usize, self.memory.len()); // for virtual target don't use real pointers
-        let end = usize::min(start as usize + size, self.memory.len());
+        let start = usize::try_from(start).map_or(self.memory.len(), |start| {
+            usize::min(start, self.memory.len())
+        });
+        let end = usize::min(start + size, self.memory.len());
 
         let stream_start = stream.pos();
         for byte in self.memory[start..end].iter() {
@@ -116,8 +677,8 @@ impl Target for VirtualTarget {
         Ok(stream.pos() - stream_start)
     }
 
-    fn wr_memory(&mut self, start: *const u8, data: &[u8]) -> Result<usize, Errors> {
-        let start = start as usize; // for virtual target don't use real pointers
+    fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+        let start = usize::try_from(start).map_err(|_| Errors::AddressOutOfRange)?;
         let end = start + data.len();
 
         if start >= self.memory.len() || end >= self.memory.len() {
@@ -134,6 +695,30 @@ impl Target for VirtualTarget {
             Ok(0)
         }
     }
+
+    fn restart(&mut self) -> Result<usize, Errors> {
+        *self = VirtualTarget::new();
+        Ok(0)
+    }
+
+    fn memory_map(&self) -> &[MemoryRegion] {
+        &VIRTUAL_MEMORY_MAP
+    }
+
+    fn thread_target(&self) -> Option<&dyn ThreadTarget> {
+        Some(self)
+    }
+
+    #[cfg(feature = "monitor")]
+    fn monitor(&mut self, cmd: &[u8], out: &mut dyn Stream) -> Result<usize, Errors> {
+        monitor::dispatch(self, &VIRTUAL_MONITOR, cmd, out)
+    }
+}
+
+impl ThreadTarget for VirtualTarget {
+    fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors> {
+        f(ThreadInfo::new(1).with_core(0).with_name(b"main"))
+    }
 }
 
 #[cfg(test)]
@@ -150,7 +735,7 @@ mod tests {
     fn exec_packet(
         result: &mut Parsed,
         rstream: &mut BufferedStream,
-        target: &mut VirtualTarget,
+        target: &mut dyn Target,
     ) -> Result<usize, Errors> {
         assert_ne!(result.response, None);
         assert_ne!(result.command, None);
@@ -163,7 +748,7 @@ mod tests {
             size += command.response(rstream, target)?;
         }
 
-        return Ok(size);
+        Ok(size)
     }
 
     #[test]
@@ -206,6 +791,147 @@ mod tests {
         assert_eq!(rstream.buffer[..rstream.pos()], b"$00000000#80"[..]);
     }
 
+    #[test]
+    fn it_should_fault_unmapped_memory() {
+        let mut target = VirtualTarget::new();
+        let mut rstream = BufferedStream::new();
+
+        let mut parser = Parser::new(b"$m200,4#5f");
+        let mut result = parser.parse_packet(&DebugCommands);
+        exec_packet(&mut result, &mut rstream, &mut target).unwrap();
+        assert_eq!(rstream.buffer[..rstream.pos()], b"$E14#aa"[..]);
+
+        let mut parser = Parser::new(b"$M1fe,4:01020304#6d");
+        let mut result = parser.parse_packet(&DebugCommands);
+        exec_packet(&mut result, &mut rstream, &mut target).unwrap();
+        assert_eq!(rstream.buffer[..rstream.pos()], b"$E14#aa"[..]);
+    }
+
+    // flash is read-only, the uart data register clears on reads
+    const RULE_MAP: [MemoryRegion; 2] = [
+        MemoryRegion::new(0, 0x100, MemoryKind::Flash { block_size: 0x10 }),
+        MemoryRegion::new(0x100, 0x10, MemoryKind::Ram),
+    ];
+
+    struct RuleTarget {
+        memory: VirtualTarget,
+        running: bool,
+    }
+
+    impl Target for RuleTarget {
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            self.memory.rd_memory(start, size, stream)
+        }
+
+        fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+            self.memory.wr_memory(start, data)
+        }
+
+        fn memory_map(&self) -> &[MemoryRegion] {
+            &RULE_MAP
+        }
+
+        fn check_memory_access(
+            &self,
+            addr: GuestAddress,
+            len: usize,
+            write: bool,
+        ) -> Result<usize, Errors> {
+            let len = memory::check_access(self.memory_map(), addr, len, write)?;
+            match self.running && addr >= 0x100 {
+                true => Err(Errors::AccessDenied),
+                false => Ok(len),
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_apply_access_rules() {
+        let mut target = RuleTarget {
+            memory: VirtualTarget::new(),
+            running: false,
+        };
+        let mut rstream = BufferedStream::new();
+        let mut exec = |packet: &[u8], target: &mut RuleTarget| {
+            rstream.reset();
+            let mut result = Parser::new(packet).parse_packet(&DebugCommands);
+            exec_packet(&mut result, &mut rstream, target).unwrap();
+            rstream.buffer[..rstream.pos()].to_vec()
+        };
+
+        assert_eq!(exec(b"$M10,2:0102#09", &mut target), b"$E13#a9");
+        assert_eq!(exec(b"$M100,2:0102#39", &mut target), b"$OK#9a");
+        assert_eq!(exec(b"$m100,2#5c", &mut target), b"$0102#c3");
+
+        target.running = true;
+        assert_eq!(exec(b"$m100,2#5c", &mut target), b"$E13#a9");
+        assert_eq!(exec(b"$m10,2#2c", &mut target), b"$0000#c0");
+    }
+
+    // two pages of 0x40 bytes at 0x1000, mapped in reverse order
+    struct PagedTarget {
+        memory: VirtualTarget,
+    }
+
+    impl Target for PagedTarget {
+        fn rd_memory(
+            &self,
+            start: GuestAddress,
+            size: usize,
+            stream: &mut dyn Stream,
+        ) -> Result<usize, Errors> {
+            self.memory.rd_memory(start, size, stream)
+        }
+
+        fn wr_memory(&mut self, start: GuestAddress, data: &[u8]) -> Result<usize, Errors> {
+            self.memory.wr_memory(start, data)
+        }
+
+        fn translate(&self, addr: GuestAddress) -> Option<PhysAddr> {
+            match addr {
+                0x1000..=0x103f => Some(addr - 0x1000 + 0x40),
+                0x1040..=0x107f => Some(addr - 0x1040),
+                _ => None,
+            }
+        }
+
+        fn page_size(&self) -> Option<usize> {
+            Some(0x40)
+        }
+    }
+
+    #[test]
+    fn it_should_translate_addresses() {
+        let mut target = PagedTarget {
+            memory: VirtualTarget::new(),
+        };
+        let mut rstream = BufferedStream::new();
+        let mut exec = |packet: &[u8], target: &mut PagedTarget| {
+            rstream.reset();
+            let mut result = Parser::new(packet).parse_packet(&DebugCommands);
+            exec_packet(&mut result, &mut rstream, target).unwrap();
+            rstream.buffer[..rstream.pos()].to_vec()
+        };
+
+        assert_eq!(exec(b"$M103e,4:01020304#6a", &mut target), b"$OK#9a");
+        let mut stream = BufferedStream::new();
+        target.memory.rd_memory(0x7e, 2, &mut stream).unwrap();
+        target.memory.rd_memory(0, 2, &mut stream).unwrap();
+        assert_eq!(stream.buffer[..stream.pos()], b"01020304"[..]);
+        assert_eq!(exec(b"$m103e,4#c6", &mut target), b"$01020304#8a");
+
+        // the page after 0x107f is not mapped
+        assert_eq!(exec(b"$m107e,4#ca", &mut target), b"$0000#c0");
+        assert_eq!(exec(b"$M107e,4:05060708#7e", &mut target), b"$E14#aa");
+        assert_eq!(exec(b"$m103e,2#c4", &mut target), b"$0102#c3");
+        assert_eq!(exec(b"$m2000,2#8d", &mut target), b"$E14#aa");
+    }
+
     #[test]
     fn it_should_read_partial_memory() {
         let mut target = VirtualTarget::new();
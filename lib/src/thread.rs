@@ -0,0 +1,368 @@
+use super::error::Errors;
+use super::memory::{self, GuestAddress};
+use super::parser::Parser;
+use super::register::RegisterValue;
+use super::stream::Stream;
+use super::target::Target;
+use super::xml;
+
+/// -1, selects every thread or process
+pub const ALL_THREADS: usize = usize::MAX;
+/// 0, selects an arbitrary thread or process
+pub const ANY_THREAD: usize = 0;
+
+/// the operations H selects a thread for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ThreadOp {
+    /// Hg, register and memory accesses
+    General,
+    /// Hc, c and s, vCont names its threads instead
+    Continue,
+}
+
+/// A thread id.
+/// With the multiprocess extension it is written as p<pid>.<tid>,
+/// otherwise only the thread is sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ThreadId {
+    pub pid: Option<usize>,
+    pub tid: usize,
+}
+
+impl ThreadId {
+    pub const fn new(tid: usize) -> Self {
+        Self { pid: None, tid }
+    }
+
+    pub const fn with_pid(mut self, pid: usize) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    fn parse_id(id: &[u8]) -> Option<usize> {
+        match id {
+            b"" => None,
+            b"-1" => Some(ALL_THREADS),
+            _ => Parser::from_hexu(id),
+        }
+    }
+
+    fn write_id(id: usize, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        match id {
+            ALL_THREADS => stream.write_all(b"-1"),
+            _ => Parser::to_hex_number(id, stream),
+        }
+    }
+
+    /// parses tid, p<pid> or p<pid>.<tid>
+    /// a process without thread selects all of its threads
+    pub fn parse(id: &[u8]) -> Option<Self> {
+        let process = match id.strip_prefix(b"p") {
+            Some(process) => process,
+            None => return Self::parse_id(id).map(Self::new),
+        };
+        let mut parts = process.splitn(2, |b| *b == b'.');
+        let pid = Self::parse_id(parts.next()?)?;
+        let tid = match parts.next() {
+            Some(tid) => Self::parse_id(tid)?,
+            None => ALL_THREADS,
+        };
+        Some(Self::new(tid).with_pid(pid))
+    }
+
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = 0;
+        if let Some(pid) = self.pid {
+            size += stream.write(b'p')?;
+            size += Self::write_id(pid, stream)?;
+            size += stream.write(b'.')?;
+        }
+        size += Self::write_id(self.tid, stream)?;
+        Ok(size)
+    }
+}
+
+/// describes a single thread of execution
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ThreadInfo<'a> {
+    pub id: usize,
+    /// the process of the thread, written with the multiprocess extension
+    pub pid: Option<usize>,
+    pub core: Option<usize>,
+    pub name: Option<&'a [u8]>,
+    /// opaque handle, e.g. the address of a task control block
+    pub handle: Option<&'a [u8]>,
+    /// program counter, listed in lldb stop replies
+    pub pc: Option<usize>,
+    /// state shown by info threads, e.g. Blocked, falls back to name
+    pub extra: Option<&'a [u8]>,
+}
+
+impl<'a> ThreadInfo<'a> {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            pid: None,
+            core: None,
+            name: None,
+            handle: None,
+            pc: None,
+            extra: None,
+        }
+    }
+
+    pub fn with_pid(mut self, pid: usize) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    pub fn with_core(mut self, core: usize) -> Self {
+        self.core = Some(core);
+        self
+    }
+
+    pub fn with_name(mut self, name: &'a [u8]) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn with_handle(mut self, handle: &'a [u8]) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    pub fn with_pc(mut self, pc: usize) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    pub fn with_extra(mut self, extra: &'a [u8]) -> Self {
+        self.extra = Some(extra);
+        self
+    }
+
+    pub fn thread_id(&self) -> ThreadId {
+        ThreadId {
+            pid: self.pid,
+            tid: self.id,
+        }
+    }
+
+    /// writes the thread as an element of the threads xml document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(b"<thread")?;
+        size += stream.write_all(b" id=\"")?;
+        size += self.thread_id().write(stream)?;
+        size += stream.write(b'"')?;
+        // gdb reads the core in decimal, unlike the core in stop replies
+        if let Some(core) = self.core {
+            size += xml::write_attr_decimal(stream, b"core", core)?;
+        }
+        if let Some(name) = self.name {
+            size += xml::write_attr(stream, b"name", name)?;
+        }
+        if let Some(handle) = self.handle {
+            size += stream.write_all(b" handle=\"")?;
+            Parser::to_hexu(handle, stream)?;
+            size += handle.len() * 2;
+            size += stream.write(b'"')?;
+        }
+        size += stream.write_all(b"/>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each thread during enumeration
+pub type ThreadCallback<'a> = dyn FnMut(ThreadInfo) -> Result<usize, Errors> + 'a;
+
+/// Thread awareness for targets running more than one thread.
+/// Supporting it enables qXfer:threads:read, qfThreadInfo, qC, T
+/// and qThreadExtraInfo.
+/// Only the current thread runs on the cpu, the registers of all
+/// other threads are read from the context saved by the scheduler.
+pub trait ThreadTarget {
+    /// enumerates all threads by calling f once per thread
+    fn threads(&self, f: &mut ThreadCallback) -> Result<usize, Errors>;
+
+    /// the thread running on the cpu, its registers are read with rd_register
+    fn current_thread(&self) -> Option<usize> {
+        None
+    }
+
+    /// the thread selected with Hg, stored by Target::select_thread
+    fn selected_thread(&self) -> Option<usize> {
+        None
+    }
+
+    /// reads a register of a suspended thread from its saved context
+    fn rd_thread_register(
+        &self,
+        _thread: usize,
+        _regno: usize,
+    ) -> Result<RegisterValue<'_>, Errors> {
+        Err(Errors::Unsupported)
+    }
+}
+
+/// Registers a scheduler saves on the stack of a thread it switches out.
+/// Slots count from the saved top of stack upwards.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackFrame<'a> {
+    /// the slot of each register by gdb register number, None if not saved
+    pub slots: &'a [Option<usize>],
+    /// size of a slot in bytes
+    pub slot_size: usize,
+    /// register number of the stack pointer
+    /// it is restored to the top of stack past the frame
+    pub sp: usize,
+    /// amount of slots pushed by the context switch
+    pub len: usize,
+}
+
+impl<'a> StackFrame<'a> {
+    /// reads register regno of a thread with saved top of stack top
+    pub fn rd_register(
+        &self,
+        ctx: &dyn Target,
+        top: GuestAddress,
+        regno: usize,
+    ) -> Result<RegisterValue<'static>, Errors> {
+        let value = match self.slots.get(regno).copied().flatten() {
+            _ if regno == self.sp => top as u128 + (self.len * self.slot_size) as u128,
+            Some(slot) => memory::read_word(
+                ctx,
+                top + (slot * self.slot_size) as GuestAddress,
+                self.slot_size,
+            )?,
+            None => return Err(Errors::InvalidRegister),
+        };
+        match self.slot_size {
+            8 => Ok(RegisterValue::U64(value as u64)),
+            _ => Ok(RegisterValue::U32(value as u32)),
+        }
+    }
+}
+
+/// the selected thread if it is not the one running on the cpu
+/// Hg0 and Hg-1 select the current thread, gdb sends Hg0 on every connect
+pub fn suspended_thread(ctx: &dyn Target) -> Option<(&dyn ThreadTarget, usize)> {
+    let threads = ctx.thread_target()?;
+    let selected = match threads.selected_thread()? {
+        ANY_THREAD | ALL_THREADS => return None,
+        selected => selected,
+    };
+    match threads.current_thread() {
+        Some(current) if current == selected => None,
+        _ => Some((threads, selected)),
+    }
+}
+
+/// reads a register of the selected thread
+/// suspended threads are read from their saved context
+pub fn rd_register(ctx: &dyn Target, regno: usize) -> Result<RegisterValue<'_>, Errors> {
+    match suspended_thread(ctx) {
+        Some((threads, thread)) => threads.rd_thread_register(thread, regno),
+        None => ctx.rd_register(regno),
+    }
+}
+
+/// finds a thread by id, pid wildcards match any process
+pub fn find_thread(
+    threads: &dyn ThreadTarget,
+    id: ThreadId,
+    f: &mut dyn FnMut(ThreadInfo) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut found = false;
+    threads
+        .threads(&mut |thread| {
+            let pid = match (id.pid, thread.pid) {
+                (Some(pid), Some(thread)) => pid == ALL_THREADS || pid == thread,
+                _ => true,
+            };
+            if found || !pid || thread.id != id.tid {
+                return Ok(0);
+            }
+            found = true;
+            f(thread)
+        })
+        .and_then(|size| match found {
+            true => Ok(size),
+            false => Err(Errors::CommandError),
+        })
+}
+
+/// writes the threads xml document
+/// threads is expected to call the callback once per thread
+pub fn write_thread_list(
+    stream: &mut dyn Stream,
+    threads: &mut dyn FnMut(&mut ThreadCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(b"<?xml version=\"1.0\"?><threads>")?;
+    size += threads(&mut |thread| thread.write_xml(stream))?;
+    size += stream.write_all(b"</threads>")?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_write_threads() {
+        let mut s = BufferedStream::new();
+        ThreadInfo::new(0x10)
+            .with_core(1)
+            .with_name(b"idle<0>")
+            .with_handle(&[0x20, 0x00, 0x01, 0x00])
+            .write_xml(&mut s)
+            .unwrap();
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<thread id=\"10\" core=\"1\" name=\"idle&lt;0&gt;\" handle=\"20000100\"/>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_write_cores_in_decimal() {
+        let mut s = BufferedStream::new();
+        ThreadInfo::new(2).with_core(12).write_xml(&mut s).unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"<thread id=\"2\" core=\"12\"/>"[..]);
+    }
+
+    #[test]
+    fn it_should_write_minimal_threads() {
+        let mut s = BufferedStream::new();
+        let size = ThreadInfo::new(1).write_xml(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"<thread id=\"1\"/>"[..]);
+    }
+
+    #[test]
+    fn it_should_parse_thread_ids() {
+        assert_eq!(ThreadId::parse(b"1f"), Some(ThreadId::new(0x1f)));
+        assert_eq!(ThreadId::parse(b"-1"), Some(ThreadId::new(ALL_THREADS)));
+        assert_eq!(
+            ThreadId::parse(b"p2a.3"),
+            Some(ThreadId::new(3).with_pid(0x2a))
+        );
+        assert_eq!(
+            ThreadId::parse(b"p2a"),
+            Some(ThreadId::new(ALL_THREADS).with_pid(0x2a))
+        );
+        assert_eq!(
+            ThreadId::parse(b"p-1.-1"),
+            Some(ThreadId::new(ALL_THREADS).with_pid(ALL_THREADS))
+        );
+        assert_eq!(ThreadId::parse(b"p"), None);
+        assert_eq!(ThreadId::parse(b"p1.x"), None);
+    }
+
+    #[test]
+    fn it_should_write_process_threads() {
+        let mut s = BufferedStream::new();
+        let size = ThreadInfo::new(2).with_pid(1).write_xml(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(s.buffer[..s.pos()], b"<thread id=\"p1.2\"/>"[..]);
+    }
+}
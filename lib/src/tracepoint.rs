@@ -0,0 +1,270 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+use super::xml;
+
+/// a tracepoint as defined by QTDP
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tracepoint<'a> {
+    pub number: usize,
+    pub addr: usize,
+    pub enabled: bool,
+    /// instructions to single-step after the hit
+    pub step: usize,
+    /// stops the experiment after this many hits, 0 never stops
+    pub pass: usize,
+    /// hex encoded agent expression that has to be true to collect
+    pub condition: Option<&'a [u8]>,
+}
+
+/// selects a trace frame for QTFrame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameQuery {
+    /// stops looking at trace frames and returns to the live target
+    Live,
+    Number(usize),
+    /// the next frame collected at pc
+    Pc(usize),
+    /// the next frame collected by a tracepoint
+    Tracepoint(usize),
+    /// the next frame with a pc in start..=end
+    Range(usize, usize),
+    /// the next frame with a pc outside of start..=end
+    Outside(usize, usize),
+}
+
+/// a collected trace frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub number: usize,
+    /// the tracepoint that collected the frame
+    pub tracepoint: usize,
+}
+
+/// why the last trace experiment ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceStop {
+    /// no experiment was started yet
+    NotRun,
+    /// stopped by QTStop
+    Stopped,
+    /// the trace buffer is full
+    Full,
+    /// a tracepoint reached its pass count
+    Passcount(usize),
+    /// a tracepoint failed to collect
+    Error(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceStatus {
+    pub running: bool,
+    pub stop: TraceStop,
+    /// frames in the trace buffer
+    pub frames: usize,
+    /// frames created in total including discarded ones
+    pub created: usize,
+}
+
+impl TraceStatus {
+    /// writes the qTStatus reply
+    pub fn write(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = stream.write_all(if self.running { b"T1;" } else { b"T0;" })?;
+        size += match self.stop {
+            TraceStop::NotRun => stream.write_all(b"tnotrun:0")?,
+            TraceStop::Stopped => stream.write_all(b"tstop:0")?,
+            TraceStop::Full => stream.write_all(b"tfull:0")?,
+            TraceStop::Passcount(tp) => {
+                stream.write_all(b"tpasscount:")? + Parser::to_hex_number(tp, stream)?
+            }
+            TraceStop::Error(tp) => {
+                stream.write_all(b"terror::")? + Parser::to_hex_number(tp, stream)?
+            }
+        };
+        size += stream.write_all(b";tframes:")?;
+        size += Parser::to_hex_number(self.frames, stream)?;
+        size += stream.write_all(b";tcreated:")?;
+        size += Parser::to_hex_number(self.created, stream)?;
+        Ok(size)
+    }
+}
+
+/// : separated list of start,end address ranges sent by QTro
+/// end is exclusive
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReadOnlyRanges<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> ReadOnlyRanges<'a> {
+    /// fails if any range is not a pair of hex numbers
+    pub fn parse(raw: &'a [u8]) -> Result<Self, Errors> {
+        let ranges = Self { raw };
+        for range in ranges.entries() {
+            Self::parse_range(range).ok_or(Errors::BadNumber)?;
+        }
+        Ok(ranges)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.raw.split(|b| *b == b':').filter(|s| !s.is_empty())
+    }
+
+    fn parse_range(range: &[u8]) -> Option<(usize, usize)> {
+        let mut parts = range.splitn(2, |b| *b == b',');
+        let start = parts.next().filter(|s| !s.is_empty())?;
+        let end = parts.next().filter(|s| !s.is_empty())?;
+        Some((Parser::from_hexu(start)?, Parser::from_hexu(end)?))
+    }
+
+    /// yields start and exclusive end of each range
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.entries().filter_map(Self::parse_range)
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.iter().any(|(start, end)| addr >= start && addr < end)
+    }
+}
+
+/// a piece of data collected in a trace frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Collected {
+    Memory {
+        start: usize,
+        len: usize,
+    },
+    /// a trace state variable
+    Variable(usize),
+}
+
+impl Collected {
+    /// writes the item as an element of the traceframe-info document
+    pub fn write_xml(&self, stream: &mut dyn Stream) -> Result<usize, Errors> {
+        let mut size = 0;
+        match *self {
+            Self::Memory { start, len } => {
+                size += stream.write_all(b"<memory")?;
+                size += xml::write_attr_addr(stream, b"start", start)?;
+                size += xml::write_attr_addr(stream, b"length", len)?;
+            }
+            Self::Variable(id) => {
+                size += stream.write_all(b"<tvar id=\"")?;
+                size += Parser::to_decimal(id, stream)?;
+                size += stream.write(b'"')?;
+            }
+        }
+        size += stream.write_all(b"/>")?;
+        Ok(size)
+    }
+}
+
+/// callback receiving each item collected in a frame
+pub type CollectedCallback<'a> = dyn FnMut(Collected) -> Result<usize, Errors> + 'a;
+
+/// writes the traceframe-info xml document
+/// items is expected to call the callback once per collected item
+pub fn write_traceframe_info(
+    stream: &mut dyn Stream,
+    items: &mut dyn FnMut(&mut CollectedCallback) -> Result<usize, Errors>,
+) -> Result<usize, Errors> {
+    let mut size = stream.write_all(b"<traceframe-info>")?;
+    size += items(&mut |item| item.write_xml(stream))?;
+    size += stream.write_all(b"</traceframe-info>")?;
+    Ok(size)
+}
+
+/// Tracepoints collect data while the target keeps running.
+/// gdb defines all tracepoints, starts the experiment and
+/// later inspects the collected frames with QTFrame.
+pub trait TraceTarget {
+    /// removes all tracepoints and collected frames
+    fn init(&mut self) -> Result<usize, Errors>;
+
+    fn define(&mut self, tracepoint: Tracepoint) -> Result<usize, Errors>;
+
+    /// adds a single action to a tracepoint, e.g. R0f or M1,2000,4
+    /// while-stepping actions are prefixed with S
+    fn add_action(&mut self, number: usize, addr: usize, action: &[u8]) -> Result<usize, Errors>;
+
+    fn set_enabled(&mut self, number: usize, addr: usize, enabled: bool) -> Result<usize, Errors>;
+
+    fn start(&mut self) -> Result<usize, Errors>;
+
+    fn stop(&mut self) -> Result<usize, Errors>;
+
+    fn status(&self) -> TraceStatus;
+
+    /// selects the frame memory and register reads refer to
+    /// returns None if no frame matches
+    fn find_frame(&mut self, query: FrameQuery) -> Result<Option<TraceFrame>, Errors>;
+
+    /// replaces the read-only ranges of the experiment
+    /// memory reads of a trace frame may use live memory for them
+    fn set_read_only(&mut self, _ranges: ReadOnlyRanges) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// enumerates what the selected frame collected by calling f once per item
+    /// returning anything but Unsupported enables qXfer:traceframe-info:read
+    fn frame_info(&self, _f: &mut CollectedCallback) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+
+    /// copies the raw trace buffer starting at offset into out
+    /// returns the amount of bytes copied, 0 past the end
+    fn read_buffer(&self, _offset: usize, _out: &mut [u8]) -> Result<usize, Errors> {
+        Err(Errors::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_parse_read_only_ranges() {
+        let ranges = ReadOnlyRanges::parse(b"1000,2000:8000,8010").unwrap();
+        assert!(ranges.contains(0x1000));
+        assert!(ranges.contains(0x800f));
+        assert!(!ranges.contains(0x2000));
+        assert_eq!(ReadOnlyRanges::parse(b"1000"), Err(Errors::BadNumber));
+        assert_eq!(ReadOnlyRanges::parse(b"").unwrap().iter().count(), 0);
+    }
+
+    #[test]
+    fn it_should_write_traceframe_info() {
+        let mut s = BufferedStream::new();
+        let size = write_traceframe_info(&mut s, &mut |f| {
+            Ok(f(Collected::Memory {
+                start: 0x2000_0000,
+                len: 0x10,
+            })? + f(Collected::Variable(1))?)
+        })
+        .unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"<traceframe-info><memory start=\"0x20000000\" length=\"0x10\"/>\
+            <tvar id=\"1\"/></traceframe-info>"[..]
+        );
+    }
+
+    #[test]
+    fn it_should_write_status() {
+        let mut s = BufferedStream::new();
+        let status = TraceStatus {
+            running: false,
+            stop: TraceStop::Passcount(2),
+            frames: 0x10,
+            created: 0x12,
+        };
+        let size = status.write(&mut s).unwrap();
+        assert_eq!(size, s.pos());
+        assert_eq!(
+            s.buffer[..s.pos()],
+            b"T0;tpasscount:2;tframes:10;tcreated:12"[..]
+        );
+    }
+}
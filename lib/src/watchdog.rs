@@ -0,0 +1,124 @@
+use super::breakpoint::BreakpointList;
+use super::error::Errors;
+use super::target::Target;
+
+/// monotonic time source
+pub trait Clock {
+    /// current time in milliseconds
+    fn now(&self) -> u64;
+}
+
+/// Detaches from the target once gdb has been silent for too long
+/// so a crashed host does not leave the target halted forever.
+/// The watchdog has to be fed whenever data from gdb arrives
+/// and while the target runs, GdbStub::with_watchdog does both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchdog {
+    timeout: u64,
+    last: u64,
+    detached: bool,
+}
+
+impl Watchdog {
+    /// timeout is in milliseconds
+    pub fn new(timeout: u64, clock: &dyn Clock) -> Self {
+        Self {
+            timeout,
+            last: clock.now(),
+            detached: false,
+        }
+    }
+
+    pub fn feed(&mut self, clock: &dyn Clock) {
+        self.last = clock.now();
+        self.detached = false;
+    }
+
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool {
+        clock.now().saturating_sub(self.last) >= self.timeout
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// removes the breakpoints and detaches the target if the timeout expired
+    /// returns true if the target was detached by this call
+    pub fn poll(
+        &mut self,
+        clock: &dyn Clock,
+        ctx: &mut dyn Target,
+        breakpoints: &mut BreakpointList,
+    ) -> Result<bool, Errors> {
+        if self.detached || !self.is_expired(clock) {
+            return Ok(false);
+        }
+        breakpoints.remove_all(ctx);
+        ctx.detach(None)?;
+        self.detached = true;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct TestClock(Cell<u64>);
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[derive(Default)]
+    struct TestCtx {
+        detached: usize,
+    }
+    impl Target for TestCtx {
+        fn detach(&mut self, _pid: Option<usize>) -> Result<usize, Errors> {
+            self.detached += 1;
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn it_should_detach_after_timeout() {
+        let clock = TestClock(Cell::new(100));
+        let mut ctx = TestCtx::default();
+        let mut watchdog = Watchdog::new(50, &clock);
+
+        clock.0.set(149);
+        assert!(!watchdog
+            .poll(&clock, &mut ctx, &mut BreakpointList::new())
+            .unwrap());
+        clock.0.set(150);
+        assert!(watchdog
+            .poll(&clock, &mut ctx, &mut BreakpointList::new())
+            .unwrap());
+        assert!(watchdog.is_detached());
+
+        // only detach once
+        clock.0.set(300);
+        assert!(!watchdog
+            .poll(&clock, &mut ctx, &mut BreakpointList::new())
+            .unwrap());
+        assert_eq!(ctx.detached, 1);
+    }
+
+    #[test]
+    fn it_should_not_detach_when_fed() {
+        let clock = TestClock(Cell::new(0));
+        let mut ctx = TestCtx::default();
+        let mut watchdog = Watchdog::new(50, &clock);
+
+        clock.0.set(40);
+        watchdog.feed(&clock);
+        clock.0.set(80);
+        assert!(!watchdog
+            .poll(&clock, &mut ctx, &mut BreakpointList::new())
+            .unwrap());
+        assert_eq!(ctx.detached, 0);
+    }
+}
@@ -0,0 +1,73 @@
+use super::error::Errors;
+use super::parser::Parser;
+use super::stream::Stream;
+
+/// writes text with xml special characters escaped
+pub fn write_escaped(stream: &mut dyn Stream, text: &[u8]) -> Result<usize, Errors> {
+    let mut size = 0;
+    for byte in text {
+        size += match byte {
+            b'&' => stream.write_all(b"&amp;")?,
+            b'<' => stream.write_all(b"&lt;")?,
+            b'>' => stream.write_all(b"&gt;")?,
+            b'"' => stream.write_all(b"&quot;")?,
+            _ => stream.write(*byte)?,
+        };
+    }
+    Ok(size)
+}
+
+/// writes name="value" with a leading space
+pub fn write_attr(stream: &mut dyn Stream, name: &[u8], value: &[u8]) -> Result<usize, Errors> {
+    let mut size = stream.write(b' ')?;
+    size += stream.write_all(name)?;
+    size += stream.write_all(b"=\"")?;
+    size += write_escaped(stream, value)?;
+    size += stream.write(b'"')?;
+    Ok(size)
+}
+
+/// writes name="value" where value is a decimal number
+pub fn write_attr_decimal(
+    stream: &mut dyn Stream,
+    name: &[u8],
+    value: usize,
+) -> Result<usize, Errors> {
+    let mut size = stream.write(b' ')?;
+    size += stream.write_all(name)?;
+    size += stream.write_all(b"=\"")?;
+    size += Parser::to_decimal(value, stream)?;
+    size += stream.write(b'"')?;
+    Ok(size)
+}
+
+/// writes name="0xvalue", the form gdb expects for addresses
+pub fn write_attr_addr(stream: &mut dyn Stream, name: &[u8], addr: usize) -> Result<usize, Errors> {
+    let mut size = stream.write(b' ')?;
+    size += stream.write_all(name)?;
+    size += stream.write_all(b"=\"0x")?;
+    size += Parser::to_hex_number(addr, stream)?;
+    size += stream.write(b'"')?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BufferedStream;
+
+    #[test]
+    fn it_should_escape_text() {
+        let mut s = BufferedStream::new();
+        write_escaped(&mut s, b"<a & \"b\">").unwrap();
+        assert_eq!(s.buffer[..s.pos()], b"&lt;a &amp; &quot;b&quot;&gt;"[..]);
+    }
+
+    #[test]
+    fn it_should_write_attributes() {
+        let mut s = BufferedStream::new();
+        write_attr(&mut s, b"name", b"main").unwrap();
+        write_attr_decimal(&mut s, b"core", 31).unwrap();
+        assert_eq!(s.buffer[..s.pos()], b" name=\"main\" core=\"31\""[..]);
+    }
+}